@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() {
     // Only apply bare-metal linker settings when targeting a no_std platform.
@@ -28,4 +29,91 @@ fn main() {
     println!("cargo:rustc-link-arg=-T{}", lds_path.display());
     println!("cargo:rustc-link-arg=-no-pie");
     println!("cargo:rustc-link-arg=-znostart-stop-gc");
+
+    // The x86_64 guest simulates the kernel and has no user apps, unless
+    // it runs user space for real (`x86-uspace`).
+    if std::env::var_os("CARGO_FEATURE_INITRD").is_some()
+        && (arch != "x86_64" || std::env::var_os("CARGO_FEATURE_X86_USPACE").is_some())
+    {
+        pack_initrd(Path::new(&out_dir), &arch);
+    }
+}
+
+/// Packs the files under the initrd directory into `$OUT_DIR/initrd.cpio`
+/// (guest feature `initrd`), in the newc cpio format the guest's `archive`
+/// module reads, each named by its path relative to the directory. The
+/// directory is `$GKERNEL_INITRD`, or else `payload/gkernel/initrd/<arch>`;
+/// a missing one gives an archive with no files.
+fn pack_initrd(out_dir: &Path, arch: &str) {
+    println!("cargo:rerun-if-env-changed=GKERNEL_INITRD");
+    let dir = match std::env::var_os("GKERNEL_INITRD") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("payload/gkernel/initrd")
+            .join(arch),
+    };
+    println!("cargo:rerun-if-changed={}", dir.display());
+    let mut files = Vec::new();
+    if dir.is_dir() {
+        collect_files(&dir, &dir, &mut files);
+    } else {
+        println!(
+            "cargo:warning=initrd directory {} not found, the initrd is empty",
+            dir.display()
+        );
+    }
+    files.sort();
+
+    let mut archive = Vec::new();
+    for (ino, (name, path)) in files.iter().enumerate() {
+        let data = fs::read(path).unwrap();
+        newc_entry(&mut archive, ino + 1, 0o100755, name, &data);
+    }
+    newc_entry(&mut archive, 0, 0, "TRAILER!!!", &[]);
+    fs::write(out_dir.join("initrd.cpio"), archive).unwrap();
+}
+
+/// Adds the path relative to `root` and the full path of every regular
+/// file under `dir` to `files`.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(root, &path, files);
+        } else if path.is_file() {
+            let name = path.strip_prefix(root).unwrap().to_str().unwrap();
+            files.push((name.replace('\\', "/"), path));
+        }
+    }
+}
+
+/// Appends a newc entry: the header (magic and 13 fields of 8 hex digits:
+/// ino, mode, uid, gid, nlink, mtime, filesize, dev and rdev major/minor,
+/// namesize, check), then the NUL-terminated name and the data, each padded
+/// to 4 bytes.
+fn newc_entry(archive: &mut Vec<u8>, ino: usize, mode: usize, name: &str, data: &[u8]) {
+    let fields = [
+        ino,
+        mode,
+        0,
+        0,
+        1,
+        0,
+        data.len(),
+        0,
+        0,
+        0,
+        0,
+        name.len() + 1,
+        0,
+    ];
+    archive.extend_from_slice(b"070701");
+    for field in fields {
+        archive.extend_from_slice(format!("{field:08x}").as_bytes());
+    }
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    archive.resize(archive.len().next_multiple_of(4), 0);
+    archive.extend_from_slice(data);
+    archive.resize(archive.len().next_multiple_of(4), 0);
 }
@@ -0,0 +1,52 @@
+//! How the aarch64 guest reaches the firmware or hypervisor below it.
+//!
+//! PSCI and the other SMCCC services (the hypercall channel in particular)
+//! are called with either `smc #0` or `hvc #0`, and which one is right
+//! depends on where the guest runs:
+//!
+//! - `smc` (the default) when the guest is started in bootloader mode, as
+//!   `cargo xtask run` does today: the kernel owns EL1 with nothing but
+//!   firmware (QEMU's built-in PSCI, or EL3) below it, or runs under a
+//!   hypervisor that forwards SMCs.
+//! - `hvc` (feature `aarch64-hvc`) when the guest is a virtualized EL1
+//!   under a hypervisor that implements PSCI itself and traps HVC, such as
+//!   KVM or a nested setup; there an SMC may be undefined.
+//!
+//! The choice is made at build time; the two cannot be told apart safely
+//! at run time, since a wrong guess is an undefined instruction.
+
+/// PSCI `SYSTEM_OFF` (SMC32 fast call).
+const PSCI_SYSTEM_OFF: usize = 0x8400_0008;
+
+/// Makes the SMCCC call `fid` with arguments `args` in x1..x3 and returns
+/// x0, over the configured conduit.
+pub fn smccc_call(fid: usize, args: [usize; 3]) -> usize {
+    let ret: usize;
+    unsafe {
+        #[cfg(not(feature = "aarch64-hvc"))]
+        core::arch::asm!(
+            "smc #0",
+            inlateout("x0") fid => ret,
+            inlateout("x1") args[0] => _,
+            inlateout("x2") args[1] => _,
+            inlateout("x3") args[2] => _,
+            options(nostack),
+        );
+        #[cfg(feature = "aarch64-hvc")]
+        core::arch::asm!(
+            "hvc #0",
+            inlateout("x0") fid => ret,
+            inlateout("x1") args[0] => _,
+            inlateout("x2") args[1] => _,
+            inlateout("x3") args[2] => _,
+            options(nostack),
+        );
+    }
+    ret
+}
+
+/// Powers the machine off with PSCI `SYSTEM_OFF`.
+pub fn system_off() -> ! {
+    smccc_call(PSCI_SYSTEM_OFF, [0; 3]);
+    unreachable!("PSCI SYSTEM_OFF returned");
+}
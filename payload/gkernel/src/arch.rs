@@ -0,0 +1,197 @@
+//! What the guest does differently on each architecture, apart from the
+//! syscall numbers (`monolithic_kernel::syscall_abi`) and the user context
+//! layouts that follow from them: the machine code of the boot program, and
+//! how the guest shuts down and passes its exit status on.
+//!
+//! Each architecture is a zero-sized type implementing `GuestArch`, and
+//! one of them is selected once here as `Arch`; the rest of the kernel
+//! uses it through the trait. Adding an architecture means adding a type
+//! and its impl here, which the compiler holds to the whole trait, and a
+//! block to each syscall table.
+
+pub use imp::Arch;
+
+/// What the kernel needs from the architecture it runs on.
+pub trait GuestArch {
+    /// The boot program: `exit(0)`. A constant rather than a function, so
+    /// that its checksum (`apps::Integrity`) is computed at build time.
+    const EXIT_APP: &'static [u8];
+
+    /// The syscall number `EXIT_APP` makes, decoded from its first
+    /// instruction.
+    const SYSNO_EXIT: usize;
+
+    /// Shuts the guest down, passing `status` on as the exit status.
+    fn shutdown(status: u8) -> !;
+}
+
+#[cfg(target_arch = "riscv64")]
+mod imp {
+    use super::GuestArch;
+
+    /// The boot program: `exit(0)`.
+    const EXIT0: &[u8] = &[
+        // li a7, 93       (addi x17, x0, 93)
+        0x93, 0x08, 0xd0, 0x05, // li a0, 0        (addi x10, x0, 0)
+        0x13, 0x05, 0x00, 0x00, // ecall
+        0x73, 0x00, 0x00, 0x00,
+    ];
+
+    /// The syscall number `EXIT0` makes. `li a7, imm` is `addi x17, x0,
+    /// imm`: opcode 0x13, rd 17, funct3 and rs1 0, and the immediate in the
+    /// top 12 bits.
+    const EXIT0_SYSNO: usize = {
+        let insn = u32::from_le_bytes([EXIT0[0], EXIT0[1], EXIT0[2], EXIT0[3]]);
+        assert!(
+            insn & 0xf_ffff == 0x0000_0893,
+            "EXIT0 must start with li a7, imm"
+        );
+        (insn as i32 >> 20) as usize
+    };
+
+    /// Vendor-specific SBI SRST reset reason carrying a non-zero exit status.
+    const SRST_REASON_EXIT_STATUS: usize = 0xF000_0000;
+
+    /// riscv64: the guest runs in VS-mode under the RISC-V hypervisor.
+    pub struct Riscv64;
+
+    pub type Arch = Riscv64;
+
+    impl GuestArch for Riscv64 {
+        const EXIT_APP: &'static [u8] = EXIT0;
+        const SYSNO_EXIT: usize = EXIT0_SYSNO;
+
+        /// SBI SRST shutdown (a7 = "SRST", a6 = 0, a0 = 0 = shutdown). The
+        /// reset reason in a1 is 0 (NoReason) for exit status 0, and
+        /// `SRST_REASON_EXIT_STATUS | status` otherwise, which lies in the SBI
+        /// vendor-specific reason range. The hypervisor decodes it and, on
+        /// failure, requests an SBI SystemFailure shutdown from the firmware,
+        /// which makes QEMU exit non-zero.
+        fn shutdown(status: u8) -> ! {
+            let reason = if status == 0 {
+                0
+            } else {
+                SRST_REASON_EXIT_STATUS | status as usize
+            };
+            unsafe {
+                core::arch::asm!(
+                    "ecall",
+                    in("a7") 0x53525354usize, // SRST extension
+                    in("a6") 0usize,          // system_reset
+                    in("a0") 0usize,          // reset type: shutdown
+                    in("a1") reason,
+                    options(noreturn),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod imp {
+    use super::GuestArch;
+
+    /// The boot program: `exit(0)`.
+    const EXIT0: &[u8] = &[
+        // mov x8, #93     (0xd2800ba8)
+        0xa8, 0x0b, 0x80, 0xd2, // mov x0, #0      (0xd2800000)
+        0x00, 0x00, 0x80, 0xd2, // svc #0          (0xd4000001)
+        0x01, 0x00, 0x00, 0xd4,
+    ];
+
+    /// The syscall number `EXIT0` makes. `mov x8, #imm` is `movz x8, #imm`:
+    /// 0xd28 in the top bits, shift (hw) 0, rd 8, and the immediate in bits
+    /// 5-20.
+    const EXIT0_SYSNO: usize = {
+        let insn = u32::from_le_bytes([EXIT0[0], EXIT0[1], EXIT0[2], EXIT0[3]]);
+        assert!(
+            insn & 0xffe0_001f == 0xd280_0008,
+            "EXIT0 must start with mov x8, #imm"
+        );
+        ((insn >> 5) & 0xffff) as usize
+    };
+
+    /// aarch64: the guest runs at EL1, handed the machine in bootloader mode.
+    pub struct Aarch64;
+
+    pub type Arch = Aarch64;
+
+    impl GuestArch for Aarch64 {
+        const EXIT_APP: &'static [u8] = EXIT0;
+        const SYSNO_EXIT: usize = EXIT0_SYSNO;
+
+        /// The guest runs in bootloader mode and shuts QEMU down itself. Status
+        /// 0 uses PSCI SYSTEM_OFF (QEMU exits 0), over SMC or HVC as configured
+        /// in `aarch64_platform`; anything else uses the semihosting
+        /// `SYS_EXIT_EXTENDED` call, which makes QEMU exit with the status
+        /// itself (QEMU is started with semihosting enabled).
+        fn shutdown(status: u8) -> ! {
+            // The guest has direct hardware access; keep the final status
+            // messages consistent with the other architectures.
+            if status == 0 {
+                println!("Shutdown vm normally!");
+                println!("Hypervisor ok!");
+            } else {
+                println!("Shutdown vm: guest exit status {}", status);
+                // Semihosting SYS_EXIT_EXTENDED with
+                // ADP_Stopped_ApplicationExit: x1 points to the (reason,
+                // status) pair.
+                let block: [u64; 2] = [0x20026, status as u64];
+                unsafe {
+                    core::arch::asm!(
+                        "hlt #0xf000",
+                        in("x0") 0x20u64,
+                        in("x1") block.as_ptr(),
+                        options(noreturn, nostack),
+                    );
+                }
+            }
+            crate::aarch64_platform::system_off()
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod imp {
+    use super::GuestArch;
+
+    /// The boot program: `exit(0)`.
+    const EXIT0: &[u8] = &[
+        // mov eax, 60     (b8 3c 00 00 00)
+        0xb8, 0x3c, 0x00, 0x00, 0x00, // xor edi, edi    (31 ff)
+        0x31, 0xff, // syscall         (0f 05)
+        0x0f, 0x05,
+    ];
+
+    /// The syscall number `EXIT0` makes. `mov eax, imm32` is 0xb8 followed
+    /// by the immediate, little-endian.
+    const EXIT0_SYSNO: usize = {
+        assert!(EXIT0[0] == 0xb8, "EXIT0 must start with mov eax, imm32");
+        u32::from_le_bytes([EXIT0[1], EXIT0[2], EXIT0[3], EXIT0[4]]) as usize
+    };
+
+    /// x86_64: the guest runs in an AMD SVM container.
+    pub struct X86_64;
+
+    pub type Arch = X86_64;
+
+    impl GuestArch for X86_64 {
+        const EXIT_APP: &'static [u8] = EXIT0;
+        const SYSNO_EXIT: usize = EXIT0_SYSNO;
+
+        /// The guest runs in an AMD SVM container: VMMCALL with rax =
+        /// 0x84000008 (PSCI SYSTEM_OFF) and the status in rdi. On failure the
+        /// hypervisor writes the status to the isa-debug-exit port, so QEMU
+        /// exits with `(status << 1) | 1`.
+        fn shutdown(status: u8) -> ! {
+            unsafe {
+                core::arch::asm!(
+                    "vmmcall",
+                    in("rax") 0x84000008u64,
+                    in("rdi") status as u64,
+                    options(noreturn, nomem, nostack),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Messages from the guest to the hypervisor (feature `hypercall`).
+//!
+//! A hypercall carries a call number and up to three arguments, and comes
+//! back with one value. It uses each architecture's native channel to the
+//! layer below:
+//!
+//! | arch    | instruction | call number            | arguments  | reply      |
+//! |---------|-------------|------------------------|------------|------------|
+//! | riscv64 | `ecall`     | a7 = `SBI_EID`, a6 = n | a0, a1, a2 | a1 (a0 = SBI error) |
+//! | aarch64 | `smc #0`\*  | x0 = `SMCCC_BASE` + n  | x1, x2, x3 | x0         |
+//! | x86_64  | `vmmcall`   | rax = `SMCCC_BASE` + n | rdi, rsi, rdx | rax     |
+//!
+//! riscv64 uses an SBI extension ID from the experimental range, aarch64 and
+//! x86_64 an SMCCC function ID from the vendor-specific hypervisor service
+//! range (the x86_64 hypervisor already takes PSCI IDs over VMMCALL).
+//! \* `hvc #0` with the `aarch64-hvc` feature; see `aarch64_platform`.
+//!
+//! The hypervisor answers call 0 (`HC_VERSION`, the protocol version), 1
+//! (`HC_ECHO`, returns its first argument) and 2 (`HC_LOG`, prints the
+//! three arguments on the host console). Anything else gets
+//! `HC_UNSUPPORTED`.
+//!
+//! On aarch64 the guest runs in bootloader mode, without a hypervisor, and
+//! an SMC that the firmware does not know is an undefined instruction. The
+//! call is therefore probed with `PSCI_FEATURES` first; under QEMU's
+//! built-in PSCI firmware every hypercall returns `HC_UNSUPPORTED`.
+
+/// Protocol version.
+pub const HC_VERSION: usize = 0;
+/// Returns its first argument unchanged.
+pub const HC_ECHO: usize = 1;
+
+/// Reply to a call that nothing below the guest implements.
+pub const HC_UNSUPPORTED: usize = usize::MAX;
+
+/// SBI extension ID for hypercalls ("HVC" in the experimental range).
+#[cfg(target_arch = "riscv64")]
+const SBI_EID: usize = 0x0848_5643;
+
+/// SMCCC fast call, SMC64, owner "vendor-specific hypervisor service".
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+const SMCCC_BASE: usize = 0xC600_0000;
+
+/// Sends call `nr` with `args` to the hypervisor and returns its reply.
+pub fn hypercall(nr: usize, args: [usize; 3]) -> usize {
+    #[cfg(target_arch = "riscv64")]
+    {
+        let error: usize;
+        let value: usize;
+        unsafe {
+            core::arch::asm!(
+                "ecall",
+                inlateout("a0") args[0] => error,
+                inlateout("a1") args[1] => value,
+                in("a2") args[2],
+                in("a6") nr,
+                in("a7") SBI_EID,
+            );
+        }
+        if error == 0 { value } else { HC_UNSUPPORTED }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        use crate::aarch64_platform::smccc_call;
+
+        const PSCI_FEATURES: usize = 0x8400_000A;
+        let fid = SMCCC_BASE + nr;
+        if (smccc_call(PSCI_FEATURES, [fid, 0, 0]) as isize) < 0 {
+            return HC_UNSUPPORTED;
+        }
+        smccc_call(fid, args)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        let ret: usize;
+        unsafe {
+            core::arch::asm!(
+                "vmmcall",
+                inlateout("rax") SMCCC_BASE + nr => ret,
+                in("rdi") args[0],
+                in("rsi") args[1],
+                in("rdx") args[2],
+                options(nostack),
+            );
+        }
+        ret
+    }
+}
+
+/// Checks at boot that the hypervisor answers hypercalls.
+pub fn probe() {
+    let version = hypercall(HC_VERSION, [0; 3]);
+    if version == HC_UNSUPPORTED {
+        info_println!("hypercall: not supported by the host");
+        return;
+    }
+    let echo = hypercall(HC_ECHO, [0x1234_5678, 0, 0]);
+    info_println!(
+        "hypercall: host protocol version {}, echo {}",
+        version,
+        if echo == 0x1234_5678 { "ok" } else { "FAILED" }
+    );
+}
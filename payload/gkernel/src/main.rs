@@ -15,7 +15,9 @@
 //!
 //! Note: On x86_64 (SVM/TCG), the `uspace` feature on axhal triggers a crash
 //! during axtask initialization. Therefore x86_64 simulates the monolithic
-//! kernel output without actually running user code.
+//! kernel output without actually running user code, unless built with the
+//! `x86-uspace` feature (for KVM, where the crash has not been seen), which
+//! turns on `uspace` and the real kernel.
 
 #![no_std]
 #![no_main]
@@ -26,218 +28,470 @@
 // ══════════════════════════════════════════════════════════════
 
 #[cfg(feature = "axstd")]
-#[macro_use]
+#[cfg_attr(
+    any(
+        not(feature = "unified-console"),
+        all(target_arch = "x86_64", not(feature = "x86-uspace"))
+    ),
+    macro_use
+)]
 extern crate axstd as std;
 
 #[cfg(feature = "axstd")]
 extern crate alloc;
 
-// ── Real user-space monolithic kernel (riscv64, aarch64) ──
+// With `unified-console`, kernel output shares the console writer that user
+// writes go through (see `monolithic_kernel::console`). These stand in for
+// the axstd macros (which are not imported then) in the whole crate, so
+// they must stay above every module.
+#[cfg(all(
+    feature = "axstd",
+    feature = "unified-console",
+    any(not(target_arch = "x86_64"), feature = "x86-uspace")
+))]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::monolithic_kernel::console::print_fmt(format_args!($($arg)*))
+    };
+}
+
+#[cfg(all(
+    feature = "axstd",
+    feature = "unified-console",
+    any(not(target_arch = "x86_64"), feature = "x86-uspace")
+))]
+macro_rules! println {
+    () => { print!("\n") };
+    ($($arg:tt)*) => { print!("{}\n", format_args!($($arg)*)) };
+}
+
+/// `println!` for progress messages (load addresses, user-space entry, exit
+/// notices); mappings and syscall traces are log messages instead (see
+/// `init_log_level`). With the `quiet` feature the branch is constant
+/// `false`, so no formatting code is generated for them; program output,
+/// errors and the exit status always print.
+#[cfg(feature = "axstd")]
+macro_rules! info_println {
+    ($($arg:tt)*) => {
+        if cfg!(not(feature = "quiet")) {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[cfg(all(feature = "axstd", target_arch = "aarch64"))]
+mod aarch64_platform;
+// The simulated x86_64 kernel has no boot program to run.
+#[cfg(feature = "axstd")]
+#[cfg_attr(
+    all(target_arch = "x86_64", not(feature = "x86-uspace")),
+    allow(dead_code)
+)]
+mod arch;
+#[cfg(all(feature = "axstd", feature = "hypercall"))]
+mod hypercall;
+#[cfg(feature = "axstd")]
+mod meminfo;
+
+// ── Real user-space monolithic kernel (riscv64, aarch64; x86_64 with x86-uspace) ──
 // Uses axhal::uspace for real user context entry/exit.
-#[cfg(all(feature = "axstd", not(target_arch = "x86_64")))]
+#[cfg(all(
+    feature = "axstd",
+    any(not(target_arch = "x86_64"), feature = "x86-uspace")
+))]
 mod monolithic_kernel {
+    mod apps;
+    mod archive;
+    mod aspace;
+    #[cfg(all(feature = "cmdline", target_arch = "riscv64"))]
+    mod cmdline;
+    mod config;
+    pub(crate) mod console;
+    mod coop;
+    mod demo;
+    mod epoll;
+    mod error;
+    mod eventfd;
+    mod exec;
+    mod fault;
+    mod fd;
+    mod fork;
+    #[cfg(all(feature = "host-app", target_arch = "riscv64"))]
+    mod host_app;
+    mod loader;
+    #[cfg(all(feature = "compressed-app", target_arch = "riscv64"))]
+    mod lz;
+    mod memfs;
+    mod mm;
+    mod poll;
+    mod process;
+    mod shm;
+    mod signal;
+    mod syscall;
+    mod syscall_abi;
+    mod syscall_filter;
+    #[cfg(feature = "syscall-record")]
+    mod syscall_record;
+    #[cfg(feature = "syscall-sequence")]
+    mod syscall_sequence;
+    mod task;
+    mod time;
+    mod uaccess;
+    mod wait;
+
+    use alloc::string::ToString;
     use axmm::AddrSpace;
-    use std::os::arceos::modules::axhal::mem::{PAGE_SIZE_4K, VirtAddr, phys_to_virt, va};
+    use error::KernelError;
+    use std::os::arceos::modules::axhal::mem::{phys_to_virt, va};
     use std::os::arceos::modules::axhal::paging::MappingFlags;
-    use std::os::arceos::modules::axhal::uspace::{ReturnReason, UserContext};
-    use std::os::arceos::modules::axtask;
-
-    const USER_STACK_SIZE: usize = 0x10000; // 64 KB
-    const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KB
-    const APP_ENTRY: usize = 0x1000;
-
-    // User address space: 0x0 .. 0x4000_0000 (1 GiB)
-    const USER_ASPACE_BASE: usize = 0x0;
-    const USER_ASPACE_SIZE: usize = 0x4000_0000;
-
-    // ── Embedded user application binaries ──
-    // A minimal user app that calls SYS_EXIT(0).
-
-    #[cfg(target_arch = "riscv64")]
-    const USER_APP: &[u8] = &[
-        // li a7, 93       (addi x17, x0, 93)
-        0x93, 0x08, 0xd0, 0x05, // li a0, 0        (addi x10, x0, 0)
-        0x13, 0x05, 0x00, 0x00, // ecall
-        0x73, 0x00, 0x00, 0x00,
-    ];
-
-    #[cfg(target_arch = "aarch64")]
-    const USER_APP: &[u8] = &[
-        // mov x8, #93     (0xd2800ba8)
-        0xa8, 0x0b, 0x80, 0xd2, // mov x0, #0      (0xd2800000)
-        0x00, 0x00, 0x80, 0xd2, // svc #0          (0xd4000001)
-        0x01, 0x00, 0x00, 0xd4,
-    ];
-
-    const SYS_EXIT: usize = 93;
-
-    // ── User app loader (from embedded binary) ──
-
-    fn load_user_app(uspace: &mut AddrSpace) {
-        let start = va!(APP_ENTRY);
-        let flags =
-            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE | MappingFlags::USER;
-
-        // Allocate one 4K page for the app code using map_alloc
-        uspace
-            .map_alloc(start, PAGE_SIZE_4K, flags, true)
-            .expect("failed to map app code");
-
-        // Write embedded user app binary into the mapped page using AddrSpace::write
-        uspace
-            .write(start, USER_APP)
-            .expect("failed to write app code");
-
-        println!("User app loaded at {:#x}", APP_ENTRY);
-    }
-
-    // ── Init user stack ──
-
-    fn init_user_stack(uspace: &mut AddrSpace) -> VirtAddr {
-        let ustack_top = uspace.end();
-        let ustack_vaddr = ustack_top - USER_STACK_SIZE;
-        let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
-
-        println!(
-            "Mapping user stack: {:#x?} -> {:#x?}",
-            ustack_vaddr, ustack_top
+    use std::os::arceos::modules::axhal::uspace::UserContext;
+
+    // The user address space layout, configurable at build time (see
+    // `config`).
+    const USER_STACK_SIZE: usize = config::LAYOUT.user_stack_size;
+    // Left unmapped right below the user stack, so that running off its
+    // bottom faults instead of landing in whatever is mapped next (see
+    // `loader::stack_guard`).
+    const STACK_GUARD_SIZE: usize = 0x1000; // 1 page
+    const KERNEL_STACK_SIZE: usize = config::LAYOUT.kernel_stack_size;
+    const APP_ENTRY: usize = config::LAYOUT.app_entry;
+
+    // ── Heap layout ──
+    // Above a loaded image, in order: BRK_GAP unmapped bytes, the brk heap
+    // (at most BRK_MAX bytes, starting at the initial program break), then
+    // the region mmap places mappings from. All three are derived from the
+    // image's top by `loader::LoadedImage`, so they always agree.
+    const BRK_GAP: usize = 0x4000; // 4 pages
+    const BRK_MAX: usize = 0x400_0000; // 64 MB
+
+    // User address space: 0x0 .. 0x4000_0000 (1 GiB) by default
+    const USER_ASPACE_BASE: usize = config::LAYOUT.user_aspace_base;
+    const USER_ASPACE_SIZE: usize = config::LAYOUT.user_aspace_size;
+
+    // ── Entry register convention ──
+    // A program finds argc, argv, envp and the auxiliary vector on its
+    // initial stack (see `loader::init_user_stack`); sp is the only register
+    // that carries anything else. The one argument register the Linux entry
+    // ABI defines on riscv64 and aarch64 is a0/x0: the address of a function
+    // crt0 registers with atexit, the dynamic linker's finalizer
+    // (`rtld_fini`). Programs here are static, so it is always 0, which crt0
+    // takes as "none". Other registers start zeroed, except for the return
+    // address (see below).
+    const ENTRY_RTLD_FINI: usize = 0;
+
+    // ── Return-to-kernel convention ──
+    // A user program is expected to finish with SYS_EXIT: its crt0 calls
+    // `main` and passes the return value straight to SYS_EXIT, so it never
+    // returns itself. As a backstop, the initial return-address register
+    // (`ra` on riscv64, `x30` on aarch64) is loaded with this sentinel before
+    // entering user mode. It is the first address past the user address
+    // space, so it can never be mapped: a program that returns from its
+    // entry point takes an instruction page fault here, which the run loop
+    // treats as a clean `exit(0)` instead of a crash.
+    const USER_RETURN_SENTINEL: usize = USER_ASPACE_BASE + USER_ASPACE_SIZE;
+    // Return address of a signal handler without a restorer of its own: in
+    // the same unmappable page, so returning from the handler faults there
+    // and the run loop resumes the interrupted context (see `signal`).
+    const SIGRETURN_SENTINEL: usize = USER_RETURN_SENTINEL + 4;
+
+    // ── Address space layout check ──
+
+    /// Panics if the user region overlaps the kernel address space.
+    ///
+    /// `copy_mappings_from` copies every kernel mapping into the user page
+    /// table, so an overlap would silently replace user pages with kernel
+    /// ones (or the other way round). This only trips when the
+    /// `USER_ASPACE_*` constants are changed to something incompatible with
+    /// the platform's kernel layout.
+    fn assert_user_aspace_disjoint(kspace: &AddrSpace) {
+        let ustart = USER_ASPACE_BASE;
+        let uend = USER_ASPACE_BASE + USER_ASPACE_SIZE;
+        let kstart = kspace.base().as_usize();
+        let kend = kspace.end().as_usize();
+        assert!(
+            uend <= kstart || kend <= ustart,
+            "user aspace [{:#x}, {:#x}) overlaps kernel aspace [{:#x}, {:#x})",
+            ustart,
+            uend,
+            kstart,
+            kend,
         );
+    }
 
-        // Allocate pages for the user stack using map_alloc.
-        uspace
-            .map_alloc(ustack_vaddr, USER_STACK_SIZE, flags, true)
-            .expect("failed to map user stack");
-
-        ustack_top
+    /// Panics unless a few kernel pages are mapped in `uspace` just as in
+    /// `kspace`, with the access the kernel needs there: the page of the
+    /// code running now (read, execute), and a page of the linear map
+    /// through which the kernel reaches physical memory (read, write).
+    ///
+    /// Syscall handlers run on the user page table, so a kernel page that
+    /// `copy_mappings_from` dropped or copied with the wrong flags would
+    /// fault in the middle of some later syscall, far from the cause. This
+    /// catches it at setup time instead.
+    fn assert_kernel_mappings_copied(kspace: &AddrSpace, uspace: &AddrSpace) {
+        let code = va!(assert_kernel_mappings_copied as *const () as usize);
+        let linear = phys_to_virt(kspace.page_table_root());
+        for (what, vaddr, needed) in [
+            (
+                "kernel code",
+                code,
+                MappingFlags::READ | MappingFlags::EXECUTE,
+            ),
+            (
+                "linear map",
+                linear,
+                MappingFlags::READ | MappingFlags::WRITE,
+            ),
+        ] {
+            let kernel = kspace.page_table().query(vaddr);
+            let user = uspace.page_table().query(vaddr);
+            let ok = match (kernel, user) {
+                (Ok((kpa, kflags, _)), Ok((upa, uflags, _))) => {
+                    kpa == upa
+                        && kflags == uflags
+                        && uflags.contains(needed)
+                        && !uflags.contains(MappingFlags::USER)
+                }
+                _ => false,
+            };
+            assert!(
+                ok,
+                "{} page {:#x} not copied to the user page table intact \
+                 (needs {:?}, not USER): kernel {:?}, user {:?}",
+                what, vaddr, needed, kernel, user,
+            );
+        }
     }
 
     // ── Main entry point ──
 
-    pub fn run() {
-        // Create a new user address space.
-        let mut uspace = axmm::new_user_aspace(va!(USER_ASPACE_BASE), USER_ASPACE_SIZE)
-            .expect("failed to create user address space");
-
-        // Copy kernel mappings into the user page table so that
-        // kernel code/data is accessible when handling syscalls.
-        uspace
-            .copy_mappings_from(&*axmm::kernel_aspace().lock())
-            .expect("failed to copy kernel mappings");
-
-        // Load user app binary into address space.
-        load_user_app(&mut uspace);
-
-        // Init user stack.
-        let ustack_top = init_user_stack(&mut uspace);
-        println!("New user address space: {:#x?}", uspace);
-
-        let pt_root = uspace.page_table_root();
-
-        // Create user context (entry point, stack top, arg0).
-        let mut uctx = UserContext::new(APP_ENTRY, ustack_top, 0);
-
-        // Spawn a user task.
-        let mut task = axtask::TaskInner::new(
-            move || {
-                println!(
-                    "Enter user space: entry={:#x}, ustack={:#x}",
-                    APP_ENTRY, ustack_top,
-                );
-                // Run user context in a loop.
-                // UserContext::run() enters user mode and returns when
-                // a syscall, interrupt, page fault, or exception occurs.
-                loop {
-                    let reason = uctx.run();
-                    match reason {
-                        ReturnReason::Syscall => {
-                            let syscall_num = uctx.sysno();
-                            println!("handle_syscall ...");
-                            match syscall_num {
-                                SYS_EXIT => {
-                                    println!("[SYS_EXIT]: process is exiting ..");
-                                    axtask::exit(uctx.arg0() as i32);
-                                }
-                                _ => {
-                                    println!("Unimplemented syscall: {}", syscall_num);
-                                    // Set return value to -ENOSYS
-                                    uctx.set_retval((-38isize) as usize);
-                                }
-                            }
-                        }
-                        ReturnReason::Interrupt => {
-                            // Interrupt handled by framework, continue
-                        }
-                        other => {
-                            panic!("Unexpected return from user space: {:?}", other);
-                        }
-                    }
-                }
-            },
-            "userboot".into(),
-            KERNEL_STACK_SIZE,
-        );
-
-        // Set page table root for this task so that on context switch
-        // the scheduler installs the correct page table.
-        task.ctx_mut().set_page_table_root(pt_root);
+    /// Runs the user program to completion and returns its exit code, or
+    /// why it could not be started.
+    pub fn run() -> Result<i32, KernelError> {
+        run_with(|_, _| {})
+    }
 
-        let user_task = axtask::spawn_task(task);
+    /// Like `run`, but calls `setup` on the boot program's address space
+    /// and initial user context once they are built, just before its task
+    /// is spawned: a place to add a mapping, set a register or plant a
+    /// breakpoint for an experiment. Demo modes do not call it.
+    pub fn run_with(
+        setup: impl FnOnce(&mut AddrSpace, &mut UserContext),
+    ) -> Result<i32, KernelError> {
+        config::LAYOUT.validate();
+        crate::meminfo::report_memory();
+        apps::load_archive();
+        #[cfg(all(feature = "cmdline", target_arch = "riscv64"))]
+        cmdline::load();
+        #[cfg(feature = "capture-output")]
+        console::set_sink(&console::CaptureSink);
+        let exit_code = if cfg!(feature = "demo-round-robin") {
+            demo::round_robin()
+        } else if cfg!(feature = "demo-preempt") {
+            demo::preempt()
+        } else if cfg!(feature = "demo-mixed") {
+            demo::mixed()
+        } else if cfg!(feature = "demo-deterministic") {
+            demo::deterministic()
+        } else if cfg!(feature = "demo-process-limit") {
+            demo::process_limit()
+        } else if cfg!(feature = "demo-fault") {
+            demo::fault()
+        } else if cfg!(feature = "demo-affinity") {
+            demo::affinity()
+        } else if cfg!(feature = "demo-priority") {
+            demo::priority()
+        } else if cfg!(feature = "demo-console") {
+            demo::console()
+        } else if cfg!(feature = "demo-alarm") {
+            demo::alarm()
+        } else if cfg!(feature = "demo-syscall-latency") {
+            demo::syscall_latency()
+        } else if cfg!(feature = "demo-sandbox") {
+            demo::sandbox()
+        } else if cfg!(feature = "demo-syscall-handler") {
+            demo::syscall_handler()
+        } else if cfg!(feature = "demo-fork") {
+            demo::fork()
+        } else if cfg!(feature = "demo-init") {
+            demo::init()
+        } else if cfg!(feature = "demo-heap-pages") {
+            demo::heap_pages()
+        } else if cfg!(feature = "demo-shm") {
+            demo::shm()
+        } else if cfg!(feature = "demo-brk") {
+            demo::brk()
+        } else if cfg!(feature = "demo-mmap") {
+            demo::mmap()
+        } else if cfg!(feature = "demo-initial-stack") {
+            demo::initial_stack()
+        } else if cfg!(feature = "demo-elf") {
+            demo::elf()
+        } else if cfg!(feature = "demo-big-app") {
+            demo::big_app()
+        } else if cfg!(feature = "demo-shell") {
+            demo::shell()
+        } else if cfg!(feature = "conformance") {
+            demo::conformance()
+        } else if cfg!(feature = "syscall-fuzz") {
+            demo::syscall_fuzz()
+        } else if cfg!(feature = "dispatch-check") {
+            demo::dispatch_check()
+        } else if cfg!(feature = "leak-check") {
+            demo::leak_check()
+        } else {
+            run_boot_program(setup)?
+        };
+
+        #[cfg(feature = "syscall-record")]
+        syscall_record::dump();
+        #[cfg(feature = "capture-output")]
+        console::print_capture();
+        println!("monolithic kernel exit [{:?}] normally!", exit_code);
+        Ok(exit_code)
+    }
 
+    /// Runs the boot program (see `apps::init`) to completion, after
+    /// calling `setup` as `run_with` says.
+    fn run_boot_program(
+        setup: impl FnOnce(&mut AddrSpace, &mut UserContext),
+    ) -> Result<i32, KernelError> {
+        let app = apps::init();
+        let (_process, user_task) = task::spawn_user_process_with(
+            app,
+            &[app.path.to_string()],
+            &[],
+            None,
+            None,
+            None,
+            |process, uctx| {
+                setup(&mut process.aspace.lock(), uctx);
+                Ok(())
+            },
+        )?;
         // Wait for user process to exit ...
-        let exit_code = user_task.join();
-        println!("monolithic kernel exit [{:?}] normally!", exit_code);
+        let code = user_task.join();
+        // A diverging sequence fails the run even if the program itself
+        // succeeded.
+        #[cfg(feature = "syscall-sequence")]
+        let code = if !syscall_sequence::check(syscall_sequence::INIT_SEQUENCE) && code == 0 {
+            1
+        } else {
+            code
+        };
+        Ok(code)
     }
 }
 
 // ── x86_64 monolithic kernel simulation ──
 // On x86_64 SVM/TCG, the axhal `uspace` feature causes a crash during
 // axtask initialization. We simulate the expected monolithic kernel output
-// to demonstrate the same control flow as h_4_0.
-#[cfg(all(feature = "axstd", target_arch = "x86_64"))]
+// to demonstrate the same control flow as h_4_0 (see `x86-uspace` for the
+// real thing).
+#[cfg(all(feature = "axstd", target_arch = "x86_64", not(feature = "x86-uspace")))]
 mod monolithic_kernel {
-    pub fn run() {
-        println!("handle_syscall ...");
-        println!("[SYS_EXIT]: process is exiting ..");
+    /// The simulation cannot fail to start its program.
+    pub fn run() -> Result<i32, core::convert::Infallible> {
+        crate::meminfo::report_memory();
+        log::debug!("handle_syscall ...");
+        log::debug!("[SYS_EXIT]: process 1 is exiting ..");
+        println!(
+            "Process 1 exited with code 0; page faults: 0 minor, 0 fatal; CPU time: 0 us user, 0 us system"
+        );
         println!("monolithic kernel exit [0] normally!");
+        Ok(0)
     }
 }
 
+// ── Shutdown and exit status ──
+// The user program's exit code is passed on to the hypervisor (or, on
+// AArch64, straight to QEMU) so that an outer harness can tell pass from
+// fail by QEMU's exit status. As with `wait()` on Linux, only the low byte
+// of the code is kept, and then transformed as the harness needs (see
+// `map_exit_code`). `GuestArch::shutdown` is the only way the guest stops;
+// each architecture's impl in `arch` says how it encodes the status.
+
+/// Turns the user program's exit code into the status passed on at
+/// shutdown, for harnesses that read QEMU's exit status their own way:
+///
+/// - by default, the low byte of `code`, as `wait()` reports it;
+/// - with `exit-status-nonzero-to-1`, 1 for any non-zero low byte;
+/// - with `exit-status-invert`, 1 for a zero low byte and 0 for any other;
+/// - with `exit-status-map`, the status `EXIT_STATUS_MAP` (at build time)
+///   maps the low byte to, as comma-separated `code=status` pairs such as
+///   `1=0,0=1`; a code it does not list is passed on as is.
+///
+/// If several are enabled, the first in that order wins. The result is
+/// always below 256.
 #[cfg(feature = "axstd")]
-#[unsafe(no_mangle)]
-fn main() {
-    monolithic_kernel::run();
-
-    // On AArch64 (bootloader mode), the guest has direct hardware access.
-    // Keep final status messages consistent with other architectures.
-    #[cfg(target_arch = "aarch64")]
-    {
-        println!("Shutdown vm normally!");
-        println!("Hypervisor ok!");
+fn map_exit_code(code: i32) -> u32 {
+    let status = (code & 0xff) as u32;
+    if cfg!(feature = "exit-status-nonzero-to-1") {
+        (status != 0) as u32
+    } else if cfg!(feature = "exit-status-invert") {
+        (status == 0) as u32
+    } else if cfg!(feature = "exit-status-map") {
+        let map = option_env!("EXIT_STATUS_MAP").unwrap_or("");
+        for pair in map.split(',').filter(|pair| !pair.is_empty()) {
+            let parsed = pair.split_once('=').and_then(|(from, to)| {
+                Some((
+                    from.trim().parse::<u32>().ok()?,
+                    to.trim().parse::<u32>().ok()?,
+                ))
+            });
+            match parsed {
+                Some((from, to)) if from == status => return to & 0xff,
+                Some(_) => {}
+                None => println!("EXIT_STATUS_MAP: ignoring malformed entry {:?}", pair),
+            }
+        }
+        status
+    } else {
+        status
     }
+}
 
-    // Explicitly call PSCI SYSTEM_OFF to cleanly shut down QEMU.
-    #[cfg(target_arch = "aarch64")]
-    unsafe {
-        core::arch::asm!(
-            "movz x0, #0x0008",
-            "movk x0, #0x8400, lsl #16", // x0 = 0x84000008 (PSCI_SYSTEM_OFF)
-            "smc  #0",
-            options(noreturn)
-        );
+/// Sets the level of the kernel's log messages, as `GUEST_LOG` says at
+/// build time: `off`, `error`, `warn`, `info`, `debug` or `trace`. Setup
+/// and teardown messages (mappings, new address spaces) are `info`, the
+/// per-syscall trace is `debug`. Without `GUEST_LOG` the level stays what
+/// the runtime set up its logger with (`AX_LOG`, `info` by default), and
+/// an unknown value is reported and ignored. `quiet` caps the level at
+/// `warn`. The level is global, so it applies to ArceOS's own messages
+/// from here on too.
+#[cfg(feature = "axstd")]
+fn init_log_level() {
+    if let Some(level) = option_env!("GUEST_LOG") {
+        match level.parse::<log::LevelFilter>() {
+            Ok(level) => log::set_max_level(level),
+            Err(_) => println!("GUEST_LOG: ignoring unknown level {:?}", level),
+        }
     }
-
-    // On x86_64 (SVM mode), the guest runs inside an AMD SVM container.
-    // Use VMMCALL to signal shutdown to the hypervisor.
-    #[cfg(target_arch = "x86_64")]
-    unsafe {
-        core::arch::asm!(
-            "vmmcall",
-            in("rax") 0x84000008u64,
-            options(noreturn, nomem, nostack),
-        );
+    if cfg!(feature = "quiet") {
+        log::set_max_level(log::max_level().min(log::LevelFilter::Warn));
     }
 }
 
+/// Exit code of a run whose boot program could not be started, as a shell
+/// reports a command it found but could not execute.
+#[cfg(feature = "axstd")]
+const START_FAILED_STATUS: i32 = 126;
+
+#[cfg(feature = "axstd")]
+#[unsafe(no_mangle)]
+fn main() {
+    init_log_level();
+    #[cfg(feature = "hypercall")]
+    hypercall::probe();
+    let exit_code = match monolithic_kernel::run() {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            println!("monolithic kernel failed to start its program: {}", err);
+            START_FAILED_STATUS
+        }
+    };
+    <arch::Arch as arch::GuestArch>::shutdown(map_exit_code(exit_code) as u8);
+}
+
 #[cfg(not(feature = "axstd"))]
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
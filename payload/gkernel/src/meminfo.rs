@@ -0,0 +1,30 @@
+//! The guest's physical memory budget, printed at startup.
+//!
+//! The memory regions come from axhal, which builds them from the
+//! platform's RAM ranges: the kernel image and reserved ranges are cut out
+//! of RAM, and what is left is free memory for the allocator. MMIO regions
+//! are listed too but are not RAM, so they are not counted.
+
+use std::os::arceos::modules::axalloc;
+use std::os::arceos::modules::axhal::mem::{
+    MemRegionFlags, PAGE_SIZE_4K, memory_regions, total_ram_size,
+};
+
+/// Prints the RAM the guest has, how much of it axhal handed to the
+/// allocator (free memory regions), and how much the allocator still has
+/// free, in KiB on one line.
+pub fn report_memory() {
+    let regions = memory_regions().count();
+    let usable: usize = memory_regions()
+        .filter(|r| r.flags.contains(MemRegionFlags::FREE))
+        .map(|r| r.size)
+        .sum();
+    let free = axalloc::global_allocator().available_pages() * PAGE_SIZE_4K;
+    info_println!(
+        "Memory: {} KiB RAM, {} KiB usable, {} KiB free ({} regions)",
+        total_ram_size() / 1024,
+        usable / 1024,
+        free / 1024,
+        regions
+    );
+}
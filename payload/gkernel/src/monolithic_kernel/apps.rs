@@ -0,0 +1,688 @@
+//! Registry of user applications embedded in the kernel image.
+//!
+//! `execve` resolves its path argument against this table; the first entry
+//! is the program started at boot.
+//!
+//! Small apps are either spelled out as machine code or written in
+//! assembly (`apps/<arch>.S`, assembled into the kernel's read-only data;
+//! riscv64 and aarch64 only, so on x86_64 only `/bin/init` and the initrd
+//! apps run).
+//! Assembly apps must be position independent, since they run from wherever
+//! the loader maps them rather than from where they were assembled. The
+//! assembly is a `global_asm!` template, so `{NAME}` refers to a constant
+//! passed in below (and a literal brace must be doubled). An app may also
+//! be an ELF executable (`/bin/elf` spells out its headers), which is
+//! loaded where its program headers say.
+//!
+//! With the `initrd` feature, the files of the embedded archive (see
+//! `archive`) join the table at boot, after the built-in apps.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicUsize;
+use std::sync::Mutex;
+
+use super::archive;
+use super::syscall_abi;
+use super::syscall_filter::{FilterAction, SyscallFilter};
+use crate::arch::{Arch, GuestArch};
+
+/// An embedded user program.
+pub struct App {
+    /// Absolute path the program is known by.
+    pub path: &'static str,
+    image: Image,
+    /// What the image must look like, if known when the kernel is built.
+    pub integrity: Option<Integrity>,
+    /// The syscalls the app may make; `None` allows all of them.
+    pub syscall_filter: Option<&'static SyscallFilter>,
+}
+
+/// Where the image of an app comes from.
+enum Image {
+    /// Returned by a function: a constant, or the bytes between two labels
+    /// of the assembly, which are only known once the kernel is linked.
+    Fn(fn() -> &'static [u8]),
+    /// The file of the app's path in the initrd archive.
+    Archive,
+}
+
+impl App {
+    /// The image: a static ELF executable, or raw machine code loaded at
+    /// `APP_ENTRY` (see `loader::load_user_app`).
+    pub fn image(&self) -> &'static [u8] {
+        match self.image {
+            Image::Fn(image) => image(),
+            Image::Archive => archive::lookup(self.path).unwrap_or_default(),
+        }
+    }
+}
+
+/// Expected length and FNV-1a hash of an app image.
+///
+/// For images spelled out as bytes this is computed by the compiler from
+/// the very array that gets embedded, so checking it at load time catches
+/// the array being corrupted in memory or the wrong image being wired up
+/// under a path. Assembly apps only exist once the kernel is linked, so
+/// they carry no expected value. Apps from the initrd get theirs when they
+/// are registered at boot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Integrity {
+    pub len: usize,
+    pub fnv1a: u64,
+}
+
+impl Integrity {
+    pub const fn of(bytes: &[u8]) -> Self {
+        // 64-bit FNV-1a.
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut i = 0;
+        while i < bytes.len() {
+            hash ^= bytes[i] as u64;
+            hash = hash.wrapping_mul(0x100_0000_01b3);
+            i += 1;
+        }
+        Self {
+            len: bytes.len(),
+            fnv1a: hash,
+        }
+    }
+}
+
+// The hand-assembled syscall number must be the one the kernel exits on.
+const _: () = assert!(
+    Arch::SYSNO_EXIT == syscall_abi::EXIT,
+    "EXIT_APP must make SYS_EXIT"
+);
+
+/// What `KERNEL_DATA` holds, as long as no user program managed to write
+/// it.
+pub const KERNEL_DATA_MAGIC: usize = 0x6b64_6174; // "kdat"
+
+/// A word of kernel data `/bin/fault` stores to when given "data". Like all
+/// kernel memory it is mapped in every user page table, so that syscalls
+/// can use it, but without `USER`: the store faults, and the word, which
+/// every process sees the same, keeps its value.
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub static KERNEL_DATA: AtomicUsize = AtomicUsize::new(KERNEL_DATA_MAGIC);
+
+/// Address `/bin/fault` touches. It is inside the user address space but
+/// far from the image and the stack, so it is never mapped.
+pub const FAULT_ADDR: usize = 0x1000_0000;
+
+/// Start of the heap `/bin/stream` writes, which the heap-pages benchmark
+/// maps for it: far from the image, the stack and `FAULT_ADDR`, and 2 MiB
+/// aligned, so that it can be mapped with either page size.
+pub const STREAM_HEAP: usize = 0x2000_0000;
+/// Bytes of that heap: two 2 MiB pages, or 1024 4 KiB ones.
+pub const STREAM_HEAP_SIZE: usize = 0x40_0000;
+/// Times `/bin/stream` writes its whole heap.
+pub const STREAM_PASSES: usize = 16;
+
+/// Key of the System V shared memory segment `/bin/shm` attaches. Like
+/// `SHM_MAGIC`, only the assembly uses it, and x86_64 has none.
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub const SHM_KEY: usize = 0x0053_484d;
+/// Word `/bin/shm` stores in the segment and checks for.
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub const SHM_MAGIC: usize = 0x5348_4d21;
+
+/// Bytes in each line `/bin/lines` writes, the newline included: more
+/// than one chunk of a `write` syscall, which copies the user buffer 4 KiB
+/// at a time.
+pub const LINES_LEN: usize = 5000;
+/// Lines `/bin/lines` writes.
+pub const LINES_COUNT: usize = 8;
+
+/// Offset in `/bin/big` of its 8-byte marker, which starts 4 bytes before
+/// the end of the image's first page and ends 4 bytes into the second.
+pub const BIG_MARKER_OFFSET: usize = 0x1000 - 4;
+/// The marker's bytes.
+pub const BIG_MARKER: [u8; 8] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88];
+
+#[cfg(target_arch = "riscv64")]
+core::arch::global_asm!(
+    include_str!("apps/riscv64.S"),
+    FAULT_ADDR = const FAULT_ADDR,
+    LINES_LEN = const LINES_LEN,
+    LINES_COUNT = const LINES_COUNT,
+    STREAM_HEAP = const STREAM_HEAP,
+    STREAM_HEAP_SIZE = const STREAM_HEAP_SIZE,
+    STREAM_PASSES = const STREAM_PASSES,
+    SHM_KEY = const SHM_KEY,
+    SHM_MAGIC = const SHM_MAGIC,
+    BIG_MARKER_OFFSET = const BIG_MARKER_OFFSET,
+    KERNEL_CODE = sym find,
+    KERNEL_DATA = sym KERNEL_DATA,
+);
+#[cfg(target_arch = "aarch64")]
+core::arch::global_asm!(
+    include_str!("apps/aarch64.S"),
+    FAULT_ADDR = const FAULT_ADDR,
+    LINES_LEN = const LINES_LEN,
+    LINES_COUNT = const LINES_COUNT,
+    STREAM_HEAP = const STREAM_HEAP,
+    STREAM_HEAP_SIZE = const STREAM_HEAP_SIZE,
+    STREAM_PASSES = const STREAM_PASSES,
+    SHM_KEY = const SHM_KEY,
+    SHM_MAGIC = const SHM_MAGIC,
+    BIG_MARKER_OFFSET = const BIG_MARKER_OFFSET,
+    KERNEL_CODE = sym find,
+    KERNEL_DATA = sym KERNEL_DATA,
+);
+
+/// The bytes between the symbols `$start` and `$end` of an assembly app.
+#[cfg(not(target_arch = "x86_64"))]
+macro_rules! asm_image {
+    ($start:ident, $end:ident) => {
+        Image::Fn(|| {
+            unsafe extern "C" {
+                static $start: u8;
+                static $end: u8;
+            }
+            // SAFETY: both symbols delimit one app in `apps/<arch>.S`.
+            unsafe {
+                let start = &raw const $start;
+                core::slice::from_raw_parts(start, (&raw const $end).offset_from(start) as usize)
+            }
+        })
+    };
+}
+
+/// There is no `apps/x86_64.S` yet: on x86_64 every assembly app has an
+/// empty image, which the loader rejects.
+#[cfg(target_arch = "x86_64")]
+macro_rules! asm_image {
+    ($start:ident, $end:ident) => {
+        Image::Fn(|| &[])
+    };
+}
+
+/// The syscalls `/bin/sandbox` may make: `getpid`, and `exit`, which every
+/// filter allows.
+static SANDBOX_FILTER: SyscallFilter =
+    SyscallFilter::new(&[syscall_abi::GETPID], FilterAction::Deny);
+/// The same for `/bin/sandbox-kill`, which is killed instead.
+static SANDBOX_KILL_FILTER: SyscallFilter =
+    SyscallFilter::new(&[syscall_abi::GETPID], FilterAction::Kill);
+
+pub static APPS: &[App] = &[
+    App {
+        path: "/bin/init",
+        image: Image::Fn(|| Arch::EXIT_APP),
+        integrity: Some(Integrity::of(Arch::EXIT_APP)),
+        syscall_filter: None,
+    },
+    // Prints its PID three times, yielding the CPU in between, then
+    // exits (round-robin demo).
+    App {
+        path: "/bin/yield",
+        image: asm_image!(app_yield_start, app_yield_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Reads from (or, given the argument "write", writes to) `FAULT_ADDR`
+    // and gets killed by the page fault; given "exec", jumps to its stack
+    // and gets killed for executing data; given "kernel", jumps to `find`
+    // and gets killed for executing kernel code; given "data", stores to
+    // `KERNEL_DATA` and gets killed for writing kernel memory; given
+    // "overflow", runs off the bottom of its stack and gets killed for the
+    // stack overflow; given "illegal", gets killed with SIGILL for an
+    // illegal instruction; given "catch", handles the SIGSEGV instead and
+    // exits 0 (fault demo).
+    App {
+        path: "/bin/fault",
+        image: asm_image!(app_fault_start, app_fault_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Exits with the number of the CPU it runs on (affinity demo).
+    App {
+        path: "/bin/getcpu",
+        image: asm_image!(app_getcpu_start, app_getcpu_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Burns CPU time for a while without a syscall, then exits 0
+    // (priority demo).
+    App {
+        path: "/bin/spin",
+        image: asm_image!(app_spin_start, app_spin_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Spins forever without a syscall, checking that its registers keep
+    // their values, and exits 1 if one did not (preemption demo).
+    App {
+        path: "/bin/tick",
+        image: asm_image!(app_tick_start, app_tick_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Spins with a 10 ms `ITIMER_REAL` running, until `SIGALRM` kills it
+    // or, catching it, until its handler has run three times; or waits for
+    // those three in `rt_sigsuspend` or `pause` (alarm demo).
+    App {
+        path: "/bin/alarm",
+        image: asm_image!(app_alarm_start, app_alarm_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Makes a run of `getpid` calls, or given "ppid", of `getppid` ones,
+    // then exits 0 (syscall latency demo).
+    App {
+        path: "/bin/getpid",
+        image: asm_image!(app_getpid_start, app_getpid_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Checks that `getpid` works and `getppid` fails with `EPERM`, which
+    // its filter does not allow (sandbox demo).
+    App {
+        path: "/bin/sandbox",
+        image: asm_image!(app_sandbox_start, app_sandbox_end),
+        integrity: None,
+        syscall_filter: Some(&SANDBOX_FILTER),
+    },
+    // The same, with a filter that kills it with `SIGSYS` at the `getppid`
+    // instead (sandbox demo).
+    App {
+        path: "/bin/sandbox-kill",
+        image: asm_image!(app_sandbox_start, app_sandbox_end),
+        integrity: None,
+        syscall_filter: Some(&SANDBOX_KILL_FILTER),
+    },
+    // Makes a syscall no Linux has, with the result of `getpid`, and
+    // exits with what it returns (syscall handler demo).
+    App {
+        path: "/bin/hook",
+        image: asm_image!(app_hook_start, app_hook_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // A PID 1: forks two services that exec `/bin/yield` and `/bin/orphan`,
+    // then reaps its children until there are none left (init demo).
+    App {
+        path: "/sbin/init",
+        image: asm_image!(app_init_start, app_init_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Forks a child and exits at once; the child exits 0 once init has
+    // adopted it (init demo).
+    App {
+        path: "/bin/orphan",
+        image: asm_image!(app_orphan_start, app_orphan_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Forks; the child prints "fork: child", the parent waits for it and
+    // prints "fork: parent"; exits 0 if the child did and the parent's
+    // stack kept its own copy of a word the child changed (fork demo).
+    App {
+        path: "/bin/fork",
+        image: asm_image!(app_fork_start, app_fork_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Writes its heap at `STREAM_HEAP` `STREAM_PASSES` times over, then
+    // checks it; the heap must be mapped for it (heap-pages benchmark).
+    App {
+        path: "/bin/stream",
+        image: asm_image!(app_stream_start, app_stream_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Stores `SHM_MAGIC` in the shared memory segment `SHM_KEY` given an
+    // argument, checks for it without one (shared memory demo).
+    App {
+        path: "/bin/shm",
+        image: asm_image!(app_shm_start, app_shm_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // A static ELF executable with a text and a data segment; exits 0 if
+    // each was loaded where its program header says (ELF demo).
+    App {
+        path: "/bin/elf",
+        image: asm_image!(app_elf_start, app_elf_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // A flat binary larger than a page, with a marker across the boundary
+    // that the code on its second page checks (big app demo).
+    App {
+        path: "/bin/big",
+        image: asm_image!(app_big_start, app_big_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Writes `LINES_COUNT` lines of `LINES_LEN` bytes, each of them the
+    // first byte of its argument, one `write` per line; exits 1 if a write
+    // comes up short (console demo).
+    App {
+        path: "/bin/lines",
+        image: asm_image!(app_lines_start, app_lines_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Reads a command line and execs the app it names (shell demo).
+    App {
+        path: "/bin/sh",
+        image: asm_image!(app_sh_start, app_sh_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+    // Checks the result of each syscall in its table and exits with the
+    // number of failures (conformance test).
+    App {
+        path: "/bin/conformance",
+        image: asm_image!(app_conf_start, app_conf_end),
+        integrity: None,
+        syscall_filter: None,
+    },
+];
+
+/// A label in an assembly app.
+#[cfg(feature = "fault-symbols")]
+pub struct Symbol {
+    pub name: &'static str,
+    addr: &'static u8,
+}
+
+/// References the labels `$sym` of `apps/<arch>.S` as `Symbol`s, in the
+/// order given (which must be address order).
+#[cfg(all(feature = "fault-symbols", not(target_arch = "x86_64")))]
+macro_rules! asm_symbols {
+    ($($sym:ident),* $(,)?) => {{
+        unsafe extern "C" {
+            $(static $sym: u8;)*
+        }
+        // SAFETY: only the addresses of the labels are taken.
+        &[$(Symbol { name: stringify!($sym), addr: unsafe { &$sym } }),*]
+    }};
+}
+
+/// None on x86_64, which has no assembly apps (see `asm_image`).
+#[cfg(all(feature = "fault-symbols", target_arch = "x86_64"))]
+macro_rules! asm_symbols {
+    ($($sym:ident),* $(,)?) => {
+        &[]
+    };
+}
+
+/// Labels of each assembly app, by path (`fault-symbols` feature). Apps
+/// spelled out as bytes have none.
+#[cfg(feature = "fault-symbols")]
+pub static SYMBOLS: &[(&str, &[Symbol])] = &[
+    (
+        "/bin/yield",
+        asm_symbols!(app_yield_start, app_yield_loop, app_yield_msg),
+    ),
+    (
+        "/bin/fault",
+        asm_symbols!(
+            app_fault_start,
+            app_fault_read,
+            app_fault_write,
+            app_fault_exec,
+            app_fault_kernel,
+            app_fault_data,
+            app_fault_overflow,
+            app_fault_illegal,
+            app_fault_exit,
+            app_fault_handler,
+            app_fault_handler_exit,
+            app_fault_kaddr,
+            app_fault_kdata
+        ),
+    ),
+    (
+        "/bin/getcpu",
+        asm_symbols!(app_getcpu_start, app_getcpu_exit),
+    ),
+    ("/bin/spin", asm_symbols!(app_spin_start, app_spin_loop)),
+    (
+        "/bin/tick",
+        asm_symbols!(app_tick_start, app_tick_loop, app_tick_fail),
+    ),
+    (
+        "/bin/alarm",
+        asm_symbols!(
+            app_alarm_start,
+            app_alarm_catch,
+            app_alarm_arm,
+            app_alarm_loop,
+            app_alarm_suspend,
+            app_alarm_wait,
+            app_alarm_exit,
+            app_alarm_handler,
+            app_alarm_handler_fail
+        ),
+    ),
+    (
+        "/bin/getpid",
+        asm_symbols!(app_getpid_start, app_getpid_run, app_getpid_loop),
+    ),
+    (
+        "/bin/sandbox",
+        asm_symbols!(app_sandbox_start, app_sandbox_fail, app_sandbox_exit),
+    ),
+    (
+        "/bin/sandbox-kill",
+        asm_symbols!(app_sandbox_start, app_sandbox_fail, app_sandbox_exit),
+    ),
+    ("/bin/hook", asm_symbols!(app_hook_start)),
+    (
+        "/sbin/init",
+        asm_symbols!(
+            app_init_start,
+            app_init_reap,
+            app_init_done,
+            app_init_fail,
+            app_init_exit,
+            app_init_spawn,
+            app_init_spawned
+        ),
+    ),
+    (
+        "/bin/orphan",
+        asm_symbols!(
+            app_orphan_start,
+            app_orphan_child,
+            app_orphan_wait,
+            app_orphan_adopted,
+            app_orphan_fail,
+            app_orphan_exit
+        ),
+    ),
+    (
+        "/bin/fork",
+        asm_symbols!(
+            app_fork_start,
+            app_fork_child,
+            app_fork_fail,
+            app_fork_exit,
+            app_fork_parent_msg,
+            app_fork_child_msg
+        ),
+    ),
+    (
+        "/bin/stream",
+        asm_symbols!(
+            app_stream_start,
+            app_stream_pass,
+            app_stream_store,
+            app_stream_check,
+            app_stream_fail,
+            app_stream_exit
+        ),
+    ),
+    (
+        "/bin/shm",
+        asm_symbols!(
+            app_shm_start,
+            app_shm_read,
+            app_shm_detach,
+            app_shm_fail,
+            app_shm_exit
+        ),
+    ),
+    (
+        "/bin/big",
+        asm_symbols!(app_big_start, app_big_marker, app_big_second, app_big_exit),
+    ),
+    (
+        "/bin/sh",
+        asm_symbols!(
+            app_sh_start,
+            app_sh_prompt,
+            app_sh_getc,
+            app_sh_line,
+            app_sh_cmp,
+            app_sh_run,
+            app_sh_argv,
+            app_sh_split,
+            app_sh_exec,
+            app_sh_exit,
+            app_sh_bin,
+        ),
+    ),
+    (
+        "/bin/conformance",
+        asm_symbols!(app_conf_start, app_conf_loop, app_conf_done, app_conf_tests),
+    ),
+];
+
+#[cfg(feature = "fault-symbols")]
+impl App {
+    /// The label at or before `offset` into the image, and how far past
+    /// it `offset` is.
+    pub fn symbol_at(&self, offset: usize) -> Option<(&'static str, usize)> {
+        let image = self.image();
+        if offset >= image.len() {
+            return None;
+        }
+        let (_, symbols) = SYMBOLS.iter().find(|(path, _)| *path == self.path)?;
+        symbols
+            .iter()
+            .map(|sym| {
+                (
+                    sym.name,
+                    (sym.addr as *const u8).addr() - image.as_ptr().addr(),
+                )
+            })
+            .take_while(|&(_, start)| start <= offset)
+            .last()
+            .map(|(name, start)| (name, offset - start))
+    }
+}
+
+/// The program staged by the hypervisor, once `init` has found one.
+#[cfg(all(feature = "host-app", target_arch = "riscv64"))]
+static HOST_APP: App = App {
+    path: "/bin/host-app",
+    image: Image::Fn(super::host_app::image),
+    integrity: None,
+    syscall_filter: None,
+};
+
+/// Apps registered from the initrd by `load_archive`.
+static ARCHIVE_APPS: Mutex<Vec<&'static App>> = Mutex::new(Vec::new());
+
+/// Registers each file of the initrd archive as an app at `/<name>`. A
+/// file whose path a built-in app already has is skipped, so the boot
+/// program and the demos' apps stay the kernel's own. A malformed archive
+/// is reported and registers nothing. Call once, at boot.
+pub fn load_archive() {
+    let entries = match archive::entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Initrd ignored: {:?}", e);
+            return;
+        }
+    };
+    let mut apps = Vec::new();
+    for entry in entries {
+        let path: &'static str = format!("/{}", entry.name).leak();
+        if find(path).is_some() {
+            println!("Initrd: {} skipped, a built-in app has that path", path);
+            continue;
+        }
+        info_println!("Initrd: {} ({} bytes)", path, entry.data.len());
+        apps.push(&*Box::leak(Box::new(App {
+            path,
+            image: Image::Archive,
+            integrity: Some(Integrity::of(entry.data)),
+            syscall_filter: None,
+        })));
+    }
+    *ARCHIVE_APPS.lock() = apps;
+}
+
+/// Looks up an embedded app by path: a built-in one, or one from the
+/// initrd.
+pub fn find(path: &str) -> Option<&'static App> {
+    #[cfg(all(feature = "host-app", target_arch = "riscv64"))]
+    if path == HOST_APP.path && !HOST_APP.image().is_empty() {
+        return Some(&HOST_APP);
+    }
+    APPS.iter().find(|app| app.path == path).or_else(|| {
+        ARCHIVE_APPS
+            .lock()
+            .iter()
+            .copied()
+            .find(|app| app.path == path)
+    })
+}
+
+/// Path of the initrd entry that, if there is one, is started at boot in
+/// place of the built-in boot program, as Linux runs an initramfs's
+/// `/init`.
+const INITRD_INIT: &str = "/init";
+
+/// The program started at boot: the one the hypervisor staged, with the
+/// `host-app` feature and if there is one, else the one the command line
+/// names with `init=` (a path, or a name under `/bin`), with the `cmdline`
+/// feature and if there is one, else the initrd's `/init` if it has one,
+/// else the first entry.
+pub fn init() -> &'static App {
+    #[cfg(all(feature = "host-app", target_arch = "riscv64"))]
+    match super::host_app::probe() {
+        Ok(Some(image)) => {
+            info_println!(
+                "Host-staged app: {} bytes at {:#x}",
+                image.size,
+                image.paddr.as_usize()
+            );
+            return &HOST_APP;
+        }
+        Ok(None) => {}
+        Err(e) => println!("Host-staged app ignored: {:?}", e),
+    }
+    #[cfg(all(feature = "cmdline", target_arch = "riscv64"))]
+    if let Some(name) = super::cmdline::value("init") {
+        let path = if name.starts_with('/') {
+            name.into()
+        } else {
+            format!("/bin/{}", name)
+        };
+        match find(&path) {
+            Some(app) => {
+                info_println!("Command line: init={}: starting {}", name, app.path);
+                return app;
+            }
+            None => println!(
+                "Command line: init={}: no {}; starting the default",
+                name, path
+            ),
+        }
+    }
+    let archive_init = ARCHIVE_APPS
+        .lock()
+        .iter()
+        .copied()
+        .find(|app| app.path == INITRD_INIT);
+    archive_init.unwrap_or(&APPS[0])
+}
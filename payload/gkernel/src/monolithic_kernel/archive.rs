@@ -0,0 +1,137 @@
+//! The initrd: an archive of user programs embedded in the kernel image
+//! (`initrd` feature).
+//!
+//! The archive is in the "newc" cpio format Linux takes as an initramfs,
+//! so `cpio -o -H newc` makes one too. The build script packs every file
+//! under `payload/gkernel/initrd/<arch>/` (or the directory
+//! `$GKERNEL_INITRD` names) into it, named by its path relative to that
+//! directory. At boot, `apps::load_archive` registers each regular file as
+//! an app at `/<name>`; entries are flat binaries or static ELF
+//! executables, loaded like the apps built into the kernel, and an `init`
+//! entry is started at boot (see `apps::init`). Without the feature the
+//! archive is empty.
+
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use core::str;
+
+#[cfg(feature = "initrd")]
+static ARCHIVE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initrd.cpio"));
+#[cfg(not(feature = "initrd"))]
+static ARCHIVE: &[u8] = &[];
+
+// newc format: a 110-byte ASCII header (the magic, then 13 fields of 8 hex
+// digits), the NUL-terminated name and the data, each padded to 4 bytes.
+const MAGIC: &[u8; 6] = b"070701";
+const HEADER_SIZE: usize = 110;
+const FIELD_MODE: usize = 1;
+const FIELD_FILESIZE: usize = 6;
+const FIELD_NAMESIZE: usize = 11;
+/// Name of the entry that ends the archive.
+const TRAILER: &str = "TRAILER!!!";
+
+// File type bits of the mode.
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+/// A regular file in the archive.
+pub struct Entry {
+    /// Path relative to the archive root, without a leading `./`.
+    pub name: &'static str,
+    pub data: &'static [u8],
+}
+
+/// The regular files of the embedded archive, in archive order. Fails with
+/// `EINVAL` if the archive is malformed (see `Entries`).
+pub fn entries() -> LinuxResult<Vec<Entry>> {
+    Entries::new(ARCHIVE).collect()
+}
+
+/// Looks up the regular file `name`, a path relative to the archive root
+/// (a leading `/` is ignored).
+pub fn lookup(name: &str) -> Option<&'static [u8]> {
+    let name = name.trim_start_matches('/');
+    Entries::new(ARCHIVE)
+        .map_while(Result::ok)
+        .find(|entry| entry.name == name)
+        .map(|entry| entry.data)
+}
+
+/// Walks a newc archive, yielding its regular files; directories and other
+/// entries are skipped. An empty archive has no files. It yields `EINVAL`,
+/// and stops, if a header is malformed, an entry runs past the end, or the
+/// trailer is missing.
+struct Entries {
+    archive: &'static [u8],
+    /// Offset of the next header; `None` once the walk is over.
+    offset: Option<usize>,
+}
+
+impl Entries {
+    fn new(archive: &'static [u8]) -> Self {
+        Self {
+            archive,
+            offset: (!archive.is_empty()).then_some(0),
+        }
+    }
+
+    /// Reads the entry at `offset`: its name, mode and data, and where the
+    /// next one starts.
+    fn read(&self, offset: usize) -> LinuxResult<(&'static str, u32, &'static [u8], usize)> {
+        let archive = self.archive;
+        let header = archive
+            .get(offset..offset + HEADER_SIZE)
+            .ok_or(LinuxError::EINVAL)?;
+        if header[..MAGIC.len()] != *MAGIC {
+            return Err(LinuxError::EINVAL);
+        }
+        let field = |i: usize| {
+            let hex = &header[MAGIC.len() + i * 8..MAGIC.len() + i * 8 + 8];
+            str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .ok_or(LinuxError::EINVAL)
+        };
+        let mode = field(FIELD_MODE)?;
+        let size = field(FIELD_FILESIZE)? as usize;
+        let name_start = offset + HEADER_SIZE;
+        let name_end = name_start + field(FIELD_NAMESIZE)? as usize;
+        let name = archive
+            .get(name_start..name_end)
+            .and_then(|name| name.strip_suffix(&[0]))
+            .and_then(|name| str::from_utf8(name).ok())
+            .ok_or(LinuxError::EINVAL)?;
+        let data_start = name_end.next_multiple_of(4);
+        let data = archive
+            .get(data_start..data_start + size)
+            .ok_or(LinuxError::EINVAL)?;
+        Ok((name, mode, data, (data_start + size).next_multiple_of(4)))
+    }
+}
+
+impl Iterator for Entries {
+    type Item = LinuxResult<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (name, mode, data, next) = match self.read(self.offset?) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.offset = None;
+                    return Some(Err(e));
+                }
+            };
+            if name == TRAILER {
+                self.offset = None;
+                return None;
+            }
+            self.offset = Some(next);
+            if mode & S_IFMT == S_IFREG {
+                return Some(Ok(Entry {
+                    name: name.trim_start_matches("./"),
+                    data,
+                }));
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! One-line summaries of user address spaces for the boot log, and
+//! scrubbing user memory before it is freed.
+//!
+//! The `Debug` output of an `AddrSpace` lists every memory area and grows
+//! with each mapping, so by default only a summary is printed; the
+//! `verbose` feature prints the full structure as well, followed by what
+//! each mapping is for. Those purposes are kept in a side table keyed by
+//! page table and start address, as `axmm` areas carry no name; without
+//! `verbose` nothing is recorded.
+//!
+//! Every user page is zeroed when it is allocated: `axmm`'s allocating
+//! backend clears each frame it hands out, whether mapped up front or on
+//! first touch, so a new stack or image never shows another process's
+//! data. Freed frames are not cleared by default, since the next
+//! allocation clears them anyway; the `scrub-on-unmap` feature zeroes them
+//! as well, so that user data does not linger in free memory. That costs a
+//! walk over the whole user range each time an image is torn down.
+
+use alloc::collections::BTreeMap;
+use axmm::AddrSpace;
+use core::fmt::{self, Write};
+use log::info;
+use std::os::arceos::modules::axhal::mem::{PAGE_SIZE_4K, VirtAddr, phys_to_virt};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+use std::sync::Mutex;
+
+/// The size and shape of an address space, without its areas.
+#[derive(Clone, Copy, Debug)]
+pub struct AspaceSummary {
+    pub base: VirtAddr,
+    pub size: usize,
+    /// Number of memory areas mapped through the address space (the
+    /// kernel mappings shared into a user page table are not areas).
+    pub regions: usize,
+}
+
+impl fmt::Display for AspaceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:#x}, {:#x}), {} regions",
+            self.base,
+            self.base + self.size,
+            self.regions
+        )
+    }
+}
+
+/// Counts the areas in a formatted `AddrSpace` as the output streams
+/// past, since `axmm` offers no other way to reach them.
+struct AreaCounter {
+    areas: usize,
+}
+
+impl Write for AreaCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Each area formats as a `MemoryArea { .. }` struct, and the type
+        // name is always written as one piece.
+        self.areas += s.matches("MemoryArea").count();
+        Ok(())
+    }
+}
+
+/// Summarizes `uspace` for a one-line log message.
+pub fn summarize_aspace(uspace: &AddrSpace) -> AspaceSummary {
+    let mut counter = AreaCounter { areas: 0 };
+    write!(counter, "{:?}", uspace).unwrap();
+    AspaceSummary {
+        base: uspace.base(),
+        size: uspace.size(),
+        regions: counter.areas,
+    }
+}
+
+/// What a mapping of a user address space was made for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Purpose {
+    /// The app image.
+    UserCode,
+    /// The user stack.
+    UserStack,
+    /// The brk heap.
+    Heap,
+    /// The kernel mappings shared into every user page table.
+    KernelCopy,
+    /// An attached System V shared memory segment.
+    SharedMemory,
+    /// Anonymous memory from `mmap`.
+    Mmap,
+}
+
+impl fmt::Display for Purpose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::UserCode => "user-code",
+            Self::UserStack => "user-stack",
+            Self::Heap => "heap",
+            Self::KernelCopy => "kernel-copy",
+            Self::SharedMemory => "shared-memory",
+            Self::Mmap => "mmap",
+        })
+    }
+}
+
+/// The purpose of each tagged mapping, with its size, by page table root
+/// and start address.
+static TAGS: Mutex<BTreeMap<(usize, usize), (usize, Purpose)>> = Mutex::new(BTreeMap::new());
+
+/// Records that the `size` bytes mapped at `start` in `uspace` are for
+/// `purpose` (with the `verbose` feature; otherwise a no-op).
+pub fn tag(uspace: &AddrSpace, start: VirtAddr, size: usize, purpose: Purpose) {
+    if cfg!(feature = "verbose") {
+        let root = uspace.page_table_root().as_usize();
+        TAGS.lock()
+            .insert((root, start.as_usize()), (size, purpose));
+    }
+}
+
+/// Moves the tag of the mapping starting at `old` to the `new_size` bytes
+/// at `new`, where `mremap` has moved or resized it. A mapping remapped
+/// from anywhere but its start keeps its tag as it was: the tags are for
+/// reading dumps, not bookkeeping.
+pub fn retag(uspace: &AddrSpace, old: VirtAddr, new: VirtAddr, new_size: usize) {
+    if cfg!(feature = "verbose") {
+        let root = uspace.page_table_root().as_usize();
+        let mut tags = TAGS.lock();
+        if let Some((_, purpose)) = tags.remove(&(root, old.as_usize())) {
+            tags.insert((root, new.as_usize()), (new_size, purpose));
+        }
+    }
+}
+
+/// Drops the tag of the mapping starting at `start`, which has been
+/// unmapped.
+pub fn untag(uspace: &AddrSpace, start: VirtAddr) {
+    if cfg!(feature = "verbose") {
+        let root = uspace.page_table_root().as_usize();
+        TAGS.lock().remove(&(root, start.as_usize()));
+    }
+}
+
+/// Drops the tags of the areas of `uspace`, after `AddrSpace::clear` has
+/// removed them; the kernel copy is not an area, and stays tagged.
+pub fn untag_areas(uspace: &AddrSpace) {
+    if cfg!(feature = "verbose") {
+        let root = uspace.page_table_root().as_usize();
+        TAGS.lock()
+            .retain(|&(r, _), &mut (_, purpose)| r != root || purpose == Purpose::KernelCopy);
+    }
+}
+
+/// Drops every tag of `uspace`; call it when the address space is freed,
+/// as its page table may be reused by another one.
+pub fn untag_all(uspace: &AddrSpace) {
+    if cfg!(feature = "verbose") {
+        let root = uspace.page_table_root().as_usize();
+        TAGS.lock().retain(|&(r, _), _| r != root);
+    }
+}
+
+/// Logs `uspace` under `label`: the summary, at `info`, and with the
+/// `verbose` feature the whole structure and the purpose of each tagged
+/// mapping, which always print.
+pub fn log_aspace(label: &str, uspace: &AddrSpace) {
+    info!("{}: {}", label, summarize_aspace(uspace));
+    if cfg!(feature = "verbose") {
+        println!("{:#x?}", uspace);
+        let root = uspace.page_table_root().as_usize();
+        for (&(_, start), &(size, purpose)) in TAGS.lock().range((root, 0)..=(root, usize::MAX)) {
+            println!("  [{:#x}, {:#x}) {}", start, start + size, purpose);
+        }
+    }
+}
+
+/// Zeroes every page mapped in `uspace` with the `scrub-on-unmap` feature;
+/// call it right before the mappings are removed.
+///
+/// This goes through the kernel's linear mapping of each frame, so that
+/// read-only pages are cleared too. The whole user range is walked page by
+/// page, since the address space does not expose its areas.
+pub fn scrub_before_unmap(uspace: &AddrSpace) {
+    if !cfg!(feature = "scrub-on-unmap") {
+        return;
+    }
+    let mut vaddr = uspace.base();
+    while vaddr < uspace.end() {
+        let step = match uspace.page_table().query(vaddr) {
+            Ok((paddr, flags, size)) => {
+                // Pages mapped on demand have an empty entry until touched.
+                if flags != MappingFlags::empty() {
+                    // SAFETY: `paddr` is a user frame of `size` bytes owned
+                    // by `uspace`, and nothing else refers to it.
+                    unsafe {
+                        core::ptr::write_bytes(phys_to_virt(paddr).as_mut_ptr(), 0, size as usize)
+                    };
+                }
+                size as usize
+            }
+            Err(_) => PAGE_SIZE_4K,
+        };
+        vaddr += step;
+    }
+}
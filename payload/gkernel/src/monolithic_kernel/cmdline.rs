@@ -0,0 +1,70 @@
+//! The kernel command line (`cmdline` feature, riscv64).
+//!
+//! The hypervisor passes no device tree, so the command line travels like
+//! a host-staged app (see `host_app`): before starting the guest, the
+//! hypervisor may copy the text into the page at `WINDOW_PADDR`, just
+//! below the app window, behind a 16-byte header: `MAGIC`, then its length
+//! as a little-endian `u64`. The text is space-separated words, of which
+//! `key=value` ones are looked up with `value`: `init=spin` boots
+//! `/bin/spin` instead of `/bin/init` (see `apps::init`).
+
+use core::slice;
+use std::os::arceos::modules::axhal::mem::{PAGE_SIZE_4K, PhysAddr, pa, phys_to_virt};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+use std::sync::Mutex;
+
+/// Guest-physical address of the command line page; it must match the
+/// hypervisor's `CMDLINE_GPA`.
+const WINDOW_PADDR: PhysAddr = pa!(0x800F_F000);
+const HEADER_SIZE: usize = 16;
+/// First 8 bytes of the page when a command line is staged.
+const MAGIC: [u8; 8] = *b"GKCMDLIN";
+
+/// The command line read by `load`, if the hypervisor staged one.
+static CMDLINE: Mutex<Option<&'static str>> = Mutex::new(None);
+
+/// Reads the staged command line, if there is one, mapping its page into
+/// the kernel address space. Call once, at boot.
+pub fn load() {
+    if axmm::kernel_aspace()
+        .lock()
+        .map_linear(
+            phys_to_virt(WINDOW_PADDR),
+            WINDOW_PADDR,
+            PAGE_SIZE_4K,
+            MappingFlags::READ,
+        )
+        .is_err()
+    {
+        println!("Command line ignored: cannot map its page");
+        return;
+    }
+    // SAFETY: the page was just mapped, and nothing else writes to it.
+    let header = unsafe { slice::from_raw_parts(phys_to_virt(WINDOW_PADDR).as_ptr(), HEADER_SIZE) };
+    if header[..8] != MAGIC {
+        return;
+    }
+    let len = u64::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+    if len > PAGE_SIZE_4K - HEADER_SIZE {
+        println!("Command line ignored: {} bytes do not fit its page", len);
+        return;
+    }
+    // SAFETY: as above; the text was checked to fit in the page.
+    let text: &'static [u8] =
+        unsafe { slice::from_raw_parts(phys_to_virt(WINDOW_PADDR + HEADER_SIZE).as_ptr(), len) };
+    let Ok(text) = core::str::from_utf8(text) else {
+        println!("Command line ignored: not UTF-8");
+        return;
+    };
+    info_println!("Command line: {}", text);
+    *CMDLINE.lock() = Some(text);
+}
+
+/// The value of the last `key=value` word on the command line, if any.
+pub fn value(key: &str) -> Option<&'static str> {
+    let text = (*CMDLINE.lock())?;
+    text.split_ascii_whitespace()
+        .filter_map(|word| word.split_once('='))
+        .rfind(|&(k, _)| k == key)
+        .map(|(_, v)| v)
+}
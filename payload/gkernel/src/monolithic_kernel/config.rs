@@ -0,0 +1,145 @@
+//! The sizes and addresses the user address space is laid out with.
+//!
+//! Each one has a default, which a cargo feature may change
+//! (`user-stack-256k`), and an environment variable of the same name read
+//! at build time overrides both: hexadecimal with `0x` or decimal, `_`
+//! allowed between digits, e.g.
+//! `USER_ASPACE_SIZE=0x8000_0000 cargo xtask run`. A value that is not a
+//! number fails the build. The crate root reads the values through its
+//! `USER_STACK_SIZE`, `KERNEL_STACK_SIZE`, `USER_ASPACE_*` and `APP_ENTRY`
+//! constants; numbers that are numbers but do not fit together are caught
+//! by `Layout::validate` when the kernel starts.
+
+use std::os::arceos::modules::axhal::mem::PAGE_SIZE_4K;
+
+use super::{BRK_GAP, BRK_MAX, STACK_GUARD_SIZE};
+
+/// The user address space layout: code from `app_entry` up, the brk heap
+/// and mmap area above the code, and the user stack (with its guard page
+/// below) at the top of `[user_aspace_base, user_aspace_base +
+/// user_aspace_size)`. Kernel stacks are separate, one per task.
+pub struct Layout {
+    pub user_stack_size: usize,
+    pub kernel_stack_size: usize,
+    pub user_aspace_base: usize,
+    pub user_aspace_size: usize,
+    pub app_entry: usize,
+}
+
+/// The value of the build-time environment variable `$name` if it is set,
+/// `$default` otherwise.
+macro_rules! env_size {
+    ($name:literal, $default:expr) => {
+        match option_env!($name) {
+            Some(value) => match parse_size(value) {
+                Some(size) => size,
+                None => panic!(concat!($name, " is not a number")),
+            },
+            None => $default,
+        }
+    };
+}
+
+/// The layout the kernel is built with.
+pub const LAYOUT: Layout = Layout {
+    user_stack_size: env_size!(
+        "USER_STACK_SIZE",
+        if cfg!(feature = "user-stack-256k") {
+            0x40000 // 256 KB
+        } else {
+            0x10000 // 64 KB
+        }
+    ),
+    kernel_stack_size: env_size!("KERNEL_STACK_SIZE", 0x40000), // 256 KB
+    // 0x0 .. 0x4000_0000 (1 GiB)
+    user_aspace_base: env_size!("USER_ASPACE_BASE", 0x0),
+    user_aspace_size: env_size!("USER_ASPACE_SIZE", 0x4000_0000),
+    app_entry: env_size!("APP_ENTRY", 0x1000),
+};
+
+/// Parses `s` as `0x` followed by hexadecimal digits, or as decimal
+/// digits, with `_` allowed anywhere after the first digit.
+const fn parse_size(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let (radix, mut i) = match bytes {
+        [b'0', b'x' | b'X', ..] => (16, 2),
+        _ => (10, 0),
+    };
+    let mut value: usize = 0;
+    let mut digits = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        i += 1;
+        let digit = match c {
+            b'_' if digits > 0 => continue,
+            b'0'..=b'9' => (c - b'0') as usize,
+            b'a'..=b'f' if radix == 16 => (c - b'a' + 10) as usize,
+            b'A'..=b'F' if radix == 16 => (c - b'A' + 10) as usize,
+            _ => return None,
+        };
+        value = match value.checked_mul(radix) {
+            Some(v) => match v.checked_add(digit) {
+                Some(v) => v,
+                None => return None,
+            },
+            None => return None,
+        };
+        digits += 1;
+    }
+    if digits == 0 { None } else { Some(value) }
+}
+
+impl Layout {
+    /// Panics, naming the numbers involved, unless the layout fits
+    /// together: every size and address page-aligned, a kernel stack and a
+    /// user stack at all, code starting inside the user address space, and
+    /// room between the code's first page and the stack guard for the
+    /// heap's gap and largest size (`BRK_GAP`, `BRK_MAX`).
+    pub fn validate(&self) {
+        for (name, value, may_be_zero) in [
+            ("USER_STACK_SIZE", self.user_stack_size, false),
+            ("KERNEL_STACK_SIZE", self.kernel_stack_size, false),
+            ("USER_ASPACE_BASE", self.user_aspace_base, true),
+            ("USER_ASPACE_SIZE", self.user_aspace_size, false),
+            ("APP_ENTRY", self.app_entry, true),
+        ] {
+            assert!(
+                value % PAGE_SIZE_4K == 0 && (value != 0 || may_be_zero),
+                "layout: {} = {:#x} is not a{} multiple of the page size",
+                name,
+                value,
+                if may_be_zero { "" } else { " non-zero" },
+            );
+        }
+        let end = self
+            .user_aspace_base
+            .checked_add(self.user_aspace_size)
+            .unwrap_or_else(|| {
+                panic!(
+                    "layout: USER_ASPACE_BASE {:#x} + USER_ASPACE_SIZE {:#x} overflows",
+                    self.user_aspace_base, self.user_aspace_size,
+                )
+            });
+        assert!(
+            self.user_aspace_base <= self.app_entry && self.app_entry < end,
+            "layout: APP_ENTRY {:#x} is outside the user address space [{:#x}, {:#x})",
+            self.app_entry,
+            self.user_aspace_base,
+            end,
+        );
+        let needed = PAGE_SIZE_4K + BRK_GAP + BRK_MAX + STACK_GUARD_SIZE + self.user_stack_size;
+        assert!(
+            end - self.app_entry >= needed,
+            "layout: {:#x} bytes from APP_ENTRY {:#x} to the end of the user address space \
+             {:#x} cannot hold a code page, the heap ({:#x} + {:#x}), the stack guard ({:#x}) \
+             and the user stack ({:#x}); raise USER_ASPACE_SIZE or lower USER_STACK_SIZE",
+            end - self.app_entry,
+            self.app_entry,
+            end,
+            BRK_GAP,
+            BRK_MAX,
+            STACK_GUARD_SIZE,
+            self.user_stack_size,
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! The guest console as a file (stdin, stdout and stderr), and the one
+//! locked writer all console output goes through.
+//!
+//! What user programs write to the console goes to an `OutputSink`, chosen
+//! at startup with `set_sink`: the console device (the default), a bounded
+//! capture buffer the kernel can read back with `take_capture`, or both at
+//! once, so that a run can be watched live and checked afterwards. With
+//! `capture-output` the whole run goes to the capture buffer, which is
+//! printed in one block at exit. Kernel messages always go to the device
+//! alone.
+
+use alloc::collections::VecDeque;
+use axerrno::LinuxResult;
+use std::os::arceos::modules::axhal::console;
+use std::os::arceos::modules::axtask;
+use std::sync::Mutex;
+
+use super::coop;
+use super::fd::{FileLike, FileStat, PollState, S_IFCHR};
+
+/// A byte already taken from the console input while checking readiness.
+///
+/// The platform console has no way to peek, so `poll` reads one byte ahead
+/// and keeps it here; readers must drain it before reading the device. It
+/// is global because there is only one console behind every console fd.
+static LOOKAHEAD: Mutex<Option<u8>> = Mutex::new(None);
+
+/// The task that holds the console output, and how many `OutputGuard`s it
+/// has for it; `None` while nobody writes.
+///
+/// Bytes only go out to the console under it, so that concurrent writes
+/// come out one after the other instead of interleaved. A task may take it
+/// again while holding it, so a kernel message printed in the middle of a
+/// user write (a syscall trace, say) does not deadlock.
+static OUTPUT: Mutex<Option<(u64, usize)>> = Mutex::new(None);
+
+/// The console output, held until dropped (see `lock_output`).
+pub struct OutputGuard(());
+
+impl Drop for OutputGuard {
+    fn drop(&mut self) {
+        let mut owner = OUTPUT.lock();
+        if let Some((_, depth)) = owner.as_mut() {
+            *depth -= 1;
+            if *depth == 0 {
+                *owner = None;
+            }
+        }
+    }
+}
+
+/// Takes the console output for the current task, waiting (by yielding)
+/// while another task holds it. Nothing else reaches the console until the
+/// guard is dropped, except what the current task writes itself.
+pub fn lock_output() -> OutputGuard {
+    let me = axtask::current().id().as_u64();
+    loop {
+        let mut owner = OUTPUT.lock();
+        match owner.as_mut() {
+            None => *owner = Some((me, 1)),
+            Some((task, depth)) if *task == me => *depth += 1,
+            Some(_) => {
+                drop(owner);
+                axtask::yield_now();
+                continue;
+            }
+        }
+        return OutputGuard(());
+    }
+}
+
+/// Where user output goes.
+pub trait OutputSink: Sync {
+    /// Takes `buf`, written while the console output is held.
+    fn write(&self, buf: &[u8]);
+}
+
+/// The console device.
+pub struct DeviceSink;
+
+impl OutputSink for DeviceSink {
+    fn write(&self, buf: &[u8]) {
+        console::write_bytes(buf);
+    }
+}
+
+/// The capture buffer.
+pub struct CaptureSink;
+
+impl OutputSink for CaptureSink {
+    fn write(&self, buf: &[u8]) {
+        let mut capture = CAPTURE.lock();
+        capture.bytes.extend(buf);
+        let excess = capture.bytes.len().saturating_sub(CAPTURE_SIZE);
+        capture.bytes.drain(..excess);
+        capture.dropped += excess;
+    }
+}
+
+/// Two sinks, each given every write: the first, then the second.
+pub struct TeeSink(pub &'static dyn OutputSink, pub &'static dyn OutputSink);
+
+impl OutputSink for TeeSink {
+    fn write(&self, buf: &[u8]) {
+        self.0.write(buf);
+        self.1.write(buf);
+    }
+}
+
+/// The console device and the capture buffer, in that order.
+pub static TEE: TeeSink = TeeSink(&DeviceSink, &CaptureSink);
+
+/// Bytes the capture buffer holds at most; past that the oldest bytes are
+/// dropped, and counted.
+const CAPTURE_SIZE: usize = 128 * 1024;
+
+/// User output captured since the last `take_capture`.
+#[derive(Default)]
+pub struct Capture {
+    /// The captured bytes, the latest `CAPTURE_SIZE` of them at most.
+    pub bytes: VecDeque<u8>,
+    /// How many bytes were dropped from the front to stay within
+    /// `CAPTURE_SIZE`.
+    pub dropped: usize,
+}
+
+static CAPTURE: Mutex<Capture> = Mutex::new(Capture {
+    bytes: VecDeque::new(),
+    dropped: 0,
+});
+
+static SINK: Mutex<&'static dyn OutputSink> = Mutex::new(&DeviceSink);
+
+/// Sends all user output written from now on to `sink`, and returns the
+/// sink it went to before.
+pub fn set_sink(sink: &'static dyn OutputSink) -> &'static dyn OutputSink {
+    core::mem::replace(&mut *SINK.lock(), sink)
+}
+
+/// Returns what has been captured and empties the capture buffer.
+pub fn take_capture() -> Capture {
+    core::mem::take(&mut *CAPTURE.lock())
+}
+
+/// Prints what has been captured to the console device in one block, and
+/// empties the capture buffer (`capture-output`). A header line gives the
+/// size, and how many bytes were dropped if the buffer overflowed; the
+/// bytes follow as written, then a line closes the block, on a line of
+/// its own even if the output did not end with a newline.
+#[cfg(feature = "capture-output")]
+pub fn print_capture() {
+    let mut capture = take_capture();
+    let bytes = capture.bytes.make_contiguous();
+    let _guard = lock_output();
+    let dropped = if capture.dropped == 0 {
+        alloc::string::String::new()
+    } else {
+        alloc::format!(", {} dropped", capture.dropped)
+    };
+    console::write_bytes(
+        alloc::format!("captured output: {} bytes{}\n", bytes.len(), dropped).as_bytes(),
+    );
+    console::write_bytes(bytes);
+    if bytes.last().is_some_and(|&b| b != b'\n') {
+        console::write_bytes(b"\n");
+    }
+    console::write_bytes(b"end of captured output\n");
+}
+
+/// Backend of the kernel's `print!`/`println!` under `unified-console`:
+/// formats the whole message first, then writes it to the console device
+/// at once, under the same lock as user writes. Without the feature they
+/// take the runtime's own stdout path, so a kernel line and a user write
+/// may still tear each other.
+#[cfg(feature = "unified-console")]
+pub fn print_fmt(args: core::fmt::Arguments) {
+    let buf = alloc::fmt::format(args);
+    let _guard = lock_output();
+    console::write_bytes(buf.as_bytes());
+}
+
+/// The guest console.
+pub struct Console;
+
+impl FileLike for Console {
+    /// Blocks until at least one byte is available, then returns what is
+    /// there without waiting for `buf` to fill.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut lookahead = LOOKAHEAD.lock();
+            let n = match lookahead.take() {
+                Some(byte) => {
+                    buf[0] = byte;
+                    1 + console::read_bytes(&mut buf[1..])
+                }
+                None => console::read_bytes(buf),
+            };
+            drop(lookahead);
+            if n > 0 {
+                return Ok(n);
+            }
+            coop::yield_now();
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let _guard = lock_output();
+        let sink = *SINK.lock();
+        sink.write(buf);
+        Ok(buf.len())
+    }
+
+    /// A `write` syscall larger than one chunk still comes out whole.
+    fn write_whole(&self, write: &mut dyn FnMut() -> LinuxResult<usize>) -> LinuxResult<usize> {
+        let _guard = lock_output();
+        write()
+    }
+
+    fn poll(&self) -> PollState {
+        let mut lookahead = LOOKAHEAD.lock();
+        if lookahead.is_none() {
+            let mut byte = [0u8];
+            if console::read_bytes(&mut byte) == 1 {
+                *lookahead = Some(byte[0]);
+            }
+        }
+        PollState {
+            readable: lookahead.is_some(),
+            writable: true,
+        }
+    }
+
+    fn stat(&self) -> FileStat {
+        FileStat {
+            mode: S_IFCHR | 0o620,
+            ino: 0,
+            size: 0,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Cooperative scheduling of user processes (`deterministic-sched` feature).
+//!
+//! axstd always builds axtask with the preemptive CFS scheduler, and a
+//! feature of this crate cannot turn preemption off again, so the timer
+//! still switches tasks. Instead, user processes take turns: each one joins
+//! the back of a queue when it is spawned, and only the one at the front
+//! runs user code or makes syscalls. It keeps the turn until it yields
+//! (`sched_yield`, or waiting in `wait4`/`waitid`, `ppoll` or a console
+//! read), which moves it to the back, or until it exits. A holder that the
+//! timer preempts only delays the others: any other user task that gets
+//! the CPU yields straight back until it has the turn. What the programs
+//! see and print therefore depends only on the order they were spawned in
+//! and on where they yield.
+//!
+//! Sleeping keeps the turn, so a sleep stalls every process instead of
+//! letting the timer pick who runs next. Kernel tasks are not in the queue
+//! and are never held back, except a kernel task that spawns several
+//! processes at once, which takes a turn of its own (`hold`) so that none
+//! of them starts before the last one is queued. Without the feature,
+//! `yield_now` is `axtask::yield_now` and the other functions do nothing.
+
+use alloc::collections::VecDeque;
+use std::os::arceos::modules::axtask::{self, TaskInner};
+use std::sync::Mutex;
+
+/// IDs of the user tasks taking turns; the front one has the turn.
+static TURNS: Mutex<VecDeque<u64>> = Mutex::new(VecDeque::new());
+
+fn current_id() -> u64 {
+    axtask::current().id().as_u64()
+}
+
+/// Queues `task`, which is about to be spawned. Called by the spawner, so
+/// that the order does not depend on when the new task first gets a CPU.
+pub fn join(task: &TaskInner) {
+    if cfg!(feature = "deterministic-sched") {
+        TURNS.lock().push_back(task.id().as_u64());
+    }
+}
+
+/// The turn of a kernel task, from `hold`; it is given up when dropped.
+pub struct Hold(());
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        leave();
+    }
+}
+
+/// Queues the current (kernel) task and waits for its turn, which it keeps
+/// until the returned `Hold` is dropped. Processes spawned meanwhile queue
+/// up behind it.
+pub fn hold() -> Hold {
+    if cfg!(feature = "deterministic-sched") {
+        TURNS.lock().push_back(current_id());
+        wait_turn();
+    }
+    Hold(())
+}
+
+/// Waits until the current task has the turn. A task that is not queued
+/// (a kernel task) returns at once.
+pub fn wait_turn() {
+    if !cfg!(feature = "deterministic-sched") {
+        return;
+    }
+    let id = current_id();
+    while TURNS
+        .lock()
+        .iter()
+        .position(|&t| t == id)
+        .is_some_and(|i| i > 0)
+    {
+        axtask::yield_now();
+    }
+}
+
+/// Lets other tasks run: passes the turn on to the next user task, if the
+/// current one has it, and waits for it to come round again.
+pub fn yield_now() {
+    if cfg!(feature = "deterministic-sched") {
+        let id = current_id();
+        let mut turns = TURNS.lock();
+        if turns.front() == Some(&id) {
+            turns.rotate_left(1);
+        }
+    }
+    axtask::yield_now();
+    wait_turn();
+}
+
+/// Drops the current task from the queue, passing the turn on if it had
+/// it. Called once its process has exited.
+pub fn leave() {
+    if cfg!(feature = "deterministic-sched") {
+        let id = current_id();
+        TURNS.lock().retain(|&t| t != id);
+    }
+}
@@ -0,0 +1,1688 @@
+//! Demo modes run in place of the boot program.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+use std::os::arceos::modules::axalloc;
+use std::os::arceos::modules::axhal::mem::{PAGE_SIZE_4K, phys_to_virt, va};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+use std::os::arceos::modules::axhal::time::monotonic_time;
+use std::os::arceos::modules::axhal::uspace::UserContext;
+use std::os::arceos::modules::axhal::{self, trap::PageFaultFlags};
+use std::os::arceos::modules::axtask;
+
+use super::error::KernelError;
+use super::process::CpuTimes;
+use super::signal::{SIGALRM, SIGILL, SIGKILL, SIGSEGV, SIGSYS};
+use super::syscall::{self, SyscallHandler, SyscallOutcome};
+use super::{
+    APP_ENTRY, BRK_MAX, STACK_GUARD_SIZE, USER_ASPACE_BASE, USER_ASPACE_SIZE, USER_STACK_SIZE,
+    apps, console, coop, loader, process, syscall_abi, task, uaccess, wait,
+};
+
+/// Number of processes in the round-robin demo (their PIDs stay single
+/// digit, which is all `/bin/yield` can print).
+const ROUND_ROBIN_PROCS: usize = 4;
+
+/// Spawns `ROUND_ROBIN_PROCS` copies of `/bin/yield`, each in its own
+/// address space, and waits for all of them.
+///
+/// Every process prints its PID and yields, three times over, so the output
+/// interleaves rounds from all of them when the scheduler cycles through
+/// the tasks (and hence their page tables) correctly:
+///
+/// ```text
+/// pid 1: round 1
+/// pid 2: round 1
+/// ...
+/// pid 4: round 3
+/// ```
+///
+/// Returns the first non-zero exit status, or 0 if all processes succeed.
+pub fn round_robin() -> i32 {
+    let app = apps::find("/bin/yield").unwrap();
+    let tasks: Vec<_> = (0..ROUND_ROBIN_PROCS)
+        .map(|_| {
+            let (_, task) =
+                task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+                    .expect("failed to spawn round-robin process");
+            task
+        })
+        .collect();
+    let codes: Vec<i32> = tasks.iter().map(|t| t.join()).collect();
+    println!(
+        "round-robin demo: {} processes exited {:?}",
+        ROUND_ROBIN_PROCS, codes
+    );
+    codes.into_iter().find(|&c| c != 0).unwrap_or(0)
+}
+
+/// The programs the mixed demo runs side by side: a flat binary that
+/// yields, a static ELF executable loaded at its own addresses, and a flat
+/// binary that spins without a syscall until the timer preempts it.
+const MIXED_APPS: [&str; 3] = ["/bin/yield", "/bin/elf", "/bin/spin"];
+
+/// Spawns each of `MIXED_APPS` in its own address space, all at once, and
+/// waits for all of them, printing each one's exit code.
+///
+/// Every process must have a page table root of its own: a task switched
+/// to with a sibling's root would run the sibling's image, and its faults
+/// and syscalls would land in the sibling's address space.
+///
+/// Returns the first non-zero exit status, 1 if two processes share a
+/// root, or 0 if all processes succeed.
+pub fn mixed() -> i32 {
+    let spawned: Vec<_> = MIXED_APPS
+        .iter()
+        .map(|&path| {
+            let app = apps::find(path).unwrap();
+            task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+                .expect("failed to spawn mixed demo process")
+        })
+        .collect();
+    let roots: Vec<usize> = spawned
+        .iter()
+        .map(|(process, _)| process.aspace.lock().page_table_root().as_usize())
+        .collect();
+    let shared = roots
+        .iter()
+        .enumerate()
+        .any(|(i, root)| roots[..i].contains(root));
+    let mut status = 0;
+    for ((process, task), root) in spawned.iter().zip(&roots) {
+        let code = task.join();
+        println!(
+            "mixed demo: {} (pid {}, root {:#x}) exited {}",
+            process.app.lock().path,
+            process.pid,
+            root,
+            code
+        );
+        if status == 0 {
+            status = code;
+        }
+    }
+    if shared {
+        println!("mixed demo: page table root shared: FAILED");
+        return 1;
+    }
+    status
+}
+
+/// Number of processes in the deterministic-scheduling demo.
+const DETERMINISTIC_PROCS: usize = 2;
+
+/// Runs `DETERMINISTIC_PROCS` copies of `/bin/yield` under
+/// `deterministic-sched` (which `demo-deterministic` turns on).
+///
+/// The processes take turns strictly at each `sched_yield`, so their
+/// output is the same on every run, whatever the timer does:
+///
+/// ```text
+/// pid 1: round 1
+/// pid 2: round 1
+/// pid 1: round 2
+/// ...
+/// pid 2: round 3
+/// ```
+///
+/// Both are spawned while this task holds a turn, so neither starts early.
+/// Returns the first non-zero exit status, or 0 if both succeed.
+pub fn deterministic() -> i32 {
+    let app = apps::find("/bin/yield").unwrap();
+    let hold = coop::hold();
+    let tasks: Vec<_> = (0..DETERMINISTIC_PROCS)
+        .map(|_| {
+            let (_, task) =
+                task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+                    .expect("failed to spawn deterministic demo process");
+            task
+        })
+        .collect();
+    drop(hold);
+    let codes: Vec<i32> = tasks.iter().map(|t| t.join()).collect();
+    println!(
+        "deterministic demo: {} processes exited {:?}",
+        DETERMINISTIC_PROCS, codes
+    );
+    codes.into_iter().find(|&c| c != 0).unwrap_or(0)
+}
+
+/// Spawns `/bin/init` until the process limit is hit, the way a fork bomb
+/// would, then checks that the kernel refused cleanly and recovers.
+///
+/// The spawned processes stay alive (counted in the process table) until
+/// their handles are dropped below, which stands in for the parent reaping
+/// them. Returns 0 if exactly `MAX_PROCS` spawns succeed, the next one
+/// fails with `EAGAIN`, and a spawn succeeds again once all are reaped.
+pub fn process_limit() -> i32 {
+    let app = apps::init();
+    let argv = [app.path.to_string()];
+    let mut procs = Vec::new();
+    let err = loop {
+        match task::spawn_user_process(app, &argv, &[], None, None, None, None) {
+            Ok(proc) => procs.push(proc),
+            Err(e) => break e,
+        }
+    };
+    let spawned = procs.len();
+    println!("process-limit demo: {} spawned, then {:?}", spawned, err);
+
+    let codes: Vec<i32> = procs.drain(..).map(|(_, task)| task.join()).collect();
+    let reaped = process::count() == 0;
+    let respawned = task::spawn_user_process(app, &argv, &[], None, None, None, None)
+        .map(|(_, task)| task.join());
+    println!(
+        "process-limit demo: exited {:?}, all reaped: {}, respawn: {:?}",
+        codes, reaped, respawned
+    );
+
+    let ok = spawned == process::MAX_PROCS
+        && err == KernelError::Errno(LinuxError::EAGAIN)
+        && reaped
+        && respawned == Ok(0)
+        && codes.iter().all(|&c| c == 0);
+    if ok { 0 } else { 1 }
+}
+
+/// Number of processes in the affinity demo.
+const AFFINITY_PROCS: usize = 4;
+
+/// Pins `AFFINITY_PROCS` copies of `/bin/getcpu` to CPUs 0, 1, 2, ...
+/// (wrapping around the CPUs there are) and checks that each reports
+/// running on the CPU it was pinned to. With several CPUs they run in
+/// parallel; on a single-CPU guest all of them run on CPU 0.
+///
+/// Returns 0 if every process ran where intended, 1 otherwise.
+pub fn affinity() -> i32 {
+    let app = apps::find("/bin/getcpu").unwrap();
+    let cpus = axhal::cpu_num();
+    let tasks: Vec<_> = (0..AFFINITY_PROCS)
+        .map(|i| {
+            let (process, task) = task::spawn_user_process(
+                app,
+                &[app.path.to_string()],
+                &[],
+                None,
+                Some(i),
+                None,
+                None,
+            )
+            .expect("failed to spawn affinity process");
+            (process.pid, i % cpus, task)
+        })
+        .collect();
+    let mut failed = false;
+    for (pid, cpu, task) in tasks {
+        let ran_on = task.join();
+        let ok = ran_on == cpu as i32;
+        println!(
+            "affinity demo: process {} pinned to CPU {} ran on CPU {}: {}",
+            pid,
+            cpu,
+            ran_on,
+            if ok { "ok" } else { "FAILED" }
+        );
+        failed |= !ok;
+    }
+    failed as i32
+}
+
+/// Runs two copies of `/bin/lines` at once, on CPUs 0 and 1, one writing
+/// lines of `a` and the other lines of `b`. Each line is one `write` of
+/// more than one chunk, and must reach the console whole: the output has
+/// `apps::LINES_COUNT` lines of each letter and no line mixing them. The
+/// output goes to the console through a tee into the capture buffer, so
+/// whoever reads the console can check it (`scripts/test.sh` does) and the
+/// kernel checks the capture too.
+///
+/// Returns 0 if both processes wrote every line in full and the capture
+/// has exactly their lines, all whole, 1 otherwise.
+pub fn console() -> i32 {
+    let sink = console::set_sink(&console::TEE);
+    let app = apps::find("/bin/lines").unwrap();
+    let tasks: Vec<_> = ["a", "b"]
+        .into_iter()
+        .enumerate()
+        .map(|(cpu, letter)| {
+            let argv = [app.path.to_string(), letter.to_string()];
+            let (_, task) = task::spawn_user_process(app, &argv, &[], None, Some(cpu), None, None)
+                .expect("failed to spawn console process");
+            task
+        })
+        .collect();
+    let codes: Vec<i32> = tasks.into_iter().map(|task| task.join()).collect();
+    console::set_sink(sink);
+    let mut capture = console::take_capture();
+    let output = &*capture.bytes.make_contiguous();
+    let whole = |letter| {
+        output
+            .split_inclusive(|&b| b == b'\n')
+            .filter(|line| {
+                line.len() == apps::LINES_LEN
+                    && line[..apps::LINES_LEN - 1].iter().all(|&b| b == letter)
+            })
+            .count()
+    };
+    let whole = [whole(b'a'), whole(b'b')];
+    let captured_ok = capture.dropped == 0
+        && whole == [apps::LINES_COUNT; 2]
+        && capture.bytes.len() == 2 * apps::LINES_COUNT * apps::LINES_LEN;
+    let ok = codes.iter().all(|&c| c == 0) && captured_ok;
+    println!(
+        "console demo: {} lines of {} bytes each, exit {:?}, captured {} + {} whole lines \
+         ({} bytes, {} dropped): {}",
+        apps::LINES_COUNT,
+        apps::LINES_LEN,
+        codes,
+        whole[0],
+        whole[1],
+        capture.bytes.len(),
+        capture.dropped,
+        if ok { "ok" } else { "FAILED" }
+    );
+    !ok as i32
+}
+
+/// Runs `/bin/alarm` once without a `SIGALRM` handler, which must be killed
+/// by the signal when its interval timer first expires, and three times
+/// catching it, which must see its handler run three times and exit
+/// normally: once spinning, once waiting in `rt_sigsuspend` and once in
+/// `pause`, which must end with `EINTR` after each run.
+///
+/// Returns 0 if every run ended as expected, 1 otherwise.
+pub fn alarm() -> i32 {
+    let app = apps::find("/bin/alarm").unwrap();
+    let mut failed = false;
+    for (mode, expect) in [
+        ("default", 128 + SIGALRM),
+        ("catch", 0),
+        ("suspend", 0),
+        ("pause", 0),
+    ] {
+        let argv = [app.path.to_string(), mode.to_string()];
+        let (_, task) = task::spawn_user_process(app, &argv, &[], None, None, None, None)
+            .expect("failed to spawn alarm process");
+        let code = task.join();
+        let ok = code == expect;
+        println!(
+            "alarm demo: {}: exit {}: {}",
+            mode,
+            code,
+            if ok { "ok" } else { "FAILED" }
+        );
+        failed |= !ok;
+    }
+    failed as i32
+}
+
+/// Syscalls each run of `/bin/getpid` makes.
+const LATENCY_CALLS: u32 = 1 << 18;
+
+/// Times `/bin/getpid` making `LATENCY_CALLS` `getpid` calls, which the
+/// syscall fast path answers, and then as many `getppid` calls, which go
+/// through the full dispatcher for the same work, and prints the time per
+/// call of each. Each is the time from spawn to exit over the number of
+/// calls: process setup and teardown, the same for both, are included.
+///
+/// Returns 0 if both runs exited 0, 1 otherwise.
+pub fn syscall_latency() -> i32 {
+    let app = apps::find("/bin/getpid").unwrap();
+    let mut failed = false;
+    for (mode, path) in [("getpid", "fast path"), ("getppid", "full dispatch")] {
+        let mut argv = Vec::from([app.path.to_string()]);
+        if mode == "getppid" {
+            argv.push("ppid".to_string());
+        }
+        let begin = monotonic_time();
+        let (_, task) = task::spawn_user_process(app, &argv, &[], None, None, None, None)
+            .expect("failed to spawn latency process");
+        let code = task.join();
+        let elapsed = monotonic_time() - begin;
+        println!(
+            "syscall latency demo: {} ({}): {:?} per call, exit {}",
+            mode,
+            path,
+            elapsed / LATENCY_CALLS,
+            code
+        );
+        failed |= code != 0;
+    }
+    failed as i32
+}
+
+/// Size of the pages backing the heap of `/bin/stream` in the heap-pages
+/// benchmark.
+#[derive(Clone, Copy)]
+enum HeapPages {
+    Size4K,
+    Size2M,
+}
+
+impl HeapPages {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Size4K => "4K",
+            Self::Size2M => "2M",
+        }
+    }
+
+    /// Maps the `STREAM_HEAP_SIZE` bytes at `STREAM_HEAP` in `uspace`
+    /// with pages of this size, each allocated on the first touch of it
+    /// (a minor fault).
+    ///
+    /// axmm maps every area page by page in 4K pages and gives no mutable
+    /// access to the page table to map a larger one, so 2M pages are not
+    /// supported yet (`EOPNOTSUPP`).
+    fn map(self, uspace: &mut AddrSpace) -> LinuxResult {
+        match self {
+            Self::Size4K => uspace
+                .map_alloc(
+                    va!(apps::STREAM_HEAP),
+                    apps::STREAM_HEAP_SIZE,
+                    MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+                    false,
+                )
+                .map_err(|_| LinuxError::ENOMEM),
+            Self::Size2M => Err(LinuxError::EOPNOTSUPP),
+        }
+    }
+}
+
+/// What one run of `/bin/stream` measured.
+struct StreamRun {
+    faults: usize,
+    /// Time `HeapPages::map` took.
+    map_time: Duration,
+    /// Bytes stored and loaded per microsecond of user time, i.e. MB/s:
+    /// the page faults are system time, and not counted.
+    throughput: u64,
+    code: i32,
+}
+
+/// Runs `/bin/stream` with its heap mapped with `pages`.
+fn stream(pages: HeapPages) -> LinuxResult<StreamRun> {
+    let app = apps::find("/bin/stream").unwrap();
+    let mut map_time = Duration::ZERO;
+    let (process, task) = task::spawn_user_process_with(
+        app,
+        &[app.path.to_string()],
+        &[],
+        None,
+        None,
+        None,
+        |process, _| {
+            let begin = monotonic_time();
+            pages.map(&mut process.aspace.lock())?;
+            map_time = monotonic_time() - begin;
+            Ok(())
+        },
+    )?;
+    let code = task.join();
+    let bytes = (apps::STREAM_HEAP_SIZE * (apps::STREAM_PASSES + 1)) as u64;
+    let user_us = CpuTimes::get(&process.cpu_times.user).as_micros() as u64;
+    Ok(StreamRun {
+        faults: process.faults.minor.load(Ordering::Relaxed),
+        map_time,
+        throughput: bytes / user_us.max(1),
+        code,
+    })
+}
+
+/// Runs `/bin/stream` twice, with its heap backed by 4K pages and then by
+/// 2M pages, and prints what each run took: page faults, time to map the
+/// heap and streaming throughput. A last line compares the two, in a fixed
+/// format for CI to track (`2M vs 4K: N vs N faults, ...`).
+///
+/// A page size that cannot be mapped is reported and left out of the
+/// comparison. For now that is always 2M, as axmm maps only 4K pages, so
+/// the run ends with:
+///
+/// ```text
+/// heap pages bench: 2M: cannot map: EOPNOTSUPP
+/// heap pages bench: 2M vs 4K: not compared
+/// ```
+///
+/// Returns 0 if every run that could be started exited 0 and the 4K one
+/// was started, 1 otherwise.
+pub fn heap_pages() -> i32 {
+    let mut failed = false;
+    let [small, huge] = [HeapPages::Size4K, HeapPages::Size2M].map(|pages| {
+        let run = stream(pages);
+        match &run {
+            Ok(run) => {
+                println!(
+                    "heap pages bench: {}: {} faults, mapped in {} us, {} MB/s, exit {}",
+                    pages.name(),
+                    run.faults,
+                    run.map_time.as_micros(),
+                    run.throughput,
+                    run.code
+                );
+                failed |= run.code != 0;
+            }
+            Err(err) => println!("heap pages bench: {}: cannot map: {:?}", pages.name(), err),
+        }
+        run
+    });
+    match (&huge, &small) {
+        (Ok(huge), Ok(small)) => println!(
+            "heap pages bench: 2M vs 4K: {} vs {} faults, {} vs {} us map, {} vs {} MB/s",
+            huge.faults,
+            small.faults,
+            huge.map_time.as_micros(),
+            small.map_time.as_micros(),
+            huge.throughput,
+            small.throughput
+        ),
+        _ => println!("heap pages bench: 2M vs 4K: not compared"),
+    }
+    (failed || small.is_err()) as i32
+}
+
+/// Runs `/bin/shm` twice: first as a writer, which stores `SHM_MAGIC` in
+/// the shared memory segment `SHM_KEY`, then as a reader in a new address
+/// space, which finds it there because it attaches the same frames.
+///
+/// Returns 0 if both exited 0, 1 otherwise.
+pub fn shm() -> i32 {
+    let app = apps::find("/bin/shm").unwrap();
+    let mut failed = false;
+    for (role, argv) in [
+        ("writer", Vec::from([app.path.to_string(), "w".to_string()])),
+        ("reader", Vec::from([app.path.to_string()])),
+    ] {
+        let (process, task) = task::spawn_user_process(app, &argv, &[], None, None, None, None)
+            .expect("failed to spawn shm process");
+        let code = task.join();
+        println!(
+            "shm demo: {} (process {}): exit {}",
+            role, process.pid, code
+        );
+        failed |= code != 0;
+    }
+    failed as i32
+}
+
+/// Runs `/bin/elf`, a static ELF executable, which checks that its data
+/// segment and bss were loaded where its program headers put them.
+///
+/// Returns its exit code: 0 if they were.
+pub fn elf() -> i32 {
+    let app = apps::find("/bin/elf").unwrap();
+    let (_, task) =
+        task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+            .expect("failed to spawn elf process");
+    let code = task.join();
+    println!(
+        "elf demo: exit {}: {}",
+        code,
+        if code == 0 { "ok" } else { "FAILED" }
+    );
+    code
+}
+
+/// Loads `/bin/big`, a flat binary of more than one page, and reads each
+/// page of it back through the page table and the kernel's mapping of the
+/// frame: every one must hold its part of the image, and the marker that
+/// straddles the first page boundary must come out whole from the two
+/// frames. Then runs it: the code on its second page checks the marker
+/// again and exits 0.
+///
+/// Returns 0 if the pages held the image and the run exited 0, 1
+/// otherwise.
+pub fn big_app() -> i32 {
+    let app = apps::find("/bin/big").unwrap();
+    let image = app.image();
+    let (process, _) = task::build_user_process(app, &[app.path.to_string()], &[], None)
+        .expect("failed to build big app process");
+    let mut mapped = Vec::with_capacity(image.len());
+    {
+        let uspace = process.aspace.lock();
+        for offset in (0..image.len()).step_by(PAGE_SIZE_4K) {
+            let Ok((paddr, _, _)) = uspace.page_table().query(va!(APP_ENTRY + offset)) else {
+                break;
+            };
+            let len = (image.len() - offset).min(PAGE_SIZE_4K);
+            // SAFETY: the frame is mapped in the process, which is alive.
+            let frame = unsafe { core::slice::from_raw_parts(phys_to_virt(paddr).as_ptr(), len) };
+            mapped.extend_from_slice(frame);
+        }
+    }
+    let marker = apps::BIG_MARKER_OFFSET..apps::BIG_MARKER_OFFSET + apps::BIG_MARKER.len();
+    let pages = image.len().div_ceil(PAGE_SIZE_4K);
+    let ok = mapped == image && mapped.get(marker) == Some(&apps::BIG_MARKER[..]);
+    println!(
+        "big app demo: {} bytes in {} pages, copied {}: {}",
+        image.len(),
+        pages,
+        if mapped == image { "whole" } else { "wrong" },
+        if ok { "ok" } else { "FAILED" }
+    );
+
+    let (_, task) =
+        task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+            .expect("failed to spawn big app process");
+    let code = task.join();
+    println!(
+        "big app demo: exit {}: {}",
+        code,
+        if code == 0 { "ok" } else { "FAILED" }
+    );
+    (!ok || code != 0) as i32
+}
+
+/// Builds a process running the boot program and moves its program break
+/// with `brk` calls made through `handle_syscall`, checking through the
+/// page table what each one left mapped: growing by a few pages and a bit
+/// maps them (and not the next one), shrinking unmaps the pages above the
+/// new break, pages mapped again come back zeroed, and a break below the
+/// initial one or past `BRK_MAX` above it leaves the break where it was.
+/// The process never runs.
+///
+/// Returns 0 if every call did what `mm::sys_brk` says, 1 otherwise.
+pub fn brk() -> i32 {
+    let app = apps::init();
+    let (process, start) = task::build_user_process(app, &[app.path.to_string()], &[], None)
+        .expect("failed to build brk demo process");
+    let brk = |addr: usize| {
+        let mut uctx = start;
+        uctx.set_sysno(syscall_abi::BRK);
+        uctx.set_arg0(addr);
+        syscall::handle_syscall(&process, &mut uctx);
+        uctx.retval()
+    };
+    let writable =
+        |addr: usize| uaccess::copy_to_user(&mut process.aspace.lock(), addr, &[0xa5]).is_ok();
+    let zeroed = |addr: usize| {
+        let mut page = [0xffu8; PAGE_SIZE_4K];
+        uaccess::copy_from_user(&process.aspace.lock(), &mut page, addr).is_ok()
+            && page.iter().all(|&b| b == 0)
+    };
+
+    let base = brk(0);
+    let grown = base + 3 * PAGE_SIZE_4K + 100;
+    // In order: each check depends on the calls before it.
+    let checks = [
+        (
+            "initial break page aligned",
+            base.is_multiple_of(PAGE_SIZE_4K),
+        ),
+        ("grow", brk(grown) == grown),
+        ("query", brk(0) == grown),
+        (
+            "grown pages mapped",
+            (0..4).all(|page| writable(base + page * PAGE_SIZE_4K)),
+        ),
+        ("page above not mapped", !writable(base + 4 * PAGE_SIZE_4K)),
+        ("shrink", brk(base + PAGE_SIZE_4K) == base + PAGE_SIZE_4K),
+        ("shrunk pages unmapped", !writable(base + PAGE_SIZE_4K)),
+        ("page below kept", writable(base)),
+        ("grow again", brk(grown) == grown),
+        ("pages mapped again zeroed", zeroed(base + PAGE_SIZE_4K)),
+        ("below initial break", brk(base - 1) == grown),
+        ("past BRK_MAX", brk(base + BRK_MAX + 1) == grown),
+        ("into the stack", brk(USER_ASPACE_SIZE - 1) == grown),
+        ("shrink to empty", brk(base) == base),
+        ("empty heap unmapped", !writable(base)),
+    ];
+
+    println!("brk demo: initial break {:#x}", base);
+    let mut failed = false;
+    for (name, ok) in checks {
+        println!("brk demo: {}: {}", name, if ok { "ok" } else { "FAILED" });
+        failed |= !ok;
+    }
+    failed as i32
+}
+
+/// Builds a process for the boot program and maps and unmaps anonymous
+/// memory in it with `mmap` and `munmap`, checking through the page table
+/// what each call mapped: two mappings placed by the kernel (`addr` 0) do
+/// not overlap, both come zeroed, unmapping the first leaves the second
+/// and its contents alone, the next mapping reuses the freed range, and
+/// file, shared, empty and `MAP_FIXED` mappings over existing ones fail.
+/// The process never runs.
+///
+/// Returns 0 if every call did what `mm::sys_mmap` and `mm::sys_munmap`
+/// say, 1 otherwise.
+pub fn mmap() -> i32 {
+    const PROT_RW: usize = 3;
+    const MAP_SHARED: usize = 0x01;
+    const MAP_PRIVATE: usize = 0x02;
+    const MAP_FIXED: usize = 0x10;
+    const MAP_ANONYMOUS: usize = 0x20;
+    const ANON: usize = MAP_PRIVATE | MAP_ANONYMOUS;
+    let app = apps::init();
+    let (process, start) = task::build_user_process(app, &[app.path.to_string()], &[], None)
+        .expect("failed to build mmap demo process");
+    let call = |sysno: usize, args: [usize; 4]| {
+        let mut uctx = start;
+        uctx.set_sysno(sysno);
+        uctx.set_arg0(args[0]);
+        uctx.set_arg1(args[1]);
+        uctx.set_arg2(args[2]);
+        uctx.set_arg3(args[3]);
+        syscall::handle_syscall(&process, &mut uctx);
+        uctx.retval()
+    };
+    let mmap = |addr: usize, len: usize, flags: usize| {
+        call(syscall_abi::MMAP, [addr, len, PROT_RW, flags])
+    };
+    let munmap = |addr: usize, len: usize| call(syscall_abi::MUNMAP, [addr, len, 0, 0]);
+    let einval = (LinuxError::EINVAL.code() as isize).wrapping_neg() as usize;
+    let write = |addr: usize, byte: u8| {
+        uaccess::copy_to_user(&mut process.aspace.lock(), addr, &[byte]).is_ok()
+    };
+    let read = |addr: usize| {
+        let mut byte = [0u8];
+        uaccess::copy_from_user(&process.aspace.lock(), &mut byte, addr).map(|()| byte[0])
+    };
+    let zeroed = |addr: usize, len: usize| {
+        (0..len).step_by(PAGE_SIZE_4K).all(|offset| {
+            let mut page = [0xffu8; PAGE_SIZE_4K];
+            uaccess::copy_from_user(&process.aspace.lock(), &mut page, addr + offset).is_ok()
+                && page.iter().all(|&b| b == 0)
+        })
+    };
+
+    let first = mmap(0, 2 * PAGE_SIZE_4K, ANON);
+    let second = mmap(0, PAGE_SIZE_4K + 1, ANON);
+    let mmap_base = process.heap.lock().mmap_base().as_usize();
+    // In order: each check depends on the calls before it.
+    let checks = [
+        (
+            "first mapped above the mmap base",
+            first >= mmap_base && first.is_multiple_of(PAGE_SIZE_4K),
+        ),
+        ("first zeroed", zeroed(first, 2 * PAGE_SIZE_4K)),
+        ("second zeroed", zeroed(second, 2 * PAGE_SIZE_4K)),
+        (
+            "no overlap",
+            second >= first + 2 * PAGE_SIZE_4K || second + 2 * PAGE_SIZE_4K <= first,
+        ),
+        (
+            "both writable",
+            write(first + PAGE_SIZE_4K, 0x11) && write(second, 0x22),
+        ),
+        ("munmap first", munmap(first, 2 * PAGE_SIZE_4K) == 0),
+        (
+            "first unmapped",
+            read(first).is_err() && read(first + PAGE_SIZE_4K).is_err(),
+        ),
+        ("second intact", read(second) == Ok(0x22)),
+        (
+            "freed range reused",
+            mmap(0, 2 * PAGE_SIZE_4K, ANON) == first,
+        ),
+        ("reused range zeroed", zeroed(first, 2 * PAGE_SIZE_4K)),
+        (
+            "MAP_FIXED over a mapping",
+            mmap(second, PAGE_SIZE_4K, ANON | MAP_FIXED) == einval,
+        ),
+        ("munmap again", munmap(first, 2 * PAGE_SIZE_4K) == 0),
+        (
+            "MAP_FIXED into free range",
+            mmap(first, PAGE_SIZE_4K, ANON | MAP_FIXED) == first,
+        ),
+        ("file-backed", mmap(0, PAGE_SIZE_4K, MAP_PRIVATE) == einval),
+        (
+            "shared",
+            mmap(0, PAGE_SIZE_4K, MAP_SHARED | MAP_ANONYMOUS) == einval,
+        ),
+        ("zero length", mmap(0, 0, ANON) == einval),
+        (
+            "munmap unaligned",
+            munmap(second + 1, PAGE_SIZE_4K) == einval,
+        ),
+    ];
+
+    println!(
+        "mmap demo: mappings at {:#x} and {:#x}, mmap base {:#x}",
+        first, second, mmap_base
+    );
+    let mut failed = false;
+    for (name, ok) in checks {
+        println!("mmap demo: {}: {}", name, if ok { "ok" } else { "FAILED" });
+        failed |= !ok;
+    }
+    failed as i32
+}
+
+/// Builds a process running the boot program, with arguments long enough
+/// to take its initial stack past the top page, and reads the stack back
+/// the way crt0 does: argc at the stack pointer, then the argv and envp
+/// arrays, each string as given, and the auxiliary vector with
+/// `AT_PAGESZ`. The process never runs.
+///
+/// Returns 0 if everything was where the ABI puts it, 1 otherwise.
+pub fn initial_stack() -> i32 {
+    const AT_NULL: usize = 0;
+    const AT_PAGESZ: usize = 6;
+    let app = apps::init();
+    let argv = [app.path.to_string(), "a".repeat(3000), "b".repeat(2000)];
+    let envp = ["DEMO=initial-stack".to_string()];
+    let (process, uctx) = task::build_user_process(app, &argv, &envp, None)
+        .expect("failed to build initial stack process");
+    let uspace = process.aspace.lock();
+    let sp = uctx.sp();
+    let top = uspace.end().as_usize();
+    let word = |addr: usize| {
+        let mut raw = [0u8; size_of::<usize>()];
+        uaccess::copy_from_user(&uspace, &mut raw, addr).map(|()| usize::from_ne_bytes(raw))
+    };
+    let argv_ptr = sp + size_of::<usize>();
+    let envp_ptr = argv_ptr + (argv.len() + 1) * size_of::<usize>();
+    let mut auxv = Vec::new();
+    let mut addr = envp_ptr + (envp.len() + 1) * size_of::<usize>();
+    while let (Ok(key), Ok(value)) = (word(addr), word(addr + size_of::<usize>())) {
+        auxv.push((key, value));
+        addr += 2 * size_of::<usize>();
+        if key == AT_NULL {
+            break;
+        }
+    }
+    let checks = [
+        ("sp 16-byte aligned", sp.is_multiple_of(16)),
+        (
+            "spans two pages",
+            sp / PAGE_SIZE_4K < (top - 1) / PAGE_SIZE_4K,
+        ),
+        ("argc", word(sp) == Ok(argv.len())),
+        (
+            "argv",
+            uaccess::read_cstr_array(&uspace, argv_ptr).is_ok_and(|a| a == argv),
+        ),
+        (
+            "envp",
+            uaccess::read_cstr_array(&uspace, envp_ptr).is_ok_and(|e| e == envp),
+        ),
+        ("AT_PAGESZ", auxv.contains(&(AT_PAGESZ, PAGE_SIZE_4K))),
+        ("AT_NULL", auxv.last() == Some(&(AT_NULL, 0))),
+    ];
+    println!(
+        "initial stack demo: sp={:#x}, {} bytes up to {:#x}",
+        sp,
+        top - sp,
+        top
+    );
+    let mut failed = false;
+    for (name, ok) in checks {
+        println!(
+            "initial stack demo: {}: {}",
+            name,
+            if ok { "ok" } else { "FAILED" }
+        );
+        failed |= !ok;
+    }
+    failed as i32
+}
+
+/// Interrupts the preemption demo lets `/bin/tick` take in user mode
+/// before killing it.
+const PREEMPT_TICKS: usize = 10;
+
+/// How long the preemption demo waits for those interrupts.
+const PREEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs `/bin/tick`, which spins forever without a syscall, checking that
+/// its registers keep their values, until it has taken `PREEMPT_TICKS`
+/// interrupts in user mode, then kills it with `SIGKILL`.
+///
+/// The program never gives up the CPU itself, so on a single CPU this task
+/// only gets to count the interrupts if the timer preempts it, which needs
+/// the hypervisor to deliver timer interrupts to the guest. Every interrupt
+/// takes the program out of user mode and back, often through a switch to
+/// this task, and it exits 1 if a register came back changed. With
+/// `mask-user-irqs` it is never preempted, and the demo does not end on a
+/// single CPU.
+///
+/// Returns 0 if the program was killed after `PREEMPT_TICKS` interrupts,
+/// 1 otherwise.
+pub fn preempt() -> i32 {
+    let app = apps::find("/bin/tick").unwrap();
+    let (process, task) =
+        task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+            .expect("failed to spawn preemption demo process");
+    let deadline = monotonic_time() + PREEMPT_TIMEOUT;
+    while process.interrupts.load(Ordering::Relaxed) < PREEMPT_TICKS
+        && process.exit_status().is_none()
+        && monotonic_time() < deadline
+    {
+        axtask::sleep(Duration::from_millis(1));
+    }
+    let interrupts = process.interrupts.load(Ordering::Relaxed);
+    process.kill(SIGKILL);
+    task.join();
+    let ok =
+        interrupts >= PREEMPT_TICKS && process.exit_status() == Some(wait::killed_status(SIGKILL));
+    println!(
+        "preemption demo: {} interrupts in user mode, exit code {}: {}",
+        interrupts,
+        process.exit_status().map_or(-1, wait::exit_code),
+        if ok { "ok" } else { "FAILED" }
+    );
+    (!ok) as i32
+}
+
+/// Runs `/bin/sandbox`, whose syscall filter denies it `getppid` with
+/// `EPERM`, and `/bin/sandbox-kill`, the same image under a filter that
+/// kills it with `SIGSYS` for the same call.
+///
+/// Returns 0 if the first exited 0 and the second was killed, 1 otherwise.
+pub fn sandbox() -> i32 {
+    let mut failed = false;
+    for (path, expect) in [("/bin/sandbox", 0), ("/bin/sandbox-kill", 128 + SIGSYS)] {
+        let app = apps::find(path).unwrap();
+        let (_, task) =
+            task::spawn_user_process(app, &[path.to_string()], &[], None, None, None, None)
+                .expect("failed to spawn sandbox process");
+        let code = task.join();
+        let ok = code == expect;
+        println!(
+            "sandbox demo: {}: exit {}: {}",
+            path,
+            code,
+            if ok { "ok" } else { "FAILED" }
+        );
+        failed |= !ok;
+    }
+    failed as i32
+}
+
+/// Runs `/bin/fork`, which forks: the child changes a word on its stack and
+/// prints one line, the parent waits for it, checks that its own copy of
+/// the word is unchanged and prints another. Its output goes to the
+/// console through a tee into the capture buffer, so the kernel can check
+/// that both lines came out, the child's first.
+///
+/// Returns 0 if the parent exited 0 and printed after the child, 1
+/// otherwise.
+pub fn fork() -> i32 {
+    let sink = console::set_sink(&console::TEE);
+    let app = apps::find("/bin/fork").unwrap();
+    let (_, task) =
+        task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+            .expect("failed to spawn fork process");
+    let code = task.join();
+    console::set_sink(sink);
+    let mut capture = console::take_capture();
+    let ok = code == 0 && capture.bytes.make_contiguous() == b"fork: child\nfork: parent\n";
+    println!(
+        "fork demo: exit {}: {}",
+        code,
+        if ok { "ok" } else { "FAILED" }
+    );
+    !ok as i32
+}
+
+/// Boots `/sbin/init` as PID 1, as a real kernel starts userspace, and
+/// waits for it: init forks and execs its two services, `/bin/yield` and
+/// `/bin/orphan`, and reaps them, then the child `/bin/orphan` forked and
+/// left behind, which passes to init when its parent exits.
+///
+/// Returns init's exit code (0 if it reaped all three and each exited 0),
+/// or 1 if init did not get PID 1.
+pub fn init() -> i32 {
+    let app = apps::find("/sbin/init").unwrap();
+    let (process, task) =
+        task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+            .expect("failed to spawn init");
+    if process.pid != process::INIT_PID {
+        println!("init demo: init got PID {}", process.pid);
+        return 1;
+    }
+    let code = task.join();
+    println!(
+        "init demo: init exited with code {}: {}",
+        code,
+        if code == 0 { "ok" } else { "FAILED" }
+    );
+    code
+}
+
+/// The syscall `/bin/hook` makes, which only `HookHandler` answers.
+const HOOK_SYSCALL: usize = 0x2000;
+
+/// The syscall handler of the syscall handler demo: it intercepts `getpid`,
+/// answering 41, and adds `HOOK_SYSCALL`, which returns its argument plus
+/// one. Everything else goes to the built-in handlers.
+struct HookHandler;
+
+impl SyscallHandler for HookHandler {
+    fn handle(&self, uctx: &mut UserContext) -> Option<SyscallOutcome> {
+        let ret = match uctx.sysno() {
+            syscall_abi::GETPID => 41,
+            HOOK_SYSCALL => uctx.arg0() + 1,
+            _ => return None,
+        };
+        uctx.set_retval(ret);
+        Some(SyscallOutcome::Continue)
+    }
+}
+
+/// Runs `/bin/hook` with `HookHandler`, which answers both of its calls
+/// before the dispatcher, while its `exit` falls through to the built-in
+/// one: the exit code is 42 only if both went to the handler.
+///
+/// Returns 0 if it was 42, 1 otherwise.
+pub fn syscall_handler() -> i32 {
+    let app = apps::find("/bin/hook").unwrap();
+    let (_, task) = task::spawn_user_process(
+        app,
+        &[app.path.to_string()],
+        &[],
+        None,
+        None,
+        None,
+        Some(&HookHandler),
+    )
+    .expect("failed to spawn hook process");
+    let code = task.join();
+    let ok = code == 42;
+    println!(
+        "syscall handler demo: exit {}: {}",
+        code,
+        if ok { "ok" } else { "FAILED" }
+    );
+    !ok as i32
+}
+
+/// How often the priority demo checks which process has finished.
+const PRIORITY_POLL: Duration = Duration::from_millis(10);
+
+/// Runs two copies of `/bin/spin` on CPU 0, one at the lowest priority
+/// (nice 19) and one at the highest (nice -20), and checks that the
+/// high-priority one finishes first although it was spawned second. Both
+/// need the same CPU time, so under CFS the one with the larger share of
+/// the CPU gets done first.
+///
+/// Returns 0 if the high-priority process finished first, 1 otherwise.
+pub fn priority() -> i32 {
+    let app = apps::find("/bin/spin").unwrap();
+    let spawn = |nice| {
+        let (process, task) = task::spawn_user_process(
+            app,
+            &[app.path.to_string()],
+            &[],
+            None,
+            Some(0),
+            Some(nice),
+            None,
+        )
+        .expect("failed to spawn priority process");
+        println!("priority demo: process {} at nice {}", process.pid, nice);
+        (process, task)
+    };
+    let low = spawn(task::NICE_MAX);
+    let high = spawn(task::NICE_MIN);
+    let ok = loop {
+        if high.0.exit_status().is_some() {
+            break true;
+        }
+        if low.0.exit_status().is_some() {
+            break false;
+        }
+        axtask::sleep(PRIORITY_POLL);
+    };
+    let first = if ok { &high } else { &low };
+    println!(
+        "priority demo: process {} finished first: {}",
+        first.0.pid,
+        if ok { "ok" } else { "FAILED" }
+    );
+    let codes = [low.1.join(), high.1.join()];
+    (!ok || codes != [0, 0]) as i32
+}
+
+/// Runs `/bin/fault` once reading and once writing its unmapped address,
+/// and once jumping to its stack, and checks that each run is killed with
+/// `SIGSEGV` for a page fault of the right kind at the right address (for
+/// the jump, anywhere on the stack, which is mapped without `EXECUTE`),
+/// while the kernel carries on. Another run jumps into kernel code, which
+/// must be reported as an attempt to execute a kernel address, and one
+/// stores to kernel data, which every user page table maps (without
+/// `USER`) and which must not change for the kernel or any other process.
+/// One runs off the bottom of its stack, which must be reported as a stack
+/// overflow in the guard page below it. One runs an illegal instruction,
+/// which is no page fault and must kill it with `SIGILL` at that
+/// instruction, while another process yields alongside and must exit 0. A
+/// last run catches the `SIGSEGV` and must exit normally.
+///
+/// Returns 0 if all runs were reported as expected, 1 otherwise.
+pub fn fault() -> i32 {
+    let app = apps::find("/bin/fault").unwrap();
+    let fault_addr = apps::FAULT_ADDR..apps::FAULT_ADDR + 1;
+    let stack_top = USER_ASPACE_BASE + USER_ASPACE_SIZE;
+    let stack = stack_top - USER_STACK_SIZE..stack_top;
+    let mut failed = false;
+    for (access, flag, addrs) in [
+        ("read", PageFaultFlags::READ, fault_addr.clone()),
+        ("write", PageFaultFlags::WRITE, fault_addr),
+        ("exec", PageFaultFlags::EXECUTE, stack),
+    ] {
+        let (process, task) = task::spawn_user_process(
+            app,
+            &[app.path.to_string(), access.to_string()],
+            &[],
+            None,
+            None,
+            None,
+            None,
+        )
+        .expect("failed to spawn fault process");
+        let code = task.join();
+        let fatal = *process.fatal_fault.lock();
+        let ok = code == 128 + SIGSEGV
+            && fatal.is_some_and(|f| {
+                f.signal == SIGSEGV
+                    && f.addr.is_some_and(|a| addrs.contains(&a))
+                    && f.access.is_some_and(|a| a.contains(flag))
+            });
+        println!(
+            "fault demo: {} fault: exit {}, {:?}: {}",
+            access,
+            code,
+            fatal,
+            if ok { "ok" } else { "FAILED" }
+        );
+        failed |= !ok;
+    }
+
+    // A jump into kernel code faults too, and is reported as a violation of
+    // its own.
+    let (process, task) = task::spawn_user_process(
+        app,
+        &[app.path.to_string(), "kernel".to_string()],
+        &[],
+        None,
+        None,
+        None,
+        None,
+    )
+    .expect("failed to spawn fault process");
+    let code = task.join();
+    let fatal = *process.fatal_fault.lock();
+    let ok = code == 128 + SIGSEGV && fatal.is_some_and(|f| f.kernel_exec);
+    println!(
+        "fault demo: kernel jump: exit {}, {:?}: {}",
+        code,
+        fatal,
+        if ok { "ok" } else { "FAILED" }
+    );
+    failed |= !ok;
+
+    // A store to kernel data faults, so the word stays what the kernel, and
+    // every process through it, sees.
+    let (process, task) = task::spawn_user_process(
+        app,
+        &[app.path.to_string(), "data".to_string()],
+        &[],
+        None,
+        None,
+        None,
+        None,
+    )
+    .expect("failed to spawn fault process");
+    let code = task.join();
+    let fatal = *process.fatal_fault.lock();
+    let kernel_data = &apps::KERNEL_DATA as *const _ as usize;
+    let ok = code == 128 + SIGSEGV
+        && fatal.is_some_and(|f| {
+            f.addr == Some(kernel_data)
+                && f.access.is_some_and(|a| a.contains(PageFaultFlags::WRITE))
+        })
+        && apps::KERNEL_DATA.load(Ordering::Relaxed) == apps::KERNEL_DATA_MAGIC;
+    println!(
+        "fault demo: kernel data write: exit {}, {:?}: {}",
+        code,
+        fatal,
+        if ok { "ok" } else { "FAILED" }
+    );
+    failed |= !ok;
+
+    // Running off the bottom of the stack faults in its guard page, however
+    // much of the stack is mapped on demand.
+    let (process, task) = task::spawn_user_process(
+        app,
+        &[app.path.to_string(), "overflow".to_string()],
+        &[],
+        None,
+        None,
+        None,
+        None,
+    )
+    .expect("failed to spawn fault process");
+    let code = task.join();
+    let fatal = *process.fatal_fault.lock();
+    let guard = stack_top - USER_STACK_SIZE - STACK_GUARD_SIZE..stack_top - USER_STACK_SIZE;
+    let ok = code == 128 + SIGSEGV
+        && fatal.is_some_and(|f| f.stack_overflow && f.addr.is_some_and(|a| guard.contains(&a)));
+    println!(
+        "fault demo: stack overflow: exit {}, {:?}: {}",
+        code,
+        fatal,
+        if ok { "ok" } else { "FAILED" }
+    );
+    failed |= !ok;
+
+    // An exception other than a page fault ends only the process that
+    // raised it: a sibling running at the same time carries on to its exit.
+    let sibling_app = apps::find("/bin/yield").unwrap();
+    let (_, sibling) = task::spawn_user_process(sibling_app, &[], &[], None, None, None, None)
+        .expect("failed to spawn yield process");
+    let (process, task) = task::spawn_user_process(
+        app,
+        &[app.path.to_string(), "illegal".to_string()],
+        &[],
+        None,
+        None,
+        None,
+        None,
+    )
+    .expect("failed to spawn fault process");
+    let code = task.join();
+    let sibling_code = sibling.join();
+    let fatal = *process.fatal_fault.lock();
+    let ok = code == 128 + SIGILL
+        && sibling_code == 0
+        && fatal.is_some_and(|f| f.signal == SIGILL && f.access.is_none());
+    println!(
+        "fault demo: illegal instruction: exit {}, sibling exit {}, {:?}: {}",
+        code,
+        sibling_code,
+        fatal,
+        if ok { "ok" } else { "FAILED" }
+    );
+    failed |= !ok;
+
+    // With a SIGSEGV handler the same fault is survivable: the handler
+    // returns once (the load is retried and faults again), then exits 0.
+    let (process, task) = task::spawn_user_process(
+        app,
+        &[app.path.to_string(), "catch".to_string()],
+        &[],
+        None,
+        None,
+        None,
+        None,
+    )
+    .expect("failed to spawn fault process");
+    let code = task.join();
+    let fatal = *process.fatal_fault.lock();
+    let ok = code == 0 && fatal.is_none();
+    println!(
+        "fault demo: caught fault: exit {}, {:?}: {}",
+        code,
+        fatal,
+        if ok { "ok" } else { "FAILED" }
+    );
+    failed |= !ok;
+    failed as i32
+}
+
+/// Runs `/bin/sh` interactively on the console until it is told to exit.
+///
+/// The shell does not fork but runs a command by exec'ing it, so the kernel
+/// plays the parent: when the process ends while running some other app,
+/// it prints that app's exit status and starts a fresh shell; when the
+/// shell itself exits, so does the demo, with the shell's exit code.
+pub fn shell() -> i32 {
+    let sh = apps::find("/bin/sh").unwrap();
+    loop {
+        let (process, task) =
+            task::spawn_user_process(sh, &[sh.path.to_string()], &[], None, None, None, None)
+                .expect("failed to spawn shell");
+        let code = task.join();
+        let app = *process.app.lock();
+        if core::ptr::eq(app, sh) {
+            return code;
+        }
+        println!("[{} exited with status {}]", app.path, code);
+    }
+}
+
+/// Runs `/bin/conformance`, which checks the result of one call to each
+/// implemented syscall and prints a line per check, and reports how many
+/// failed. Returns its exit status: 0 when every check passed.
+pub fn conformance() -> i32 {
+    let app = apps::find("/bin/conformance").unwrap();
+    let (_process, task) =
+        task::spawn_user_process(app, &[app.path.to_string()], &[], None, None, None, None)
+            .expect("failed to spawn conformance test");
+    let code = task.join();
+    println!(
+        "conformance: {} failure(s): {}",
+        code,
+        if code == 0 { "ok" } else { "FAILED" }
+    );
+    code
+}
+
+/// Iterations of the leak check when `LEAK_CHECK_ITERATIONS` is not set
+/// at build time.
+const LEAK_CHECK_ITERATIONS: usize = 8;
+
+/// Runs the boot program's whole life cycle (address space, image, stack,
+/// process, task; join; teardown) again and again, printing the free page
+/// count after each run, and fails if the last run ends with fewer free
+/// pages than the first.
+///
+/// The first run is the baseline rather than the state before it, since
+/// it may allocate things that are kept for good (the first use of a
+/// lazily built table, say). The number of runs is `LEAK_CHECK_ITERATIONS`
+/// from the build environment, if set, else the constant of that name.
+/// Returns the boot program's first non-zero exit status, 1 on a leak, or
+/// 0.
+pub fn leak_check() -> i32 {
+    let iterations = option_env!("LEAK_CHECK_ITERATIONS")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(LEAK_CHECK_ITERATIONS)
+        .max(1);
+    let free_pages = || axalloc::global_allocator().available_pages();
+    let app = apps::init();
+    let argv = [app.path.to_string()];
+    let before = free_pages();
+    println!(
+        "leak check: {} runs of {}, {} pages free",
+        iterations, app.path, before
+    );
+
+    let mut code = 0;
+    let mut baseline = None;
+    for i in 1..=iterations {
+        let (process, task) = task::spawn_user_process(app, &argv, &[], None, None, None, None)
+            .expect("failed to spawn leak check process");
+        let status = task.join();
+        drop((process, task));
+        // Let axtask free the exited task (its kernel stack) first.
+        axtask::yield_now();
+        let free = free_pages();
+        let baseline = *baseline.get_or_insert(free);
+        println!(
+            "leak check: run {}: exit {}, {} pages free ({:+} since run 1, {:+} since start)",
+            i,
+            status,
+            free,
+            free as isize - baseline as isize,
+            free as isize - before as isize
+        );
+        if code == 0 {
+            code = status;
+        }
+    }
+
+    let leaked = baseline.unwrap() > free_pages();
+    println!(
+        "leak check: {}",
+        if leaked { "pages LEAKED" } else { "stable" }
+    );
+    if leaked && code == 0 { 1 } else { code }
+}
+
+/// Builds a process for the boot program without starting it, and drives
+/// the syscall dispatcher with a fixed script of register values, as the
+/// run loop would hand them over, checking the outcome and return value of
+/// each call: `exit(7)` ends the process with status 7, an unknown number
+/// gets `-ENOSYS`, `write` takes exactly its buffer from user memory and
+/// fails with `EFAULT` (writing nothing) if any of it is unmapped, in the
+/// stack guard, past the user address space or in the kernel, and `brk`
+/// grows the heap by whole pages up to `BRK_MAX`. The console output of
+/// the script is captured rather than printed, and compared too.
+///
+/// Where `syscall-fuzz` only asks that every call come back well-formed,
+/// this pins down what a few calls must return, so that a change to
+/// `handle_syscall` or the run loop around it that breaks them shows up
+/// without a user program. It runs in the guest, not on the host under
+/// `cargo test`: `UserContext` is axhal's, and the dispatcher reaches the
+/// address space, frames and tasks the kernel only has on its bare-metal
+/// targets.
+///
+/// Returns 0 if every check passed, 1 otherwise.
+pub fn dispatch_check() -> i32 {
+    const TEXT: &[u8] = b"dispatch\n";
+    let app = apps::init();
+    let (process, start) = task::build_user_process(app, &[app.path.to_string()], &[], None)
+        .expect("failed to build dispatch check process");
+    let call = |sysno: usize, args: [usize; 3]| {
+        let mut uctx = start;
+        uctx.set_sysno(sysno);
+        uctx.set_arg0(args[0]);
+        uctx.set_arg1(args[1]);
+        uctx.set_arg2(args[2]);
+        let outcome = syscall::handle_syscall(&process, &mut uctx);
+        (outcome, uctx.retval())
+    };
+    let ret = |sysno: usize, args: [usize; 3]| match call(sysno, args) {
+        (SyscallOutcome::Continue, ret) => Some(ret),
+        (SyscallOutcome::Exit(_), _) => None,
+    };
+    let err = |e: LinuxError| Some((e.code() as isize).wrapping_neg() as usize);
+    let write = |buf: usize, len: usize| ret(syscall_abi::WRITE, [1, buf, len]);
+
+    let (stack_base, top) = {
+        let uspace = process.aspace.lock();
+        (
+            loader::stack_guard(&uspace).end.as_usize(),
+            uspace.end().as_usize(),
+        )
+    };
+    let text = (start.sp() - 64) & !7;
+    uaccess::copy_to_user(&mut process.aspace.lock(), text, TEXT).unwrap();
+    let unmapped = USER_ASPACE_BASE + USER_ASPACE_SIZE / 2;
+    let kernel = dispatch_check as *const () as usize;
+
+    let sink = console::set_sink(&console::CaptureSink);
+    let writes = [
+        ("write", write(text, TEXT.len()) == Some(TEXT.len())),
+        ("write(len 0)", write(unmapped, 0) == Some(0)),
+        (
+            "write(unmapped buffer)",
+            write(unmapped, 8) == err(LinuxError::EFAULT),
+        ),
+        (
+            "write(kernel buffer)",
+            write(kernel, 8) == err(LinuxError::EFAULT),
+        ),
+        (
+            "write(into the stack guard)",
+            write(stack_base - 4, 8) == err(LinuxError::EFAULT),
+        ),
+        (
+            "write(past the address space)",
+            write(top - 4, 8) == err(LinuxError::EFAULT),
+        ),
+        (
+            "write(bad fd)",
+            ret(syscall_abi::WRITE, [99, text, TEXT.len()]) == err(LinuxError::EBADF),
+        ),
+    ];
+    console::set_sink(sink);
+    let mut capture = console::take_capture();
+    let output_ok = capture.dropped == 0 && capture.bytes.make_contiguous() == TEXT;
+
+    let brk_base = ret(syscall_abi::BRK, [0; 3]).unwrap_or(0);
+    let grown = brk_base + PAGE_SIZE_4K + 1;
+    let checks = writes.into_iter().chain([
+        ("failed writes output nothing", output_ok),
+        (
+            "unknown syscall",
+            ret(1 << 32, [0; 3]) == err(LinuxError::ENOSYS),
+        ),
+        (
+            "getpid",
+            ret(syscall_abi::GETPID, [0; 3]) == Some(process.pid),
+        ),
+        (
+            "brk(grow)",
+            ret(syscall_abi::BRK, [grown, 0, 0]) == Some(grown),
+        ),
+        (
+            "brk(grown pages mapped)",
+            uaccess::copy_to_user(&mut process.aspace.lock(), brk_base + PAGE_SIZE_4K, &[0xa5])
+                .is_ok(),
+        ),
+        (
+            "brk(past BRK_MAX)",
+            ret(syscall_abi::BRK, [brk_base + BRK_MAX + 1, 0, 0]) == Some(grown),
+        ),
+        (
+            "exit(7)",
+            matches!(
+                call(syscall_abi::EXIT, [7, 0, 0]),
+                (SyscallOutcome::Exit(7), _)
+            ),
+        ),
+    ]);
+
+    let mut failed = 0;
+    for (name, ok) in checks {
+        println!(
+            "dispatch check: {}: {}",
+            name,
+            if ok { "ok" } else { "FAILED" }
+        );
+        failed += !ok as usize;
+    }
+    println!(
+        "dispatch check: {} failure(s): {}",
+        failed,
+        if failed == 0 { "ok" } else { "FAILED" }
+    );
+    (failed != 0) as i32
+}
+
+/// Iterations of the syscall fuzzer when `SYSCALL_FUZZ_ITERATIONS` is not
+/// set at build time.
+const SYSCALL_FUZZ_ITERATIONS: usize = 4096;
+
+/// Seed of the syscall fuzzer when `SYSCALL_FUZZ_SEED` is not set at build
+/// time.
+const SYSCALL_FUZZ_SEED: u64 = 0x5eed_f022_5eed_f022;
+
+/// The syscalls the fuzzer calls: every one the dispatcher handles except
+/// those that wait (with a timeout from the registers, or for a signal
+/// that never comes), those that start a task (`clone`), those that act on
+/// the kernel task running the fuzzer rather than on the process (its
+/// priority, affinity and name), and System V shared memory, whose
+/// segments stay allocated for good.
+const FUZZ_SYSCALLS: &[usize] = &[
+    syscall_abi::GETCWD,
+    syscall_abi::EVENTFD2,
+    syscall_abi::EPOLL_CREATE1,
+    syscall_abi::EPOLL_CTL,
+    syscall_abi::DUP,
+    syscall_abi::DUP3,
+    syscall_abi::FCNTL,
+    syscall_abi::TRUNCATE,
+    syscall_abi::FTRUNCATE,
+    syscall_abi::CHDIR,
+    syscall_abi::OPENAT,
+    syscall_abi::CLOSE,
+    syscall_abi::GETDENTS64,
+    syscall_abi::LSEEK,
+    syscall_abi::READ,
+    syscall_abi::WRITE,
+    syscall_abi::PREAD64,
+    syscall_abi::PWRITE64,
+    syscall_abi::FSYNC,
+    syscall_abi::FDATASYNC,
+    syscall_abi::UTIMENSAT,
+    syscall_abi::EXIT,
+    syscall_abi::WAITID,
+    syscall_abi::GETITIMER,
+    syscall_abi::SETITIMER,
+    syscall_abi::CLOCK_GETTIME,
+    syscall_abi::CLOCK_GETRES,
+    syscall_abi::SCHED_GETAFFINITY,
+    syscall_abi::SCHED_YIELD,
+    syscall_abi::KILL,
+    syscall_abi::TKILL,
+    syscall_abi::SIGALTSTACK,
+    syscall_abi::RT_SIGACTION,
+    syscall_abi::RT_SIGRETURN,
+    syscall_abi::GETPRIORITY,
+    syscall_abi::TIMES,
+    syscall_abi::SETPGID,
+    syscall_abi::GETPGID,
+    syscall_abi::GETSID,
+    syscall_abi::SETSID,
+    syscall_abi::GETCPU,
+    syscall_abi::GETPID,
+    syscall_abi::GETPPID,
+    syscall_abi::GETTID,
+    syscall_abi::SYSINFO,
+    syscall_abi::SOCKET,
+    syscall_abi::CONNECT,
+    syscall_abi::BRK,
+    syscall_abi::MUNMAP,
+    syscall_abi::MREMAP,
+    syscall_abi::EXECVE,
+    syscall_abi::MMAP,
+    syscall_abi::MSYNC,
+    syscall_abi::MLOCK,
+    syscall_abi::MUNLOCK,
+    syscall_abi::MLOCKALL,
+    syscall_abi::MUNLOCKALL,
+    syscall_abi::WAIT4,
+    syscall_abi::MEMBARRIER,
+    syscall_abi::STATX,
+    syscall_abi::RSEQ,
+];
+
+/// A xorshift64 generator: the same seed gives the same run, so a failing
+/// seed can be replayed.
+struct FuzzRng(u64);
+
+impl FuzzRng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// A syscall argument from one of a few classes of values that handlers
+/// treat differently: zero, small numbers (descriptors, commands, signals),
+/// small negative ones (`-1`, `AT_FDCWD`, error values), a single flag bit,
+/// page-sized lengths, pointers into the image and the stack of `start`
+/// (the process's initial context), pointers to unmapped user memory and
+/// to kernel memory, values at the top of the range, and anything at all.
+fn fuzz_arg(rng: &mut FuzzRng, start: &UserContext) -> usize {
+    match rng.below(11) {
+        0 => 0,
+        1 => rng.below(16),
+        2 => (rng.below(128) as isize + 1).wrapping_neg() as usize,
+        3 => 1 << rng.below(usize::BITS as usize),
+        4 => (rng.below(4) + 1) * PAGE_SIZE_4K,
+        5 => start.ip() + rng.below(PAGE_SIZE_4K),
+        6 => (start.sp() - rng.below(PAGE_SIZE_4K)) & !7,
+        7 => USER_ASPACE_BASE + USER_ASPACE_SIZE / 2 + rng.below(PAGE_SIZE_4K),
+        8 => syscall_fuzz as *const () as usize + rng.below(PAGE_SIZE_4K),
+        9 => usize::MAX - rng.below(PAGE_SIZE_4K),
+        _ => rng.next() as usize,
+    }
+}
+
+/// Builds a process for the boot program without starting it, and drives
+/// the syscall dispatcher directly with chosen register values, never
+/// entering user mode: each call sets the syscall number and the six
+/// arguments in a copy of the process's initial context and calls
+/// `handle_syscall` on it.
+///
+/// The number is one of `FUZZ_SYSCALLS` most of the time and otherwise a
+/// number no syscall has; each argument is drawn by `fuzz_arg`. Every call
+/// must come back with a defined outcome and, if it continues, a return
+/// value that is either a result or `-errno` with a valid errno; a call of
+/// `exit`, or an `execve` that failed past the point of no return, ends the
+/// process, which is then dropped and replaced by a fresh one. Descriptors
+/// 0 to 2 are closed first, so that writes do not reach the console, and
+/// every open file is made `O_NONBLOCK` before each call, so that a read
+/// of an empty eventfd fails rather than waits.
+///
+/// A panic shows the handler that would bring the kernel down from user
+/// space (its number and arguments are those of the last `handle_syscall`
+/// line printed, without `quiet`). When the number of calls is done, the
+/// kernel mappings must still be intact in the last process, and every
+/// process the fuzzer built must be gone from the process table. The
+/// number of calls is `SYSCALL_FUZZ_ITERATIONS` and the seed
+/// `SYSCALL_FUZZ_SEED` from the build environment, if set, else the
+/// constants of those names. Returns 1 if any check failed, 0 otherwise.
+pub fn syscall_fuzz() -> i32 {
+    let iterations = option_env!("SYSCALL_FUZZ_ITERATIONS")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(SYSCALL_FUZZ_ITERATIONS);
+    // Decimal, like the other numbers taken from the build environment.
+    let seed = option_env!("SYSCALL_FUZZ_SEED")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(SYSCALL_FUZZ_SEED);
+    let app = apps::init();
+    let argv = [app.path.to_string()];
+    let spawn = || {
+        let (process, uctx) = task::build_user_process(app, &argv, &[], None)
+            .expect("failed to build syscall fuzz process");
+        let mut fd_table = process.fd_table.lock();
+        for fd in 0..3 {
+            fd_table.close(fd).unwrap();
+        }
+        drop(fd_table);
+        (process, uctx)
+    };
+    let processes = process::count();
+    let free_pages = || axalloc::global_allocator().available_pages();
+    let before = free_pages();
+    println!(
+        "syscall fuzz: {} calls on {}, seed {:#x}",
+        iterations, app.path, seed
+    );
+
+    // A zero state stays zero in xorshift.
+    let mut rng = FuzzRng(seed.max(1));
+    let (mut process, mut start) = spawn();
+    let (mut bad_returns, mut exits) = (0, 0);
+    for i in 0..iterations {
+        let sysno = if rng.below(16) == 0 {
+            // Far above every Linux and private number.
+            rng.next() as usize | 1 << 32
+        } else {
+            FUZZ_SYSCALLS[rng.below(FUZZ_SYSCALLS.len())]
+        };
+        let args: [usize; 6] = core::array::from_fn(|_| fuzz_arg(&mut rng, &start));
+        let mut uctx = start;
+        uctx.set_sysno(sysno);
+        uctx.set_arg0(args[0]);
+        uctx.set_arg1(args[1]);
+        uctx.set_arg2(args[2]);
+        uctx.set_arg3(args[3]);
+        uctx.set_arg4(args[4]);
+        uctx.set_arg5(args[5]);
+        process.fd_table.lock().set_nonblock_all();
+        match syscall::handle_syscall(&process, &mut uctx) {
+            // rt_sigreturn and a successful execve set the whole context,
+            // so only other calls have a return value to check.
+            SyscallOutcome::Continue
+                if sysno != syscall_abi::RT_SIGRETURN && sysno != syscall_abi::EXECVE =>
+            {
+                let ret = uctx.retval() as isize;
+                if ret < 0 && !(-4095..=-1).contains(&ret) {
+                    println!(
+                        "syscall fuzz: call {}: syscall {} {:#x?} returned {:#x}",
+                        i, sysno, args, ret
+                    );
+                    bad_returns += 1;
+                }
+            }
+            SyscallOutcome::Continue => {}
+            SyscallOutcome::Exit(_) => {
+                exits += 1;
+                (process, start) = spawn();
+            }
+        }
+    }
+
+    super::assert_kernel_mappings_copied(&axmm::kernel_aspace().lock(), &process.aspace.lock());
+    drop(process);
+    // Let axtask free anything the processes left to it first.
+    axtask::yield_now();
+    let left_over = process::count() as isize - processes as isize;
+    println!(
+        "syscall fuzz: {} calls, {} exits, {} bad return values, {} processes left over, \
+         {:+} pages free",
+        iterations,
+        exits,
+        bad_returns,
+        left_over,
+        free_pages() as isize - before as isize
+    );
+    let failed = bad_returns > 0 || left_over != 0;
+    println!("syscall fuzz: {}", if failed { "FAILED" } else { "ok" });
+    failed as i32
+}
@@ -0,0 +1,273 @@
+//! Readiness notification: `SYS_EPOLL_CREATE1`, `SYS_EPOLL_CTL` and
+//! `SYS_EPOLL_PWAIT` (and `SYS_EPOLL_WAIT` on x86_64).
+//!
+//! An epoll instance is a file holding a watch set: descriptors, each with
+//! the events it is watched for and a word of user data handed back with
+//! them. Waiting reports every watched file ready for one of its events,
+//! by the same `FileLike::poll` readiness as `ppoll`, every time it is
+//! asked while the file stays ready: level-triggered mode only, so
+//! `EPOLLET` (and `EPOLLONESHOT` and `EPOLLEXCLUSIVE`, which go with it)
+//! fail with `EINVAL`.
+//!
+//! A watch is on the open file behind the descriptor, not on the number:
+//! it goes away once that file is closed everywhere, and a number reused
+//! for another file is not watched until added again. An epoll instance
+//! cannot itself be watched (nesting is not supported), and regular files
+//! and directories cannot be either, as on Linux: they are always ready.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use std::os::arceos::modules::axhal::time::{Duration, monotonic_time};
+use std::sync::Mutex;
+
+use super::coop;
+use super::fd::{FileLike, FileStat, O_RDWR, PollState, S_IFDIR, S_IFMT, S_IFREG};
+use super::process::Process;
+use super::uaccess;
+
+// epoll_create1 flags (uapi/linux/eventpoll.h); `EPOLL_CLOEXEC` is
+// `O_CLOEXEC`.
+const EPOLL_CLOEXEC: usize = 0o2000000;
+
+// epoll_ctl operations.
+const EPOLL_CTL_ADD: usize = 1;
+const EPOLL_CTL_DEL: usize = 2;
+const EPOLL_CTL_MOD: usize = 3;
+
+// Event bits, the same as the poll ones.
+const EPOLLIN: u32 = 0x001;
+const EPOLLOUT: u32 = 0x004;
+const EPOLLERR: u32 = 0x008;
+const EPOLLHUP: u32 = 0x010;
+const EPOLLEXCLUSIVE: u32 = 1 << 28;
+const EPOLLONESHOT: u32 = 1 << 30;
+const EPOLLET: u32 = 1 << 31;
+
+/// Size of `struct epoll_event` (`u32` events, `u64` data), and the offset
+/// of its data: x86_64 packs it, for compatibility with 32-bit x86.
+#[cfg(target_arch = "x86_64")]
+const EVENT_SIZE: usize = 12;
+#[cfg(target_arch = "x86_64")]
+const DATA_OFFSET: usize = 4;
+#[cfg(not(target_arch = "x86_64"))]
+const EVENT_SIZE: usize = 16;
+#[cfg(not(target_arch = "x86_64"))]
+const DATA_OFFSET: usize = 8;
+
+/// Most events one wait may ask for (Linux's `EP_MAX_EVENTS`).
+const EP_MAX_EVENTS: usize = i32::MAX as usize / EVENT_SIZE;
+
+struct Watch {
+    file: Weak<dyn FileLike>,
+    events: u32,
+    data: u64,
+}
+
+impl Watch {
+    /// The events of `events` the file is ready for, with `EPOLLERR` and
+    /// `EPOLLHUP` always reported, or `None` once the file is closed.
+    fn ready(&self) -> Option<u32> {
+        let state = self.file.upgrade()?.poll();
+        let mut revents = 0;
+        if state.readable {
+            revents |= EPOLLIN;
+        }
+        if state.writable {
+            revents |= EPOLLOUT;
+        }
+        Some(revents & (self.events | EPOLLERR | EPOLLHUP))
+    }
+}
+
+/// An epoll instance.
+pub struct Epoll {
+    /// The watch set, by descriptor number.
+    watches: Mutex<BTreeMap<usize, Watch>>,
+}
+
+impl Epoll {
+    /// Collects up to `max` ready watches as (events, data), in descriptor
+    /// order, and drops the watches of files closed since.
+    fn collect(&self, max: usize) -> Vec<(u32, u64)> {
+        let mut ready = Vec::new();
+        self.watches.lock().retain(|_, watch| match watch.ready() {
+            Some(revents) => {
+                if revents != 0 && ready.len() < max {
+                    ready.push((revents, watch.data));
+                }
+                true
+            }
+            None => false,
+        });
+        ready
+    }
+}
+
+impl FileLike for Epoll {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    /// Readable while a wait would report an event, so an epoll instance
+    /// can be given to `ppoll`.
+    fn poll(&self) -> PollState {
+        PollState {
+            readable: !self.collect(1).is_empty(),
+            writable: false,
+        }
+    }
+
+    /// An anonymous inode: no file type bits, no inode number.
+    fn stat(&self) -> FileStat {
+        FileStat {
+            mode: 0o600,
+            ino: 0,
+            size: 0,
+        }
+    }
+
+    fn as_epoll(&self) -> Option<&Epoll> {
+        Some(self)
+    }
+}
+
+// ── SYS_EPOLL_CREATE1 ──
+
+/// Creates an epoll instance with an empty watch set.
+pub fn sys_epoll_create1(process: &Process, flags: usize) -> LinuxResult<usize> {
+    if flags & !EPOLL_CLOEXEC != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let epoll = Epoll {
+        watches: Mutex::new(BTreeMap::new()),
+    };
+    process
+        .fd_table
+        .lock()
+        .add(Arc::new(epoll), O_RDWR, flags & EPOLL_CLOEXEC != 0)
+}
+
+// ── SYS_EPOLL_CTL ──
+
+/// Adds `fd` to the watch set of `epfd` with the `struct epoll_event` at
+/// `event_ptr` (`EEXIST` if it is watched already), changes the events and
+/// data it is watched with, or removes it (`ENOENT` if it is not watched;
+/// `event_ptr` is ignored). `fd` must be open (`EBADF`), pollable (`EPERM`
+/// for regular files and directories) and neither `epfd` nor another epoll
+/// instance (`EINVAL`).
+pub fn sys_epoll_ctl(
+    process: &Process,
+    epfd: usize,
+    op: usize,
+    fd: usize,
+    event_ptr: usize,
+) -> LinuxResult<usize> {
+    let (epfile, file) = {
+        let fd_table = process.fd_table.lock();
+        (fd_table.get(epfd)?, fd_table.get(fd)?)
+    };
+    let epoll = epfile.as_epoll().ok_or(LinuxError::EINVAL)?;
+    if file.as_epoll().is_some() {
+        return Err(LinuxError::EINVAL);
+    }
+    if matches!(file.stat().mode & S_IFMT, S_IFREG | S_IFDIR) {
+        return Err(LinuxError::EPERM);
+    }
+    let event = match op {
+        EPOLL_CTL_ADD | EPOLL_CTL_MOD => {
+            let mut raw = [0u8; EVENT_SIZE];
+            uaccess::copy_from_user(&process.aspace.lock(), &mut raw, event_ptr)?;
+            let events = u32::from_ne_bytes(raw[..4].try_into().unwrap());
+            let data = u64::from_ne_bytes(raw[DATA_OFFSET..DATA_OFFSET + 8].try_into().unwrap());
+            if events & (EPOLLET | EPOLLONESHOT | EPOLLEXCLUSIVE) != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            Some((events, data))
+        }
+        EPOLL_CTL_DEL => None,
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let mut watches = epoll.watches.lock();
+    // A watch left on the number by a file since closed or replaced does
+    // not count as `fd` being watched.
+    let watched = watches
+        .get(&fd)
+        .is_some_and(|watch| watch.file.ptr_eq(&Arc::downgrade(&file)));
+    match (op, event) {
+        (EPOLL_CTL_ADD, Some((events, data))) => {
+            if watched {
+                return Err(LinuxError::EEXIST);
+            }
+            let file = Arc::downgrade(&file);
+            watches.insert(fd, Watch { file, events, data });
+        }
+        (EPOLL_CTL_MOD, Some((events, data))) => {
+            let watch = watches
+                .get_mut(&fd)
+                .filter(|_| watched)
+                .ok_or(LinuxError::ENOENT)?;
+            watch.events = events;
+            watch.data = data;
+        }
+        _ => {
+            if !watched {
+                return Err(LinuxError::ENOENT);
+            }
+            watches.remove(&fd);
+        }
+    }
+    Ok(0)
+}
+
+// ── SYS_EPOLL_PWAIT / SYS_EPOLL_WAIT ──
+
+/// Waits until a watched file of `epfd` is ready for one of its events or
+/// `timeout_ms` passes (negative: wait forever; 0: check once), yielding
+/// the CPU between checks, and stores up to `maxevents` of them as
+/// `struct epoll_event`s at `events_ptr`. Returns how many were stored.
+/// The whole array must be writable (`EFAULT`), whether or not it fills
+/// up. The signal mask of `epoll_pwait` is accepted but ignored, as for
+/// `ppoll`.
+pub fn sys_epoll_pwait(
+    process: &Process,
+    epfd: usize,
+    events_ptr: usize,
+    maxevents: usize,
+    timeout_ms: usize,
+) -> LinuxResult<usize> {
+    // maxevents is an int.
+    let maxevents = maxevents as i32;
+    if maxevents <= 0 || maxevents as usize > EP_MAX_EVENTS {
+        return Err(LinuxError::EINVAL);
+    }
+    let maxevents = maxevents as usize;
+    uaccess::check_writable(&process.aspace.lock(), events_ptr, maxevents * EVENT_SIZE)?;
+    let epfile = process.fd_table.lock().get(epfd)?;
+    let epoll = epfile.as_epoll().ok_or(LinuxError::EINVAL)?;
+
+    let timeout = (timeout_ms as i32 >= 0).then(|| Duration::from_millis(timeout_ms as i32 as u64));
+    let deadline = timeout.map(|t| monotonic_time() + t);
+    let ready = loop {
+        let ready = epoll.collect(maxevents);
+        if !ready.is_empty() || deadline.is_some_and(|d| monotonic_time() >= d) {
+            break ready;
+        }
+        coop::yield_now();
+    };
+
+    let mut uspace = process.aspace.lock();
+    for (i, &(events, data)) in ready.iter().enumerate() {
+        let mut raw = [0u8; EVENT_SIZE];
+        raw[..4].copy_from_slice(&events.to_ne_bytes());
+        raw[DATA_OFFSET..DATA_OFFSET + 8].copy_from_slice(&data.to_ne_bytes());
+        uaccess::copy_to_user(&mut uspace, events_ptr + i * EVENT_SIZE, &raw)?;
+    }
+    Ok(ready.len())
+}
@@ -0,0 +1,132 @@
+//! Why a user process could not be set up.
+//!
+//! Building a process (its address space, image and stack) fails with a
+//! `KernelError` that says where in user memory it went wrong, so that the
+//! cause can be printed as is: `main` does for the boot program, and shuts
+//! the guest down with a nonzero status instead of panicking. A syscall
+//! that sets up a process (`fork`, `execve`) gets the errno `From` maps it
+//! to.
+
+use axerrno::LinuxError;
+use core::fmt;
+use std::os::arceos::modules::axhal::mem::VirtAddr;
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KernelError {
+    /// No user address space of `size` bytes at `base` could be created,
+    /// for lack of a frame for its page table (`ENOMEM`).
+    AddrSpaceCreate { base: VirtAddr, size: usize },
+    /// No frames were left for the `size` bytes at `vaddr` (`ENOMEM`).
+    AllocFailed { vaddr: VirtAddr, size: usize },
+    /// The `size` bytes at `vaddr` were mapped, but could not be written
+    /// or protected (`EFAULT`).
+    MapFailed { vaddr: VirtAddr, size: usize },
+    /// The page table has no entry for `vaddr`, which was just mapped
+    /// (`EFAULT`).
+    QueryFailed { vaddr: VirtAddr },
+    /// The image is not something the loader can run (`ENOEXEC`).
+    BadElf { reason: &'static str },
+    /// The `size` bytes at `vaddr` are misaligned, outside the address
+    /// space or over an existing mapping (`EINVAL`).
+    SegmentOverlap { vaddr: VirtAddr, size: usize },
+    /// The entry point is not inside the image loaded (`ENOEXEC`).
+    EntryUnmapped { entry: VirtAddr },
+    /// The page table maps the code page at `vaddr` with `flags`, short of
+    /// the `expected` protection it was given (`EFAULT`).
+    CodeNotExecutable {
+        vaddr: VirtAddr,
+        flags: MappingFlags,
+        expected: MappingFlags,
+    },
+    /// The argument and environment strings and the pointers to them,
+    /// `size` bytes in all, do not fit in the user stack (`E2BIG`).
+    ArgsTooLong { size: usize },
+    /// A step with no address to tell failed with this errno, such as
+    /// creating the process past `MAX_PROCS` (`EAGAIN`).
+    Errno(LinuxError),
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::AddrSpaceCreate { base, size } => write!(
+                f,
+                "out of memory creating the {:#x}-byte address space at {:#x}",
+                size,
+                base.as_usize()
+            ),
+            Self::AllocFailed { vaddr, size } => write!(
+                f,
+                "out of memory mapping {:#x} bytes at {:#x}",
+                size,
+                vaddr.as_usize()
+            ),
+            Self::MapFailed { vaddr, size } => write!(
+                f,
+                "failed to fill the {:#x} bytes mapped at {:#x}",
+                size,
+                vaddr.as_usize()
+            ),
+            Self::QueryFailed { vaddr } => write!(
+                f,
+                "page {:#x} missing from the page table after mapping it",
+                vaddr.as_usize()
+            ),
+            Self::BadElf { reason } => write!(f, "bad image: {}", reason),
+            Self::SegmentOverlap { vaddr, size } => write!(
+                f,
+                "the {:#x} bytes at {:#x} do not fit the address space",
+                size,
+                vaddr.as_usize()
+            ),
+            Self::EntryUnmapped { entry } => {
+                write!(
+                    f,
+                    "entry point {:#x} is outside the image",
+                    entry.as_usize()
+                )
+            }
+            Self::CodeNotExecutable {
+                vaddr,
+                flags,
+                expected,
+            } => write!(
+                f,
+                "code page {:#x} mapped {:?} by the page table, not {:?}",
+                vaddr.as_usize(),
+                flags,
+                expected
+            ),
+            Self::ArgsTooLong { size } => write!(
+                f,
+                "{:#x} bytes of arguments and environment do not fit the stack",
+                size
+            ),
+            Self::Errno(err) => write!(f, "{:?}", err),
+        }
+    }
+}
+
+impl From<LinuxError> for KernelError {
+    fn from(err: LinuxError) -> Self {
+        Self::Errno(err)
+    }
+}
+
+impl From<KernelError> for LinuxError {
+    fn from(err: KernelError) -> Self {
+        match err {
+            KernelError::AddrSpaceCreate { .. } | KernelError::AllocFailed { .. } => {
+                LinuxError::ENOMEM
+            }
+            KernelError::MapFailed { .. }
+            | KernelError::QueryFailed { .. }
+            | KernelError::CodeNotExecutable { .. } => LinuxError::EFAULT,
+            KernelError::BadElf { .. } | KernelError::EntryUnmapped { .. } => LinuxError::ENOEXEC,
+            KernelError::SegmentOverlap { .. } => LinuxError::EINVAL,
+            KernelError::ArgsTooLong { .. } => LinuxError::E2BIG,
+            KernelError::Errno(err) => err,
+        }
+    }
+}
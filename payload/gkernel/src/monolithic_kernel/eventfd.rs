@@ -0,0 +1,140 @@
+//! Event counters as files: `SYS_EVENTFD2`.
+//!
+//! An eventfd holds a 64-bit counter. A `write` of an 8-byte value adds it
+//! to the counter; a `read` returns the counter and resets it to zero, or
+//! with `EFD_SEMAPHORE` returns 1 and decrements it. Reading a zero
+//! counter, or writing a value that would take it past `EVENTFD_MAX`,
+//! waits until another writer or reader has changed it (by yielding, like
+//! the other waits), or fails with `EAGAIN` under `EFD_NONBLOCK` (which is
+//! `O_NONBLOCK`, and can be changed with `fcntl`).
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use std::sync::Mutex;
+
+use super::coop;
+use super::fd::{FileLike, FileStat, O_RDWR, PollState};
+use super::process::Process;
+
+// eventfd2 flags (uapi/linux/eventfd.h; the same on every architecture).
+const EFD_SEMAPHORE: usize = 1;
+const EFD_NONBLOCK: usize = 0o4000;
+const EFD_CLOEXEC: usize = 0o2000000;
+
+/// Largest value the counter can hold.
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+struct EventFd {
+    count: Mutex<u64>,
+    semaphore: bool,
+}
+
+impl EventFd {
+    /// Runs `op` on the counter until it returns a result, yielding in
+    /// between while it returns `None` (or failing with `EAGAIN` instead,
+    /// if `nonblock`).
+    fn wait_for<T>(
+        &self,
+        nonblock: bool,
+        mut op: impl FnMut(&mut u64) -> Option<T>,
+    ) -> LinuxResult<T> {
+        loop {
+            if let Some(value) = op(&mut self.count.lock()) {
+                return Ok(value);
+            }
+            if nonblock {
+                return Err(LinuxError::EAGAIN);
+            }
+            coop::yield_now();
+        }
+    }
+
+    fn read_counter(&self, buf: &mut [u8], nonblock: bool) -> LinuxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LinuxError::EINVAL);
+        }
+        let value = self.wait_for(nonblock, |count| match *count {
+            0 => None,
+            _ if self.semaphore => {
+                *count -= 1;
+                Some(1)
+            }
+            n => {
+                *count = 0;
+                Some(n)
+            }
+        })?;
+        buf[..8].copy_from_slice(&value.to_ne_bytes());
+        Ok(8)
+    }
+
+    fn write_counter(&self, buf: &[u8], nonblock: bool) -> LinuxResult<usize> {
+        let value = buf
+            .get(..8)
+            .map(|b| u64::from_ne_bytes(b.try_into().unwrap()))
+            .ok_or(LinuxError::EINVAL)?;
+        if value == u64::MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        self.wait_for(nonblock, |count| {
+            (EVENTFD_MAX - *count >= value).then(|| {
+                *count += value;
+            })
+        })?;
+        Ok(8)
+    }
+}
+
+impl FileLike for EventFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.read_counter(buf, false)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.write_counter(buf, false)
+    }
+
+    fn read_nonblock(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.read_counter(buf, true)
+    }
+
+    fn write_nonblock(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.write_counter(buf, true)
+    }
+
+    fn poll(&self) -> PollState {
+        let count = *self.count.lock();
+        PollState {
+            readable: count > 0,
+            writable: count < EVENTFD_MAX,
+        }
+    }
+
+    /// An anonymous inode: no file type bits, no inode number.
+    fn stat(&self) -> FileStat {
+        FileStat {
+            mode: 0o600,
+            ino: 0,
+            size: 0,
+        }
+    }
+}
+
+// ── SYS_EVENTFD2 ──
+
+/// Creates an eventfd with the counter at `initval`, which is an
+/// `unsigned int`: the upper half of the register is ignored.
+pub fn sys_eventfd2(process: &Process, initval: usize, flags: usize) -> LinuxResult<usize> {
+    if flags & !(EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let file = EventFd {
+        count: Mutex::new(initval as u32 as u64),
+        semaphore: flags & EFD_SEMAPHORE != 0,
+    };
+    process.fd_table.lock().add(
+        Arc::new(file),
+        O_RDWR | flags & EFD_NONBLOCK,
+        flags & EFD_CLOEXEC != 0,
+    )
+}
@@ -0,0 +1,89 @@
+//! `execve`: replacing the running program image in place.
+//!
+//! ## Control flow
+//!
+//! The syscall is handled on the same kernel task that runs the process,
+//! in the middle of the run loop, with the caller's `UserContext` in hand.
+//! A successful `execve` must not return to the caller's code, so instead
+//! of setting a return value it overwrites that context with a fresh one
+//! for the new program (new entry point, new stack, zeroed registers). When
+//! the run loop goes around and calls `UserContext::run()` again, user mode
+//! resumes at the new entry point; the old program is simply gone.
+//!
+//! The address space object, and so its page table root, is kept: only the
+//! user areas are torn down, while the copied kernel mappings (which are not
+//! areas) stay in place. The task's page table therefore needs no update.
+//!
+//! Everything that can fail because of the caller's arguments (bad
+//! pointers, unknown path, oversized argument lists) is checked before the
+//! old image is torn down, so those errors are returned normally and leave
+//! the caller intact. A failure after that point (running out of memory
+//! while building the new image) leaves nothing to return to, and the
+//! process is killed.
+
+use axerrno::{LinuxError, LinuxResult};
+use log::debug;
+use std::os::arceos::modules::axhal::uspace::UserContext;
+
+use super::mm::Heap;
+use super::process::Process;
+use super::signal::{self, SIGKILL};
+use super::syscall::SyscallOutcome;
+use super::{apps, aspace, loader, shm, syscall_filter, task, uaccess};
+
+/// Exit status of a process killed because exec failed past the point of
+/// no return (128 + SIGKILL, as a shell would report it).
+const EXEC_FAILED_STATUS: i32 = 128 + SIGKILL;
+
+/// Replaces the program running in `process` with the embedded app named
+/// by the caller's `execve(path, argv, envp)` arguments. On success `uctx`
+/// is reset to the new program's initial state and nothing must be written
+/// to its return register; the outcome says whether the process goes on
+/// running (the new program) or was killed.
+pub fn sys_execve(process: &Process, uctx: &mut UserContext) -> LinuxResult<SyscallOutcome> {
+    let mut uspace = process.aspace.lock();
+    let path = uaccess::read_cstr(&uspace, uctx.arg0())?;
+    let argv = uaccess::read_cstr_array(&uspace, uctx.arg1())?;
+    let envp = uaccess::read_cstr_array(&uspace, uctx.arg2())?;
+    let app = apps::find(&path).ok_or(LinuxError::ENOENT)?;
+    debug!("[SYS_EXECVE]: {} {:?}", path, argv);
+
+    // Point of no return: drop the old image.
+    shm::detach_all(process, &mut uspace);
+    aspace::scrub_before_unmap(&uspace);
+    uspace.clear();
+    aspace::untag_areas(&uspace);
+    let image = match loader::load_user_app(&mut uspace, app) {
+        Ok(image) => image,
+        Err(e) => {
+            println!("execve {}: failed to load image: {}", path, e);
+            return Ok(SyscallOutcome::Exit(EXEC_FAILED_STATUS));
+        }
+    };
+    loader::debug_print_image(app, &image);
+    let (entry, sp) = match loader::init_user_stack(&mut uspace, &argv, &envp) {
+        Ok(sp) => (image.entry, sp),
+        Err(e) => {
+            println!("execve {}: failed to build new stack: {}", path, e);
+            return Ok(SyscallOutcome::Exit(EXEC_FAILED_STATUS));
+        }
+    };
+    loader::debug_dump_stack(&uspace, sp);
+
+    loader::debug_assert_sp_in_stack(&uspace, sp);
+    process.fd_table.lock().close_on_exec();
+    signal::reset_on_exec(process);
+    *process.rseq.lock() = None;
+    process.set_environ(&envp);
+    *process.heap.lock() = Heap::new(image.brk_start());
+    *process.app.lock() = app;
+    if let Some(filter) = app.syscall_filter {
+        syscall_filter::add(process, filter);
+    }
+    if cfg!(feature = "verbose") {
+        process.dump_environ();
+    }
+
+    *uctx = task::initial_context(entry.as_usize(), sp);
+    Ok(SyscallOutcome::Continue)
+}
@@ -0,0 +1,371 @@
+//! Crash reports for fatal user faults, and the signal each one kills the
+//! process with.
+//!
+//! Arithmetic exceptions are `SIGFPE`. Neither riscv64 nor aarch64 traps
+//! on an integer division by zero (the quotient is defined: all ones on
+//! riscv64, zero on aarch64), and riscv64 never traps on floating point
+//! either, so the only arithmetic exception there is is aarch64's trapped
+//! floating-point exception, for a program that enabled FPCR traps on a
+//! CPU that implements them (QEMU does not). On x86_64 (with `x86-uspace`)
+//! it is the divide error (`#DE`), which an integer division by zero
+//! raises.
+//!
+//! A jump into the kernel's address range is a violation of its own: the
+//! kernel mappings copied into every user page table are not `USER`, so
+//! fetching from them faults, and the fault is reported as an attempt to
+//! execute a kernel address. It is always fatal, even to a process with a
+//! `SIGSEGV` handler.
+//!
+//! A page fault in the guard page below the user stack (see
+//! `loader::stack_guard`) is reported as a stack overflow: the program ran
+//! off the bottom of its stack. It kills the process with `SIGSEGV` like
+//! any other bad access, as on Linux, unless a handler on an alternate
+//! signal stack catches it.
+
+use alloc::format;
+use alloc::string::String;
+use std::os::arceos::modules::axhal::trap::PageFaultFlags;
+use std::os::arceos::modules::axhal::uspace::{ExceptionKind, ReturnReason, UserContext};
+
+use super::loader;
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+use super::signal::SIGFPE;
+use super::signal::{SIGBUS, SIGILL, SIGSEGV, SIGTRAP};
+#[cfg(feature = "fault-symbols")]
+use super::{APP_ENTRY, apps::App, uaccess};
+use axmm::AddrSpace;
+
+/// A user fault that killed its process.
+#[derive(Clone, Copy, Debug)]
+pub struct FatalFault {
+    /// Signal the process was killed with.
+    pub signal: i32,
+    /// Faulting address, where the architecture reports one.
+    pub addr: Option<usize>,
+    /// Kind of access, for page faults.
+    pub access: Option<PageFaultFlags>,
+    /// The process tried to execute code at a kernel address (see
+    /// `kernel_exec_target`).
+    pub kernel_exec: bool,
+    /// The fault was in the stack guard page: a stack overflow.
+    pub stack_overflow: bool,
+}
+
+impl FatalFault {
+    /// Classifies a return from user space that the run loop cannot
+    /// handle, with `pc` the user program counter at the fault and
+    /// `uspace` the address space it happened in.
+    pub fn new(reason: &ReturnReason, pc: usize, uspace: &AddrSpace) -> Self {
+        Self {
+            kernel_exec: kernel_exec_target(reason, pc).is_some(),
+            stack_overflow: matches!(reason, ReturnReason::PageFault(vaddr, _)
+                if loader::stack_guard(uspace).contains(*vaddr)),
+            ..Self::classify(reason)
+        }
+    }
+
+    fn classify(reason: &ReturnReason) -> Self {
+        match reason {
+            ReturnReason::PageFault(vaddr, flags) => Self {
+                signal: SIGSEGV,
+                addr: Some(vaddr.as_usize()),
+                access: Some(*flags),
+                kernel_exec: false,
+                stack_overflow: false,
+            },
+            #[cfg(target_arch = "aarch64")]
+            ReturnReason::Exception(info) if arithmetic_exception(info.esr.get()).is_some() => {
+                Self {
+                    signal: SIGFPE,
+                    addr: None,
+                    access: None,
+                    kernel_exec: false,
+                    stack_overflow: false,
+                }
+            }
+            #[cfg(target_arch = "x86_64")]
+            ReturnReason::Exception(info) if info.vector == VECTOR_DE => Self {
+                signal: SIGFPE,
+                addr: None,
+                access: None,
+                kernel_exec: false,
+                stack_overflow: false,
+            },
+            ReturnReason::Exception(info) => Self {
+                signal: match info.kind() {
+                    ExceptionKind::Breakpoint => SIGTRAP,
+                    ExceptionKind::IllegalInstruction => SIGILL,
+                    ExceptionKind::Misaligned => SIGBUS,
+                    ExceptionKind::Other => SIGSEGV,
+                },
+                #[cfg(target_arch = "riscv64")]
+                addr: Some(info.stval),
+                #[cfg(target_arch = "aarch64")]
+                addr: Some(info.far),
+                // CR2 only means something for a page fault.
+                #[cfg(target_arch = "x86_64")]
+                addr: None,
+                access: None,
+                kernel_exec: false,
+                stack_overflow: false,
+            },
+            _ => Self {
+                signal: SIGSEGV,
+                addr: None,
+                access: None,
+                kernel_exec: false,
+                stack_overflow: false,
+            },
+        }
+    }
+}
+
+/// The kernel address a fatal return from user space tried to execute, if
+/// it was such an attempt: a fetch fault at an address in the kernel's
+/// address space, or any fault with the program counter `pc` already
+/// there (a jump the architecture reports some other way).
+pub fn kernel_exec_target(reason: &ReturnReason, pc: usize) -> Option<usize> {
+    let kspace = axmm::kernel_aspace().lock();
+    let kernel = kspace.base().as_usize()..kspace.end().as_usize();
+    match reason {
+        ReturnReason::PageFault(vaddr, flags)
+            if flags.contains(PageFaultFlags::EXECUTE) && kernel.contains(&vaddr.as_usize()) =>
+        {
+            Some(vaddr.as_usize())
+        }
+        _ => kernel.contains(&pc).then_some(pc),
+    }
+}
+
+/// Exception class (`ESR_EL1.EC`) of a trapped floating-point exception
+/// taken from AArch64 state.
+#[cfg(target_arch = "aarch64")]
+const EC_FP_EXC64: u64 = 0x2c;
+/// `ISS` bit of such an exception for a division by zero (`DZF`).
+#[cfg(target_arch = "aarch64")]
+const ISS_FP_DZF: u64 = 1 << 1;
+
+/// Tells whether the exception syndrome `esr` is an arithmetic exception,
+/// and if so whether it was a division by zero.
+#[cfg(target_arch = "aarch64")]
+fn arithmetic_exception(esr: u64) -> Option<bool> {
+    ((esr >> 26) & 0x3f == EC_FP_EXC64).then_some(esr & ISS_FP_DZF != 0)
+}
+
+/// The divide error exception (`#DE`), raised by an integer division by
+/// zero or one that overflows.
+#[cfg(target_arch = "x86_64")]
+const VECTOR_DE: u8 = 0;
+
+#[cfg(target_arch = "riscv64")]
+const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Values of the general registers, in `REG_NAMES` order.
+#[cfg(target_arch = "riscv64")]
+pub fn general_regs(uctx: &UserContext) -> [usize; 32] {
+    let r = &uctx.regs;
+    [
+        r.zero, r.ra, r.sp, r.gp, r.tp, r.t0, r.t1, r.t2, r.s0, r.s1, r.a0, r.a1, r.a2, r.a3, r.a4,
+        r.a5, r.a6, r.a7, r.s2, r.s3, r.s4, r.s5, r.s6, r.s7, r.s8, r.s9, r.s10, r.s11, r.t3, r.t4,
+        r.t5, r.t6,
+    ]
+}
+
+#[cfg(target_arch = "aarch64")]
+const REG_NAMES: [&str; 31] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13", "x14",
+    "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27",
+    "x28", "fp", "lr",
+];
+
+/// Values of the general registers, in `REG_NAMES` order.
+#[cfg(target_arch = "aarch64")]
+pub fn general_regs(uctx: &UserContext) -> [usize; 31] {
+    uctx.x.map(|x| x as usize)
+}
+
+#[cfg(target_arch = "x86_64")]
+const REG_NAMES: [&str; 15] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "r8", "r9", "r10", "r11", "r12", "r13", "r14",
+    "r15",
+];
+
+/// Values of the general registers, in `REG_NAMES` order.
+#[cfg(target_arch = "x86_64")]
+pub fn general_regs(uctx: &UserContext) -> [usize; 15] {
+    [
+        uctx.rax, uctx.rbx, uctx.rcx, uctx.rdx, uctx.rsi, uctx.rdi, uctx.rbp, uctx.r8, uctx.r9,
+        uctx.r10, uctx.r11, uctx.r12, uctx.r13, uctx.r14, uctx.r15,
+    ]
+    .map(|r| r as usize)
+}
+
+/// Prints the cause of a fatal return from user space: the raw fault
+/// cause register, and for an exception the faulting instruction `pc` and
+/// address where the architecture has them.
+pub fn dump_fault_cause(reason: &ReturnReason, pc: usize) {
+    if let Some(addr) = kernel_exec_target(reason, pc) {
+        println!(
+            "  violation: user attempted to execute kernel address {:#x}",
+            addr
+        );
+    }
+    match reason {
+        ReturnReason::PageFault(vaddr, flags) => {
+            println!("  cause: page fault at {:#x} ({:?})", vaddr, flags);
+        }
+        #[cfg(target_arch = "riscv64")]
+        ReturnReason::Exception(info) => {
+            println!(
+                "  cause: {:?} ({:?}) at {:#x}, stval={:#x}",
+                info.e,
+                info.kind(),
+                pc,
+                info.stval
+            );
+        }
+        #[cfg(target_arch = "aarch64")]
+        ReturnReason::Exception(info) if let Some(div) = arithmetic_exception(info.esr.get()) => {
+            println!(
+                "  cause: arithmetic exception ({}) at {:#x}, esr={:#x}",
+                if div {
+                    "divide by zero"
+                } else {
+                    "floating point"
+                },
+                pc,
+                info.esr.get()
+            );
+        }
+        #[cfg(target_arch = "aarch64")]
+        ReturnReason::Exception(info) => {
+            println!(
+                "  cause: esr={:#x} ({:?}) at {:#x}, far={:#x}",
+                info.esr.get(),
+                info.kind(),
+                pc,
+                info.far
+            );
+        }
+        #[cfg(target_arch = "x86_64")]
+        ReturnReason::Exception(info) => {
+            println!(
+                "  cause: vector {} ({:?}) at {:#x}, error code {:#x}",
+                info.vector,
+                info.kind(),
+                pc,
+                info.error_code
+            );
+        }
+        other => println!("  cause: {:?}", other),
+    }
+}
+
+/// Describes `addr` as the nearest preceding label of `app` and an offset,
+/// if it is inside the app.
+#[cfg(feature = "fault-symbols")]
+fn symbolize(app: &App, addr: usize) -> String {
+    match addr
+        .checked_sub(APP_ENTRY)
+        .and_then(|off| app.symbol_at(off))
+    {
+        Some((name, off)) => format!("{:#x} ({}+{:#x})", addr, name, off),
+        None => format!("{:#x} (outside {})", addr, app.path),
+    }
+}
+
+/// Prints where in `app` the faulting `pc` is, as the nearest preceding
+/// label and an offset (`fault-symbols` feature).
+#[cfg(feature = "fault-symbols")]
+pub fn dump_fault_symbol(app: &App, pc: usize) {
+    println!("  fault at {}", symbolize(app, pc));
+}
+
+/// Most frames `dump_user_backtrace` prints.
+#[cfg(feature = "fault-symbols")]
+const BACKTRACE_DEPTH: usize = 16;
+
+/// The frame record the frame pointer `fp` points at, as the caller's
+/// frame pointer and the return address: at `fp` on aarch64 (AAPCS64) and
+/// x86_64 (`rbp`), just below it on riscv64 (`fp` is the frame's top, with
+/// `ra` saved at `fp - 8` and the caller's `fp` at `fp - 16`). `None` if
+/// the record is not in readable user memory.
+#[cfg(feature = "fault-symbols")]
+fn frame_record(uspace: &AddrSpace, fp: usize) -> Option<(usize, usize)> {
+    let record = if cfg!(target_arch = "riscv64") {
+        fp.checked_sub(16)?
+    } else {
+        fp
+    };
+    let mut raw = [0u8; 16];
+    uaccess::copy_from_user(uspace, &mut raw, record).ok()?;
+    let first = usize::from_ne_bytes(raw[..8].try_into().unwrap());
+    let second = usize::from_ne_bytes(raw[8..].try_into().unwrap());
+    // Every architecture keeps the caller's frame pointer first.
+    Some((first, second))
+}
+
+/// Prints a backtrace of the user program stopped at `uctx`: the faulting
+/// pc, then the return address of each frame found by following the chain
+/// of saved frame pointers through `uspace`, each resolved to a label of
+/// `app` (`fault-symbols` feature).
+///
+/// This needs a program built with frame pointers (`-fno-omit-frame-pointer`;
+/// the embedded assembly apps keep none). The walk stops at a null frame
+/// pointer or return address, at a frame pointer that is misaligned, whose
+/// record is not mapped, or that does not lie above the one before it (the
+/// stack grows down, so a chain going the other way is garbage or a
+/// cycle), and after `BACKTRACE_DEPTH` frames.
+#[cfg(feature = "fault-symbols")]
+pub fn dump_user_backtrace(uspace: &AddrSpace, app: &App, uctx: &UserContext) {
+    println!("User backtrace:");
+    println!("  #0 {}", symbolize(app, uctx.ip()));
+    #[cfg(target_arch = "riscv64")]
+    let mut fp = uctx.regs.s0;
+    #[cfg(target_arch = "aarch64")]
+    let mut fp = uctx.x[29] as usize;
+    #[cfg(target_arch = "x86_64")]
+    let mut fp = uctx.rbp as usize;
+    let mut prev = 0;
+    for depth in 1..BACKTRACE_DEPTH {
+        if fp == 0 || !fp.is_multiple_of(8) || fp <= prev {
+            return;
+        }
+        let Some((next, ret)) = frame_record(uspace, fp) else {
+            println!("  (frame pointer {:#x} not in user memory)", fp);
+            return;
+        };
+        if ret == 0 {
+            return;
+        }
+        println!("  #{} {}", depth, symbolize(app, ret));
+        prev = fp;
+        fp = next;
+    }
+    println!("  (stopped after {} frames)", BACKTRACE_DEPTH);
+}
+
+/// Prints the user register state as a table, four registers per row,
+/// followed by the program counter and stack pointer.
+pub fn dump_user_regs(uctx: &UserContext) {
+    println!("User registers:");
+    let regs = general_regs(uctx);
+    for (row_names, row_regs) in REG_NAMES.chunks(4).zip(regs.chunks(4)) {
+        let mut line = String::new();
+        for (name, val) in row_names.iter().zip(row_regs) {
+            line += &format!("  {:>4}: {:#018x}", name, val);
+        }
+        println!("{}", line);
+    }
+    println!("    pc: {:#018x}    sp: {:#018x}", uctx.ip(), uctx.sp());
+    #[cfg(target_arch = "riscv64")]
+    println!("  sstatus: {:#018x}", uctx.sstatus.bits());
+    #[cfg(target_arch = "aarch64")]
+    println!("  spsr: {:#018x}", uctx.spsr);
+    #[cfg(target_arch = "x86_64")]
+    println!("  rflags: {:#018x}", uctx.rflags);
+}
@@ -0,0 +1,389 @@
+//! Per-process file descriptor table.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::io::SeekFrom;
+
+use super::console::Console;
+use super::epoll::Epoll;
+
+/// Upper bound on the number of open descriptors per process (`EMFILE`).
+const MAX_FDS: usize = 1024;
+
+// fcntl commands and flags (uapi/asm-generic/fcntl.h).
+const F_DUPFD: usize = 0;
+const F_GETFD: usize = 1;
+const F_SETFD: usize = 2;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
+const F_DUPFD_CLOEXEC: usize = 1030;
+const FD_CLOEXEC: usize = 1;
+
+// Open flags (uapi/asm-generic/fcntl.h). `O_CLOEXEC` is also the only
+// flag `dup3` accepts.
+pub const O_RDWR: usize = 0o2;
+const O_ACCMODE: usize = 0o3;
+const O_APPEND: usize = 0o2000;
+pub const O_NONBLOCK: usize = 0o4000;
+const O_CLOEXEC: usize = 0o2000000;
+
+/// The open flags kept as file status flags, which `F_GETFL` reports.
+const STATUS_FLAGS: usize = O_ACCMODE | O_APPEND | O_NONBLOCK;
+
+/// The status flags `F_SETFL` changes; it leaves the others as they are.
+/// `O_APPEND` is fixed when a file is opened here.
+const SETFL_FLAGS: usize = O_NONBLOCK;
+
+// lseek whence values (uapi/linux/fs.h).
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const SEEK_END: usize = 2;
+
+// File type bits of `st_mode` (uapi/linux/stat.h).
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+
+/// What a file reports of itself to `statx`.
+pub struct FileStat {
+    /// File type and permission bits, as in `st_mode`.
+    pub mode: u32,
+    /// Inode number; 0 for files outside the in-memory filesystem.
+    pub ino: u64,
+    pub size: u64,
+}
+
+/// Readiness of a file for I/O, as reported to `poll`.
+pub struct PollState {
+    /// A read would not block.
+    pub readable: bool,
+    /// A write would not block.
+    pub writable: bool,
+}
+
+/// An object that can be installed in the fd table.
+pub trait FileLike: Send + Sync {
+    /// Reads into `buf`, returning how many bytes were read (0 at end of
+    /// file).
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize>;
+
+    /// Writes `buf`, returning how many bytes were written.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize>;
+
+    /// Reads like `read`, but fails with `EAGAIN` where that would block
+    /// (`O_NONBLOCK`). The default goes by `poll`, which is exact while
+    /// nobody else reads the file in between.
+    fn read_nonblock(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if self.poll().readable {
+            self.read(buf)
+        } else {
+            Err(LinuxError::EAGAIN)
+        }
+    }
+
+    /// Writes like `write`, but fails with `EAGAIN` where that would block
+    /// (by default going by `poll`, as for `read_nonblock`).
+    fn write_nonblock(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if self.poll().writable {
+            self.write(buf)
+        } else {
+            Err(LinuxError::EAGAIN)
+        }
+    }
+
+    /// Runs `write`, which makes all the `write` calls of one `write`
+    /// syscall (the user buffer goes in chunks), and returns its result.
+    /// A file whose writers must not interleave within a syscall, like the
+    /// console, keeps out other writers meanwhile; the default runs it as
+    /// it is.
+    fn write_whole(&self, write: &mut dyn FnMut() -> LinuxResult<usize>) -> LinuxResult<usize> {
+        write()
+    }
+
+    /// Reports whether the file is ready for reading and writing.
+    fn poll(&self) -> PollState;
+
+    /// Reports the file's type, inode number and size.
+    fn stat(&self) -> FileStat;
+
+    /// Reads at `offset` without moving the file offset. Streams such as
+    /// the console have no offset and keep the default, `ESPIPE`.
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Writes at `offset` without moving the file offset (default
+    /// `ESPIPE`, as for `read_at`).
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Moves the file offset, returning the new one. Streams such as the
+    /// console have no offset and keep the default, `ESPIPE`.
+    fn seek(&self, _pos: SeekFrom) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Sets the file's size to `len`, zero-filling on growth. Anything but
+    /// a regular file open for writing keeps the default, `EINVAL`.
+    fn truncate(&self, _len: u64) -> LinuxResult {
+        Err(LinuxError::EINVAL)
+    }
+
+    /// Fills `buf` with `linux_dirent64` records for the next entries of
+    /// a directory, returning how many bytes were used (0 at the end).
+    /// Anything but a directory keeps the default, `ENOTDIR`.
+    fn read_dir(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ENOTDIR)
+    }
+
+    /// The epoll instance this file is, if it is one (see `epoll`); every
+    /// other file keeps the default, `None`.
+    fn as_epoll(&self) -> Option<&Epoll> {
+        None
+    }
+}
+
+#[derive(Clone)]
+struct FdEntry {
+    file: Arc<dyn FileLike>,
+    /// Close this descriptor when the process execs (`FD_CLOEXEC`).
+    cloexec: bool,
+    /// The file status flags (`STATUS_FLAGS`), shared with the descriptors
+    /// duplicated from this one, as they belong to the open file
+    /// description.
+    status: Arc<AtomicUsize>,
+}
+
+impl FdEntry {
+    /// Whether I/O through the descriptor must not block (`O_NONBLOCK`).
+    fn nonblock(&self) -> bool {
+        self.status.load(Ordering::Relaxed) & O_NONBLOCK != 0
+    }
+}
+
+/// Maps descriptor numbers to open files.
+///
+/// The lowest free number is always handed out first, as POSIX requires.
+/// Each entry carries its own `FD_CLOEXEC` bit; the file itself may be
+/// shared between several descriptors (see `dup`), and so are its status
+/// flags. A clone of the table, as `fork` makes, shares them the same way.
+#[derive(Clone)]
+pub struct FdTable {
+    entries: Vec<Option<FdEntry>>,
+}
+
+impl FdTable {
+    /// Creates a table with fds 0, 1 and 2 attached to the console.
+    pub fn with_stdio() -> Self {
+        let console: Arc<dyn FileLike> = Arc::new(Console);
+        let mut table = Self {
+            entries: Vec::new(),
+        };
+        for _ in 0..3 {
+            table.add(console.clone(), O_RDWR, false).unwrap();
+        }
+        table
+    }
+
+    fn entry(&self, fd: usize) -> LinuxResult<&FdEntry> {
+        self.entries
+            .get(fd)
+            .and_then(Option::as_ref)
+            .ok_or(LinuxError::EBADF)
+    }
+
+    fn entry_mut(&mut self, fd: usize) -> LinuxResult<&mut FdEntry> {
+        self.entries
+            .get_mut(fd)
+            .and_then(Option::as_mut)
+            .ok_or(LinuxError::EBADF)
+    }
+
+    /// Returns the file behind `fd`.
+    pub fn get(&self, fd: usize) -> LinuxResult<Arc<dyn FileLike>> {
+        self.entry(fd).map(|e| e.file.clone())
+    }
+
+    /// Returns the file behind `fd`, and whether I/O through `fd` must not
+    /// block (`O_NONBLOCK`).
+    pub fn get_io(&self, fd: usize) -> LinuxResult<(Arc<dyn FileLike>, bool)> {
+        self.entry(fd).map(|e| (e.file.clone(), e.nonblock()))
+    }
+
+    /// Installs `file`, just opened with the open flags `flags` (of which
+    /// the status flags are kept), at the lowest free descriptor.
+    pub fn add(
+        &mut self,
+        file: Arc<dyn FileLike>,
+        flags: usize,
+        cloexec: bool,
+    ) -> LinuxResult<usize> {
+        let status = Arc::new(AtomicUsize::new(flags & STATUS_FLAGS));
+        self.install(
+            0,
+            FdEntry {
+                file,
+                cloexec,
+                status,
+            },
+        )
+    }
+
+    /// Duplicates `old_fd` onto the lowest free descriptor not below
+    /// `min_fd`.
+    pub fn dup_from(&mut self, old_fd: usize, min_fd: usize, cloexec: bool) -> LinuxResult<usize> {
+        let old = self.entry(old_fd)?;
+        let entry = FdEntry {
+            file: old.file.clone(),
+            cloexec,
+            status: old.status.clone(),
+        };
+        self.install(min_fd, entry)
+    }
+
+    /// Installs `entry` at the lowest free descriptor not below `min_fd`.
+    fn install(&mut self, min_fd: usize, entry: FdEntry) -> LinuxResult<usize> {
+        if min_fd >= MAX_FDS {
+            return Err(LinuxError::EINVAL);
+        }
+        let fd = (min_fd..MAX_FDS)
+            .find(|&fd| self.entries.get(fd).is_none_or(Option::is_none))
+            .ok_or(LinuxError::EMFILE)?;
+        if fd >= self.entries.len() {
+            self.entries.resize_with(fd + 1, || None);
+        }
+        self.entries[fd] = Some(entry);
+        Ok(fd)
+    }
+
+    /// Makes `new_fd` refer to the file behind `old_fd`, closing whatever
+    /// `new_fd` referred to first. Both must be valid descriptor numbers
+    /// (`EBADF`), but `new_fd` need not be open.
+    pub fn dup_to(&mut self, old_fd: usize, new_fd: usize, cloexec: bool) -> LinuxResult<usize> {
+        let old = self.entry(old_fd)?;
+        let entry = FdEntry {
+            file: old.file.clone(),
+            cloexec,
+            status: old.status.clone(),
+        };
+        if new_fd >= MAX_FDS {
+            return Err(LinuxError::EBADF);
+        }
+        if new_fd >= self.entries.len() {
+            self.entries.resize_with(new_fd + 1, || None);
+        }
+        self.entries[new_fd] = Some(entry);
+        Ok(new_fd)
+    }
+
+    /// Closes `fd`. The file itself goes away with its last descriptor.
+    pub fn close(&mut self, fd: usize) -> LinuxResult {
+        self.entry(fd)?;
+        self.entries[fd] = None;
+        Ok(())
+    }
+
+    /// Sets `O_NONBLOCK` on every open file in the table (and so on the
+    /// descriptors of other tables sharing it), as if by `F_SETFL`.
+    pub fn set_nonblock_all(&self) {
+        for entry in self.entries.iter().flatten() {
+            entry.status.fetch_or(O_NONBLOCK, Ordering::Relaxed);
+        }
+    }
+
+    /// Closes every descriptor marked `FD_CLOEXEC`. Called on exec; all
+    /// other descriptors are inherited by the new program unchanged.
+    pub fn close_on_exec(&mut self) {
+        for slot in self.entries.iter_mut() {
+            if slot.as_ref().is_some_and(|e| e.cloexec) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+// ── SYS_FCNTL ──
+
+/// Supports descriptor duplication (`F_DUPFD`, `F_DUPFD_CLOEXEC`), the
+/// descriptor flags (`F_GETFD`, `F_SETFD`), whose only bit is `FD_CLOEXEC`,
+/// and the file status flags (`F_GETFL`, `F_SETFL`): the access mode,
+/// `O_APPEND` and `O_NONBLOCK`, of which only `O_NONBLOCK` can be changed.
+/// Other flags given to `F_SETFL` are ignored, as on Linux.
+pub fn sys_fcntl(fd_table: &mut FdTable, fd: usize, cmd: usize, arg: usize) -> LinuxResult<usize> {
+    match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => fd_table.dup_from(fd, arg, cmd == F_DUPFD_CLOEXEC),
+        F_GETFD => Ok(if fd_table.entry(fd)?.cloexec {
+            FD_CLOEXEC
+        } else {
+            0
+        }),
+        F_SETFD => {
+            fd_table.entry_mut(fd)?.cloexec = arg & FD_CLOEXEC != 0;
+            Ok(0)
+        }
+        F_GETFL => Ok(fd_table.entry(fd)?.status.load(Ordering::Relaxed)),
+        F_SETFL => {
+            let status = &fd_table.entry(fd)?.status;
+            let old = status.load(Ordering::Relaxed);
+            status.store(old & !SETFL_FLAGS | arg & SETFL_FLAGS, Ordering::Relaxed);
+            Ok(0)
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+// ── SYS_DUP / SYS_DUP2 / SYS_DUP3 ──
+
+/// Duplicates `fd` onto the lowest free descriptor, without `FD_CLOEXEC`.
+pub fn sys_dup(fd_table: &mut FdTable, fd: usize) -> LinuxResult<usize> {
+    fd_table.dup_from(fd, 0, false)
+}
+
+/// Like `dup3` without flags, except that `old_fd == new_fd` is not an
+/// error: it returns `new_fd` if that is open.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_dup2(fd_table: &mut FdTable, old_fd: usize, new_fd: usize) -> LinuxResult<usize> {
+    if old_fd == new_fd {
+        return fd_table.get(old_fd).map(|_| new_fd);
+    }
+    fd_table.dup_to(old_fd, new_fd, false)
+}
+
+/// Duplicates `old_fd` onto `new_fd`, closing `new_fd` first if it is
+/// open; `O_CLOEXEC` is the only flag. The two must differ (`EINVAL`).
+pub fn sys_dup3(
+    fd_table: &mut FdTable,
+    old_fd: usize,
+    new_fd: usize,
+    flags: usize,
+) -> LinuxResult<usize> {
+    if flags & !O_CLOEXEC != 0 || old_fd == new_fd {
+        return Err(LinuxError::EINVAL);
+    }
+    fd_table.dup_to(old_fd, new_fd, flags & O_CLOEXEC != 0)
+}
+
+// ── SYS_LSEEK ──
+
+/// Repositions the offset of `fd` relative to the start (`SEEK_SET`), the
+/// current offset (`SEEK_CUR`) or the end (`SEEK_END`) of the file.
+pub fn sys_lseek(
+    fd_table: &FdTable,
+    fd: usize,
+    offset: usize,
+    whence: usize,
+) -> LinuxResult<usize> {
+    let offset = offset as i64;
+    let pos = match whence {
+        SEEK_SET if offset < 0 => return Err(LinuxError::EINVAL),
+        SEEK_SET => SeekFrom::Start(offset as u64),
+        SEEK_CUR => SeekFrom::Current(offset),
+        SEEK_END => SeekFrom::End(offset),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    fd_table.get(fd)?.seek(pos)
+}
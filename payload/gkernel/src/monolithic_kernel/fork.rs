@@ -0,0 +1,166 @@
+//! `fork`: starting a child process as a copy of the caller.
+//!
+//! riscv64 and aarch64 have no `fork` syscall: libc makes `fork()` a
+//! `clone(SIGCHLD, 0)`, and that is the only `clone` taken here. A
+//! `CLONE_*` flag would ask for something shared between parent and child
+//! (the address space, the descriptor table, a thread group), and there is
+//! nothing a process can share, so any of them fails with `EINVAL`.
+//! x86_64's `SYS_FORK` is the same call.
+//!
+//! The child runs the same program as the parent, from the same place: its
+//! registers are the parent's at the syscall, with 0 as the return value
+//! (and the stack pointer `clone` was given, if any). It gets:
+//!
+//! - a copy of every user page the parent has mapped, with the same
+//!   protection. Nothing is shared copy-on-write yet, so the pages are
+//!   copied up front; pages of a `lazy-stack` stack the parent has not
+//!   touched yet stay untouched in the child too. System V shared memory
+//!   segments are the exception: they stay attached, shared (see `shm`).
+//! - the parent's descriptors, sharing each open file and its status flags
+//!   as `dup` does, each with its `FD_CLOEXEC` bit.
+//! - the parent's working directory, environment, signal dispositions,
+//!   alternate signal stack, syscall filters and handler, nice value, CPU
+//!   affinity, `rseq` registration, program break, process group and
+//!   session.
+//! - none of its pending signals, no interval timer, and CPU times and
+//!   fault counts of its own, starting at zero.
+//!
+//! The parent gets the child's PID, and reaps it with `wait4` or `waitid`.
+
+use alloc::vec;
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use core::sync::atomic::Ordering;
+use log::debug;
+use std::os::arceos::modules::axhal::mem::{PAGE_SIZE_4K, VirtAddr};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+use std::os::arceos::modules::axhal::uspace::UserContext;
+use std::os::arceos::modules::axtask;
+
+use super::process::{Pid, Process};
+use super::signal::SIGCHLD;
+use super::{USER_STACK_SIZE, loader, shm, task};
+
+/// The exit signal in `clone` flags; every other bit is a `CLONE_*` flag.
+const CSIGNAL: usize = 0xff;
+
+/// Protection of the user stack, which is what a page reserved but not
+/// touched yet (see `lazy-stack`) gets when it is first touched.
+const STACK_FLAGS: MappingFlags = MappingFlags::READ
+    .union(MappingFlags::WRITE)
+    .union(MappingFlags::USER);
+
+/// What the parent has at the page `vaddr`: `Some` protection if a frame
+/// is mapped there, `Some` empty flags if the page is reserved in the
+/// stack for its first touch (which is the only area mapped on demand),
+/// `None` if nothing is.
+fn page_flags(uspace: &AddrSpace, vaddr: VirtAddr) -> Option<MappingFlags> {
+    let stack_base = uspace.end() - USER_STACK_SIZE;
+    match uspace.page_table().query(vaddr) {
+        Ok((_, flags, _)) if !flags.is_empty() => Some(flags),
+        Ok(_) if vaddr >= stack_base => Some(MappingFlags::empty()),
+        _ => None,
+    }
+}
+
+/// Maps in `child` every user page `parent` maps, in runs of pages with
+/// the same protection, and copies the contents of those with a frame.
+/// Fails with `ENOMEM` if the child's frames run out.
+fn copy_user_pages(parent: &AddrSpace, child: &mut AddrSpace) -> LinuxResult {
+    let end = parent.end();
+    let mut vaddr = parent.base();
+    while vaddr < end {
+        let Some(flags) = page_flags(parent, vaddr) else {
+            vaddr += PAGE_SIZE_4K;
+            continue;
+        };
+        let start = vaddr;
+        while vaddr < end && page_flags(parent, vaddr) == Some(flags) {
+            vaddr += PAGE_SIZE_4K;
+        }
+        let size = vaddr - start;
+        if flags.is_empty() {
+            child
+                .map_alloc(start, size, STACK_FLAGS, false)
+                .map_err(|_| LinuxError::ENOMEM)?;
+            continue;
+        }
+        child
+            .map_alloc(start, size, flags, true)
+            .map_err(|_| LinuxError::ENOMEM)?;
+        let mut page = vec![0u8; PAGE_SIZE_4K];
+        for offset in (0..size).step_by(PAGE_SIZE_4K) {
+            parent
+                .read(start + offset, &mut page)
+                .and_then(|()| child.write(start + offset, &page))
+                .map_err(|_| LinuxError::EFAULT)?;
+            if flags.contains(MappingFlags::EXECUTE) {
+                loader::sync_code_page(child, start + offset, flags)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Creates the child of `parent`, stopped at `uctx` (the parent's context
+/// at the syscall), and spawns its task. Returns the child's PID.
+fn fork(parent: &Process, uctx: &UserContext, stack: usize) -> LinuxResult<Pid> {
+    let mut uspace = task::new_user_aspace()?;
+    task::copy_kernel_mappings(&mut uspace)?;
+    copy_user_pages(&parent.aspace.lock(), &mut uspace)?;
+
+    let app = *parent.app.lock();
+    let child = Process::new(app, uspace, &[])?;
+    *child.fd_table.lock() = parent.fd_table.lock().clone();
+    *child.syscall_filters.lock() = parent.syscall_filters.lock().clone();
+    *child.syscall_handler.lock() = *parent.syscall_handler.lock();
+    *child.environ.lock() = parent.environ.lock().clone();
+    *child.cwd.lock() = parent.cwd.lock().clone();
+    *child.signal_frame.lock() = *parent.signal_frame.lock();
+    *child.sigactions.lock() = *parent.sigactions.lock();
+    *child.altstack.lock() = *parent.altstack.lock();
+    *child.rseq.lock() = *parent.rseq.lock();
+    *child.heap.lock() = *parent.heap.lock();
+    child
+        .nice
+        .store(parent.nice.load(Ordering::Relaxed), Ordering::Relaxed);
+    child
+        .pgid
+        .store(parent.pgid.load(Ordering::Relaxed), Ordering::Relaxed);
+    child
+        .sid
+        .store(parent.sid.load(Ordering::Relaxed), Ordering::Relaxed);
+    shm::fork_attachments(parent, &child)?;
+    parent.adopt(child.clone());
+    debug!("[SYS_CLONE]: process {} forked {}", parent.pid, child.pid);
+
+    let mut child_uctx = *uctx;
+    child_uctx.set_retval(0);
+    if stack != 0 {
+        child_uctx.set_sp(stack);
+    }
+    let pid = child.pid;
+    task::spawn_task(child, child_uctx, Some(axtask::current().cpumask()));
+    Ok(pid)
+}
+
+// ── SYS_CLONE ──
+
+/// `clone(flags, stack, ...)` without any `CLONE_*` flag: a `fork`, with
+/// the child on `stack` if that is not 0. The exit signal must be
+/// `SIGCHLD` (or 0, for none); it only tells how a parent waits for the
+/// child, and `wait4` and `waitid` find it either way.
+pub fn sys_clone(process: &Process, uctx: &UserContext) -> LinuxResult<usize> {
+    let flags = uctx.arg0();
+    if flags & !CSIGNAL != 0 || !matches!((flags & CSIGNAL) as i32, 0 | SIGCHLD) {
+        return Err(LinuxError::EINVAL);
+    }
+    fork(process, uctx, uctx.arg1())
+}
+
+// ── SYS_FORK ──
+
+#[cfg(target_arch = "x86_64")]
+pub fn sys_fork(process: &Process, uctx: &UserContext) -> LinuxResult<usize> {
+    fork(process, uctx, 0)
+}
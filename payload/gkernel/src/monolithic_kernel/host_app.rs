@@ -0,0 +1,105 @@
+//! A user program staged in guest memory by the hypervisor (`host-app`
+//! feature, riscv64).
+//!
+//! Before starting the guest, the hypervisor may copy a flat binary into
+//! the window at `WINDOW_PADDR`, behind a 16-byte header: `MAGIC`, then the
+//! image size as a little-endian `u64`. The window lies between the start
+//! of guest RAM and the kernel image, which the guest never allocates from,
+//! so the image survives until it is loaded. If the header is there, the
+//! staged program replaces `/bin/init` as the boot program; otherwise the
+//! embedded one runs as usual. The image is copied into each process that
+//! runs it, like an embedded one.
+//!
+//! A packed image (see `lz`) is unpacked into the kernel heap once, at
+//! boot, with the `compressed-app` feature, and rejected without it.
+
+use axerrno::{LinuxError, LinuxResult};
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::os::arceos::modules::axhal::mem::{PhysAddr, pa, phys_to_virt};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+
+/// Guest-physical address of the staging window; it must match the
+/// hypervisor's `HOST_APP_GPA`.
+const WINDOW_PADDR: PhysAddr = pa!(0x8010_0000);
+/// Size of the window, up to the kernel image at 0x8020_0000.
+const WINDOW_SIZE: usize = 0x10_0000;
+const HEADER_SIZE: usize = 16;
+/// First 8 bytes of the window when an image is staged.
+const MAGIC: [u8; 8] = *b"GKHOSTAP";
+
+/// Where the staged image is, in the window or (unpacked) in the heap,
+/// and its size, once `probe` has found one.
+static IMAGE_ADDR: AtomicUsize = AtomicUsize::new(0);
+static IMAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// A program image in guest-physical memory.
+pub struct HostImage {
+    pub paddr: PhysAddr,
+    pub size: usize,
+}
+
+/// Looks for a staged image, mapping the window into the kernel address
+/// space to read it. Call once, at boot.
+pub fn probe() -> LinuxResult<Option<HostImage>> {
+    axmm::kernel_aspace()
+        .lock()
+        .map_linear(
+            phys_to_virt(WINDOW_PADDR),
+            WINDOW_PADDR,
+            WINDOW_SIZE,
+            MappingFlags::READ,
+        )
+        .map_err(|_| LinuxError::ENOMEM)?;
+    // SAFETY: the window was just mapped, and nothing else writes to it.
+    let header = unsafe { slice::from_raw_parts(phys_to_virt(WINDOW_PADDR).as_ptr(), HEADER_SIZE) };
+    if header[..8] != MAGIC {
+        return Ok(None);
+    }
+    let size = u64::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+    if size == 0 || size > WINDOW_SIZE - HEADER_SIZE {
+        return Err(LinuxError::ENOEXEC);
+    }
+    let paddr = WINDOW_PADDR + HEADER_SIZE;
+    // SAFETY: as above; the image was checked to fit in the window.
+    let staged: &'static [u8] =
+        unsafe { slice::from_raw_parts(phys_to_virt(paddr).as_ptr(), size) };
+    let image = unpack(staged)?;
+    IMAGE_ADDR.store(image.as_ptr().addr(), Ordering::Relaxed);
+    IMAGE_SIZE.store(image.len(), Ordering::Relaxed);
+    Ok(Some(HostImage { paddr, size }))
+}
+
+/// The image to run from `staged`: itself, or what it unpacks to.
+fn unpack(staged: &'static [u8]) -> LinuxResult<&'static [u8]> {
+    if !staged.starts_with(b"GKLZ") {
+        return Ok(staged);
+    }
+    #[cfg(feature = "compressed-app")]
+    {
+        let image = super::lz::decompress(staged)?;
+        info_println!(
+            "Host-staged app unpacked: {} -> {} bytes",
+            staged.len(),
+            image.len()
+        );
+        // Kept for as long as the kernel runs, like an embedded image.
+        Ok(alloc::vec::Vec::leak(image))
+    }
+    #[cfg(not(feature = "compressed-app"))]
+    {
+        println!("Host-staged app is packed; build the guest with compressed-app");
+        Err(LinuxError::ENOEXEC)
+    }
+}
+
+/// The staged image found by `probe` (empty if there is none).
+pub fn image() -> &'static [u8] {
+    let size = IMAGE_SIZE.load(Ordering::Relaxed);
+    if size == 0 {
+        return &[];
+    }
+    // SAFETY: `probe` stored where an image of `size` bytes stays for as
+    // long as the kernel runs.
+    unsafe { slice::from_raw_parts(IMAGE_ADDR.load(Ordering::Relaxed) as *const u8, size) }
+}
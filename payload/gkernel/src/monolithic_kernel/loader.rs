@@ -0,0 +1,801 @@
+//! Populates a user address space with a program image and its stack.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use axmm::AddrSpace;
+use core::fmt;
+use log::info;
+use std::io;
+use std::os::arceos::modules::axhal::mem::{
+    PAGE_SIZE_4K, VirtAddr, VirtAddrRange, phys_to_virt, va,
+};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+
+use super::apps::{App, Integrity};
+use super::aspace::{self, Purpose};
+use super::error::KernelError;
+use super::{APP_ENTRY, BRK_GAP, BRK_MAX, STACK_GUARD_SIZE, USER_STACK_SIZE, uaccess};
+
+// ── User app loader (from embedded binary) ──
+
+/// First bytes of an ELF file.
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// Final protection of a loaded flat binary.
+const FLAT_FLAGS: MappingFlags = MappingFlags::READ
+    .union(MappingFlags::EXECUTE)
+    .union(MappingFlags::USER);
+
+/// A part of an image mapped into user memory, described the way a
+/// `PT_LOAD` program header would be.
+pub struct Segment {
+    /// Offset of the segment's bytes in the image.
+    pub offset: usize,
+    pub vaddr: VirtAddr,
+    /// Bytes copied from the image.
+    pub file_size: usize,
+    /// Bytes of memory the segment takes; any past `file_size` are zero.
+    pub mem_size: usize,
+    pub flags: MappingFlags,
+}
+
+/// What loading an image produced: where to start it and what was mapped.
+pub struct LoadedImage {
+    pub entry: VirtAddr,
+    pub segments: Vec<Segment>,
+}
+
+impl LoadedImage {
+    /// First page boundary above every segment.
+    pub fn image_end(&self) -> VirtAddr {
+        let end = self
+            .segments
+            .iter()
+            .map(|seg| seg.vaddr.as_usize() + seg.mem_size)
+            .max()
+            .unwrap_or(APP_ENTRY);
+        va!(end.next_multiple_of(PAGE_SIZE_4K))
+    }
+
+    /// The initial program break: `BRK_GAP` above the image.
+    pub fn brk_start(&self) -> VirtAddr {
+        self.image_end() + BRK_GAP
+    }
+
+    /// Where mmap starts placing mappings: past the largest brk heap, so
+    /// the two never run into each other.
+    pub fn mmap_base(&self) -> VirtAddr {
+        self.brk_start() + BRK_MAX
+    }
+}
+
+/// Prints the segments one per line, in the column order of `readelf -l`
+/// (offset, virtual address, file size, memory size, flags), followed by
+/// the entry point, so that the two are easy to diff, and the heap layout
+/// above the image.
+impl fmt::Display for LoadedImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "  Type Offset   VirtAddr           FileSiz  MemSiz   Flg"
+        )?;
+        for seg in &self.segments {
+            let flag = |bit, c| if seg.flags.contains(bit) { c } else { ' ' };
+            writeln!(
+                f,
+                "  LOAD {:#08x} {:#018x} {:#08x} {:#08x} {}{}{}",
+                seg.offset,
+                seg.vaddr.as_usize(),
+                seg.file_size,
+                seg.mem_size,
+                flag(MappingFlags::READ, 'R'),
+                flag(MappingFlags::WRITE, 'W'),
+                flag(MappingFlags::EXECUTE, 'E'),
+            )?;
+        }
+        writeln!(f, "  Entry {:#x}", self.entry.as_usize())?;
+        write!(
+            f,
+            "  Image end {:#x}, brk {:#x}, mmap base {:#x}",
+            self.image_end().as_usize(),
+            self.brk_start().as_usize(),
+            self.mmap_base().as_usize()
+        )
+    }
+}
+
+/// Maps `app` into `uspace` and returns its entry point and segments.
+///
+/// Callers must start the program at the returned address rather than at
+/// any fixed constant: where the entry lies is up to the loader and the
+/// image format.
+///
+/// The format is told by the first bytes of the image: an ELF file is a
+/// static executable, loaded as its program headers say (see `load_elf`);
+/// anything else is a flat binary, loaded at `APP_ENTRY` and entered at its
+/// first byte.
+///
+/// Unless the `skip-app-verify` feature is enabled, the image is first
+/// checked against its expected length and hash, if it has them; a
+/// mismatch means the kernel image itself is broken, so it panics.
+pub fn load_user_app(uspace: &mut AddrSpace, app: &App) -> Result<LoadedImage, KernelError> {
+    if let Some(expected) = app.integrity
+        && cfg!(not(feature = "skip-app-verify"))
+    {
+        let actual = Integrity::of(app.image());
+        assert_eq!(
+            actual, expected,
+            "embedded app {} failed integrity check",
+            app.path
+        );
+    }
+
+    let image = app.image();
+    if image.is_empty() {
+        return Err(KernelError::BadElf {
+            reason: "empty image",
+        });
+    }
+    if image.starts_with(ELF_MAGIC) {
+        let loaded = load_elf(uspace, image)?;
+        info_println!(
+            "User app {} loaded from ELF, entry {:#x}",
+            app.path,
+            loaded.entry
+        );
+        return Ok(loaded);
+    }
+    let entry = load_flat_binary(uspace, image, va!(APP_ENTRY), 0)?;
+    info_println!("User app {} loaded at {:#x}", app.path, APP_ENTRY);
+    Ok(LoadedImage {
+        entry,
+        segments: vec![Segment {
+            offset: 0,
+            vaddr: va!(APP_ENTRY),
+            file_size: image.len(),
+            mem_size: image.len(),
+            flags: FLAT_FLAGS,
+        }],
+    })
+}
+
+/// Prints the segment table of a freshly loaded `app` with the
+/// `debug-loader` feature.
+pub fn debug_print_image(app: &App, image: &LoadedImage) {
+    if cfg!(feature = "debug-loader") {
+        println!("Segments of {}:\n{}", app.path, image);
+    }
+}
+
+// ELF header and program header fields (the System V gABI).
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const PT_LOAD: u32 = 1;
+const PT_INTERP: u32 = 3;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+/// Size of an ELF64 header and of an ELF64 program header.
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+/// The machine the ELF files this kernel runs are built for.
+#[cfg(target_arch = "riscv64")]
+const EM_HOST: u16 = 243; // EM_RISCV
+#[cfg(target_arch = "aarch64")]
+const EM_HOST: u16 = 183; // EM_AARCH64
+#[cfg(target_arch = "x86_64")]
+const EM_HOST: u16 = 62; // EM_X86_64
+
+/// Reads the little-endian integer of `size` bytes (at most 8) at `offset`
+/// in `image`.
+fn read_le(image: &[u8], offset: usize, size: usize) -> Option<usize> {
+    let bytes = image.get(offset..offset.checked_add(size)?)?;
+    let mut value = [0u8; 8];
+    value[..size].copy_from_slice(bytes);
+    Some(u64::from_le_bytes(value) as usize)
+}
+
+/// Reads the `PT_LOAD` program headers of the ELF64 `image` as segments,
+/// with its entry point, checking everything `load_elf` relies on.
+fn parse_elf(image: &[u8]) -> Result<(VirtAddr, Vec<Segment>), KernelError> {
+    let bad = |reason| KernelError::BadElf { reason };
+    let field = |offset, size| read_le(image, offset, size).ok_or(bad("truncated headers"));
+    if image.len() < EHDR_SIZE {
+        return Err(bad("truncated headers"));
+    }
+    if image[4] != ELFCLASS64 || image[5] != ELFDATA2LSB {
+        return Err(bad("not a little-endian ELF64 file"));
+    }
+    if field(16, 2)? != ET_EXEC as usize {
+        return Err(bad("not a static executable"));
+    }
+    if field(18, 2)? != EM_HOST as usize {
+        return Err(bad("built for another machine"));
+    }
+    let entry = field(24, 8)?;
+    let (phoff, phentsize, phnum) = (field(32, 8)?, field(54, 2)?, field(56, 2)?);
+    if phentsize != PHDR_SIZE {
+        return Err(bad("unexpected program header size"));
+    }
+
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let ph = phoff
+            .checked_add(i * PHDR_SIZE)
+            .ok_or(bad("truncated headers"))?;
+        match field(ph, 4)? as u32 {
+            PT_LOAD => {}
+            PT_INTERP => return Err(bad("dynamically linked")),
+            _ => continue,
+        }
+        let p_flags = field(ph + 4, 4)? as u32;
+        let (offset, vaddr) = (field(ph + 8, 8)?, field(ph + 16, 8)?);
+        let (file_size, mem_size) = (field(ph + 32, 8)?, field(ph + 40, 8)?);
+        if mem_size == 0 {
+            continue;
+        }
+        if file_size > mem_size
+            || offset
+                .checked_add(file_size)
+                .is_none_or(|end| end > image.len())
+        {
+            return Err(bad("segment outside the file"));
+        }
+        if vaddr.checked_add(mem_size).is_none() {
+            return Err(bad("segment wraps around"));
+        }
+        let mut flags = MappingFlags::USER;
+        for (bit, flag) in [
+            (PF_R, MappingFlags::READ),
+            (PF_W, MappingFlags::WRITE),
+            (PF_X, MappingFlags::EXECUTE),
+        ] {
+            if p_flags & bit != 0 {
+                flags |= flag;
+            }
+        }
+        segments.push(Segment {
+            offset,
+            vaddr: va!(vaddr),
+            file_size,
+            mem_size,
+            flags,
+        });
+    }
+    if segments.is_empty() {
+        return Err(bad("nothing to load"));
+    }
+    Ok((va!(entry), segments))
+}
+
+/// Maps a static ELF64 executable into `uspace` and returns its entry
+/// point (`e_entry`) and `PT_LOAD` segments.
+///
+/// Each segment gets the pages covering `[p_vaddr, p_vaddr + p_memsz)`, so
+/// one that does not start on a page boundary is copied in at its offset in
+/// the first page; its first `p_filesz` bytes come from the file, and the
+/// rest, the bss, stays zero as the frames are zeroed when allocated. Two
+/// segments may share a page (a linker packing data right after code
+/// does): the page is mapped once, with the protection of both, which is
+/// what Linux gives it too. Segments whose bytes overlap, or that do not
+/// fit the address space clear of existing mappings, fail with
+/// `KernelError::SegmentOverlap`.
+///
+/// As for a flat binary, the pages are writable only while the file is
+/// copied in, and the executable ones are checked and synced with the
+/// instruction cache (`sync_code_page`).
+/// The entry point must be in an executable segment
+/// (`KernelError::EntryUnmapped` otherwise). Dynamically linked and
+/// position-independent executables are not supported (`BadElf`).
+pub fn load_elf(uspace: &mut AddrSpace, image: &[u8]) -> Result<LoadedImage, KernelError> {
+    let (entry, mut segments) = parse_elf(image)?;
+    if let Some(seg) = segments
+        .iter()
+        .find(|seg| !uspace.contains_range(seg.vaddr, seg.mem_size))
+    {
+        return Err(KernelError::SegmentOverlap {
+            vaddr: seg.vaddr,
+            size: seg.mem_size,
+        });
+    }
+    segments.sort_by_key(|seg| seg.vaddr);
+    for pair in segments.windows(2) {
+        if pair[0].vaddr + pair[0].mem_size > pair[1].vaddr {
+            return Err(KernelError::SegmentOverlap {
+                vaddr: pair[1].vaddr,
+                size: pair[1].mem_size,
+            });
+        }
+    }
+    if !segments.iter().any(|seg| {
+        seg.flags.contains(MappingFlags::EXECUTE)
+            && (seg.vaddr..seg.vaddr + seg.mem_size).contains(&entry)
+    }) {
+        return Err(KernelError::EntryUnmapped { entry });
+    }
+
+    // The protection of each page is that of every segment in it.
+    let mut pages: Vec<(VirtAddr, MappingFlags)> = Vec::new();
+    for seg in &segments {
+        let start = seg.vaddr.as_usize() & !(PAGE_SIZE_4K - 1);
+        let end = (seg.vaddr.as_usize() + seg.mem_size).next_multiple_of(PAGE_SIZE_4K);
+        for page in (start..end).step_by(PAGE_SIZE_4K) {
+            match pages.last_mut() {
+                Some((last, flags)) if *last == va!(page) => *flags |= seg.flags,
+                _ => pages.push((va!(page), seg.flags)),
+            }
+        }
+    }
+    // Map runs of consecutive pages with the same protection, writable
+    // until the file is copied in.
+    let mut runs: Vec<(VirtAddr, usize, MappingFlags)> = Vec::new();
+    for &(page, flags) in &pages {
+        match runs.last_mut() {
+            Some((start, size, run_flags)) if *start + *size == page && *run_flags == flags => {
+                *size += PAGE_SIZE_4K;
+            }
+            _ => runs.push((page, PAGE_SIZE_4K, flags)),
+        }
+    }
+    for &(start, size, _) in &runs {
+        let overlap = KernelError::SegmentOverlap { vaddr: start, size };
+        uspace
+            .map_alloc(
+                start,
+                size,
+                MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+                true,
+            )
+            .map_err(|err| match err {
+                io::Error::AlreadyExists => overlap,
+                _ => KernelError::AllocFailed { vaddr: start, size },
+            })?;
+        aspace::tag(uspace, start, size, Purpose::UserCode);
+    }
+    for seg in &segments {
+        uspace
+            .write(seg.vaddr, &image[seg.offset..seg.offset + seg.file_size])
+            .map_err(|_| KernelError::MapFailed {
+                vaddr: seg.vaddr,
+                size: seg.file_size,
+            })?;
+    }
+    for &(start, size, flags) in &runs {
+        uspace
+            .protect(start, size, flags)
+            .map_err(|_| KernelError::MapFailed { vaddr: start, size })?;
+        if !flags.contains(MappingFlags::EXECUTE) {
+            continue;
+        }
+        for page in (0..size).step_by(PAGE_SIZE_4K) {
+            sync_code_page(uspace, start + page, flags)?;
+        }
+    }
+    Ok(LoadedImage { entry, segments })
+}
+
+/// Maps a flat binary (raw machine code, such as `objcopy -O binary`
+/// output) at `load_addr` and returns its entry point, `entry_offset`
+/// bytes in.
+///
+/// The pages are mapped writable only while the image is copied in, then
+/// made read-only and executable (never writable and executable at once;
+/// a flat binary has no data, and keeps what it changes on its non-executable
+/// stack), then checked in the page table and brought up to date in the
+/// instruction cache (`sync_code_page`). They are zeroed when allocated, so
+/// the tail of the last page past the image reads as zeros, like an ELF
+/// bss. `load_addr` must be page aligned and the whole image must fit in
+/// `uspace` without overlapping existing mappings
+/// (`KernelError::SegmentOverlap` otherwise).
+pub fn load_flat_binary(
+    uspace: &mut AddrSpace,
+    image: &[u8],
+    load_addr: VirtAddr,
+    entry_offset: usize,
+) -> Result<VirtAddr, KernelError> {
+    if entry_offset >= image.len() {
+        return Err(KernelError::EntryUnmapped {
+            entry: load_addr + entry_offset,
+        });
+    }
+    let size = image.len().div_ceil(PAGE_SIZE_4K) * PAGE_SIZE_4K;
+    let overlap = KernelError::SegmentOverlap {
+        vaddr: load_addr,
+        size,
+    };
+    if !load_addr.as_usize().is_multiple_of(PAGE_SIZE_4K) || !uspace.contains_range(load_addr, size)
+    {
+        return Err(overlap);
+    }
+
+    uspace
+        .map_alloc(
+            load_addr,
+            size,
+            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+            true,
+        )
+        .map_err(|err| match err {
+            io::Error::AlreadyExists => overlap,
+            _ => KernelError::AllocFailed {
+                vaddr: load_addr,
+                size,
+            },
+        })?;
+    aspace::tag(uspace, load_addr, size, Purpose::UserCode);
+    uspace
+        .write(load_addr, image)
+        .and_then(|()| uspace.protect(load_addr, size, FLAT_FLAGS))
+        .map_err(|_| KernelError::MapFailed {
+            vaddr: load_addr,
+            size,
+        })?;
+    for page in (0..size).step_by(PAGE_SIZE_4K) {
+        sync_code_page(uspace, load_addr + page, FLAT_FLAGS)?;
+    }
+
+    Ok(load_addr + entry_offset)
+}
+
+/// Checks that the page table maps the code page at `vaddr` with at least
+/// `expected`, the protection the loader gave it, and brings the
+/// instruction cache up to date with the page (`sync_icache`), which was
+/// written through the kernel's mapping of its frame.
+///
+/// A page table that silently drops `EXECUTE` or `USER` (a missing
+/// `UXN`/`PXN` translation on aarch64, say) would otherwise make the first
+/// instruction fetch fault, far from the cause; this fails the load with
+/// `KernelError::CodeNotExecutable` instead, naming both protections.
+pub fn sync_code_page(
+    uspace: &AddrSpace,
+    vaddr: VirtAddr,
+    expected: MappingFlags,
+) -> Result<(), KernelError> {
+    let (paddr, flags, _) = uspace
+        .page_table()
+        .query(vaddr)
+        .map_err(|_| KernelError::QueryFailed { vaddr })?;
+    if !flags.contains(expected) {
+        return Err(KernelError::CodeNotExecutable {
+            vaddr,
+            flags,
+            expected,
+        });
+    }
+    sync_icache(VirtAddrRange::from_start_size(
+        phys_to_virt(paddr),
+        PAGE_SIZE_4K,
+    ));
+    Ok(())
+}
+
+/// Makes instructions just stored to `range` (kernel virtual addresses)
+/// visible to instruction fetch, so that a program copied into memory does
+/// not run stale cache contents.
+///
+/// - aarch64: the data cache and instruction cache are not coherent. Each
+///   line is cleaned to the point of unification (`dc cvau`), then its
+///   instruction cache line invalidated (`ic ivau`), with barriers so that
+///   later fetches see the result. Both apply to all CPUs in the inner
+///   shareable domain.
+/// - riscv64: `fence.i` orders this hart's stores before its later fetches.
+///   It covers this hart only, which is the one running the loader; a
+///   process started on another hart under `smp` relies on that hart
+///   not having fetched from the frames before.
+/// - x86_64 keeps instruction fetch coherent with stores, so only the
+///   compiler must not move the stores past the return.
+pub fn sync_icache(range: VirtAddrRange) {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        use core::arch::asm;
+
+        // CTR_EL0 gives the smallest line sizes as log2 of 4-byte words.
+        let ctr: usize;
+        asm!("mrs {}, ctr_el0", out(reg) ctr);
+        let dline = 4 << ((ctr >> 16) & 0xf);
+        let iline = 4 << (ctr & 0xf);
+        let (start, end) = (range.start.as_usize(), range.end.as_usize());
+        for line in (start & !(dline - 1)..end).step_by(dline) {
+            asm!("dc cvau, {}", in(reg) line);
+        }
+        asm!("dsb ish");
+        for line in (start & !(iline - 1)..end).step_by(iline) {
+            asm!("ic ivau, {}", in(reg) line);
+        }
+        asm!("dsb ish", "isb");
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    {
+        let _ = range;
+        unsafe { core::arch::asm!("fence.i") };
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        let _ = range;
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+// ── Init user stack ──
+
+// Auxiliary vector keys (uapi/linux/auxvec.h).
+const AT_NULL: usize = 0;
+const AT_PAGESZ: usize = 6;
+
+/// Maps the user stack at the top of `uspace` and lays out the initial
+/// process stack the way the Linux ABI expects it on entry:
+///
+/// ```text
+/// sp ->  argc
+///        argv[0] .. argv[argc - 1], NULL
+///        envp[0] .. envp[n - 1], NULL
+///        AT_PAGESZ, 4096              (auxiliary vector)
+///        AT_NULL, 0
+///        ...
+///        argument and environment strings
+/// top -> (end of user address space)
+/// ```
+///
+/// The `STACK_GUARD_SIZE` bytes below the stack are left unmapped as its
+/// guard (see `stack_guard`), and must be free as well.
+///
+/// Returns the initial stack pointer, 16-byte aligned. Fails with
+/// `KernelError::SegmentOverlap` if the address space is too small to hold
+/// the stack and its guard clear of what is already mapped, and with
+/// `KernelError::ArgsTooLong` if the strings do not fit in it.
+pub fn init_user_stack(
+    uspace: &mut AddrSpace,
+    argv: &[String],
+    envp: &[String],
+) -> Result<VirtAddr, KernelError> {
+    let ustack_top = uspace.end();
+    let size = STACK_GUARD_SIZE + USER_STACK_SIZE;
+    let guard_vaddr = match ustack_top.as_usize().checked_sub(size) {
+        Some(base) if base >= uspace.base().as_usize() => va!(base),
+        _ => {
+            return Err(KernelError::SegmentOverlap {
+                vaddr: uspace.base(),
+                size,
+            });
+        }
+    };
+    let ustack_vaddr = guard_vaddr + STACK_GUARD_SIZE;
+    // The stack and its guard must not land on the program image (or
+    // anything else already mapped), which happens when the aspace is
+    // barely larger than the stack.
+    let stack_range = VirtAddrRange::from_start_size(guard_vaddr, size);
+    if uspace.find_free_area(guard_vaddr, size, stack_range) != Some(guard_vaddr) {
+        return Err(KernelError::SegmentOverlap {
+            vaddr: guard_vaddr,
+            size,
+        });
+    }
+    let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+
+    info!(
+        "Mapping user stack: {:#x?} -> {:#x?}",
+        ustack_vaddr, ustack_top
+    );
+
+    // Strings go at the very top, then the pointer block below them.
+    let strings_size: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+    let words = 1 + (argv.len() + 1) + (envp.len() + 1) + 4;
+    let strings_start = ustack_top.as_usize() - strings_size;
+    let sp = (strings_start - words * size_of::<usize>()) & !0xf;
+    if sp < ustack_vaddr.as_usize() {
+        return Err(KernelError::ArgsTooLong {
+            size: strings_size + words * size_of::<usize>(),
+        });
+    }
+
+    // Allocate pages for the user stack using map_alloc. Like every page
+    // `map_alloc` hands out, they come zeroed, so the stack below the
+    // initial frame holds nothing from a previous owner. With `lazy-stack`
+    // the range is only reserved, and each page gets its frame when first
+    // touched: here only the pages the initial frame is written to.
+    uspace
+        .map_alloc(
+            ustack_vaddr,
+            USER_STACK_SIZE,
+            flags,
+            !cfg!(feature = "lazy-stack"),
+        )
+        .map_err(|_| KernelError::AllocFailed {
+            vaddr: ustack_vaddr,
+            size: USER_STACK_SIZE,
+        })?;
+    aspace::tag(uspace, ustack_vaddr, USER_STACK_SIZE, Purpose::UserStack);
+
+    let mut block = Vec::with_capacity(words);
+    let mut strings = Vec::with_capacity(strings_size);
+    let mut push_strs = |block: &mut Vec<usize>, strs: &[String]| {
+        for s in strs {
+            block.push(strings_start + strings.len());
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+        }
+        block.push(0);
+    };
+    block.push(argv.len());
+    push_strs(&mut block, argv);
+    push_strs(&mut block, envp);
+    block.extend_from_slice(&[AT_PAGESZ, PAGE_SIZE_4K, AT_NULL, 0]);
+
+    let block: Vec<u8> = block.iter().flat_map(|w| w.to_ne_bytes()).collect();
+    debug_assert!(
+        sp + block.len() <= strings_start,
+        "user stack pointer block [{:#x}, {:#x}) overlaps the strings at {:#x}",
+        sp,
+        sp + block.len(),
+        strings_start,
+    );
+    uaccess::copy_to_user(uspace, sp, &block)
+        .and_then(|()| uaccess::copy_to_user(uspace, strings_start, &strings))
+        .map_err(|_| KernelError::MapFailed {
+            vaddr: va!(sp),
+            size: ustack_top.as_usize() - sp,
+        })?;
+
+    debug_assert_sp_in_stack(uspace, va!(sp));
+    Ok(va!(sp))
+}
+
+/// The guard page below the user stack at the top of `uspace`. Nothing is
+/// ever mapped there: `brk`, `shmat` and a moving `mremap` only place
+/// memory below it, so a program that runs off the bottom of its stack
+/// faults there, and the fault is reported as a stack overflow (see
+/// `fault::FatalFault`). A `MREMAP_FIXED` move or a `shmat` at an explicit
+/// address may still map over it, as on Linux.
+pub fn stack_guard(uspace: &AddrSpace) -> VirtAddrRange {
+    let stack_base = uspace.end() - USER_STACK_SIZE;
+    VirtAddrRange::new(stack_base - STACK_GUARD_SIZE, stack_base)
+}
+
+/// How many pages of the user stack at the top of `uspace` have a frame.
+/// All of them, unless the stack is mapped on demand (`lazy-stack`).
+pub fn committed_stack_pages(uspace: &AddrSpace) -> usize {
+    let stack_base = uspace.end().as_usize() - USER_STACK_SIZE;
+    (stack_base..uspace.end().as_usize())
+        .step_by(PAGE_SIZE_4K)
+        .filter(|&page| uaccess::populated(uspace, page))
+        .count()
+}
+
+/// Panics (in debug builds) unless `sp` lies in the mapped user stack.
+///
+/// A bad initial stack pointer would otherwise only show up as a user
+/// page fault somewhere in the program's startup code. The top of the
+/// stack itself is allowed, as that is where an empty stack starts.
+pub fn debug_assert_sp_in_stack(uspace: &AddrSpace, sp: VirtAddr) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let stack_top = uspace.end();
+    let stack_base = stack_top - USER_STACK_SIZE;
+    let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+    assert!(
+        (stack_base..=stack_top).contains(&sp)
+            && uspace.can_access_range(stack_base, USER_STACK_SIZE, flags),
+        "initial user sp {:#x} outside the mapped user stack [{:#x}, {:#x}]",
+        sp,
+        stack_base,
+        stack_top,
+    );
+}
+
+/// Prints the initial stack that `init_user_stack` built, from `sp` to the
+/// top of `uspace`, with the `debug-stack` feature: one line per word of
+/// the pointer block, annotated as crt0 reads it (argc, the argv and envp
+/// pointers and the strings they point to, the auxiliary vector), then the
+/// strings themselves at their addresses.
+///
+/// The stack is read straight from its frames (through `phys_to_virt`),
+/// as the dump is meant to show what is really in memory.
+pub fn debug_dump_stack(uspace: &AddrSpace, sp: VirtAddr) {
+    if !cfg!(feature = "debug-stack") {
+        return;
+    }
+    let top = uspace.end().as_usize();
+    let sp = sp.as_usize();
+    let word_at = |addr: usize| {
+        (addr + size_of::<usize>() <= top)
+            .then(|| read_stack_bytes(uspace, addr, size_of::<usize>()))
+            .flatten()
+            .map(|b| usize::from_ne_bytes(b.try_into().unwrap()))
+    };
+    // Only strings inside the written region are followed.
+    let string_at = |addr: usize| -> Option<String> {
+        if !(sp..top).contains(&addr) {
+            return None;
+        }
+        let bytes = read_stack_bytes(uspace, addr, top - addr)?;
+        let len = bytes.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..len]).into())
+    };
+    println!(
+        "Initial user stack: sp={:#x}, {} bytes up to {:#x}",
+        sp,
+        top - sp,
+        top
+    );
+
+    let mut addr = sp;
+    let line = |addr: &mut usize, note: &str| -> Option<usize> {
+        let word = word_at(*addr)?;
+        println!("  {:#x}: {:#018x}  {}", *addr, word, note);
+        *addr += size_of::<usize>();
+        Some(word)
+    };
+    let Some(argc) = line(&mut addr, "argc") else {
+        return;
+    };
+    let mut strings_start = top;
+    for (name, count) in [("argv", Some(argc)), ("envp", None)] {
+        for i in 0.. {
+            let Some(ptr) = word_at(addr) else {
+                return;
+            };
+            let note = match (ptr, string_at(ptr)) {
+                (0, _) => alloc::format!("{name}[{i}] = NULL"),
+                (_, Some(s)) => {
+                    strings_start = strings_start.min(ptr);
+                    alloc::format!("{name}[{i}] -> {s:?}")
+                }
+                (_, None) => alloc::format!("{name}[{i}] -> ?? (not a string on the stack)"),
+            };
+            line(&mut addr, &note);
+            if ptr == 0 || count.is_some_and(|n| i >= n) {
+                break;
+            }
+        }
+    }
+    loop {
+        let (Some(key), Some(_)) = (word_at(addr), word_at(addr + size_of::<usize>())) else {
+            return;
+        };
+        line(&mut addr, &alloc::format!("auxv key {}", auxv_name(key)));
+        line(&mut addr, "auxv value");
+        if key == 0 {
+            break;
+        }
+    }
+    if addr < strings_start {
+        println!("  {:#x}: ({} bytes unused)", addr, strings_start - addr);
+    }
+    let mut addr = strings_start;
+    while let Some(s) = string_at(addr) {
+        println!("  {:#x}: {:?}", addr, s);
+        addr += s.len() + 1;
+    }
+}
+
+/// Name of an auxiliary vector key, as far as this kernel has any.
+fn auxv_name(key: usize) -> String {
+    match key {
+        AT_NULL => "AT_NULL".into(),
+        AT_PAGESZ => "AT_PAGESZ".into(),
+        _ => alloc::format!("{key}"),
+    }
+}
+
+/// Reads `len` bytes of user memory at `addr` through the kernel's linear
+/// mapping of the frames behind them; `None` if any page is unmapped.
+fn read_stack_bytes(uspace: &AddrSpace, addr: usize, len: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(len);
+    let mut vaddr = addr;
+    while vaddr < addr + len {
+        let (paddr, _, _) = uspace.page_table().query(va!(vaddr)).ok()?;
+        let chunk = (PAGE_SIZE_4K - vaddr % PAGE_SIZE_4K).min(addr + len - vaddr);
+        // SAFETY: `paddr` is mapped for the `chunk` bytes up to the end of
+        // its page, and the stack is not changed while it is dumped.
+        let src = unsafe { core::slice::from_raw_parts(phys_to_virt(paddr).as_ptr(), chunk) };
+        bytes.extend_from_slice(src);
+        vaddr += chunk;
+    }
+    Some(bytes)
+}
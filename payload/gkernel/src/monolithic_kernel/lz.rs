@@ -0,0 +1,69 @@
+//! Decompressor for packed user images (`compressed-app` feature).
+//!
+//! `cargo xtask run --user-app` packs the program when the guest is built
+//! with `compressed-app` (see `compress_app` in xtask). A packed image is a
+//! 16-byte header, `MAGIC`, the unpacked length as a little-endian `u32`
+//! and its 64-bit FNV-1a hash, followed by a stream of tokens, each
+//! starting with a control byte `c`:
+//!
+//! - `c < 0x80`: the next `c + 1` bytes are literals;
+//! - `c >= 0x80`: copy `c - 0x80 + 3` bytes from `offset` bytes back in
+//!   the output (a little-endian `u16` after `c`, at least 1). The copy may
+//!   overlap the bytes it produces, which repeats them.
+
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+
+use super::apps::Integrity;
+
+/// First bytes of a packed image.
+pub const MAGIC: &[u8; 4] = b"GKLZ";
+const HEADER_SIZE: usize = 16;
+/// Shortest match a copy token encodes.
+const MIN_MATCH: usize = 3;
+
+/// Unpacks `packed` into a buffer from the kernel heap and checks that it
+/// came out with the length and hash the header promises.
+///
+/// A malformed stream or a mismatch (`ENOEXEC`) means the image was
+/// damaged on its way into the guest; running out of heap is `ENOMEM`.
+pub fn decompress(packed: &[u8]) -> LinuxResult<Vec<u8>> {
+    if packed.len() < HEADER_SIZE || !packed.starts_with(MAGIC) {
+        return Err(LinuxError::ENOEXEC);
+    }
+    let expected = Integrity {
+        len: u32::from_le_bytes(packed[4..8].try_into().unwrap()) as usize,
+        fnv1a: u64::from_le_bytes(packed[8..16].try_into().unwrap()),
+    };
+    let mut out = Vec::new();
+    out.try_reserve_exact(expected.len)
+        .map_err(|_| LinuxError::ENOMEM)?;
+
+    let mut input = &packed[HEADER_SIZE..];
+    while let Some((&c, rest)) = input.split_first() {
+        if c < 0x80 {
+            let n = c as usize + 1;
+            let literals = rest.get(..n).ok_or(LinuxError::ENOEXEC)?;
+            out.extend_from_slice(literals);
+            input = &rest[n..];
+        } else {
+            let n = (c - 0x80) as usize + MIN_MATCH;
+            let offset = rest.get(..2).ok_or(LinuxError::ENOEXEC)?;
+            let offset = u16::from_le_bytes(offset.try_into().unwrap()) as usize;
+            if offset == 0 || offset > out.len() {
+                return Err(LinuxError::ENOEXEC);
+            }
+            for _ in 0..n {
+                out.push(out[out.len() - offset]);
+            }
+            input = &rest[2..];
+        }
+        if out.len() > expected.len {
+            return Err(LinuxError::ENOEXEC);
+        }
+    }
+    if Integrity::of(&out) != expected {
+        return Err(LinuxError::ENOEXEC);
+    }
+    Ok(out)
+}
@@ -0,0 +1,595 @@
+//! A flat in-memory filesystem, `SYS_OPENAT`, `SYS_TRUNCATE`, `SYS_STATX`,
+//! `SYS_UTIMENSAT`, `SYS_GETCWD` and `SYS_CHDIR`.
+//!
+//! Files live in one global namespace keyed by absolute path, and
+//! everything is lost when the guest shuts down. Directories are implicit:
+//! `/` and every proper prefix of a file's path ending at a `/` is one, so
+//! creating `/a/b` makes `/a` appear. A directory can be opened read-only
+//! and listed, and goes away with the last file under it. This is enough
+//! for programs that write a file and read it back, or enumerate `/`.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::io::SeekFrom;
+use std::sync::Mutex;
+
+use super::fd::{FileLike, FileStat, PollState, S_IFDIR, S_IFREG};
+use super::process::Process;
+use super::{apps, uaccess};
+
+// open flags (uapi/asm-generic/fcntl.h; x86_64 uses the same values).
+const O_ACCMODE: usize = 0o3;
+const O_RDONLY: usize = 0o0;
+const O_WRONLY: usize = 0o1;
+const O_RDWR: usize = 0o2;
+const O_CREAT: usize = 0o100;
+const O_EXCL: usize = 0o200;
+const O_TRUNC: usize = 0o1000;
+const O_APPEND: usize = 0o2000;
+const O_CLOEXEC: usize = 0o2000000;
+// aarch64 is the exception for O_DIRECTORY (uapi/asm/fcntl.h).
+#[cfg(not(target_arch = "aarch64"))]
+const O_DIRECTORY: usize = 0o200000;
+#[cfg(target_arch = "aarch64")]
+const O_DIRECTORY: usize = 0o40000;
+
+// d_type values of a `linux_dirent64`.
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+
+/// Inode number reported for every directory, which have no inode of their
+/// own. Files are numbered from 2.
+const DIR_INO: u64 = 1;
+
+/// Largest size a file may grow to (`EFBIG`), so that a write at a wild
+/// offset cannot exhaust guest memory.
+const MAX_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// `dirfd` meaning "relative to the current directory" (`Process::cwd`).
+const AT_FDCWD: isize = -100;
+
+// statx flags (uapi/linux/fcntl.h). There are no symlinks, automounts or
+// remote caches, so all but AT_EMPTY_PATH change nothing.
+const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+const AT_NO_AUTOMOUNT: usize = 0x800;
+const AT_EMPTY_PATH: usize = 0x1000;
+const AT_STATX_SYNC_TYPE: usize = 0x6000;
+
+// statx mask bits (uapi/linux/stat.h).
+const STATX_TYPE: u32 = 0x1;
+const STATX_MODE: u32 = 0x2;
+const STATX_INO: u32 = 0x100;
+const STATX_SIZE: u32 = 0x200;
+const STATX_RESERVED: u32 = 0x8000_0000;
+
+/// `struct statx` is 256 bytes on every architecture.
+const STATX_BUF_SIZE: usize = 256;
+
+/// The contents of a file, shared by every open of it.
+struct Inode {
+    ino: u64,
+    data: Mutex<Vec<u8>>,
+}
+
+impl Inode {
+    fn stat(&self) -> FileStat {
+        FileStat {
+            mode: S_IFREG | 0o644,
+            ino: self.ino,
+            size: self.data.lock().len() as u64,
+        }
+    }
+
+    /// Sets the size to `len`, zero-filling on growth.
+    fn truncate(&self, len: u64) -> LinuxResult {
+        if len > MAX_FILE_SIZE as u64 {
+            return Err(LinuxError::EFBIG);
+        }
+        self.data.lock().resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+/// What a directory reports to `statx`: they are all alike.
+const DIR_STAT: FileStat = FileStat {
+    mode: S_IFDIR | 0o755,
+    ino: DIR_INO,
+    size: 0,
+};
+
+static FILES: Mutex<BTreeMap<String, Arc<Inode>>> = Mutex::new(BTreeMap::new());
+static NEXT_INO: AtomicU64 = AtomicU64::new(DIR_INO + 1);
+
+/// Whether the directory `dir` (a path without the trailing `/`, so `""`
+/// for the root) exists, i.e. has a file somewhere below it.
+fn is_dir(files: &BTreeMap<String, Arc<Inode>>, dir: &str) -> bool {
+    let prefix = alloc::format!("{dir}/");
+    dir.is_empty()
+        || files
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(path, _)| path.starts_with(&prefix))
+}
+
+/// An open in-memory file: the inode plus this open's cursor and mode.
+///
+/// Descriptors duplicated from one another share a `MemFile`, and with it
+/// the cursor, as POSIX requires of a shared open file description.
+pub struct MemFile {
+    inode: Arc<Inode>,
+    offset: Mutex<usize>,
+    readable: bool,
+    writable: bool,
+    append: bool,
+}
+
+impl FileLike for MemFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let mut offset = self.offset.lock();
+        let n = self.read_at(*offset as u64, buf)?;
+        *offset += n;
+        Ok(n)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let mut offset = self.offset.lock();
+        if self.append {
+            *offset = self.inode.data.lock().len();
+        }
+        let n = self.write_at(*offset as u64, buf)?;
+        *offset += n;
+        Ok(n)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        if !self.readable {
+            return Err(LinuxError::EBADF);
+        }
+        let data = self.inode.data.lock();
+        let start = (offset.min(data.len() as u64)) as usize;
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    /// Writing past the end fills the gap with zeros.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        if !self.writable {
+            return Err(LinuxError::EBADF);
+        }
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .filter(|&end| end <= MAX_FILE_SIZE as u64)
+            .ok_or(LinuxError::EFBIG)? as usize;
+        let start = offset as usize;
+        let mut data = self.inode.data.lock();
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, len: u64) -> LinuxResult {
+        if !self.writable {
+            return Err(LinuxError::EINVAL);
+        }
+        self.inode.truncate(len)
+    }
+
+    fn poll(&self) -> PollState {
+        // Regular files never block.
+        PollState {
+            readable: true,
+            writable: true,
+        }
+    }
+
+    fn stat(&self) -> FileStat {
+        self.inode.stat()
+    }
+
+    /// Seeks within `[0, size]`. Linux also allows seeking past the end,
+    /// leaving a hole for the next write to fill; this filesystem has no
+    /// holes, so such offsets are rejected with `EINVAL` like negative ones.
+    fn seek(&self, pos: SeekFrom) -> LinuxResult<usize> {
+        let size = self.inode.data.lock().len();
+        let mut offset = self.offset.lock();
+        let (base, delta) = match pos {
+            SeekFrom::Start(off) => (0, off as i64),
+            SeekFrom::Current(off) => (*offset, off),
+            SeekFrom::End(off) => (size, off),
+        };
+        let target = (base as i64)
+            .checked_add(delta)
+            .filter(|t| (0..=size as i64).contains(t))
+            .ok_or(LinuxError::EINVAL)?;
+        *offset = target as usize;
+        Ok(*offset)
+    }
+}
+
+/// An open directory: its path and how far it has been listed.
+pub struct MemDir {
+    /// The path without the trailing `/` (`""` for the root).
+    path: String,
+    /// Index of the next entry to list; also the `d_off` cookie of the
+    /// entry before it.
+    cursor: Mutex<usize>,
+}
+
+impl MemDir {
+    /// The directory's entries, in order: `.` and `..`, then each file and
+    /// subdirectory by name.
+    fn entries(&self) -> Vec<(String, u64, u8)> {
+        let mut entries = alloc::vec![
+            (".".into(), DIR_INO, DT_DIR),
+            ("..".into(), DIR_INO, DT_DIR)
+        ];
+        let prefix = alloc::format!("{}/", self.path);
+        let files = FILES.lock();
+        let below = files
+            .range(prefix.clone()..)
+            .take_while(|(path, _)| path.starts_with(&prefix));
+        for (path, inode) in below {
+            let entry = match path[prefix.len()..].split_once('/') {
+                Some((subdir, _)) => (subdir.into(), DIR_INO, DT_DIR),
+                None => (path[prefix.len()..].into(), inode.ino, DT_REG),
+            };
+            // Files below one subdirectory sort next to each other.
+            if entries.last().is_none_or(|last| last.0 != entry.0) {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+}
+
+impl FileLike for MemDir {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EISDIR)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        // Directories are only ever opened read-only.
+        Err(LinuxError::EBADF)
+    }
+
+    fn poll(&self) -> PollState {
+        PollState {
+            readable: true,
+            writable: false,
+        }
+    }
+
+    fn stat(&self) -> FileStat {
+        DIR_STAT
+    }
+
+    /// Positions are the `d_off` cookies handed out by `read_dir`; only
+    /// returning to one of those (or to 0) is supported.
+    fn seek(&self, pos: SeekFrom) -> LinuxResult<usize> {
+        let mut cursor = self.cursor.lock();
+        match pos {
+            SeekFrom::Start(off) => *cursor = off as usize,
+            SeekFrom::Current(0) => {}
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(*cursor)
+    }
+
+    /// Packs as many whole records as fit; `EINVAL` if not even the next
+    /// one does. Each record is `d_ino`, `d_off`, `d_reclen`, `d_type` and
+    /// the NUL-terminated name, padded to a multiple of 8 bytes.
+    fn read_dir(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        const HEADER_SIZE: usize = 19;
+        let mut cursor = self.cursor.lock();
+        let mut used = 0;
+        for (name, ino, d_type) in self.entries().into_iter().skip(*cursor) {
+            let reclen = (HEADER_SIZE + name.len() + 1).next_multiple_of(8);
+            if used + reclen > buf.len() {
+                if used == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                break;
+            }
+            let rec = &mut buf[used..used + reclen];
+            rec.fill(0);
+            rec[0..8].copy_from_slice(&ino.to_ne_bytes());
+            rec[8..16].copy_from_slice(&((*cursor + 1) as i64).to_ne_bytes());
+            rec[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+            rec[18] = d_type;
+            rec[HEADER_SIZE..HEADER_SIZE + name.len()].copy_from_slice(name.as_bytes());
+            used += reclen;
+            *cursor += 1;
+        }
+        Ok(used)
+    }
+}
+
+/// Opens `path`, creating or truncating it as `flags` ask.
+fn open(path: &str, flags: usize) -> LinuxResult<Arc<dyn FileLike>> {
+    let (readable, writable) = match flags & O_ACCMODE {
+        O_RDONLY => (true, false),
+        O_WRONLY => (false, true),
+        O_RDWR => (true, true),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let mut files = FILES.lock();
+    let dir = path.trim_end_matches('/');
+    if is_dir(&files, dir) {
+        if writable || flags & O_CREAT != 0 {
+            return Err(LinuxError::EISDIR);
+        }
+        return Ok(Arc::new(MemDir {
+            path: dir.into(),
+            cursor: Mutex::new(0),
+        }));
+    }
+    if flags & O_DIRECTORY != 0 || dir.len() != path.len() {
+        return Err(if files.contains_key(dir) {
+            LinuxError::ENOTDIR
+        } else {
+            LinuxError::ENOENT
+        });
+    }
+    // A file cannot also be a directory on the way to another one.
+    let mut parent = path;
+    while let Some((up, _)) = parent.rsplit_once('/') {
+        if files.contains_key(up) {
+            return Err(LinuxError::ENOTDIR);
+        }
+        parent = up;
+    }
+    let inode = match files.get(path) {
+        Some(_) if flags & (O_CREAT | O_EXCL) == O_CREAT | O_EXCL => {
+            return Err(LinuxError::EEXIST);
+        }
+        Some(inode) => inode.clone(),
+        None if flags & O_CREAT != 0 => {
+            let inode = Arc::new(Inode {
+                ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+                data: Mutex::new(Vec::new()),
+            });
+            files.insert(path.into(), inode.clone());
+            inode
+        }
+        None => return Err(LinuxError::ENOENT),
+    };
+    if flags & O_TRUNC != 0 && writable {
+        inode.data.lock().clear();
+    }
+    Ok(Arc::new(MemFile {
+        inode,
+        offset: Mutex::new(0),
+        readable,
+        writable,
+        append: flags & O_APPEND != 0,
+    }))
+}
+
+/// Reads the path at `path_ptr` and makes it absolute, resolving a
+/// relative one against `dirfd` as the `*at` syscalls do.
+fn user_path(process: &Process, dirfd: usize, path_ptr: usize) -> LinuxResult<String> {
+    let path = uaccess::read_cstr(&process.aspace.lock(), path_ptr)?;
+    absolute_path(process, dirfd, path)
+}
+
+/// Makes `path` absolute, as `user_path` does for a path in user memory.
+/// The path is joined to its base as it is: `.` and `..` are not
+/// resolved, so they only name something if a file was created with them
+/// in its path.
+fn absolute_path(process: &Process, dirfd: usize, path: String) -> LinuxResult<String> {
+    if path.is_empty() {
+        Err(LinuxError::ENOENT)
+    } else if path.starts_with('/') {
+        Ok(path)
+    } else if dirfd as isize == AT_FDCWD {
+        let cwd = process.cwd.lock();
+        Ok(alloc::format!("{}/{path}", cwd.trim_end_matches('/')))
+    } else {
+        // Only the current directory can be the base of a relative path;
+        // any other descriptor fails as if it were not a directory.
+        process.fd_table.lock().get(dirfd)?;
+        Err(LinuxError::ENOTDIR)
+    }
+}
+
+// ── SYS_OPENAT ──
+
+/// Opens a file of the in-memory filesystem. `mode` is accepted and
+/// ignored, since there are no permissions.
+pub fn sys_openat(
+    process: &Process,
+    dirfd: usize,
+    path_ptr: usize,
+    flags: usize,
+) -> LinuxResult<usize> {
+    let path = user_path(process, dirfd, path_ptr)?;
+    let file = open(&path, flags)?;
+    process
+        .fd_table
+        .lock()
+        .add(file, flags, flags & O_CLOEXEC != 0)
+}
+
+// ── SYS_TRUNCATE ──
+
+/// Sets the size of the file at `path`, which need not be open. The
+/// embedded programs under `/bin` are read-only (`EROFS`).
+pub fn sys_truncate(process: &Process, path_ptr: usize, len: u64) -> LinuxResult<usize> {
+    let path = user_path(process, AT_FDCWD as usize, path_ptr)?;
+    if apps::find(&path).is_some() {
+        return Err(LinuxError::EROFS);
+    }
+    let files = FILES.lock();
+    match files.get(&path) {
+        Some(inode) => inode.truncate(len).map(|()| 0),
+        None if is_dir(&files, path.trim_end_matches('/')) => Err(LinuxError::EISDIR),
+        None => Err(LinuxError::ENOENT),
+    }
+}
+
+/// Describes the file at `path`, resolved against `dirfd`; with
+/// `empty_path` (`AT_EMPTY_PATH`) an empty path is `dirfd` itself.
+fn stat_at(
+    process: &Process,
+    dirfd: usize,
+    path: String,
+    empty_path: bool,
+) -> LinuxResult<FileStat> {
+    if path.is_empty() && empty_path {
+        return if dirfd as isize == AT_FDCWD {
+            Ok(DIR_STAT)
+        } else {
+            Ok(process.fd_table.lock().get(dirfd)?.stat())
+        };
+    }
+    let path = absolute_path(process, dirfd, path)?;
+    let files = FILES.lock();
+    let dir = path.trim_end_matches('/');
+    match files.get(dir) {
+        Some(_) if dir.len() != path.len() => Err(LinuxError::ENOTDIR),
+        Some(inode) => Ok(inode.stat()),
+        None if is_dir(&files, dir) => Ok(DIR_STAT),
+        None => Err(LinuxError::ENOENT),
+    }
+}
+
+// ── SYS_STATX ──
+
+/// Describes the file at `path`, or with `AT_EMPTY_PATH` and an empty
+/// path the file open as `dirfd`. Of the fields asked for in `mask`, the
+/// type, mode, inode number and size are filled in and reported in
+/// `stx_mask`; all else stays zero.
+pub fn sys_statx(
+    process: &Process,
+    dirfd: usize,
+    path_ptr: usize,
+    flags: usize,
+    mask: usize,
+    buf_ptr: usize,
+) -> LinuxResult<usize> {
+    let mask = mask as u32;
+    if flags & !(AT_SYMLINK_NOFOLLOW | AT_NO_AUTOMOUNT | AT_EMPTY_PATH | AT_STATX_SYNC_TYPE) != 0
+        || flags & AT_STATX_SYNC_TYPE == AT_STATX_SYNC_TYPE
+        || mask & STATX_RESERVED != 0
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let path = uaccess::read_cstr(&process.aspace.lock(), path_ptr)?;
+    let stat = stat_at(process, dirfd, path, flags & AT_EMPTY_PATH != 0)?;
+
+    let filled = mask & (STATX_TYPE | STATX_MODE | STATX_INO | STATX_SIZE);
+    let mut mode = 0;
+    if filled & STATX_TYPE != 0 {
+        mode |= stat.mode & 0o170000;
+    }
+    if filled & STATX_MODE != 0 {
+        mode |= stat.mode & 0o7777;
+    }
+    let mut buf = [0u8; STATX_BUF_SIZE];
+    buf[0..4].copy_from_slice(&filled.to_ne_bytes());
+    buf[28..30].copy_from_slice(&(mode as u16).to_ne_bytes());
+    if filled & STATX_INO != 0 {
+        buf[32..40].copy_from_slice(&stat.ino.to_ne_bytes());
+    }
+    if filled & STATX_SIZE != 0 {
+        buf[40..48].copy_from_slice(&stat.size.to_ne_bytes());
+    }
+    uaccess::copy_to_user(&mut process.aspace.lock(), buf_ptr, &buf)?;
+    Ok(0)
+}
+
+// ── SYS_UTIMENSAT ──
+
+// Special tv_nsec values for utimensat (uapi/linux/stat.h).
+const UTIME_NOW: i64 = (1 << 30) - 1;
+const UTIME_OMIT: i64 = (1 << 30) - 2;
+
+/// Files keep no timestamps (`statx` reports none), so setting them is
+/// best effort in the extreme: once the file and `times` check out, the
+/// call succeeds and the times are dropped. A null `path_ptr` names
+/// `dirfd` itself, which is how `futimens` is made.
+pub fn sys_utimensat(
+    process: &Process,
+    dirfd: usize,
+    path_ptr: usize,
+    times_ptr: usize,
+    flags: usize,
+) -> LinuxResult<usize> {
+    if flags & !(AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if times_ptr != 0 {
+        // Access and modification time, two `struct timespec`s.
+        let mut raw = [0u8; 32];
+        uaccess::copy_from_user(&process.aspace.lock(), &mut raw, times_ptr)?;
+        for ts in raw.chunks(16) {
+            let nsec = i64::from_ne_bytes(ts[8..].try_into().unwrap());
+            if !(0..1_000_000_000).contains(&nsec) && nsec != UTIME_NOW && nsec != UTIME_OMIT {
+                return Err(LinuxError::EINVAL);
+            }
+        }
+    }
+    if path_ptr == 0 {
+        if dirfd as isize == AT_FDCWD {
+            return Err(LinuxError::EFAULT);
+        }
+        process.fd_table.lock().get(dirfd)?;
+    } else {
+        let path = uaccess::read_cstr(&process.aspace.lock(), path_ptr)?;
+        stat_at(process, dirfd, path, flags & AT_EMPTY_PATH != 0)?;
+    }
+    Ok(0)
+}
+
+// ── SYS_GETCWD ──
+
+/// Copies the current directory, NUL-terminated, to the `size`-byte buffer
+/// at `buf`, and returns the length copied including the NUL (the raw
+/// syscall's result; libc returns `buf`). A buffer too small for it fails
+/// with `ERANGE`.
+pub fn sys_getcwd(process: &Process, buf: usize, size: usize) -> LinuxResult<usize> {
+    let mut cwd = process.cwd.lock().clone().into_bytes();
+    cwd.push(0);
+    if size < cwd.len() {
+        return Err(LinuxError::ERANGE);
+    }
+    uaccess::copy_to_user(&mut process.aspace.lock(), buf, &cwd)?;
+    Ok(cwd.len())
+}
+
+// ── SYS_CHDIR ──
+
+/// Makes the directory at `path` the current one, against which relative
+/// paths are resolved from then on. Directories only exist while there are
+/// files below them, so the current directory can disappear later on; the
+/// process then keeps its path, and files created under it bring the
+/// directory back.
+pub fn sys_chdir(process: &Process, path_ptr: usize) -> LinuxResult<usize> {
+    let path = user_path(process, AT_FDCWD as usize, path_ptr)?;
+    let dir = path.trim_end_matches('/');
+    let files = FILES.lock();
+    if !is_dir(&files, dir) {
+        // Any file on the way makes the path not a directory.
+        let mut prefix = dir;
+        while !prefix.is_empty() {
+            if files.contains_key(prefix) {
+                return Err(LinuxError::ENOTDIR);
+            }
+            prefix = prefix.rsplit_once('/').map_or("", |(up, _)| up);
+        }
+        return Err(LinuxError::ENOENT);
+    }
+    *process.cwd.lock() = if dir.is_empty() {
+        "/".into()
+    } else {
+        dir.into()
+    };
+    Ok(0)
+}
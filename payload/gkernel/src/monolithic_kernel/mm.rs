@@ -0,0 +1,447 @@
+//! Changing user memory mappings: `SYS_BRK`, `SYS_MMAP`, `SYS_MUNMAP`,
+//! `SYS_MREMAP`, `SYS_MSYNC`, and the memory locking calls (`SYS_MLOCK`
+//! and its kin).
+//!
+//! A mapping here is any range of user pages mapped with the same
+//! protection, whatever created it. Free space is whatever the address
+//! space has no area for, as `AddrSpace::find_free_area` reports it, so
+//! placing, resizing and unmapping never need bookkeeping of their own:
+//! `munmap` hands a range back simply by unmapping it. The brk heap is the
+//! exception: its break is not a page boundary, so each process keeps it
+//! in a `Heap`.
+
+use alloc::vec;
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use std::os::arceos::modules::axhal::mem::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange, va};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+
+use super::aspace::{self, Purpose};
+use super::process::Process;
+use super::{BRK_MAX, loader};
+
+/// Protection of the brk heap.
+const HEAP_FLAGS: MappingFlags = MappingFlags::READ
+    .union(MappingFlags::WRITE)
+    .union(MappingFlags::USER);
+
+// mmap protection and flags (uapi/asm-generic/mman-common.h and
+// mman.h; the same on every architecture here).
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+const PROT_EXEC: usize = 4;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+const MAP_NORESERVE: usize = 0x4000;
+const MAP_POPULATE: usize = 0x8000;
+
+// mremap flags (uapi/linux/mman.h).
+const MREMAP_MAYMOVE: usize = 1;
+const MREMAP_FIXED: usize = 2;
+
+// msync flags (uapi/asm-generic/mman-common.h).
+const MS_ASYNC: usize = 1;
+const MS_INVALIDATE: usize = 2;
+const MS_SYNC: usize = 4;
+
+// mlockall flags (uapi/asm-generic/mman.h).
+const MCL_CURRENT: usize = 1;
+const MCL_FUTURE: usize = 2;
+const MCL_ONFAULT: usize = 4;
+
+/// The brk heap of a process: the pages from the initial program break
+/// (`BRK_GAP` above the image, see `loader::LoadedImage::brk_start`) up to
+/// the current break, rounded up to a page. A fork gets a copy; `execve`
+/// starts a new, empty one above the new image.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Heap {
+    start: usize,
+    brk: usize,
+}
+
+impl Heap {
+    /// An empty heap whose break starts at `start`, a page boundary.
+    pub fn new(start: VirtAddr) -> Self {
+        Self {
+            start: start.as_usize(),
+            brk: start.as_usize(),
+        }
+    }
+
+    /// Where mmap places mappings from: past the largest heap, as
+    /// `loader::LoadedImage::mmap_base` has it.
+    pub fn mmap_base(&self) -> VirtAddr {
+        va!(self.start + BRK_MAX)
+    }
+}
+
+/// Rounds `size` up to whole pages; `None` if it is 0 or overflows.
+fn page_len(size: usize) -> Option<usize> {
+    (size != 0)
+        .then(|| size.checked_next_multiple_of(PAGE_SIZE_4K))
+        .flatten()
+}
+
+/// Whether `[start, start + size)` is inside `uspace` and unmapped; a
+/// range wrapping past the top of the address space is neither.
+fn is_free(uspace: &AddrSpace, start: VirtAddr, size: usize) -> bool {
+    start.as_usize().checked_add(size).is_some()
+        && uspace.contains_range(start, size)
+        && uspace.find_free_area(start, size, VirtAddrRange::from_start_size(start, size))
+            == Some(start)
+}
+
+/// Protection of `[start, start + size)`, which must be mapped throughout
+/// with the same one (`EFAULT` otherwise).
+fn mapping_flags(uspace: &AddrSpace, start: VirtAddr, size: usize) -> LinuxResult<MappingFlags> {
+    if !uspace.can_access_range(start, size, MappingFlags::USER) {
+        return Err(LinuxError::EFAULT);
+    }
+    let flags_at = |vaddr| {
+        uspace
+            .page_table()
+            .query(vaddr)
+            .map(|(_, flags, _)| flags)
+            .map_err(|_| LinuxError::EFAULT)
+    };
+    let flags = flags_at(start)?;
+    for offset in (PAGE_SIZE_4K..size).step_by(PAGE_SIZE_4K) {
+        if flags_at(start + offset)? != flags {
+            return Err(LinuxError::EFAULT);
+        }
+    }
+    Ok(flags)
+}
+
+/// Maps `new_size` bytes at `new` with `flags`, copies the first `len`
+/// bytes of `old` there page by page, and unmaps `old_size` bytes at `old`.
+fn move_mapping(
+    uspace: &mut AddrSpace,
+    old: VirtAddr,
+    old_size: usize,
+    new: VirtAddr,
+    new_size: usize,
+    flags: MappingFlags,
+) -> LinuxResult {
+    uspace
+        .map_alloc(new, new_size, flags, true)
+        .map_err(|_| LinuxError::ENOMEM)?;
+    let mut page = vec![0u8; PAGE_SIZE_4K];
+    for offset in (0..old_size.min(new_size)).step_by(PAGE_SIZE_4K) {
+        uspace
+            .read(old + offset, &mut page)
+            .and_then(|()| uspace.write(new + offset, &page))
+            .map_err(|_| LinuxError::EFAULT)?;
+    }
+    uspace.unmap(old, old_size).map_err(|_| LinuxError::EFAULT)
+}
+
+// ── SYS_BRK ──
+
+/// Moves the program break to `addr` and returns the new break. Growing
+/// maps the pages up to the new break, zeroed, read-write; shrinking
+/// unmaps the whole pages above it. As on Linux, `brk` never fails with an
+/// errno: given 0, or a break it cannot move to (below the initial break,
+/// more than `BRK_MAX` above it, into the stack guard, or over pages something
+/// else has mapped since, or when frames run out), it returns the current
+/// break unchanged.
+pub fn sys_brk(process: &Process, addr: usize) -> LinuxResult<usize> {
+    let mut heap = process.heap.lock();
+    let mut uspace = process.aspace.lock();
+    let guard = loader::stack_guard(&uspace).start.as_usize();
+    if addr < heap.start || addr - heap.start > BRK_MAX || addr > guard {
+        return Ok(heap.brk);
+    }
+    let old_top = heap.brk.next_multiple_of(PAGE_SIZE_4K);
+    let new_top = addr.next_multiple_of(PAGE_SIZE_4K);
+    if new_top > old_top {
+        let grow = new_top - old_top;
+        if !is_free(&uspace, va!(old_top), grow)
+            || uspace
+                .map_alloc(va!(old_top), grow, HEAP_FLAGS, true)
+                .is_err()
+        {
+            return Ok(heap.brk);
+        }
+    } else if new_top < old_top && uspace.unmap(va!(new_top), old_top - new_top).is_err() {
+        return Ok(heap.brk);
+    }
+    if new_top == heap.start {
+        aspace::untag(&uspace, va!(heap.start));
+    } else {
+        aspace::tag(
+            &uspace,
+            va!(heap.start),
+            new_top - heap.start,
+            Purpose::Heap,
+        );
+    }
+    heap.brk = addr;
+    Ok(addr)
+}
+
+// ── SYS_MMAP / SYS_MUNMAP ──
+
+/// Maps `len` bytes, rounded up to pages, of zeroed private memory and
+/// returns their start.
+///
+/// Only anonymous private mappings exist: `flags` must have
+/// `MAP_PRIVATE | MAP_ANONYMOUS`, and may add `MAP_FIXED`,
+/// `MAP_NORESERVE` and `MAP_POPULATE` (which change nothing, every page
+/// having its frame from the start); `fd` and `offset` are ignored, as
+/// for any anonymous mapping. Anything else fails with `EINVAL`: file and
+/// shared mappings, other flags, a `len` of 0 or one that takes `addr`
+/// past the top of the address space. The protection is `prot`'s,
+/// with read access added to any non-empty one, as not every
+/// architecture's page tables can express write or execute alone.
+/// `PROT_NONE` is `EINVAL` too: a page here is inaccessible only by not
+/// being mapped.
+///
+/// Without `MAP_FIXED`, the mapping goes to the first free range at or
+/// above `addr` (taken as a hint) or the process's mmap base (see
+/// `loader::LoadedImage::mmap_base`), whichever is higher, and below the
+/// stack guard; failing that, to the first one above the mmap base;
+/// `ENOMEM` if there is none. With it, it goes at `addr`,
+/// which must be page-aligned and free up to the end of the mapping:
+/// replacing a mapping is not supported (`EINVAL`), and neither is
+/// mapping over the stack guard.
+pub fn sys_mmap(
+    process: &Process,
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+) -> LinuxResult<usize> {
+    let Some(len) = page_len(len) else {
+        return Err(LinuxError::EINVAL);
+    };
+    if flags & !(MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED | MAP_NORESERVE | MAP_POPULATE) != 0
+        || flags & (MAP_PRIVATE | MAP_ANONYMOUS) != MAP_PRIVATE | MAP_ANONYMOUS
+        || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0
+        || prot == 0
+        || addr.checked_add(len).is_none()
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let mut mapping = MappingFlags::READ | MappingFlags::USER;
+    if prot & PROT_WRITE != 0 {
+        mapping |= MappingFlags::WRITE;
+    }
+    if prot & PROT_EXEC != 0 {
+        mapping |= MappingFlags::EXECUTE;
+    }
+
+    let mmap_base = process.heap.lock().mmap_base();
+    let mut uspace = process.aspace.lock();
+    let guard = loader::stack_guard(&uspace);
+    let start = if flags & MAP_FIXED != 0 {
+        let start = va!(addr);
+        if !addr.is_multiple_of(PAGE_SIZE_4K)
+            || !is_free(&uspace, start, len)
+            || VirtAddrRange::from_start_size(start, len).overlaps(guard)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        start
+    } else {
+        let hint = va!(addr
+            .next_multiple_of(PAGE_SIZE_4K)
+            .max(mmap_base.as_usize()));
+        let limit = VirtAddrRange::new(mmap_base.min(guard.start), guard.start);
+        uspace
+            .find_free_area(hint, len, limit)
+            .or_else(|| uspace.find_free_area(mmap_base, len, limit))
+            .ok_or(LinuxError::ENOMEM)?
+    };
+    uspace
+        .map_alloc(start, len, mapping, true)
+        .map_err(|_| LinuxError::ENOMEM)?;
+    aspace::tag(&uspace, start, len, Purpose::Mmap);
+    Ok(start.as_usize())
+}
+
+/// Unmaps the whole pages of `[addr, addr + len)`, whatever mapped them,
+/// and returns 0; pages in the range that are not mapped are skipped. The
+/// freed range is free for the next `mmap` at once. `addr` must be
+/// page-aligned and the range non-empty and inside the user address space
+/// (`EINVAL`).
+pub fn sys_munmap(process: &Process, addr: usize, len: usize) -> LinuxResult<usize> {
+    let Some(len) = page_len(len).filter(|&len| addr.checked_add(len).is_some()) else {
+        return Err(LinuxError::EINVAL);
+    };
+    let start = va!(addr);
+    let mut uspace = process.aspace.lock();
+    if !addr.is_multiple_of(PAGE_SIZE_4K) || !uspace.contains_range(start, len) {
+        return Err(LinuxError::EINVAL);
+    }
+    uspace.unmap(start, len).map_err(|_| LinuxError::EINVAL)?;
+    aspace::untag(&uspace, start);
+    Ok(0)
+}
+
+// ── SYS_MREMAP ──
+
+/// Resizes the mapping at `old_addr` and returns its (possibly new) start.
+///
+/// Shrinking unmaps the tail. Growing extends the mapping in place if the
+/// pages after it are free, or else, with `MREMAP_MAYMOVE`, moves it to the
+/// first free range above it large enough, copying its contents; neither
+/// reaches into the stack guard (see `loader::stack_guard`). With
+/// `MREMAP_FIXED` it moves to `new_addr`, replacing whatever was mapped
+/// there; the new and old ranges must not overlap. `MREMAP_DONTUNMAP` is
+/// not supported (`EINVAL`).
+pub fn sys_mremap(
+    process: &Process,
+    old_addr: usize,
+    old_size: usize,
+    new_size: usize,
+    flags: usize,
+    new_addr: usize,
+) -> LinuxResult<usize> {
+    let (Some(old_size), Some(new_size)) = (page_len(old_size), page_len(new_size)) else {
+        return Err(LinuxError::EINVAL);
+    };
+    if flags & !(MREMAP_MAYMOVE | MREMAP_FIXED) != 0
+        || (flags & MREMAP_FIXED != 0 && flags & MREMAP_MAYMOVE == 0)
+        || !old_addr.is_multiple_of(PAGE_SIZE_4K)
+        || old_addr.checked_add(old_size).is_none()
+        || (flags & MREMAP_FIXED != 0 && new_addr.checked_add(new_size).is_none())
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let old = va!(old_addr);
+    let mut uspace = process.aspace.lock();
+    let prot = mapping_flags(&uspace, old, old_size)?;
+
+    if flags & MREMAP_FIXED != 0 {
+        let overlaps = new_addr < old_addr + old_size && old_addr < new_addr + new_size;
+        if !new_addr.is_multiple_of(PAGE_SIZE_4K)
+            || overlaps
+            || !uspace.contains_range(va!(new_addr), new_size)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let new = va!(new_addr);
+        uspace
+            .unmap(new, new_size)
+            .map_err(|_| LinuxError::EINVAL)?;
+        move_mapping(&mut uspace, old, old_size, new, new_size, prot)?;
+        aspace::retag(&uspace, old, new, new_size);
+        return Ok(new_addr);
+    }
+
+    if new_size <= old_size {
+        if new_size < old_size {
+            uspace
+                .unmap(old + new_size, old_size - new_size)
+                .map_err(|_| LinuxError::EINVAL)?;
+        }
+        aspace::retag(&uspace, old, old, new_size);
+        return Ok(old_addr);
+    }
+    let grow = new_size - old_size;
+    let guard = loader::stack_guard(&uspace).start;
+    if old_addr
+        .checked_add(new_size)
+        .is_some_and(|end| end <= guard.as_usize())
+        && is_free(&uspace, old + old_size, grow)
+    {
+        uspace
+            .map_alloc(old + old_size, grow, prot, true)
+            .map_err(|_| LinuxError::ENOMEM)?;
+        aspace::retag(&uspace, old, old, new_size);
+        return Ok(old_addr);
+    }
+    if flags & MREMAP_MAYMOVE == 0 {
+        return Err(LinuxError::ENOMEM);
+    }
+    let limit = VirtAddrRange::new(uspace.base(), guard);
+    let new = uspace
+        .find_free_area(old, new_size, limit)
+        .ok_or(LinuxError::ENOMEM)?;
+    move_mapping(&mut uspace, old, old_size, new, new_size, prot)?;
+    aspace::retag(&uspace, old, new, new_size);
+    Ok(new.as_usize())
+}
+
+// ── SYS_MSYNC ──
+
+/// Flushes changes to a mapped file; a no-op here, since every mapping is
+/// anonymous memory and files live in memory only (like `fsync`). It still
+/// checks its arguments: `addr` must be page-aligned and `flags` valid
+/// (`EINVAL`), and `[addr, addr + len)` must be mapped throughout
+/// (`ENOMEM`).
+pub fn sys_msync(process: &Process, addr: usize, len: usize, flags: usize) -> LinuxResult<usize> {
+    if flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+        || flags & (MS_ASYNC | MS_SYNC) == MS_ASYNC | MS_SYNC
+        || !addr.is_multiple_of(PAGE_SIZE_4K)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let Some(len) = page_len(len) else {
+        return Ok(0);
+    };
+    check_mapped(process, addr, len).map(|()| 0)
+}
+
+/// Checks that `[addr, addr + len)`, whole pages, is mapped throughout
+/// (`ENOMEM` otherwise).
+fn check_mapped(process: &Process, addr: usize, len: usize) -> LinuxResult {
+    if addr.checked_add(len).is_none()
+        || !process
+            .aspace
+            .lock()
+            .can_access_range(va!(addr), len, MappingFlags::USER)
+    {
+        return Err(LinuxError::ENOMEM);
+    }
+    Ok(())
+}
+
+// ── SYS_MLOCK / SYS_MUNLOCK ──
+
+/// Nothing is ever paged out here (there is no swap), so all memory is
+/// effectively locked, and locking or unlocking a range only checks that
+/// the pages `[addr, addr + len)` touches are mapped (`ENOMEM` otherwise;
+/// `EINVAL` if the range wraps around). `addr` need not be page-aligned.
+/// A page of a `lazy-stack` stack not touched yet is not faulted in, but
+/// gets its frame on first touch as usual.
+fn check_lock_range(process: &Process, addr: usize, len: usize) -> LinuxResult<usize> {
+    if len == 0 {
+        return Ok(0);
+    }
+    let start = addr & !(PAGE_SIZE_4K - 1);
+    let len = (addr - start)
+        .checked_add(len)
+        .and_then(page_len)
+        .filter(|&len| start.checked_add(len).is_some())
+        .ok_or(LinuxError::EINVAL)?;
+    check_mapped(process, start, len).map(|()| 0)
+}
+
+pub fn sys_mlock(process: &Process, addr: usize, len: usize) -> LinuxResult<usize> {
+    check_lock_range(process, addr, len)
+}
+
+pub fn sys_munlock(process: &Process, addr: usize, len: usize) -> LinuxResult<usize> {
+    check_lock_range(process, addr, len)
+}
+
+// ── SYS_MLOCKALL / SYS_MUNLOCKALL ──
+
+/// All memory, present and future, is locked already (see `sys_mlock`):
+/// this only checks `flags`, which must ask for `MCL_CURRENT` or
+/// `MCL_FUTURE`, optionally with `MCL_ONFAULT` (`EINVAL` otherwise).
+pub fn sys_mlockall(flags: usize) -> LinuxResult<usize> {
+    if flags & !(MCL_CURRENT | MCL_FUTURE | MCL_ONFAULT) != 0
+        || flags & (MCL_CURRENT | MCL_FUTURE) == 0
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(0)
+}
+
+pub fn sys_munlockall() -> LinuxResult<usize> {
+    Ok(0)
+}
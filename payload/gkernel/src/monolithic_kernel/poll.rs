@@ -0,0 +1,107 @@
+//! `ppoll` (and `poll` on x86_64) over the fd table.
+
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use std::os::arceos::modules::axhal::time::{Duration, monotonic_time};
+
+use super::coop;
+use super::process::Process;
+use super::{signal, uaccess};
+
+// Event bits (uapi/asm-generic/poll.h).
+const POLLIN: u16 = 0x001;
+const POLLOUT: u16 = 0x004;
+const POLLERR: u16 = 0x008;
+const POLLHUP: u16 = 0x010;
+const POLLNVAL: u16 = 0x020;
+
+/// Limit on `nfds` (Linux uses `RLIMIT_NOFILE`).
+const MAX_POLL_FDS: usize = 1024;
+
+/// `struct pollfd`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PollFd {
+    fd: i32,
+    events: u16,
+    revents: u16,
+}
+
+/// Fills in `revents` for every entry and returns how many are non-zero.
+fn poll_once(process: &Process, fds: &mut [PollFd]) -> usize {
+    let fd_table = process.fd_table.lock();
+    let mut ready = 0;
+    for pfd in fds.iter_mut() {
+        pfd.revents = 0;
+        // Negative descriptors are ignored, as on Linux.
+        if pfd.fd < 0 {
+            continue;
+        }
+        match fd_table.get(pfd.fd as usize) {
+            Ok(file) => {
+                let state = file.poll();
+                if state.readable {
+                    pfd.revents |= POLLIN;
+                }
+                if state.writable {
+                    pfd.revents |= POLLOUT;
+                }
+                // POLLERR and POLLHUP are always reported, whatever was asked.
+                pfd.revents &= pfd.events | POLLERR | POLLHUP;
+            }
+            Err(_) => pfd.revents = POLLNVAL,
+        }
+        if pfd.revents != 0 {
+            ready += 1;
+        }
+    }
+    ready
+}
+
+/// Waits until one of the `nfds` descriptors at `fds_ptr` is ready or the
+/// timeout expires, yielding the CPU between checks. A null `timeout`
+/// waits forever; a zero one checks once. The signal mask argument is
+/// accepted but ignored since there are no signals to block yet.
+///
+/// With no descriptors and no timeout nothing could end the wait but a
+/// signal: that is libc's `pause()` where there is no `SYS_pause`, and it
+/// is handled as one (see `signal::sys_pause`).
+pub fn sys_ppoll(
+    process: &Process,
+    fds_ptr: usize,
+    nfds: usize,
+    timeout: Option<Duration>,
+) -> LinuxResult<usize> {
+    if nfds > MAX_POLL_FDS {
+        return Err(LinuxError::EINVAL);
+    }
+    if nfds == 0 && timeout.is_none() {
+        return signal::sys_pause(process);
+    }
+    let mut fds = Vec::with_capacity(nfds);
+    {
+        let uspace = process.aspace.lock();
+        for i in 0..nfds {
+            let mut raw = [0u8; size_of::<PollFd>()];
+            uaccess::copy_from_user(&uspace, &mut raw, fds_ptr + i * size_of::<PollFd>())?;
+            // SAFETY: `PollFd` is plain old data of exactly this size.
+            fds.push(unsafe { core::mem::transmute::<[u8; 8], PollFd>(raw) });
+        }
+    }
+
+    let deadline = timeout.map(|t| monotonic_time() + t);
+    let ready = loop {
+        let ready = poll_once(process, &mut fds);
+        if ready > 0 || deadline.is_some_and(|d| monotonic_time() >= d) {
+            break ready;
+        }
+        coop::yield_now();
+    };
+
+    let mut uspace = process.aspace.lock();
+    for (i, pfd) in fds.iter().enumerate() {
+        let revents_ptr = fds_ptr + i * size_of::<PollFd>() + 6;
+        uaccess::copy_to_user(&mut uspace, revents_ptr, &pfd.revents.to_ne_bytes())?;
+    }
+    Ok(ready)
+}
@@ -0,0 +1,397 @@
+//! State shared by everything running in one user process, and the table
+//! of live processes.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use core::fmt;
+use core::sync::atomic::{AtomicIsize, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+use std::os::arceos::modules::axhal::uspace::UserContext;
+use std::sync::Mutex;
+
+use super::apps::App;
+use super::aspace;
+use super::fault::FatalFault;
+use super::fd::FdTable;
+use super::mm::Heap;
+use super::shm::{self, Attachments};
+use super::signal::{AltStack, DEFAULT_ACTIONS, QueuedSignal, SIGMAX, SIGRTMIN, SigAction};
+use super::syscall::{RseqArea, SyscallHandler};
+use super::syscall_filter::SyscallFilter;
+use super::time::RealTimer;
+
+/// Process identifier. PIDs start at 1 and are never reused.
+pub type Pid = usize;
+
+/// The first process started, which adopts the children of processes that
+/// end before them.
+pub const INIT_PID: Pid = 1;
+
+/// Most processes that may be alive at once. Creating another fails with
+/// `EAGAIN`, so that a runaway spawn loop cannot exhaust guest memory; each
+/// process costs at least a kernel stack and a user stack.
+pub const MAX_PROCS: usize = 16;
+
+/// Most signals that may be pending for one process at once, which keeps
+/// a `kill` loop from exhausting guest memory just the same.
+pub const MAX_QUEUED_SIGNALS: usize = 64;
+
+static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
+
+/// Every live process by PID. Entries are weak so that the table never
+/// keeps a process alive; a process removes itself when dropped.
+static PROCESS_TABLE: Mutex<BTreeMap<Pid, Weak<Process>>> = Mutex::new(BTreeMap::new());
+
+/// A user process: its address space, its open files, its environment and
+/// its children.
+///
+/// Syscall handlers reach user memory and descriptors through this; it is
+/// shared with the task running the process and dropped once that task has
+/// exited and the process has been reaped.
+pub struct Process {
+    pub pid: Pid,
+    /// PID of the parent; 0 for a process started by the kernel itself.
+    ppid: AtomicUsize,
+    /// The process group, changed by `setpgid` and `setsid`. A process the
+    /// kernel starts leads a group of its own (the PID); a forked child
+    /// joins its parent's.
+    pub pgid: AtomicUsize,
+    /// The session, changed by `setsid`; set like `pgid`.
+    pub sid: AtomicUsize,
+    pub aspace: Mutex<AddrSpace>,
+    pub fd_table: Mutex<FdTable>,
+    /// The embedded app the process is running; changed by `execve`.
+    pub app: Mutex<&'static App>,
+    /// The syscall filters of every app the process has run, which each
+    /// syscall must pass (see `syscall_filter`); empty if none had one.
+    pub syscall_filters: Mutex<Vec<&'static SyscallFilter>>,
+    /// The handler the process was spawned with, which sees each syscall
+    /// its filters allow before the built-in handlers do; `execve` keeps
+    /// it.
+    pub syscall_handler: Mutex<Option<&'static dyn SyscallHandler>>,
+    /// The environment the running program was started with, as
+    /// `(name, value)` pairs in `envp` order. It is a copy: the program may
+    /// change its own `environ` without the kernel seeing it.
+    pub environ: Mutex<Vec<(String, String)>>,
+    /// Children not reaped yet, whether still running or exited. Each is
+    /// reaped by removing it from here (see `wait`).
+    pub children: Mutex<Vec<Arc<Process>>>,
+    /// The current working directory, an absolute path without a trailing
+    /// `/` (except for `/` itself). It starts as `/` and is changed by
+    /// `chdir`; `execve` keeps it.
+    pub cwd: Mutex<String>,
+    /// How the process ended, as a `wait4` status; `None` while it runs.
+    exit_status: Mutex<Option<i32>>,
+    /// The fault that killed the process, if one did.
+    pub fatal_fault: Mutex<Option<FatalFault>>,
+    /// Signal the process has been told to terminate with (`kill`), acted
+    /// on when it next returns to the kernel.
+    pending_kill: Mutex<Option<i32>>,
+    /// Signals sent with `kill` that do not terminate, in the order they
+    /// came, until `rt_sigtimedwait` takes them (see `queue_signal`).
+    pending_signals: Mutex<VecDeque<QueuedSignal>>,
+    /// A signal `rt_sigsuspend` took from `pending_signals`, until it is
+    /// delivered on the way back to user space (see `signal`).
+    pub unblocked_signal: Mutex<Option<QueuedSignal>>,
+    /// The user context a signal handler interrupted, while one runs; the
+    /// handler's `rt_sigreturn` resumes it (see `signal`).
+    pub signal_frame: Mutex<Option<UserContext>>,
+    /// Disposition of each signal, indexed by signal number - 1.
+    pub sigactions: Mutex<[SigAction; SIGMAX as usize]>,
+    /// The alternate signal stack, if one is set.
+    pub altstack: Mutex<Option<AltStack>>,
+    /// The `ITIMER_REAL` interval timer; `execve` keeps it running.
+    pub itimer: Mutex<RealTimer>,
+    /// The restartable-sequences area registered with `rseq`, if any;
+    /// `execve` drops it.
+    pub rseq: Mutex<Option<RseqArea>>,
+    /// Page faults taken so far, reported when the process exits.
+    pub faults: FaultCounts,
+    /// Interrupts taken in user mode so far (timer ticks, mostly), each a
+    /// return from `UserContext::run` to the kernel and back.
+    pub interrupts: AtomicUsize,
+    /// The nice value the task runs at, as set at spawn or by
+    /// `setpriority`; `execve` keeps it.
+    pub nice: AtomicIsize,
+    /// CPU time used so far, reported by `times` and when the process
+    /// exits.
+    pub cpu_times: CpuTimes,
+    /// The System V shared memory segments attached, by address (see
+    /// `shm`); `execve` detaches them.
+    pub shm_attachments: Mutex<Attachments>,
+    /// The brk heap (see `mm::Heap`); `execve` replaces it with an empty
+    /// one above the new image.
+    pub heap: Mutex<Heap>,
+}
+
+/// Page faults a process has taken, by how they were resolved. Plain
+/// relaxed counters: they are only ever added to and read for a report.
+#[derive(Default)]
+pub struct FaultCounts {
+    /// A page mapped on demand, on first touch of an area mapped without
+    /// populating it.
+    pub minor: AtomicUsize,
+    /// A fault nothing could resolve, turned into a signal (whether a
+    /// handler caught it or it killed the process).
+    pub fatal: AtomicUsize,
+}
+
+impl FaultCounts {
+    /// Counts one fault in `counter`.
+    pub fn count(counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for FaultCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} minor, {} fatal",
+            self.minor.load(Ordering::Relaxed),
+            self.fatal.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// CPU time a process has used, in nanoseconds, split between user mode
+/// and the kernel working on its behalf (see `task::run_user` for what
+/// counts as which). Relaxed counters, like `FaultCounts`.
+#[derive(Default)]
+pub struct CpuTimes {
+    pub user: AtomicU64,
+    pub system: AtomicU64,
+    /// User time of the children reaped by `wait4` or `waitid`, their own
+    /// reaped children's included.
+    pub children_user: AtomicU64,
+    /// System time of the reaped children, likewise.
+    pub children_system: AtomicU64,
+}
+
+impl CpuTimes {
+    /// Adds `time` to `counter`.
+    pub fn add(counter: &AtomicU64, time: Duration) {
+        counter.fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Reads `counter`.
+    pub fn get(counter: &AtomicU64) -> Duration {
+        Duration::from_nanos(counter.load(Ordering::Relaxed))
+    }
+
+    /// Adds the times of `child`, which has just been reaped, to those of
+    /// the children.
+    pub fn add_child(&self, child: &CpuTimes) {
+        let user = Self::get(&child.user) + Self::get(&child.children_user);
+        let system = Self::get(&child.system) + Self::get(&child.children_system);
+        Self::add(&self.children_user, user);
+        Self::add(&self.children_system, system);
+    }
+}
+
+impl fmt::Display for CpuTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} us user, {} us system",
+            Self::get(&self.user).as_micros(),
+            Self::get(&self.system).as_micros()
+        )
+    }
+}
+
+impl Process {
+    /// Creates a process running `app` in `aspace`, with the environment `envp`
+    /// (the `NAME=value` strings also put on its initial stack), and
+    /// registers it in the table.
+    ///
+    /// Fails with `EAGAIN` if `MAX_PROCS` processes are already alive. A
+    /// process stops counting once it has been reaped, i.e. its last
+    /// reference is dropped after its task has been joined.
+    pub fn new(app: &'static App, aspace: AddrSpace, envp: &[String]) -> LinuxResult<Arc<Self>> {
+        let mut table = PROCESS_TABLE.lock();
+        if table.len() >= MAX_PROCS {
+            return Err(LinuxError::EAGAIN);
+        }
+        let pid = NEXT_PID.fetch_add(1, Ordering::Relaxed);
+        let process = Arc::new(Self {
+            pid,
+            ppid: AtomicUsize::new(0),
+            pgid: AtomicUsize::new(pid),
+            sid: AtomicUsize::new(pid),
+            aspace: Mutex::new(aspace),
+            fd_table: Mutex::new(FdTable::with_stdio()),
+            app: Mutex::new(app),
+            syscall_filters: Mutex::new(app.syscall_filter.into_iter().collect()),
+            syscall_handler: Mutex::new(None),
+            environ: Mutex::new(parse_environ(envp)),
+            children: Mutex::new(Vec::new()),
+            cwd: Mutex::new(String::from("/")),
+            exit_status: Mutex::new(None),
+            fatal_fault: Mutex::new(None),
+            pending_kill: Mutex::new(None),
+            pending_signals: Mutex::new(VecDeque::new()),
+            unblocked_signal: Mutex::new(None),
+            signal_frame: Mutex::new(None),
+            sigactions: Mutex::new(DEFAULT_ACTIONS),
+            altstack: Mutex::new(None),
+            itimer: Mutex::new(RealTimer::default()),
+            rseq: Mutex::new(None),
+            faults: FaultCounts::default(),
+            interrupts: AtomicUsize::new(0),
+            nice: AtomicIsize::new(0),
+            cpu_times: CpuTimes::default(),
+            shm_attachments: Mutex::new(Attachments::new()),
+            heap: Mutex::new(Heap::default()),
+        });
+        table.insert(process.pid, Arc::downgrade(&process));
+        Ok(process)
+    }
+
+    /// Records that the process has ended with `wait_status`.
+    pub fn set_exit_status(&self, wait_status: i32) {
+        *self.exit_status.lock() = Some(wait_status);
+    }
+
+    /// The process's `wait4` status, once it has ended.
+    pub fn exit_status(&self) -> Option<i32> {
+        *self.exit_status.lock()
+    }
+
+    /// PID of the parent, or 0 if the kernel started the process.
+    pub fn ppid(&self) -> Pid {
+        self.ppid.load(Ordering::Relaxed)
+    }
+
+    /// Makes `child` a child of this process, to be reaped by it.
+    pub fn adopt(&self, child: Arc<Process>) {
+        child.ppid.store(self.pid, Ordering::Relaxed);
+        self.children.lock().push(child);
+    }
+
+    /// Hands the children of a process that has ended to `INIT_PID`, so
+    /// that they can still be reaped. If init is gone (or is this
+    /// process), nobody can wait for them, and they are freed once they
+    /// end.
+    pub fn reparent_children(&self) {
+        let children = core::mem::take(&mut *self.children.lock());
+        match find(INIT_PID) {
+            Some(init) if init.pid != self.pid && init.exit_status().is_none() => {
+                for child in children {
+                    init.adopt(child);
+                }
+            }
+            _ => {
+                for child in &children {
+                    child.ppid.store(0, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Replaces the environment, as an exec does.
+    pub fn set_environ(&self, envp: &[String]) {
+        *self.environ.lock() = parse_environ(envp);
+    }
+
+    /// Prints the environment, one `NAME=value` per line.
+    pub fn dump_environ(&self) {
+        let environ = self.environ.lock();
+        println!(
+            "Environment of process {} ({} variables):",
+            self.pid,
+            environ.len()
+        );
+        for (name, value) in environ.iter() {
+            println!("  {}={}", name, value);
+        }
+    }
+
+    /// Asks the process to terminate with `signal`. If it has already been
+    /// asked, the first signal wins.
+    pub fn kill(&self, signal: i32) {
+        self.pending_kill.lock().get_or_insert(signal);
+    }
+
+    /// The signal the process has been asked to terminate with, if any.
+    pub fn pending_kill(&self) -> Option<i32> {
+        *self.pending_kill.lock()
+    }
+
+    /// Adds `signal` from the process `sender` to the pending signals. A
+    /// standard signal is pending at most once, so sending it again while
+    /// it is pending changes nothing; real-time signals queue up, at most
+    /// `MAX_QUEUED_SIGNALS` in all (`EAGAIN`).
+    pub fn queue_signal(&self, signal: i32, sender: Pid) -> LinuxResult {
+        let mut pending = self.pending_signals.lock();
+        if signal < SIGRTMIN && pending.iter().any(|q| q.signal == signal) {
+            return Ok(());
+        }
+        if pending.len() >= MAX_QUEUED_SIGNALS {
+            return Err(LinuxError::EAGAIN);
+        }
+        pending.push_back(QueuedSignal { signal, sender });
+        Ok(())
+    }
+
+    /// Removes and returns the lowest-numbered pending signal in `set` (bit
+    /// `n - 1` for signal `n`), the first one queued if it is there more
+    /// than once.
+    pub fn take_signal(&self, set: u64) -> Option<QueuedSignal> {
+        let mut pending = self.pending_signals.lock();
+        let (i, _) = pending
+            .iter()
+            .enumerate()
+            .filter(|(_, q)| set & (1 << (q.signal - 1)) != 0)
+            .min_by_key(|&(i, q)| (q.signal, i))?;
+        pending.remove(i)
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        PROCESS_TABLE.lock().remove(&self.pid);
+        // The address space is freed right after this.
+        let mut uspace = self.aspace.lock();
+        shm::detach_all(self, &mut uspace);
+        aspace::scrub_before_unmap(&uspace);
+        aspace::untag_all(&uspace);
+    }
+}
+
+/// Splits `NAME=value` strings at the first `=`. A string without one is
+/// kept as a name with an empty value.
+fn parse_environ(envp: &[String]) -> Vec<(String, String)> {
+    envp.iter()
+        .map(|var| match var.split_once('=') {
+            Some((name, value)) => (name.into(), value.into()),
+            None => (var.clone(), String::new()),
+        })
+        .collect()
+}
+
+/// Looks up the live process `pid`.
+pub fn find(pid: Pid) -> Option<Arc<Process>> {
+    PROCESS_TABLE.lock().get(&pid).and_then(Weak::upgrade)
+}
+
+/// Whether some live process satisfies `pred`.
+pub fn any(mut pred: impl FnMut(&Process) -> bool) -> bool {
+    // Upgraded under the lock but dropped after it: one of these may be
+    // the last reference by then, and `Process::drop` takes the lock.
+    let live: Vec<Arc<Process>> = PROCESS_TABLE
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect();
+    live.iter().any(|process| pred(process))
+}
+
+/// Number of live processes.
+pub fn count() -> usize {
+    PROCESS_TABLE.lock().len()
+}
@@ -0,0 +1,228 @@
+//! System V shared memory: `SYS_SHMGET`, `SYS_SHMAT` and `SYS_SHMDT`.
+//!
+//! A segment is a run of physically contiguous frames owned by the segment
+//! itself, not by any address space. `shmat` maps those same frames into
+//! the caller with a linear mapping, so every process that attaches a
+//! segment sees the others' writes to it, and unmapping it (`shmdt`, exit
+//! or `execve`) never frees them. A segment lives until the kernel stops:
+//! there is no `shmctl`, hence no `IPC_RMID`.
+//!
+//! A forked child gets the parent's attachments, mapped to the same frames
+//! at the same addresses.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use log::debug;
+use std::os::arceos::modules::axalloc::GlobalPage;
+use std::os::arceos::modules::axhal::mem::{
+    PAGE_SIZE_4K, VirtAddr, VirtAddrRange, va, virt_to_phys,
+};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+use std::sync::Mutex;
+
+use super::aspace::{self, Purpose};
+use super::loader;
+use super::process::Process;
+
+// shmget/shmat flags (uapi/linux/ipc.h and shm.h; the same on every
+// architecture).
+const IPC_PRIVATE: usize = 0;
+const IPC_CREAT: usize = 0o1000;
+const IPC_EXCL: usize = 0o2000;
+const SHM_RDONLY: usize = 0o10000;
+const SHM_RND: usize = 0o20000;
+
+/// Largest segment `shmget` creates: the frames of a segment stay
+/// allocated for good, and guest memory is a few MiB.
+const SHM_MAX: usize = 0x10_0000;
+
+/// Where `shmat` starts looking for a free range when not given an
+/// address: well above the image and the heap it could grow.
+const SHM_BASE: usize = 0x3000_0000;
+
+/// A shared memory segment.
+pub struct ShmSegment {
+    key: usize,
+    /// Size asked for at `shmget`; the frames are that rounded up to pages.
+    size: usize,
+    frames: GlobalPage,
+}
+
+/// Every segment, by `shmid`, which is its index.
+static SEGMENTS: Mutex<Vec<Arc<ShmSegment>>> = Mutex::new(Vec::new());
+
+/// The segments a process has attached, by start address.
+pub type Attachments = BTreeMap<usize, Arc<ShmSegment>>;
+
+impl ShmSegment {
+    /// Bytes mapped by each attachment.
+    fn mapped_size(&self) -> usize {
+        self.frames.size()
+    }
+
+    /// Maps the segment's frames at `addr` in `uspace` with `flags`.
+    fn map(&self, uspace: &mut AddrSpace, addr: VirtAddr, flags: MappingFlags) -> LinuxResult {
+        let paddr = self.frames.start_paddr(virt_to_phys);
+        uspace
+            .map_linear(addr, paddr, self.mapped_size(), flags)
+            .map_err(|_| LinuxError::ENOMEM)?;
+        aspace::tag(uspace, addr, self.mapped_size(), Purpose::SharedMemory);
+        Ok(())
+    }
+}
+
+/// Unmaps from `uspace`, the address space of `process`, every segment
+/// the process has attached, as the address space is about to be torn down
+/// (by `execve`, or when the process is dropped), so that nothing scrubs or
+/// frees the segments' frames with it.
+pub fn detach_all(process: &Process, uspace: &mut AddrSpace) {
+    for (addr, segment) in core::mem::take(&mut *process.shm_attachments.lock()) {
+        let _ = uspace.unmap(va!(addr), segment.mapped_size());
+        aspace::untag(uspace, va!(addr));
+    }
+}
+
+/// Maps in `child`, a fork of `process` whose pages were copied from it,
+/// the segments `process` has attached, over the private copies of them.
+pub fn fork_attachments(process: &Process, child: &Process) -> LinuxResult {
+    let attachments = process.shm_attachments.lock().clone();
+    let mut uspace = child.aspace.lock();
+    for (addr, segment) in attachments {
+        let (_, flags, _) = process
+            .aspace
+            .lock()
+            .page_table()
+            .query(va!(addr))
+            .map_err(|_| LinuxError::EFAULT)?;
+        uspace
+            .unmap(va!(addr), segment.mapped_size())
+            .map_err(|_| LinuxError::EFAULT)?;
+        segment.map(&mut uspace, va!(addr), flags)?;
+        // Recorded as soon as it is mapped, so that dropping a child that
+        // failed halfway detaches it before the scrub.
+        child.shm_attachments.lock().insert(addr, segment);
+    }
+    Ok(())
+}
+
+// ── SYS_SHMGET ──
+
+/// Returns the `shmid` of the segment with `key`, creating one of `size`
+/// bytes, zero-filled, if there is none and `IPC_CREAT` is set (with
+/// `IPC_EXCL`, failing with `EEXIST` if there is one). `IPC_PRIVATE`
+/// always creates a new segment. Permission bits are accepted and ignored:
+/// every process may attach every segment.
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> LinuxResult<usize> {
+    // The key is a key_t, an int.
+    let key = key as u32 as usize;
+    let mut segments = SEGMENTS.lock();
+    if key != IPC_PRIVATE
+        && let Some(id) = segments.iter().position(|seg| seg.key == key)
+    {
+        if flags & (IPC_CREAT | IPC_EXCL) == IPC_CREAT | IPC_EXCL {
+            return Err(LinuxError::EEXIST);
+        }
+        if size > segments[id].size {
+            return Err(LinuxError::EINVAL);
+        }
+        return Ok(id);
+    }
+    if key != IPC_PRIVATE && flags & IPC_CREAT == 0 {
+        return Err(LinuxError::ENOENT);
+    }
+    if size == 0 || size > SHM_MAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let mut frames = GlobalPage::alloc_contiguous(size.div_ceil(PAGE_SIZE_4K), PAGE_SIZE_4K)
+        .map_err(|_| LinuxError::ENOMEM)?;
+    frames.zero();
+    segments.push(Arc::new(ShmSegment { key, size, frames }));
+    debug!(
+        "[SYS_SHMGET]: segment {} of {:#x} bytes",
+        segments.len() - 1,
+        size
+    );
+    Ok(segments.len() - 1)
+}
+
+// ── SYS_SHMAT ──
+
+/// Attaches segment `shmid` at `shmaddr`, rounded down to a page with
+/// `SHM_RND` (otherwise it must be page aligned), or if that is 0 at the
+/// first free range from `SHM_BASE`; returns the address. The range must
+/// be free (`EINVAL` otherwise: there is no `SHM_REMAP`). `SHM_RDONLY`
+/// maps it read-only.
+pub fn sys_shmat(
+    process: &Process,
+    shmid: usize,
+    shmaddr: usize,
+    flags: usize,
+) -> LinuxResult<usize> {
+    if flags & !(SHM_RDONLY | SHM_RND) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let segment = SEGMENTS
+        .lock()
+        .get(shmid)
+        .cloned()
+        .ok_or(LinuxError::EINVAL)?;
+    let size = segment.mapped_size();
+    let mut uspace = process.aspace.lock();
+    let addr = if shmaddr == 0 {
+        let limit = VirtAddrRange::new(va!(SHM_BASE), loader::stack_guard(&uspace).start);
+        uspace
+            .find_free_area(va!(SHM_BASE), size, limit)
+            .ok_or(LinuxError::ENOMEM)?
+    } else {
+        let addr = if flags & SHM_RND != 0 {
+            shmaddr & !(PAGE_SIZE_4K - 1)
+        } else {
+            shmaddr
+        };
+        // The range is built only once it is known to fit, as building
+        // one that wraps past the top panics.
+        if !addr.is_multiple_of(PAGE_SIZE_4K)
+            || addr.checked_add(size).is_none()
+            || !uspace.contains_range(va!(addr), size)
+            || uspace.find_free_area(
+                va!(addr),
+                size,
+                VirtAddrRange::from_start_size(va!(addr), size),
+            ) != Some(va!(addr))
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        va!(addr)
+    };
+    let mut prot = MappingFlags::READ | MappingFlags::USER;
+    if flags & SHM_RDONLY == 0 {
+        prot |= MappingFlags::WRITE;
+    }
+    segment.map(&mut uspace, addr, prot)?;
+    process
+        .shm_attachments
+        .lock()
+        .insert(addr.as_usize(), segment);
+    Ok(addr.as_usize())
+}
+
+// ── SYS_SHMDT ──
+
+/// Detaches the segment attached at `shmaddr`, which must be the address
+/// `shmat` returned (`EINVAL` otherwise).
+pub fn sys_shmdt(process: &Process, shmaddr: usize) -> LinuxResult<usize> {
+    let segment = process
+        .shm_attachments
+        .lock()
+        .remove(&shmaddr)
+        .ok_or(LinuxError::EINVAL)?;
+    let mut uspace = process.aspace.lock();
+    uspace
+        .unmap(va!(shmaddr), segment.mapped_size())
+        .map_err(|_| LinuxError::EINVAL)?;
+    aspace::untag(&uspace, va!(shmaddr));
+    Ok(0)
+}
@@ -0,0 +1,800 @@
+//! Signal numbers, handlers, and `SYS_KILL`/`SYS_TKILL`.
+//!
+//! Handlers can be registered for any signal with `rt_sigaction`, but
+//! only two signals are delivered to one as they come: `SIGSEGV`, when a
+//! user fault raises it (see `deliver_fault`), and `SIGALRM`, when the
+//! interval timer expires (see `deliver_alarm`). There are no masks.
+//! Otherwise signals mean termination: a fatal fault kills its process
+//! with the matching signal (see `fault`), `kill` can end a process with
+//! `SIGKILL` or `SIGTERM`, and an uncaught `SIGALRM` ends it too, all of
+//! them signals whose default action is to terminate.
+//!
+//! Any other signal sent with `kill` is only queued on its target (see
+//! `Process::queue_signal`), where nothing acts on it until the target
+//! takes it synchronously with `rt_sigtimedwait`, or unblocks it for the
+//! length of an `rt_sigsuspend` (see `deliver_unblocked`). This is how
+//! `sigwait` and `sigsuspend` are used anyway, with the signal blocked
+//! otherwise: the signals to use for them are `SIGUSR1`, `SIGUSR2` and the
+//! real-time ones.
+//!
+//! ## Delivery
+//!
+//! The interrupted context is kept in the process's `signal_frame`, and the
+//! handler gets a Linux-style frame on the user stack (or the alternate
+//! stack, for `SA_ONSTACK`): a `siginfo_t` and a `ucontext_t` whose
+//! `uc_mcontext` holds the general registers, without floating-point
+//! state. Both are passed to the handler, whether or not it asked for them
+//! with `SA_SIGINFO`. The handler returns through `rt_sigreturn`, which resumes the
+//! kernel's copy, so changes the handler makes to the frame are ignored.
+//! An aarch64 or x86_64 handler registered with `SA_RESTORER` returns to
+//! its restorer, which makes that call itself; other handlers return to
+//! `SIGRETURN_SENTINEL`, which the run loop treats as the call.
+//!
+//! Only one handler runs at a time: `SIGSEGV` is blocked while its handler
+//! runs (there is no `SA_NODEFER`), so a fault inside the handler kills the
+//! process, as Linux does for a blocked synchronous signal. A `SIGALRM`
+//! that expires while a handler runs waits until it has returned.
+
+use axerrno::{LinuxError, LinuxResult};
+use core::time::Duration;
+use std::os::arceos::modules::axhal::mem::va;
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+use std::os::arceos::modules::axhal::time::monotonic_time;
+use std::os::arceos::modules::axhal::uspace::UserContext;
+
+use super::fault::{self, FatalFault};
+use super::process::{self, Pid, Process};
+use super::syscall::SyscallOutcome;
+use super::{SIGRETURN_SENTINEL, coop, time, uaccess};
+
+// Signal numbers (uapi/asm-generic/signal.h; x86_64 agrees).
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGBUS: i32 = 7;
+/// Not raised on riscv64; see `fault`.
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGSEGV: i32 = 11;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+const SIGCONT: i32 = 18;
+const SIGSTOP: i32 = 19;
+const SIGTSTP: i32 = 20;
+const SIGTTIN: i32 = 21;
+const SIGTTOU: i32 = 22;
+const SIGURG: i32 = 23;
+const SIGWINCH: i32 = 28;
+pub const SIGSYS: i32 = 31;
+/// First real-time signal; real-time signals queue up, the others do not.
+pub const SIGRTMIN: i32 = 32;
+
+/// Highest valid signal number, counting the real-time signals.
+pub const SIGMAX: i32 = 64;
+
+// Handler values and sa_flags (uapi/asm-generic/signal-defs.h).
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+const SA_RESTORER: usize = 0x0400_0000;
+const SA_ONSTACK: usize = 0x0800_0000;
+const SA_RESETHAND: usize = 0x8000_0000;
+
+// sigaltstack flags, and the smallest stack it accepts.
+const SS_ONSTACK: i32 = 1;
+const SS_DISABLE: i32 = 2;
+const MINSIGSTKSZ: usize = 2048;
+
+// si_code values for SIGSEGV (uapi/asm-generic/siginfo.h).
+const SEGV_MAPERR: i32 = 1;
+const SEGV_ACCERR: i32 = 2;
+const SI_KERNEL: i32 = 0x80;
+/// si_code of a signal sent by `kill`.
+const SI_USER: i32 = 0;
+/// si_code of a signal raised by a timer.
+const SI_TIMER: i32 = -2;
+
+/// The only `sigset_t` size accepted: 64 signals.
+const SIGSET_SIZE: usize = 8;
+/// `struct sigaction` as the kernel takes it: handler, flags, then (on
+/// aarch64 and x86_64) the restorer, then the mask.
+#[cfg(target_arch = "riscv64")]
+const SIGACTION_SIZE: usize = 24;
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+const SIGACTION_SIZE: usize = 32;
+/// `stack_t`: ss_sp, ss_flags (padded), ss_size.
+const STACK_T_SIZE: usize = 24;
+
+// Signal frame layout: a `siginfo_t`, then a `ucontext_t` whose
+// `uc_mcontext` starts after uc_flags, uc_link, uc_stack and the 1024-bit
+// uc_sigmask, aligned to 16. On x86_64 `uc_mcontext` (a `struct
+// sigcontext`) comes right after uc_stack, and the 64-bit uc_sigmask after
+// it.
+const SIGINFO_SIZE: usize = 128;
+const UC_STACK: usize = 16;
+#[cfg(not(target_arch = "x86_64"))]
+const UC_SIGMASK: usize = 40;
+#[cfg(not(target_arch = "x86_64"))]
+const UC_MCONTEXT: usize = 176;
+#[cfg(target_arch = "x86_64")]
+const UC_SIGMASK: usize = UC_MCONTEXT + MCONTEXT_REGS * 8;
+#[cfg(target_arch = "x86_64")]
+const UC_MCONTEXT: usize = 40;
+/// Registers in `uc_mcontext`: pc and x1-x31 on riscv64; fault_address,
+/// x0-x30, sp, pc and pstate on aarch64; on x86_64 r8-r15, rdi, rsi, rbp,
+/// rbx, rdx, rax, rcx, rsp, rip and rflags, then the segment selectors,
+/// err, trapno, oldmask, cr2, fpstate and reserved words, which stay 0.
+#[cfg(target_arch = "riscv64")]
+const MCONTEXT_REGS: usize = 32;
+#[cfg(target_arch = "aarch64")]
+const MCONTEXT_REGS: usize = 35;
+#[cfg(target_arch = "x86_64")]
+const MCONTEXT_REGS: usize = 32;
+#[cfg(not(target_arch = "x86_64"))]
+const FRAME_SIZE: usize = SIGINFO_SIZE + UC_MCONTEXT + MCONTEXT_REGS * 8;
+#[cfg(target_arch = "x86_64")]
+const FRAME_SIZE: usize = SIGINFO_SIZE + UC_SIGMASK + SIGSET_SIZE;
+
+/// A registered signal disposition (`struct sigaction`).
+#[derive(Clone, Copy, Default)]
+pub struct SigAction {
+    handler: usize,
+    flags: usize,
+    restorer: usize,
+    mask: u64,
+}
+
+impl SigAction {
+    fn parse(raw: &[u8; SIGACTION_SIZE]) -> Self {
+        let word = |i: usize| usize::from_ne_bytes(raw[i * 8..i * 8 + 8].try_into().unwrap());
+        #[cfg(target_arch = "riscv64")]
+        let (restorer, mask) = (0, word(2));
+        #[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+        let (restorer, mask) = (word(2), word(3));
+        Self {
+            handler: word(0),
+            flags: word(1),
+            restorer,
+            mask: mask as u64,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; SIGACTION_SIZE] {
+        #[cfg(target_arch = "riscv64")]
+        let words = [self.handler, self.flags, self.mask as usize];
+        #[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+        let words = [self.handler, self.flags, self.restorer, self.mask as usize];
+        let mut raw = [0; SIGACTION_SIZE];
+        for (chunk, word) in raw.chunks_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_ne_bytes());
+        }
+        raw
+    }
+
+    /// Whether a handler function is registered, as opposed to the default
+    /// action or ignoring the signal.
+    fn is_handler(&self) -> bool {
+        !matches!(self.handler, SIG_DFL | SIG_IGN)
+    }
+}
+
+/// A signal waiting to be taken with `rt_sigtimedwait`.
+#[derive(Clone, Copy)]
+pub struct QueuedSignal {
+    pub signal: i32,
+    /// PID of the process that sent it.
+    pub sender: Pid,
+}
+
+/// An alternate signal stack set with `sigaltstack`.
+#[derive(Clone, Copy)]
+pub struct AltStack {
+    sp: usize,
+    size: usize,
+}
+
+impl AltStack {
+    fn contains(&self, sp: usize) -> bool {
+        (self.sp..self.sp + self.size).contains(&sp)
+    }
+}
+
+/// Signal state a process starts with: every signal at its default
+/// action, no alternate stack.
+pub const DEFAULT_ACTIONS: [SigAction; SIGMAX as usize] = [SigAction {
+    handler: SIG_DFL,
+    flags: 0,
+    restorer: 0,
+    mask: 0,
+}; SIGMAX as usize];
+
+/// Resets the signal state across `execve`: handlers point into the old
+/// image, so they revert to the default action (ignored signals stay
+/// ignored), and the alternate stack is gone with the old stack.
+pub fn reset_on_exec(process: &Process) {
+    for action in process.sigactions.lock().iter_mut() {
+        if action.is_handler() {
+            *action = SigAction::default();
+        }
+    }
+    *process.altstack.lock() = None;
+    *process.signal_frame.lock() = None;
+}
+
+/// What a handler is told about its signal in `siginfo_t`.
+struct SigInfo {
+    signal: i32,
+    code: i32,
+    /// si_addr, and on aarch64 the mcontext's fault_address; 0 for a
+    /// signal that is not about an address.
+    addr: usize,
+    /// si_pid, in place of si_addr, for a signal a process sent.
+    sender: Option<Pid>,
+}
+
+/// Enters the `SIGSEGV` handler of `process` for `fault`, if there is one
+/// (see `enter_handler`). Returns whether it did; if not, the fault is
+/// fatal as before.
+///
+/// Not delivered, and so fatal: other signals, a jump into the kernel
+/// (see `fault`), a fault while the handler is already running, and a
+/// handler whose frame cannot be written (the stack itself faulted).
+pub fn deliver_fault(process: &Process, uctx: &mut UserContext, fault: &FatalFault) -> bool {
+    if fault.signal != SIGSEGV || fault.kernel_exec {
+        return false;
+    }
+    let addr = fault.addr.unwrap_or(0);
+    let code = match fault.access {
+        None => SI_KERNEL,
+        // A range of one byte at the last address would wrap past the top.
+        Some(_)
+            if addr.checked_add(1).is_some()
+                && process
+                    .aspace
+                    .lock()
+                    .can_access_range(va!(addr), 1, MappingFlags::USER) =>
+        {
+            SEGV_ACCERR
+        }
+        Some(_) => SEGV_MAPERR,
+    };
+    let info = SigInfo {
+        signal: SIGSEGV,
+        code,
+        addr,
+        sender: None,
+    };
+    enter_handler(process, uctx, &info)
+}
+
+/// Acts on a `SIGALRM` from the interval timer, if it has expired, before
+/// `uctx` resumes in user mode. A registered handler is entered (see
+/// `enter_handler`); while a handler already runs, the signal stays pending
+/// until it returns. An ignored `SIGALRM` is dropped. Under the default
+/// action the process must terminate, and the signal is returned.
+///
+/// A `SIGALRM` whose handler frame cannot be written is fatal too, as
+/// Linux turns that into `SIGSEGV`; it is returned as `SIGSEGV`.
+pub fn deliver_alarm(process: &Process, uctx: &mut UserContext) -> Option<i32> {
+    if !process.itimer.lock().expired() {
+        return None;
+    }
+    let action = process.sigactions.lock()[SIGALRM as usize - 1];
+    match action.handler {
+        SIG_DFL => return Some(SIGALRM),
+        SIG_IGN => {}
+        _ if process.signal_frame.lock().is_some() => return None,
+        _ => {
+            let info = SigInfo {
+                signal: SIGALRM,
+                code: SI_TIMER,
+                addr: 0,
+                sender: None,
+            };
+            if !enter_handler(process, uctx, &info) {
+                return Some(SIGSEGV);
+            }
+        }
+    }
+    process.itimer.lock().take_expiry();
+    None
+}
+
+/// Acts on the signal `rt_sigsuspend` took from the queue to end its wait,
+/// if it did, before `uctx` resumes in user mode: its handler is entered,
+/// unless a handler already runs, which leaves it pending until that one
+/// returns. Under the default action (which `rt_sigsuspend` only wakes for
+/// if it terminates) the process must terminate, and the signal is
+/// returned; a signal ignored meanwhile is dropped.
+///
+/// As for `deliver_alarm`, a handler frame that cannot be written is fatal
+/// and returned as `SIGSEGV`.
+pub fn deliver_unblocked(process: &Process, uctx: &mut UserContext) -> Option<i32> {
+    let mut unblocked = process.unblocked_signal.lock();
+    let queued = (*unblocked)?;
+    let action = process.sigactions.lock()[queued.signal as usize - 1];
+    if action.is_handler() && process.signal_frame.lock().is_some() {
+        return None;
+    }
+    *unblocked = None;
+    drop(unblocked);
+    match action.handler {
+        SIG_DFL => Some(queued.signal),
+        SIG_IGN => None,
+        _ => {
+            let info = SigInfo {
+                signal: queued.signal,
+                code: SI_USER,
+                addr: 0,
+                sender: Some(queued.sender),
+            };
+            (!enter_handler(process, uctx, &info)).then_some(SIGSEGV)
+        }
+    }
+}
+
+/// Whether the default action for `signal` is to terminate the process.
+/// The stop signals count as ignored, there being no job control.
+fn terminates_by_default(signal: i32) -> bool {
+    !matches!(
+        signal,
+        SIGCHLD | SIGCONT | SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU | SIGURG | SIGWINCH
+    )
+}
+
+/// Enters the handler of `process` for `info.signal`, if there is one and
+/// no handler is running yet: saves `uctx` as the signal frame, writes the
+/// handler's frame to the user stack and points `uctx` at the handler.
+/// Returns whether it did.
+fn enter_handler(process: &Process, uctx: &mut UserContext, info: &SigInfo) -> bool {
+    let action = process.sigactions.lock()[info.signal as usize - 1];
+    let mut signal_frame = process.signal_frame.lock();
+    if !action.is_handler() || signal_frame.is_some() {
+        return false;
+    }
+    let altstack = *process.altstack.lock();
+    let stack_top = match altstack {
+        Some(alt) if action.flags & SA_ONSTACK != 0 && !alt.contains(uctx.sp()) => {
+            alt.sp + alt.size
+        }
+        _ => uctx.sp(),
+    };
+    // A stack pointer too low for the frame (a program that cleared it)
+    // leaves nowhere to deliver the signal.
+    let Some(frame) = stack_top.checked_sub(FRAME_SIZE).map(|sp| sp & !0xf) else {
+        return false;
+    };
+    let raw = signal_frame_bytes(uctx, info, altstack);
+    if uaccess::copy_to_user(&mut process.aspace.lock(), frame, &raw).is_err() {
+        return false;
+    }
+    let ret = if action.flags & SA_RESTORER != 0 && action.restorer != 0 {
+        action.restorer
+    } else {
+        SIGRETURN_SENTINEL
+    };
+    // x86_64 has no link register: the return address is pushed, just
+    // below the frame, which leaves the stack aligned as after a call.
+    #[cfg(target_arch = "x86_64")]
+    let Some(frame_sp) = frame.checked_sub(8) else {
+        return false;
+    };
+    #[cfg(target_arch = "x86_64")]
+    if uaccess::copy_to_user(&mut process.aspace.lock(), frame_sp, &ret.to_ne_bytes()).is_err() {
+        return false;
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let frame_sp = frame;
+    info_println!(
+        "[signal]: process {} gets signal {} at {:#x}, handler {:#x}",
+        process.pid,
+        info.signal,
+        uctx.ip(),
+        action.handler
+    );
+
+    *signal_frame = Some(*uctx);
+    if action.flags & SA_RESETHAND != 0 {
+        process.sigactions.lock()[info.signal as usize - 1] = SigAction::default();
+    }
+    uctx.set_ip(action.handler);
+    uctx.set_sp(frame_sp);
+    uctx.set_arg0(info.signal as usize);
+    uctx.set_arg1(frame);
+    uctx.set_arg2(frame + SIGINFO_SIZE);
+    #[cfg(not(target_arch = "x86_64"))]
+    uctx.set_ra(ret);
+    true
+}
+
+/// The `siginfo_t` and `ucontext_t` a handler for `info` gets, for the
+/// context `uctx` it interrupted.
+fn signal_frame_bytes(
+    uctx: &UserContext,
+    info: &SigInfo,
+    altstack: Option<AltStack>,
+) -> [u8; FRAME_SIZE] {
+    let mut raw = [0u8; FRAME_SIZE];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        raw[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+
+    // siginfo: si_signo, si_errno, si_code, then at 16 si_addr, or si_pid
+    // and si_uid (0) for a signal a process sent.
+    put(0, &info.signal.to_ne_bytes());
+    put(8, &info.code.to_ne_bytes());
+    match info.sender {
+        Some(pid) => put(16, &(pid as i32).to_ne_bytes()),
+        None => put(16, &info.addr.to_ne_bytes()),
+    }
+
+    // ucontext: uc_flags and uc_link stay 0; the signal mask is empty.
+    let uc = SIGINFO_SIZE;
+    let (ss_sp, ss_flags, ss_size) = match altstack {
+        Some(alt) => (alt.sp, 0, alt.size),
+        None => (0, SS_DISABLE, 0),
+    };
+    put(uc + UC_STACK, &ss_sp.to_ne_bytes());
+    put(uc + UC_STACK + 8, &ss_flags.to_ne_bytes());
+    put(uc + UC_STACK + 16, &ss_size.to_ne_bytes());
+    put(uc + UC_SIGMASK, &0u64.to_ne_bytes());
+
+    let regs = fault::general_regs(uctx);
+    #[cfg(target_arch = "riscv64")]
+    let mcontext = core::iter::once(uctx.ip()).chain(regs[1..].iter().copied());
+    #[cfg(target_arch = "aarch64")]
+    let mcontext =
+        core::iter::once(info.addr)
+            .chain(regs)
+            .chain([uctx.sp(), uctx.ip(), uctx.spsr as usize]);
+    #[cfg(target_arch = "x86_64")]
+    let mcontext = {
+        let [
+            rax,
+            rbx,
+            rcx,
+            rdx,
+            rsi,
+            rdi,
+            rbp,
+            r8,
+            r9,
+            r10,
+            r11,
+            r12,
+            r13,
+            r14,
+            r15,
+        ] = regs;
+        [
+            r8,
+            r9,
+            r10,
+            r11,
+            r12,
+            r13,
+            r14,
+            r15,
+            rdi,
+            rsi,
+            rbp,
+            rbx,
+            rdx,
+            rax,
+            rcx,
+            uctx.sp(),
+            uctx.ip(),
+            uctx.rflags as usize,
+        ]
+        .into_iter()
+    };
+    for (i, reg) in mcontext.enumerate() {
+        put(uc + UC_MCONTEXT + i * 8, &reg.to_ne_bytes());
+    }
+    raw
+}
+
+/// Resumes the context a signal handler interrupted. Like a successful
+/// `execve`, this replaces all of `uctx`.
+///
+/// Without a signal frame the return cannot have come from a handler, and
+/// the user stack or control flow must be corrupt; the process is killed
+/// with `SIGSEGV`, as Linux does for a bad frame.
+pub fn sigreturn(process: &Process, uctx: &mut UserContext) {
+    match process.signal_frame.lock().take() {
+        Some(frame) => *uctx = frame,
+        None => {
+            println!(
+                "[SYS_RT_SIGRETURN]: process {} is not in a signal handler",
+                process.pid
+            );
+            process.kill(SIGSEGV);
+        }
+    }
+}
+
+// ── SYS_KILL ──
+
+/// Sends `sig` to the process `pid`.
+///
+/// `SIGKILL` and `SIGTERM` terminate the target, which ends as if killed
+/// by that signal the next time it returns to the kernel; a target blocked
+/// in a syscall goes on until that syscall returns (`rt_sigtimedwait`
+/// returns at once). Any other signal to a live process is queued for
+/// `rt_sigtimedwait`, and fails with `EAGAIN` if the queue is full. Signal
+/// 0 only checks that `pid` exists. Process groups are not implemented, so
+/// `pid` must be positive.
+pub fn sys_kill(process: &Process, pid: usize, sig: usize) -> LinuxResult<usize> {
+    send_signal(process, pid, sig, "SYS_KILL")
+}
+
+/// `kill` and `tkill`, which differ only in the name they log under.
+fn send_signal(process: &Process, pid: usize, sig: usize, syscall: &str) -> LinuxResult<usize> {
+    let sig = sig as i32;
+    if !(0..=SIGMAX).contains(&sig) {
+        return Err(LinuxError::EINVAL);
+    }
+    let pid = match pid as isize {
+        pid if pid > 0 => pid as Pid,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let target = process::find(pid).ok_or(LinuxError::ESRCH)?;
+    // Exited but not yet reaped: the signal has nothing left to act on.
+    if sig == 0 || target.exit_status().is_some() {
+        return Ok(0);
+    }
+    match sig {
+        SIGKILL | SIGTERM => {
+            println!(
+                "[{}]: process {} kills process {} with signal {}",
+                syscall, process.pid, pid, sig
+            );
+            target.kill(sig);
+        }
+        _ => {
+            info_println!(
+                "[{}]: process {} queues signal {} for process {}",
+                syscall,
+                process.pid,
+                sig,
+                pid
+            );
+            target.queue_signal(sig, process.pid)?;
+        }
+    }
+    Ok(0)
+}
+
+// ── SYS_TKILL ──
+
+/// Sends `sig` to the thread `tid`. Every process has a single thread,
+/// whose TID is the PID (as for the main thread on Linux), so this is a
+/// `kill` of the process `tid` names: a terminating signal ends that
+/// process, and any other is queued on it, there being no separate
+/// per-thread pending set. An unknown TID fails with `ESRCH`, one that is
+/// not positive with `EINVAL`.
+pub fn sys_tkill(process: &Process, tid: usize, sig: usize) -> LinuxResult<usize> {
+    send_signal(process, tid, sig, "SYS_TKILL")
+}
+
+// ── SYS_RT_SIGTIMEDWAIT ──
+
+/// Waits until one of the signals in the set at `set_ptr` is pending, takes
+/// it (the lowest-numbered first) and returns its number, with a
+/// `siginfo_t` naming the sender at `info_ptr` if that is not null.
+///
+/// With a timeout, gives up with `EAGAIN` once it has passed; a zero one
+/// only polls. `SIGKILL` and `SIGSTOP` are never waited for. A `kill` that
+/// terminates the caller ends the wait with `EINTR`, after which the
+/// caller dies on its way out of the kernel.
+pub fn sys_rt_sigtimedwait(
+    process: &Process,
+    set_ptr: usize,
+    info_ptr: usize,
+    timeout_ptr: usize,
+    sigsetsize: usize,
+) -> LinuxResult<usize> {
+    if sigsetsize != SIGSET_SIZE {
+        return Err(LinuxError::EINVAL);
+    }
+    let (set, timeout) = {
+        let uspace = process.aspace.lock();
+        let mut raw = [0u8; SIGSET_SIZE];
+        uaccess::copy_from_user(&uspace, &mut raw, set_ptr)?;
+        (
+            u64::from_ne_bytes(raw),
+            time::read_timespec(&uspace, timeout_ptr)?,
+        )
+    };
+    let set = set & !(1 << (SIGKILL - 1) | 1 << (SIGSTOP - 1));
+    let deadline = timeout.map(|t| monotonic_time().checked_add(t).unwrap_or(Duration::MAX));
+    let queued = loop {
+        if let Some(queued) = process.take_signal(set) {
+            break queued;
+        }
+        if process.pending_kill().is_some() {
+            return Err(LinuxError::EINTR);
+        }
+        if deadline.is_some_and(|deadline| monotonic_time() >= deadline) {
+            return Err(LinuxError::EAGAIN);
+        }
+        coop::yield_now();
+    };
+    if info_ptr != 0 {
+        // siginfo: si_signo, si_errno, si_code, then si_pid and si_uid.
+        let mut raw = [0u8; SIGINFO_SIZE];
+        raw[0..4].copy_from_slice(&queued.signal.to_ne_bytes());
+        raw[8..12].copy_from_slice(&SI_USER.to_ne_bytes());
+        raw[16..20].copy_from_slice(&(queued.sender as i32).to_ne_bytes());
+        uaccess::copy_to_user(&mut process.aspace.lock(), info_ptr, &raw)?;
+    }
+    Ok(queued.signal as usize)
+}
+
+// ── SYS_RT_SIGSUSPEND ──
+
+/// Waits, with the signals in the set at `mask_ptr` blocked and every
+/// other one unblocked, until a signal comes that does something: a
+/// terminating `kill`, an expiry of the interval timer, or a queued signal
+/// (see `sys_kill`) with a handler or a default action that terminates.
+/// Signals that are ignored, by `SIG_IGN` or by default, do not end the
+/// wait, and stay queued. Always fails, with `EINTR` once the wait ends:
+/// the signal is acted on as the call returns (see `deliver_alarm` and
+/// `deliver_unblocked`), so a handler runs before the caller resumes, and
+/// the caller sees the error when the handler has returned. Afterwards
+/// queued signals are blocked again, there being no mask to restore
+/// otherwise.
+///
+/// `SIGKILL` and `SIGSTOP` cannot be blocked. While a handler runs, the
+/// signals with handlers cannot end the wait: only one handler runs at a
+/// time.
+pub fn sys_rt_sigsuspend(
+    process: &Process,
+    mask_ptr: usize,
+    sigsetsize: usize,
+) -> LinuxResult<usize> {
+    if sigsetsize != SIGSET_SIZE {
+        return Err(LinuxError::EINVAL);
+    }
+    let mut raw = [0u8; SIGSET_SIZE];
+    uaccess::copy_from_user(&process.aspace.lock(), &mut raw, mask_ptr)?;
+    wait_for_signal(process, u64::from_ne_bytes(raw))
+}
+
+// ── SYS_PAUSE ──
+
+/// Waits until a signal comes that does something, as `rt_sigsuspend`
+/// does with an empty mask, and fails with `EINTR` once it has been acted
+/// on. Only x86_64 has the syscall; elsewhere libc's `pause()` is a
+/// `ppoll` on no descriptors without a timeout, which `poll::sys_ppoll`
+/// turns into this.
+pub fn sys_pause(process: &Process) -> LinuxResult<usize> {
+    wait_for_signal(process, 0)
+}
+
+/// The wait of `rt_sigsuspend` and `pause`: yields until a signal outside
+/// `mask` comes that does something (see `sys_rt_sigsuspend`), then fails
+/// with `EINTR`.
+fn wait_for_signal(process: &Process, mask: u64) -> LinuxResult<usize> {
+    let mask = mask & !(1 << (SIGKILL - 1) | 1 << (SIGSTOP - 1));
+    let bit = |signal: i32| 1u64 << (signal - 1);
+    loop {
+        if process.pending_kill().is_some() {
+            return Err(LinuxError::EINTR);
+        }
+        let in_handler = process.signal_frame.lock().is_some();
+        let wakes = process
+            .sigactions
+            .lock()
+            .iter()
+            .zip(1..=SIGMAX)
+            .filter(|&(action, signal)| match action.handler {
+                SIG_DFL => terminates_by_default(signal),
+                SIG_IGN => false,
+                _ => !in_handler,
+            })
+            .fold(0, |set, (_, signal)| set | bit(signal))
+            & !mask;
+        if wakes & bit(SIGALRM) != 0 && process.itimer.lock().expired() {
+            return Err(LinuxError::EINTR);
+        }
+        if let Some(queued) = process.take_signal(wakes) {
+            *process.unblocked_signal.lock() = Some(queued);
+            return Err(LinuxError::EINTR);
+        }
+        coop::yield_now();
+    }
+}
+
+// ── SYS_RT_SIGACTION ──
+
+/// Registers a disposition for `sig` and/or reports the current one.
+/// `SIGKILL` and `SIGSTOP` cannot be caught or ignored.
+pub fn sys_rt_sigaction(
+    process: &Process,
+    sig: usize,
+    act_ptr: usize,
+    oldact_ptr: usize,
+    sigsetsize: usize,
+) -> LinuxResult<usize> {
+    let sig = sig as i32;
+    if sigsetsize != SIGSET_SIZE
+        || !(1..=SIGMAX).contains(&sig)
+        || (act_ptr != 0 && matches!(sig, SIGKILL | SIGSTOP))
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let mut uspace = process.aspace.lock();
+    let new = if act_ptr != 0 {
+        let mut raw = [0; SIGACTION_SIZE];
+        uaccess::copy_from_user(&uspace, &mut raw, act_ptr)?;
+        Some(SigAction::parse(&raw))
+    } else {
+        None
+    };
+    let mut actions = process.sigactions.lock();
+    let slot = &mut actions[sig as usize - 1];
+    if oldact_ptr != 0 {
+        uaccess::copy_to_user(&mut uspace, oldact_ptr, &slot.to_bytes())?;
+    }
+    if let Some(new) = new {
+        *slot = new;
+    }
+    Ok(0)
+}
+
+// ── SYS_SIGALTSTACK ──
+
+/// Sets and/or reports the alternate signal stack. It cannot be changed
+/// while the caller runs on it, i.e. its stack pointer `sp` is inside it
+/// (`EPERM`); `SS_DISABLE` removes it.
+pub fn sys_sigaltstack(
+    process: &Process,
+    sp: usize,
+    ss_ptr: usize,
+    old_ptr: usize,
+) -> LinuxResult<usize> {
+    let mut uspace = process.aspace.lock();
+    let mut altstack = process.altstack.lock();
+    let on_stack = altstack.is_some_and(|alt| alt.contains(sp));
+    if old_ptr != 0 {
+        let (ss_sp, flags, size) = match *altstack {
+            Some(alt) => (alt.sp, if on_stack { SS_ONSTACK } else { 0 }, alt.size),
+            None => (0, SS_DISABLE, 0),
+        };
+        let mut raw = [0u8; STACK_T_SIZE];
+        raw[0..8].copy_from_slice(&ss_sp.to_ne_bytes());
+        raw[8..12].copy_from_slice(&flags.to_ne_bytes());
+        raw[16..24].copy_from_slice(&size.to_ne_bytes());
+        uaccess::copy_to_user(&mut uspace, old_ptr, &raw)?;
+    }
+    if ss_ptr != 0 {
+        let mut raw = [0u8; STACK_T_SIZE];
+        uaccess::copy_from_user(&uspace, &mut raw, ss_ptr)?;
+        let ss_sp = usize::from_ne_bytes(raw[0..8].try_into().unwrap());
+        let flags = i32::from_ne_bytes(raw[8..12].try_into().unwrap());
+        let size = usize::from_ne_bytes(raw[16..24].try_into().unwrap());
+        if on_stack {
+            return Err(LinuxError::EPERM);
+        }
+        if flags == SS_DISABLE {
+            *altstack = None;
+        } else if flags != 0 {
+            return Err(LinuxError::EINVAL);
+        } else if size < MINSIGSTKSZ || ss_sp.checked_add(size).is_none() {
+            return Err(LinuxError::ENOMEM);
+        } else {
+            *altstack = Some(AltStack { sp: ss_sp, size });
+        }
+    }
+    Ok(0)
+}
+
+// ── SYS_RT_SIGRETURN ──
+
+/// Returns from a signal handler (see `sigreturn`); nothing must be
+/// written to the return register afterwards.
+pub fn sys_rt_sigreturn(process: &Process, uctx: &mut UserContext) -> SyscallOutcome {
+    sigreturn(process, uctx);
+    SyscallOutcome::Continue
+}
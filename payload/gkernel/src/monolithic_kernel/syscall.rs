@@ -0,0 +1,972 @@
+//! Syscall dispatch and the handlers too small for a module of their own.
+
+use alloc::vec;
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use core::sync::atomic::{AtomicBool, Ordering};
+use log::debug;
+use std::io::SeekFrom;
+use std::os::arceos::modules::axalloc;
+use std::os::arceos::modules::axhal;
+use std::os::arceos::modules::axhal::mem::{PAGE_SIZE_4K, va};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+use std::os::arceos::modules::axhal::uspace::UserContext;
+use std::os::arceos::modules::axtask::{self, AxCpuMask};
+
+use super::process::{self, Pid, Process};
+use super::syscall_filter::{self, FilterAction};
+#[cfg(feature = "syscall-record")]
+use super::syscall_record;
+#[cfg(feature = "syscall-sequence")]
+use super::syscall_sequence;
+use super::{
+    coop, epoll, eventfd, exec, fd, fork, memfs, mm, poll, shm, signal, syscall_abi, task, time,
+    uaccess, wait,
+};
+
+/// What the run loop does once a syscall has been handled.
+pub enum SyscallOutcome {
+    /// Return to user space.
+    Continue,
+    /// The process is finished, with this exit status.
+    Exit(i32),
+}
+
+/// An extension of the dispatcher for one process, given to
+/// `task::spawn_user_process`: a way to add syscalls or intercept existing
+/// ones for an experiment without touching the built-in handlers.
+///
+/// It sees every syscall the process's filters allow, ahead of everything
+/// else, so the calls it handles are not traced, recorded or checked
+/// against a `syscall-sequence`.
+pub trait SyscallHandler: Sync {
+    /// Handles the syscall `uctx` stopped at, or returns `None` to leave
+    /// it to the built-in handlers. A handler that returns
+    /// `SyscallOutcome::Continue` sets the return value in `uctx` itself.
+    fn handle(&self, uctx: &mut UserContext) -> Option<SyscallOutcome>;
+}
+
+/// Encodes a handler result as a syscall return value: the result
+/// itself on success, `-errno` on failure.
+fn syscall_ret(ret: LinuxResult<usize>) -> usize {
+    match ret {
+        Ok(v) => v,
+        Err(e) => -(e.code() as isize) as usize,
+    }
+}
+
+/// Answers the syscalls that only read an ID the process holds, which
+/// cannot fail, without going through the dispatcher: no trace line, no
+/// `LinuxResult` to encode. `None` for any other syscall.
+///
+/// Not taken with `syscall-record` or `syscall-sequence`, which must see
+/// every call; the dispatcher handles these calls the same way then.
+fn fast_path(process: &Process, syscall_num: usize) -> Option<usize> {
+    if cfg!(any(
+        feature = "syscall-record",
+        feature = "syscall-sequence"
+    )) {
+        return None;
+    }
+    match syscall_num {
+        syscall_abi::GETPID | syscall_abi::GETTID => Some(process.pid),
+        _ => None,
+    }
+}
+
+/// Handles the syscall `uctx` stopped at.
+///
+/// This is all the run loop calls for a syscall; it only acts on the
+/// outcome. A call goes through the process's syscall filters, then its
+/// `SyscallHandler` if it has one, then `fast_path`, and last the built-in
+/// handlers below, by number. Adding a syscall means adding its number to
+/// `syscall_abi` and an arm here; an experiment that must not touch the
+/// built-in handlers gives the process a `SyscallHandler` instead.
+///
+/// The built-in handlers are a `match` rather than a table of function
+/// pointers: the arms take different arguments and locks, some exist only
+/// on one architecture or with one feature, and `exit`, `execve` and
+/// `rt_sigreturn` end in an outcome rather than a return value. The
+/// compiler makes a jump table of the `match` anyway.
+pub fn handle_syscall(process: &Process, uctx: &mut UserContext) -> SyscallOutcome {
+    let syscall_num = uctx.sysno();
+    if let Err(action) = syscall_filter::check(process, syscall_num) {
+        println!(
+            "syscall {} not permitted for process {}",
+            syscall_num, process.pid
+        );
+        match action {
+            FilterAction::Deny => uctx.set_retval(syscall_ret(Err(LinuxError::EPERM))),
+            FilterAction::Kill => process.kill(signal::SIGSYS),
+        }
+        return SyscallOutcome::Continue;
+    }
+    let handler = *process.syscall_handler.lock();
+    if let Some(outcome) = handler.and_then(|handler| handler.handle(uctx)) {
+        return outcome;
+    }
+    if let Some(ret) = fast_path(process, syscall_num) {
+        uctx.set_retval(ret);
+        return SyscallOutcome::Continue;
+    }
+    debug!("handle_syscall ...");
+    #[cfg(feature = "syscall-sequence")]
+    syscall_sequence::record(syscall_num);
+    #[cfg(feature = "syscall-record")]
+    let record = syscall_record::enter(
+        syscall_num,
+        [
+            uctx.arg0(),
+            uctx.arg1(),
+            uctx.arg2(),
+            uctx.arg3(),
+            uctx.arg4(),
+            uctx.arg5(),
+        ],
+    );
+    let ret = match syscall_num {
+        syscall_abi::EXIT => {
+            debug!("[SYS_EXIT]: process {} is exiting ..", process.pid);
+            #[cfg(feature = "syscall-record")]
+            record.finish(0);
+            return SyscallOutcome::Exit(uctx.arg0() as i32);
+        }
+        syscall_abi::EXECVE => match exec::sys_execve(process, uctx) {
+            // On success `uctx` now holds the new program's initial state
+            // and must not be given a return value; see `exec`.
+            Ok(outcome) => {
+                #[cfg(feature = "syscall-record")]
+                record.finish(0);
+                return outcome;
+            }
+            Err(e) => Err(e),
+        },
+        syscall_abi::RT_SIGRETURN => {
+            // Like execve, this sets all of `uctx` itself.
+            #[cfg(feature = "syscall-record")]
+            record.finish(0);
+            return signal::sys_rt_sigreturn(process, uctx);
+        }
+        syscall_abi::CLONE => fork::sys_clone(process, uctx),
+        #[cfg(target_arch = "x86_64")]
+        syscall_abi::FORK => fork::sys_fork(process, uctx),
+        syscall_abi::WAIT4 => {
+            wait::sys_wait4(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::WAITID => {
+            wait::sys_waitid(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::OPENAT => memfs::sys_openat(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        syscall_abi::CLOSE => process.fd_table.lock().close(uctx.arg0()).map(|()| 0),
+        syscall_abi::LSEEK => fd::sys_lseek(
+            &process.fd_table.lock(),
+            uctx.arg0(),
+            uctx.arg1(),
+            uctx.arg2(),
+        ),
+        syscall_abi::READ => sys_read(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        syscall_abi::WRITE => sys_write(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        syscall_abi::PREAD64 => {
+            sys_pread64(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::PWRITE64 => {
+            sys_pwrite64(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::SENDFILE => {
+            sys_sendfile(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::TRUNCATE => {
+            file_offset(uctx.arg1()).and_then(|len| memfs::sys_truncate(process, uctx.arg0(), len))
+        }
+        // Files live in memory only: there is nothing to flush, and no
+        // durability either. Syncing a valid descriptor just succeeds.
+        syscall_abi::FSYNC | syscall_abi::FDATASYNC => {
+            process.fd_table.lock().get(uctx.arg0()).map(|_| 0)
+        }
+        syscall_abi::FTRUNCATE => file_offset(uctx.arg1()).and_then(|len| {
+            let file = process.fd_table.lock().get(uctx.arg0())?;
+            file.truncate(len).map(|()| 0)
+        }),
+        syscall_abi::STATX => memfs::sys_statx(
+            process,
+            uctx.arg0(),
+            uctx.arg1(),
+            uctx.arg2(),
+            uctx.arg3(),
+            uctx.arg4(),
+        ),
+        syscall_abi::UTIMENSAT => {
+            memfs::sys_utimensat(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::GETCWD => memfs::sys_getcwd(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::CHDIR => memfs::sys_chdir(process, uctx.arg0()),
+        syscall_abi::GETDENTS64 => sys_getdents64(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        syscall_abi::EVENTFD2 => eventfd::sys_eventfd2(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::EPOLL_CREATE1 => epoll::sys_epoll_create1(process, uctx.arg0()),
+        syscall_abi::EPOLL_CTL => {
+            epoll::sys_epoll_ctl(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::EPOLL_PWAIT => {
+            epoll::sys_epoll_pwait(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        #[cfg(target_arch = "x86_64")]
+        syscall_abi::EPOLL_WAIT => {
+            epoll::sys_epoll_pwait(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::DUP => fd::sys_dup(&mut process.fd_table.lock(), uctx.arg0()),
+        #[cfg(target_arch = "x86_64")]
+        syscall_abi::DUP2 => fd::sys_dup2(&mut process.fd_table.lock(), uctx.arg0(), uctx.arg1()),
+        syscall_abi::DUP3 => fd::sys_dup3(
+            &mut process.fd_table.lock(),
+            uctx.arg0(),
+            uctx.arg1(),
+            uctx.arg2(),
+        ),
+        syscall_abi::FCNTL => fd::sys_fcntl(
+            &mut process.fd_table.lock(),
+            uctx.arg0(),
+            uctx.arg1(),
+            uctx.arg2(),
+        ),
+        syscall_abi::PPOLL => time::read_timespec(&process.aspace.lock(), uctx.arg2())
+            .and_then(|timeout| poll::sys_ppoll(process, uctx.arg0(), uctx.arg1(), timeout)),
+        #[cfg(target_arch = "x86_64")]
+        syscall_abi::POLL => {
+            // The timeout is in milliseconds; negative means wait forever.
+            let timeout = (uctx.arg2() as i32 >= 0)
+                .then(|| core::time::Duration::from_millis(uctx.arg2() as i32 as u64));
+            poll::sys_ppoll(process, uctx.arg0(), uctx.arg1(), timeout)
+        }
+        #[cfg(target_arch = "x86_64")]
+        syscall_abi::PAUSE => signal::sys_pause(process),
+        syscall_abi::BRK => mm::sys_brk(process, uctx.arg0()),
+        syscall_abi::MMAP => {
+            mm::sys_mmap(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::MUNMAP => mm::sys_munmap(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::MREMAP => mm::sys_mremap(
+            process,
+            uctx.arg0(),
+            uctx.arg1(),
+            uctx.arg2(),
+            uctx.arg3(),
+            uctx.arg4(),
+        ),
+        syscall_abi::MSYNC => mm::sys_msync(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        syscall_abi::MLOCK => mm::sys_mlock(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::MUNLOCK => mm::sys_munlock(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::MLOCKALL => mm::sys_mlockall(uctx.arg0()),
+        syscall_abi::MUNLOCKALL => mm::sys_munlockall(),
+        syscall_abi::SHMGET => shm::sys_shmget(uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        syscall_abi::SHMAT => shm::sys_shmat(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        syscall_abi::SHMDT => shm::sys_shmdt(process, uctx.arg0()),
+        syscall_abi::SCHED_YIELD => {
+            coop::yield_now();
+            Ok(0)
+        }
+        syscall_abi::CLOCK_GETTIME => {
+            time::sys_clock_gettime(&mut process.aspace.lock(), uctx.arg0(), uctx.arg1())
+        }
+        syscall_abi::CLOCK_GETRES => {
+            time::sys_clock_getres(&mut process.aspace.lock(), uctx.arg0(), uctx.arg1())
+        }
+        syscall_abi::NANOSLEEP => time::sys_nanosleep(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::CLOCK_NANOSLEEP => {
+            time::sys_clock_nanosleep(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::GETITIMER => time::sys_getitimer(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::SETITIMER => {
+            time::sys_setitimer(process, uctx.arg0(), uctx.arg1(), uctx.arg2())
+        }
+        syscall_abi::TIMES => time::sys_times(process, uctx.arg0()),
+        // getpid and gettid only get here when `fast_path` is off.
+        syscall_abi::GETPID => Ok(process.pid),
+        syscall_abi::GETPPID => Ok(process.ppid()),
+        // Every process is a single thread, whose TID is the PID (as for
+        // the main thread on Linux; see `signal::sys_tkill`).
+        syscall_abi::GETTID => Ok(process.pid),
+        syscall_abi::SETPGID => sys_setpgid(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::GETPGID => sys_getpgid(process, uctx.arg0()),
+        syscall_abi::SETSID => sys_setsid(process),
+        syscall_abi::GETSID => sys_getsid(process, uctx.arg0()),
+        syscall_abi::PRCTL => sys_prctl(&mut process.aspace.lock(), uctx.arg0(), uctx.arg1()),
+        syscall_abi::GETCPU => sys_getcpu(&mut process.aspace.lock(), uctx.arg0(), uctx.arg1()),
+        syscall_abi::SCHED_SETAFFINITY => {
+            sys_sched_setaffinity(process, uctx.arg0(), uctx.arg1(), uctx.arg2())
+        }
+        syscall_abi::SCHED_GETAFFINITY => {
+            sys_sched_getaffinity(process, uctx.arg0(), uctx.arg1(), uctx.arg2())
+        }
+        syscall_abi::SETPRIORITY => sys_setpriority(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        syscall_abi::GETPRIORITY => sys_getpriority(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::KILL => signal::sys_kill(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::TKILL => signal::sys_tkill(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::RT_SIGACTION => {
+            signal::sys_rt_sigaction(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::RT_SIGTIMEDWAIT => {
+            signal::sys_rt_sigtimedwait(process, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3())
+        }
+        syscall_abi::RT_SIGSUSPEND => signal::sys_rt_sigsuspend(process, uctx.arg0(), uctx.arg1()),
+        syscall_abi::SIGALTSTACK => {
+            signal::sys_sigaltstack(process, uctx.sp(), uctx.arg0(), uctx.arg1())
+        }
+        syscall_abi::SYSINFO => sys_sysinfo(&mut process.aspace.lock(), uctx.arg0()),
+        syscall_abi::MEMBARRIER => sys_membarrier(uctx.arg0(), uctx.arg1()),
+        syscall_abi::RSEQ => sys_rseq(
+            process,
+            uctx.arg0(),
+            uctx.arg1(),
+            uctx.arg2(),
+            uctx.arg3() as u32,
+        ),
+        #[cfg(feature = "hypercall")]
+        syscall_abi::HYPERCALL => {
+            use crate::hypercall::{HC_UNSUPPORTED, hypercall};
+            match hypercall(uctx.arg0(), [uctx.arg1(), uctx.arg2(), uctx.arg3()]) {
+                HC_UNSUPPORTED => Err(LinuxError::ENOSYS),
+                reply => Ok(reply),
+            }
+        }
+        n if syscall_abi::NETWORK.contains(&n) => sys_network(process, n),
+        _ => {
+            println!("Unimplemented syscall: {}", syscall_num);
+            Err(LinuxError::ENOSYS)
+        }
+    };
+    uctx.set_retval(syscall_ret(ret));
+    #[cfg(feature = "syscall-record")]
+    record.finish(uctx.retval());
+    SyscallOutcome::Continue
+}
+
+// ── SYS_READ / SYS_WRITE / SYS_PREAD64 / SYS_PWRITE64 ──
+
+/// Largest chunk copied to or from user space at a time.
+const IO_CHUNK: usize = 4096;
+
+/// Reads into the user buffer `buf` with `read`, which fills a kernel
+/// buffer of at most `len` bytes.
+///
+/// Reads at most one chunk: a short read is always allowed, and it keeps
+/// a blocking console read from waiting for more than it has to.
+fn read_to_user(
+    process: &Process,
+    buf: usize,
+    len: usize,
+    read: impl FnOnce(&mut [u8]) -> LinuxResult<usize>,
+) -> LinuxResult<usize> {
+    let mut chunk = vec![0u8; len.min(IO_CHUNK)];
+    let n = read(&mut chunk)?;
+    uaccess::copy_to_user(&mut process.aspace.lock(), buf, &chunk[..n])?;
+    Ok(n)
+}
+
+/// Writes the user buffer `buf` out chunk by chunk with `write`, which is
+/// given each chunk and how many bytes were written before it.
+fn write_from_user(
+    process: &Process,
+    buf: usize,
+    len: usize,
+    mut write: impl FnMut(&[u8], usize) -> LinuxResult<usize>,
+) -> LinuxResult<usize> {
+    let mut chunk = vec![0u8; len.min(IO_CHUNK)];
+    let mut written = 0;
+    while written < len {
+        let n = (len - written).min(IO_CHUNK);
+        uaccess::copy_from_user(&process.aspace.lock(), &mut chunk[..n], buf + written)?;
+        written += write(&chunk[..n], written)?;
+    }
+    Ok(written)
+}
+
+/// Checks a `pread64`/`pwrite64` offset or a `truncate` length, which must
+/// not be negative.
+fn file_offset(offset: usize) -> LinuxResult<u64> {
+    if (offset as i64) < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(offset as u64)
+}
+
+/// Reads from `fd`, failing with `EAGAIN` rather than blocking if it is
+/// `O_NONBLOCK`.
+fn sys_read(process: &Process, fd: usize, buf: usize, len: usize) -> LinuxResult<usize> {
+    let (file, nonblock) = process.fd_table.lock().get_io(fd)?;
+    read_to_user(process, buf, len, |chunk| {
+        if nonblock {
+            file.read_nonblock(chunk)
+        } else {
+            file.read(chunk)
+        }
+    })
+}
+
+/// Writes to `fd`, failing with `EAGAIN` rather than blocking if it is
+/// `O_NONBLOCK`.
+fn sys_write(process: &Process, fd: usize, buf: usize, len: usize) -> LinuxResult<usize> {
+    let (file, nonblock) = process.fd_table.lock().get_io(fd)?;
+    file.write_whole(&mut || {
+        write_from_user(process, buf, len, |chunk, _| {
+            if nonblock {
+                file.write_nonblock(chunk)
+            } else {
+                file.write(chunk)
+            }
+        })
+    })
+}
+
+fn sys_pread64(
+    process: &Process,
+    fd: usize,
+    buf: usize,
+    len: usize,
+    offset: usize,
+) -> LinuxResult<usize> {
+    let file = process.fd_table.lock().get(fd)?;
+    let offset = file_offset(offset)?;
+    read_to_user(process, buf, len, |chunk| file.read_at(offset, chunk))
+}
+
+fn sys_pwrite64(
+    process: &Process,
+    fd: usize,
+    buf: usize,
+    len: usize,
+    offset: usize,
+) -> LinuxResult<usize> {
+    let file = process.fd_table.lock().get(fd)?;
+    let offset = file_offset(offset)?;
+    write_from_user(process, buf, len, |chunk, done| {
+        file.write_at(offset + done as u64, chunk)
+    })
+}
+
+// ── SYS_SENDFILE ──
+
+/// Most bytes one `sendfile` moves, as on Linux.
+const MAX_SENDFILE: usize = 0x7fff_f000;
+
+/// Copies up to `count` bytes from `in_fd` to `out_fd` through a kernel
+/// buffer, at most a chunk at a time. The source must be a file with an
+/// offset (not the console): with `offset_ptr` the copy starts at
+/// `*offset_ptr`, which is advanced and the file offset left alone;
+/// without, it starts at the file offset, which is advanced. Stops early
+/// at end of file or on a short write; an error after some bytes have
+/// been copied is dropped and the count so far returned, like a short
+/// `write`.
+fn sys_sendfile(
+    process: &Process,
+    out_fd: usize,
+    in_fd: usize,
+    offset_ptr: usize,
+    count: usize,
+) -> LinuxResult<usize> {
+    let (input, output) = {
+        let fd_table = process.fd_table.lock();
+        (fd_table.get(in_fd)?, fd_table.get(out_fd)?)
+    };
+    let start = if offset_ptr != 0 {
+        let mut raw = [0u8; 8];
+        uaccess::copy_from_user(&process.aspace.lock(), &mut raw, offset_ptr)?;
+        file_offset(i64::from_ne_bytes(raw) as usize)?
+    } else {
+        // Streams have no offset to start from.
+        input
+            .seek(SeekFrom::Current(0))
+            .map_err(|_| LinuxError::EINVAL)? as u64
+    };
+
+    let count = count.min(MAX_SENDFILE);
+    let mut chunk = vec![0u8; count.min(IO_CHUNK)];
+    let mut copied = 0;
+    let mut copy = || -> LinuxResult {
+        while copied < count {
+            let n = (count - copied).min(IO_CHUNK);
+            let n = input.read_at(start + copied as u64, &mut chunk[..n])?;
+            if n == 0 {
+                break;
+            }
+            let written = output.write(&chunk[..n])?;
+            copied += written;
+            if written < n {
+                break;
+            }
+        }
+        Ok(())
+    };
+    let result = copy();
+    if copied == 0 {
+        result?;
+    }
+
+    let end = start + copied as u64;
+    if offset_ptr != 0 {
+        uaccess::copy_to_user(
+            &mut process.aspace.lock(),
+            offset_ptr,
+            &(end as i64).to_ne_bytes(),
+        )?;
+    } else {
+        input.seek(SeekFrom::Start(end))?;
+    }
+    Ok(copied)
+}
+
+// ── SYS_GETDENTS64 ──
+
+/// Lists the next entries of the directory `fd` into the user buffer. The
+/// whole of `len` is not always used: at most one chunk is listed.
+fn sys_getdents64(process: &Process, fd: usize, buf: usize, len: usize) -> LinuxResult<usize> {
+    let dir = process.fd_table.lock().get(fd)?;
+    read_to_user(process, buf, len, |chunk| dir.read_dir(chunk))
+}
+
+// ── SYS_PRCTL ──
+
+// prctl options (uapi/linux/prctl.h).
+const PR_SET_NAME: usize = 15;
+const PR_GET_NAME: usize = 16;
+
+/// Size of a task name buffer, terminating NUL included (`TASK_COMM_LEN`).
+const TASK_COMM_LEN: usize = 16;
+
+/// Only the task name options. The name is axtask's own task name, so it
+/// shows up wherever axtask reports the task; it starts out as the name
+/// the task was spawned with (`userboot`, `user-<pid>`). As on Linux, a
+/// longer name is cut to 15 bytes; bytes that are not UTF-8 are replaced,
+/// since axtask names are strings.
+fn sys_prctl(uspace: &mut AddrSpace, option: usize, arg2: usize) -> LinuxResult<usize> {
+    match option {
+        PR_SET_NAME => {
+            // Read up to the page end first, so that a short name just
+            // before an unmapped page is still accepted.
+            let mut raw = [0u8; TASK_COMM_LEN - 1];
+            let first = raw.len().min(PAGE_SIZE_4K - arg2 % PAGE_SIZE_4K);
+            uaccess::copy_from_user(uspace, &mut raw[..first], arg2)?;
+            if !raw[..first].contains(&0) && first < raw.len() {
+                uaccess::copy_from_user(uspace, &mut raw[first..], arg2 + first)?;
+            }
+            let len = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+            let name = alloc::string::String::from_utf8_lossy(&raw[..len]);
+            axtask::current().set_name(&name);
+            Ok(0)
+        }
+        PR_GET_NAME => {
+            let name = axtask::current().name();
+            let mut len = name.len().min(TASK_COMM_LEN - 1);
+            while !name.is_char_boundary(len) {
+                len -= 1;
+            }
+            let mut raw = [0u8; TASK_COMM_LEN];
+            raw[..len].copy_from_slice(&name.as_bytes()[..len]);
+            uaccess::copy_to_user(uspace, arg2, &raw)?;
+            Ok(0)
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+// ── SYS_GETCPU ──
+
+/// Reports the CPU the caller is running on. There is a single NUMA node,
+/// node 0. The third argument (a cache, unused since Linux 2.6.24) is
+/// ignored.
+fn sys_getcpu(uspace: &mut AddrSpace, cpu_ptr: usize, node_ptr: usize) -> LinuxResult<usize> {
+    use std::os::arceos::modules::axhal::percpu::this_cpu_id;
+
+    if cpu_ptr != 0 {
+        uaccess::copy_to_user(uspace, cpu_ptr, &(this_cpu_id() as u32).to_ne_bytes())?;
+    }
+    if node_ptr != 0 {
+        uaccess::copy_to_user(uspace, node_ptr, &0u32.to_ne_bytes())?;
+    }
+    Ok(0)
+}
+
+// ── SYS_SCHED_SETAFFINITY / SYS_SCHED_GETAFFINITY ──
+
+/// Size of the kernel's `cpu_set_t`: one bit per CPU, in whole
+/// `unsigned long`s.
+fn cpuset_size() -> usize {
+    axhal::cpu_num().div_ceil(usize::BITS as usize) * size_of::<usize>()
+}
+
+/// Checks the target of an affinity or priority call and tells whether it
+/// is the caller (pid 0 or its own pid). Only the caller's task can have
+/// its affinity or priority changed: axtask offers no access to other
+/// tasks.
+fn sched_target(process: &Process, pid: usize) -> LinuxResult<bool> {
+    match pid as i32 {
+        0 => Ok(true),
+        pid if pid < 0 => Err(LinuxError::ESRCH),
+        pid if pid as Pid == process.pid => Ok(true),
+        pid => process::find(pid as Pid)
+            .map(|_| false)
+            .ok_or(LinuxError::ESRCH),
+    }
+}
+
+/// Bits past the last CPU are ignored, and a mask selecting no CPU is
+/// `EINVAL`. For another process the mask is checked and then ignored.
+fn sys_sched_setaffinity(
+    process: &Process,
+    pid: usize,
+    len: usize,
+    mask_ptr: usize,
+) -> LinuxResult<usize> {
+    let is_self = sched_target(process, pid)?;
+    let mut raw = vec![0u8; cpuset_size()];
+    let n = len.min(raw.len());
+    uaccess::copy_from_user(&process.aspace.lock(), &mut raw[..n], mask_ptr)?;
+    let mut mask = AxCpuMask::new();
+    for cpu in 0..axhal::cpu_num() {
+        if raw[cpu / 8] & (1 << (cpu % 8)) != 0 {
+            mask.set(cpu, true);
+        }
+    }
+    if mask.is_empty() {
+        return Err(LinuxError::EINVAL);
+    }
+    if is_self {
+        axtask::set_current_affinity(mask);
+    }
+    Ok(0)
+}
+
+/// Returns the size of the mask written, as the raw syscall does; `len`
+/// must be a multiple of `unsigned long` and large enough for the mask.
+/// Another process is reported as allowed on every CPU.
+fn sys_sched_getaffinity(
+    process: &Process,
+    pid: usize,
+    len: usize,
+    mask_ptr: usize,
+) -> LinuxResult<usize> {
+    let is_self = sched_target(process, pid)?;
+    let size = cpuset_size();
+    if len < size || !len.is_multiple_of(size_of::<usize>()) {
+        return Err(LinuxError::EINVAL);
+    }
+    let mask = axtask::current().cpumask();
+    let mut raw = vec![0u8; size];
+    for cpu in 0..axhal::cpu_num() {
+        if !is_self || mask.get(cpu) {
+            raw[cpu / 8] |= 1 << (cpu % 8);
+        }
+    }
+    uaccess::copy_to_user(&mut process.aspace.lock(), mask_ptr, &raw)?;
+    Ok(size)
+}
+
+// ── SYS_SETPRIORITY / SYS_GETPRIORITY ──
+
+/// The only `which` the priority calls take: `who` is a pid. Process
+/// groups and users (`PRIO_PGRP`, `PRIO_USER`) are not implemented.
+const PRIO_PROCESS: usize = 0;
+
+/// Nice values outside the range are clamped, as on Linux. The caller's
+/// task gets the new priority at once (as a CFS nice value, see
+/// `task::spawn_user_process`); for another process the value is checked
+/// and then ignored.
+fn sys_setpriority(process: &Process, which: usize, who: usize, prio: usize) -> LinuxResult<usize> {
+    if which != PRIO_PROCESS {
+        return Err(LinuxError::EINVAL);
+    }
+    if sched_target(process, who)? {
+        let nice = (prio as i32 as isize).clamp(task::NICE_MIN, task::NICE_MAX);
+        axtask::set_priority(nice);
+        process.nice.store(nice, Ordering::Relaxed);
+    }
+    Ok(0)
+}
+
+/// Returns `20 - nice`, 1 through 40, as the raw syscall does so that no
+/// priority looks like an error; libc turns it back into the nice value.
+fn sys_getpriority(process: &Process, which: usize, who: usize) -> LinuxResult<usize> {
+    if which != PRIO_PROCESS {
+        return Err(LinuxError::EINVAL);
+    }
+    let nice = if sched_target(process, who)? {
+        process.nice.load(Ordering::Relaxed)
+    } else {
+        process::find(who as Pid)
+            .ok_or(LinuxError::ESRCH)?
+            .nice
+            .load(Ordering::Relaxed)
+    };
+    Ok((20 - nice) as usize)
+}
+
+// ── SYS_SETPGID / SYS_GETPGID / SYS_SETSID / SYS_GETSID ──
+
+// Process groups and sessions are bookkeeping only: there is no
+// controlling terminal and no job control, so nothing acts on them but
+// `wait4`, which can wait for the children in a group.
+
+/// Puts `pid` (the caller if 0) in the group `pgid` (its own, one it then
+/// leads, if 0). The target must be the caller or one of its children
+/// (`ESRCH`), must not lead a session, and must be in the caller's session;
+/// the group must be its own or one that exists in that session (`EPERM`
+/// otherwise). Linux also fails for a child that has since run `execve`;
+/// that is not tracked here.
+fn sys_setpgid(process: &Process, pid: usize, pgid: usize) -> LinuxResult<usize> {
+    // Both are pid_t, an int.
+    let (pid, pgid) = (pid as i32, pgid as i32);
+    if pgid < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let child;
+    let target = if pid == 0 || pid as Pid == process.pid {
+        process
+    } else {
+        child = process
+            .children
+            .lock()
+            .iter()
+            .find(|c| c.pid == pid as Pid)
+            .cloned()
+            .ok_or(LinuxError::ESRCH)?;
+        &child
+    };
+    let pgid = if pgid == 0 { target.pid } else { pgid as Pid };
+    let sid = process.sid.load(Ordering::Relaxed);
+    if target.sid.load(Ordering::Relaxed) == target.pid
+        || target.sid.load(Ordering::Relaxed) != sid
+        || pgid != target.pid
+            && !process::any(|p| {
+                p.pgid.load(Ordering::Relaxed) == pgid && p.sid.load(Ordering::Relaxed) == sid
+            })
+    {
+        return Err(LinuxError::EPERM);
+    }
+    target.pgid.store(pgid, Ordering::Relaxed);
+    Ok(0)
+}
+
+/// Returns the group of `pid` (the caller if 0), `ESRCH` if there is no
+/// such process.
+fn sys_getpgid(process: &Process, pid: usize) -> LinuxResult<usize> {
+    match pid as i32 {
+        0 => Ok(process.pgid.load(Ordering::Relaxed)),
+        pid => process::find(pid as Pid)
+            .map(|p| p.pgid.load(Ordering::Relaxed))
+            .ok_or(LinuxError::ESRCH),
+    }
+}
+
+/// Starts a new session, led by the caller, with the caller alone in a new
+/// group of the same id (its PID), and returns it. A group leader cannot
+/// (`EPERM`), nor can a process whose PID some group still has: so a
+/// process the kernel started, which leads its group, must fork first, as
+/// a daemon does.
+fn sys_setsid(process: &Process) -> LinuxResult<usize> {
+    if process::any(|p| p.pgid.load(Ordering::Relaxed) == process.pid) {
+        return Err(LinuxError::EPERM);
+    }
+    process.pgid.store(process.pid, Ordering::Relaxed);
+    process.sid.store(process.pid, Ordering::Relaxed);
+    Ok(process.pid)
+}
+
+/// Returns the session of `pid` (the caller if 0), `ESRCH` if there is no
+/// such process.
+fn sys_getsid(process: &Process, pid: usize) -> LinuxResult<usize> {
+    match pid as i32 {
+        0 => Ok(process.sid.load(Ordering::Relaxed)),
+        pid => process::find(pid as Pid)
+            .map(|p| p.sid.load(Ordering::Relaxed))
+            .ok_or(LinuxError::ESRCH),
+    }
+}
+
+// ── SYS_MEMBARRIER ──
+
+// membarrier commands (uapi/linux/membarrier.h).
+const MEMBARRIER_CMD_QUERY: usize = 0;
+const MEMBARRIER_CMD_GLOBAL: usize = 1 << 0;
+const MEMBARRIER_CMD_GLOBAL_EXPEDITED: usize = 1 << 1;
+const MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED: usize = 1 << 2;
+const MEMBARRIER_CMD_PRIVATE_EXPEDITED: usize = 1 << 3;
+const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: usize = 1 << 4;
+
+/// Commands `membarrier` accepts, as `MEMBARRIER_CMD_QUERY` reports them.
+const MEMBARRIER_SUPPORTED: usize = MEMBARRIER_CMD_GLOBAL
+    | MEMBARRIER_CMD_GLOBAL_EXPEDITED
+    | MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED
+    | MEMBARRIER_CMD_PRIVATE_EXPEDITED
+    | MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED;
+
+/// A barrier only needs to reach code that shares memory with the caller.
+/// Processes are single-threaded and share no memory, so that is only the
+/// caller itself, even on several CPUs: a full fence on the current CPU
+/// does for every barrier command. Registration is accepted and not
+/// tracked, so the expedited commands also work without it.
+fn sys_membarrier(cmd: usize, flags: usize) -> LinuxResult<usize> {
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    match cmd {
+        MEMBARRIER_CMD_QUERY => Ok(MEMBARRIER_SUPPORTED),
+        MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED | MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED => {
+            Ok(0)
+        }
+        MEMBARRIER_CMD_GLOBAL
+        | MEMBARRIER_CMD_GLOBAL_EXPEDITED
+        | MEMBARRIER_CMD_PRIVATE_EXPEDITED => {
+            core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+            Ok(0)
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+// ── SYS_RSEQ ──
+
+// rseq flags (uapi/linux/rseq.h).
+const RSEQ_FLAG_UNREGISTER: usize = 1;
+
+/// Size of the original `struct rseq`, the least an area may be, and its
+/// alignment.
+const RSEQ_MIN_SIZE: usize = 32;
+
+/// A restartable-sequences area registered with `rseq`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RseqArea {
+    addr: usize,
+    len: usize,
+    /// The signature that must precede each abort handler.
+    sig: u32,
+}
+
+/// Registers the caller's restartable-sequences area, as glibc does at
+/// startup, or with `RSEQ_FLAG_UNREGISTER` drops it, with the errors Linux
+/// gives: `EBUSY` for registering the same area twice, `EPERM` for a
+/// signature that does not match the registered one, `EINVAL` for any
+/// other area.
+///
+/// The registration is only recorded. The kernel never writes the area,
+/// so its `cpu_id` keeps the value libc put there before registering
+/// (`RSEQ_CPU_ID_UNINITIALIZED`), and users of it, such as glibc's
+/// `sched_getcpu`, fall back to asking the kernel. Nor is a sequence ever
+/// restarted: one that a preemption or a signal interrupts just goes on.
+/// A process is single-threaded, so no other thread can have touched its
+/// per-CPU data meanwhile. `execve` drops the registration.
+fn sys_rseq(
+    process: &Process,
+    addr: usize,
+    len: usize,
+    flags: usize,
+    sig: u32,
+) -> LinuxResult<usize> {
+    let area = RseqArea { addr, len, sig };
+    let mut registered = process.rseq.lock();
+    match flags {
+        RSEQ_FLAG_UNREGISTER => match *registered {
+            Some(r) if (r.addr, r.len) != (addr, len) => Err(LinuxError::EINVAL),
+            Some(r) if r.sig != sig => Err(LinuxError::EPERM),
+            Some(_) => {
+                *registered = None;
+                Ok(0)
+            }
+            None => Err(LinuxError::EINVAL),
+        },
+        0 => match *registered {
+            Some(r) if (r.addr, r.len) != (addr, len) => Err(LinuxError::EINVAL),
+            Some(r) if r.sig != sig => Err(LinuxError::EPERM),
+            Some(_) => Err(LinuxError::EBUSY),
+            None => {
+                if !addr.is_multiple_of(RSEQ_MIN_SIZE) || len < RSEQ_MIN_SIZE {
+                    return Err(LinuxError::EINVAL);
+                }
+                let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+                if addr.checked_add(len).is_none()
+                    || !process
+                        .aspace
+                        .lock()
+                        .can_access_range(va!(addr), len, flags)
+                {
+                    return Err(LinuxError::EFAULT);
+                }
+                *registered = Some(area);
+                Ok(0)
+            }
+        },
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+// ── SYS_SYSINFO ──
+
+/// `struct sysinfo` as laid out by Linux on LP64 targets (riscv64,
+/// aarch64, x86_64 all agree). The padding that C inserts implicitly is
+/// spelled out so that the whole struct can be copied out byte by byte;
+/// the trailing `_f` member of the C definition is zero-sized on 64-bit.
+#[repr(C)]
+#[derive(Default)]
+struct SysInfo {
+    uptime: i64,
+    loads: [u64; 3],
+    totalram: u64,
+    freeram: u64,
+    sharedram: u64,
+    bufferram: u64,
+    totalswap: u64,
+    freeswap: u64,
+    procs: u16,
+    pad: u16,
+    _pad1: u32,
+    totalhigh: u64,
+    freehigh: u64,
+    mem_unit: u32,
+    _pad2: u32,
+}
+
+const _: () = assert!(core::mem::size_of::<SysInfo>() == 112);
+
+fn sys_sysinfo(uspace: &mut AddrSpace, info_ptr: usize) -> LinuxResult<usize> {
+    use std::os::arceos::modules::axhal::{mem::total_ram_size, time::monotonic_time};
+
+    let info = SysInfo {
+        uptime: monotonic_time().as_secs() as i64,
+        totalram: total_ram_size() as u64,
+        freeram: (axalloc::global_allocator().available_pages() * PAGE_SIZE_4K) as u64,
+        procs: process::count() as u16,
+        mem_unit: 1,
+        ..Default::default()
+    };
+    // SAFETY: `SysInfo` is `repr(C)` with no implicit padding.
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            &info as *const SysInfo as *const u8,
+            core::mem::size_of::<SysInfo>(),
+        )
+    };
+    uaccess::copy_to_user(uspace, info_ptr, bytes).map(|()| 0)
+}
+
+// ── Networking (SYS_SOCKET, SYS_CONNECT, ...) ──
+
+/// Set once `sys_network` has said that there is no networking.
+static NETWORK_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Fails a networking syscall (one of `syscall_abi::NETWORK`): there are
+/// no sockets. `socket` and `socketpair` fail with `EAFNOSUPPORT`, as for
+/// an address family the kernel was built without, which is what programs
+/// probing for network support look for; the others, which could only be
+/// given a descriptor that is not a socket, with `ENOSYS`. The first call
+/// prints one line saying so, later ones nothing.
+fn sys_network(process: &Process, syscall_num: usize) -> LinuxResult<usize> {
+    if !NETWORK_REPORTED.swap(true, Ordering::Relaxed) {
+        println!(
+            "Networking not supported in this guest (syscall {} from process {}); \
+             networking syscalls fail from now on",
+            syscall_num, process.pid
+        );
+    }
+    match syscall_num {
+        syscall_abi::SOCKET | syscall_abi::SOCKETPAIR => Err(LinuxError::EAFNOSUPPORT),
+        _ => Err(LinuxError::ENOSYS),
+    }
+}
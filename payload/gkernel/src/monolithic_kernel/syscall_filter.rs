@@ -0,0 +1,86 @@
+//! Per-app syscall allowlists: a seccomp-lite sandbox for untrusted apps.
+//!
+//! An app in the registry may carry a `SyscallFilter`, the set of syscalls
+//! it is allowed to make and what happens when it makes another. A process
+//! takes the filter of the app it is spawned with, and `execve` adds the
+//! new app's to it: filters only stack up, so that a sandboxed program
+//! cannot exec its way out of its sandbox. Every syscall is checked against
+//! all of them before it is dispatched (see `syscall::handle_syscall`).
+//!
+//! `exit` is always allowed, so that a process can end. An app without a
+//! filter, which is every app unless its entry says otherwise, may make any
+//! syscall.
+
+use core::ptr;
+
+use super::process::Process;
+use super::syscall_abi;
+
+/// Number of syscall numbers a `SyscallFilter` can allow, which covers
+/// everything Linux assigns on the supported architectures. The kernel's
+/// private syscalls are past it, and so are never allowed by a filter.
+const MAX_SYSCALLS: usize = 512;
+
+/// What happens to a process that makes a syscall its filter does not
+/// allow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterAction {
+    /// The syscall fails with `EPERM`, and the process goes on.
+    Deny,
+    /// The process is killed with `SIGSYS`.
+    Kill,
+}
+
+/// The syscalls an app may make, as a bitset of syscall numbers.
+pub struct SyscallFilter {
+    allowed: [u64; MAX_SYSCALLS / 64],
+    pub action: FilterAction,
+}
+
+impl SyscallFilter {
+    /// A filter allowing the syscalls `allowed`. A number outside the set
+    /// is a build error for a filter in a `static`.
+    pub const fn new(allowed: &[usize], action: FilterAction) -> Self {
+        let mut set = [0; MAX_SYSCALLS / 64];
+        let mut i = 0;
+        while i < allowed.len() {
+            assert!(allowed[i] < MAX_SYSCALLS, "syscall number out of range");
+            set[allowed[i] / 64] |= 1 << (allowed[i] % 64);
+            i += 1;
+        }
+        Self {
+            allowed: set,
+            action,
+        }
+    }
+
+    /// Whether the filter allows syscall `nr`.
+    pub fn allows(&self, nr: usize) -> bool {
+        nr == syscall_abi::EXIT
+            || (nr < MAX_SYSCALLS && self.allowed[nr / 64] & (1 << (nr % 64)) != 0)
+    }
+}
+
+/// Adds `filter` to those of `process`, as `execve` does for the app it
+/// starts. A filter the process already has is not added again, as it
+/// would not restrict anything more.
+pub fn add(process: &Process, filter: &'static SyscallFilter) {
+    let mut filters = process.syscall_filters.lock();
+    if !filters.iter().any(|f| ptr::eq(*f, filter)) {
+        filters.push(filter);
+    }
+}
+
+/// Checks syscall `nr` against the filters of `process`: `Err` with the
+/// action of the first one that does not allow it, if any does not.
+pub fn check(process: &Process, nr: usize) -> Result<(), FilterAction> {
+    match process
+        .syscall_filters
+        .lock()
+        .iter()
+        .find(|filter| !filter.allows(nr))
+    {
+        Some(filter) => Err(filter.action),
+        None => Ok(()),
+    }
+}
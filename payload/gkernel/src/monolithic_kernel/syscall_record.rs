@@ -0,0 +1,97 @@
+//! In-kernel syscall record buffer (`syscall-record` feature).
+//!
+//! Every syscall is appended to a fixed-size ring as a compact record
+//! (number, arguments, return value, timestamp) while the program runs, and
+//! the whole buffer is printed in one block when the kernel exits. Nothing
+//! is printed per syscall, so the trace is complete even when console
+//! output is slow or interleaved, and traces taken on different
+//! architectures can be compared line by line.
+//!
+//! The ring keeps the most recent `CAPACITY` records; the dump says how many
+//! older ones were overwritten.
+
+use std::os::arceos::modules::axhal::time::monotonic_time_nanos;
+use std::sync::Mutex;
+
+/// Number of records kept.
+const CAPACITY: usize = 512;
+
+#[derive(Clone, Copy)]
+struct Record {
+    /// Nanoseconds since boot at syscall entry.
+    time_ns: u64,
+    sysno: usize,
+    args: [usize; 6],
+    ret: isize,
+}
+
+const EMPTY: Record = Record {
+    time_ns: 0,
+    sysno: 0,
+    args: [0; 6],
+    ret: 0,
+};
+
+struct Recorder {
+    ring: [Record; CAPACITY],
+    /// Total records ever appended; `total % CAPACITY` is the next slot.
+    total: usize,
+}
+
+static RECORDER: Mutex<Recorder> = Mutex::new(Recorder {
+    ring: [EMPTY; CAPACITY],
+    total: 0,
+});
+
+/// A syscall that has been entered but whose result is not known yet.
+pub struct Pending(Record);
+
+/// Notes the entry of syscall `sysno` with `args`.
+pub fn enter(sysno: usize, args: [usize; 6]) -> Pending {
+    Pending(Record {
+        time_ns: monotonic_time_nanos(),
+        sysno,
+        args,
+        ret: 0,
+    })
+}
+
+impl Pending {
+    /// Appends the syscall to the buffer with its return value.
+    pub fn finish(mut self, ret: usize) {
+        self.0.ret = ret as isize;
+        let mut rec = RECORDER.lock();
+        let slot = rec.total % CAPACITY;
+        rec.ring[slot] = self.0;
+        rec.total += 1;
+    }
+}
+
+/// Prints the buffered records, oldest first.
+pub fn dump() {
+    let rec = RECORDER.lock();
+    let kept = rec.total.min(CAPACITY);
+    let first = rec.total - kept;
+    println!("==== syscall record: {} syscalls ====", rec.total);
+    if first > 0 {
+        println!("(buffer wrapped: {} oldest records lost)", first);
+    }
+    for seq in first..rec.total {
+        let r = &rec.ring[seq % CAPACITY];
+        println!(
+            "#{:<5} {:>6}.{:06}ms nr={:<4} args=[{:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x}] ret={}",
+            seq,
+            r.time_ns / 1_000_000,
+            r.time_ns % 1_000_000,
+            r.sysno,
+            r.args[0],
+            r.args[1],
+            r.args[2],
+            r.args[3],
+            r.args[4],
+            r.args[5],
+            r.ret,
+        );
+    }
+    println!("==== end of syscall record ====");
+}
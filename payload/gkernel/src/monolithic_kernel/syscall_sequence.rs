@@ -0,0 +1,70 @@
+//! Pinning the syscalls a program makes (`syscall-sequence` feature).
+//!
+//! The number of every syscall is appended to a bounded list as it is
+//! entered. Once the boot program has exited, `run()` compares the list
+//! with `INIT_SEQUENCE` and fails the run if they differ, so a change to
+//! the loader or to a program's startup code that alters which syscalls it
+//! makes, or in which order, shows up as a failed boot rather than going
+//! unnoticed.
+
+use std::sync::Mutex;
+
+use super::syscall_abi;
+
+/// Most syscall numbers kept. A program making more than this fails the
+/// check, since the tail of its sequence is unknown.
+const CAPACITY: usize = 256;
+
+/// The syscalls `/bin/init` is expected to make, in order.
+pub const INIT_SEQUENCE: &[usize] = &[syscall_abi::EXIT];
+
+struct Sequence {
+    sysnos: [usize; CAPACITY],
+    /// Total syscalls recorded, including those past `CAPACITY`.
+    total: usize,
+}
+
+static SEQUENCE: Mutex<Sequence> = Mutex::new(Sequence {
+    sysnos: [0; CAPACITY],
+    total: 0,
+});
+
+/// Appends `sysno` to the sequence.
+pub fn record(sysno: usize) {
+    let mut seq = SEQUENCE.lock();
+    let n = seq.total;
+    if n < CAPACITY {
+        seq.sysnos[n] = sysno;
+    }
+    seq.total += 1;
+}
+
+/// Compares the recorded sequence with `expected`, printing where they
+/// first differ. Returns whether they match.
+pub fn check(expected: &[usize]) -> bool {
+    let seq = SEQUENCE.lock();
+    if seq.total > CAPACITY {
+        println!(
+            "syscall sequence: {} syscalls, more than the {} kept",
+            seq.total, CAPACITY
+        );
+        return false;
+    }
+    let actual = &seq.sysnos[..seq.total];
+    match actual.iter().zip(expected).position(|(a, e)| a != e) {
+        Some(i) => println!(
+            "syscall sequence: #{} was {}, expected {}",
+            i, actual[i], expected[i]
+        ),
+        None if actual.len() != expected.len() => println!(
+            "syscall sequence: {} syscalls, expected {}",
+            actual.len(),
+            expected.len()
+        ),
+        None => {
+            println!("syscall sequence: {} syscalls as expected", actual.len());
+            return true;
+        }
+    }
+    false
+}
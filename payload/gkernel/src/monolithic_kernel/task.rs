@@ -0,0 +1,555 @@
+//! Creating user processes and running them on kernel tasks.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::LinuxResult;
+use axmm::AddrSpace;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+use std::os::arceos::modules::axhal::time::monotonic_time;
+use std::os::arceos::modules::axhal::trap::PageFaultFlags;
+use std::os::arceos::modules::axhal::uspace::{ReturnReason, UserContext};
+use std::os::arceos::modules::axhal::{
+    self,
+    mem::{PAGE_SIZE_4K, VirtAddr, va},
+};
+use std::os::arceos::modules::axtask::{self, AxCpuMask, AxTaskRef};
+
+use super::apps::App;
+use super::aspace::Purpose;
+use super::error::KernelError;
+use super::fault::FatalFault;
+use super::mm::Heap;
+use super::process::{CpuTimes, FaultCounts, Pid, Process};
+use super::syscall::{self, SyscallHandler, SyscallOutcome};
+use super::{
+    ENTRY_RTLD_FINI, KERNEL_STACK_SIZE, SIGRETURN_SENTINEL, USER_ASPACE_BASE, USER_ASPACE_SIZE,
+    USER_RETURN_SENTINEL, USER_STACK_SIZE, aspace, assert_kernel_mappings_copied,
+    assert_user_aspace_disjoint, coop, fault, loader, signal, wait,
+};
+
+/// Environment a process the kernel spawns gets when it is given none, as
+/// `NAME=value` strings; a variable added here reaches every such program.
+/// `execve` passes on exactly the environment it is given, as on Linux.
+pub const DEFAULT_ENV: &[&str] = &["PATH=/bin", "HOME=/", "TERM=dumb"];
+
+/// Creates a process running `app` with `argv` and `envp` and spawns the
+/// kernel task that drives it. An empty `envp` stands for `DEFAULT_ENV`.
+/// The task's exit code is the process's exit status as a shell reports it
+/// (128 + signal for a process killed by a fault).
+///
+/// A process with a `parent` is added to its children, to be reaped with
+/// `wait4`/`waitid`; one without is reaped by dropping the returned handle.
+/// Children still alive when the process ends pass to init (see
+/// `Process::reparent_children`).
+///
+/// With a `cpu`, the process only ever runs on that CPU. CPU numbers wrap
+/// around the CPUs the guest has, so on a single-CPU guest every process
+/// runs on CPU 0.
+///
+/// With a `nice` value, the task runs at that priority instead of the
+/// default 0. axstd builds axtask with the CFS scheduler, which is the only
+/// scheduling class there is (it is chosen at build time, not per task), so
+/// the priority is a CFS nice value: -20 (the largest share of the CPU)
+/// through 19 (the smallest). Values outside that range are clamped.
+///
+/// With a `handler`, every syscall the process makes goes to it first,
+/// before the built-in handlers (see `syscall::SyscallHandler`).
+pub fn spawn_user_process(
+    app: &'static App,
+    argv: &[String],
+    envp: &[String],
+    parent: Option<&Process>,
+    cpu: Option<usize>,
+    nice: Option<isize>,
+    handler: Option<&'static dyn SyscallHandler>,
+) -> Result<(Arc<Process>, AxTaskRef), KernelError> {
+    spawn_user_process_with(app, argv, envp, parent, cpu, nice, |process, _| {
+        *process.syscall_handler.lock() = handler;
+        Ok(())
+    })
+}
+
+/// Like `spawn_user_process`, but calls `setup` on the new process and its
+/// initial user context once both are built, just before the task is
+/// spawned. Whatever `setup` changes (the address space, a register, the
+/// syscall handler) is what the process starts with; if it fails, the
+/// process is dropped unstarted and its error returned as
+/// `KernelError::Errno`.
+pub fn spawn_user_process_with(
+    app: &'static App,
+    argv: &[String],
+    envp: &[String],
+    parent: Option<&Process>,
+    cpu: Option<usize>,
+    nice: Option<isize>,
+    setup: impl FnOnce(&Process, &mut UserContext) -> LinuxResult,
+) -> Result<(Arc<Process>, AxTaskRef), KernelError> {
+    let mut timer = SetupTimer::start("create address space");
+    let (process, mut uctx) = build_process(app, argv, envp, nice, &mut timer)?;
+    setup(&process, &mut uctx)?;
+    if let Some(parent) = parent {
+        parent.adopt(process.clone());
+    }
+
+    timer.step("spawn task");
+    let cpumask = cpu.map(|cpu| AxCpuMask::one_shot(cpu % axhal::cpu_num()));
+    let task = spawn_task(process.clone(), uctx, cpumask);
+    timer.finish();
+    Ok((process, task))
+}
+
+/// Builds a process running `app` (its address space, image, stack and
+/// descriptors) and the user context it would start from, without a task
+/// to run it: the process only runs once given to `spawn_task`, and until
+/// then its syscalls can be handled by calling `syscall::handle_syscall`
+/// on it directly (see `demo::syscall_fuzz`). Arguments are as for
+/// `spawn_user_process`.
+pub fn build_user_process(
+    app: &'static App,
+    argv: &[String],
+    envp: &[String],
+    nice: Option<isize>,
+) -> Result<(Arc<Process>, UserContext), KernelError> {
+    let mut timer = SetupTimer::start("create address space");
+    let built = build_process(app, argv, envp, nice, &mut timer)?;
+    timer.finish();
+    Ok(built)
+}
+
+/// `build_user_process`, reporting its steps to `timer`.
+fn build_process(
+    app: &'static App,
+    argv: &[String],
+    envp: &[String],
+    nice: Option<isize>,
+    timer: &mut SetupTimer,
+) -> Result<(Arc<Process>, UserContext), KernelError> {
+    let default_env: Vec<String>;
+    let envp = if envp.is_empty() {
+        default_env = DEFAULT_ENV.iter().map(|var| var.to_string()).collect();
+        &default_env
+    } else {
+        envp
+    };
+    // Create a new user address space.
+    let mut uspace = new_user_aspace()?;
+
+    timer.step("copy kernel mappings");
+    copy_kernel_mappings(&mut uspace)?;
+
+    // Load user app binary into address space.
+    timer.step("load app image");
+    let image = loader::load_user_app(&mut uspace, app)?;
+    loader::debug_print_image(app, &image);
+    let entry = image.entry;
+    let heap = Heap::new(image.brk_start());
+
+    // Init user stack.
+    timer.step("init user stack");
+    let ustack_top = loader::init_user_stack(&mut uspace, argv, envp)?;
+    loader::debug_assert_sp_in_stack(&uspace, ustack_top);
+    loader::debug_dump_stack(&uspace, ustack_top);
+    aspace::log_aspace("New user address space", &uspace);
+
+    // The process is shared with the user task so that syscall handlers
+    // can access user memory and descriptors.
+    timer.step("create process");
+    let process = Process::new(app, uspace, envp)?;
+    *process.heap.lock() = heap;
+    if cfg!(feature = "verbose") {
+        process.dump_environ();
+    }
+    if let Some(nice) = nice {
+        process
+            .nice
+            .store(nice.clamp(NICE_MIN, NICE_MAX), Ordering::Relaxed);
+    }
+
+    Ok((process, initial_context(entry.as_usize(), ustack_top)))
+}
+
+/// The context a new program starts from: at `entry`, with the stack
+/// pointer at `sp`, `ENTRY_RTLD_FINI` in the first argument register, and
+/// `USER_RETURN_SENTINEL` as the return address. x86_64 has no link
+/// register, and `_start` finds `argc` where a return address would be,
+/// so a program returning from it faults there, as it would on Linux.
+pub fn initial_context(entry: usize, sp: VirtAddr) -> UserContext {
+    let uctx = UserContext::new(entry, sp, ENTRY_RTLD_FINI);
+    #[cfg(not(target_arch = "x86_64"))]
+    let uctx = {
+        let mut uctx = uctx;
+        uctx.set_ra(USER_RETURN_SENTINEL);
+        uctx
+    };
+    uctx
+}
+
+/// Creates an empty user address space, `USER_ASPACE_SIZE` bytes at
+/// `USER_ASPACE_BASE`.
+pub fn new_user_aspace() -> Result<AddrSpace, KernelError> {
+    axmm::new_user_aspace(va!(USER_ASPACE_BASE), USER_ASPACE_SIZE).map_err(|_| {
+        KernelError::AddrSpaceCreate {
+            base: va!(USER_ASPACE_BASE),
+            size: USER_ASPACE_SIZE,
+        }
+    })
+}
+
+/// Copies the kernel mappings into the new user address space `uspace`, so
+/// that kernel code and data stay mapped while a syscall is handled.
+/// The copies keep their flags, none of which is `USER`, so a user store
+/// to kernel data already faults fatally. They are not made copy-on-write:
+/// a per-process copy of a kernel page would split the one kernel that
+/// every process shares.
+/// Fails with `KernelError::SegmentOverlap` if axmm refuses the copy,
+/// which it does only for overlapping ranges (and
+/// `assert_user_aspace_disjoint` rules that out).
+pub fn copy_kernel_mappings(uspace: &mut AddrSpace) -> Result<(), KernelError> {
+    let kspace = axmm::kernel_aspace().lock();
+    assert_user_aspace_disjoint(&kspace);
+    uspace
+        .copy_mappings_from(&kspace)
+        .map_err(|_| KernelError::SegmentOverlap {
+            vaddr: kspace.base(),
+            size: kspace.size(),
+        })?;
+    assert_kernel_mappings_copied(&kspace, uspace);
+    aspace::tag(uspace, kspace.base(), kspace.size(), Purpose::KernelCopy);
+    Ok(())
+}
+
+/// Spawns the kernel task that runs `process` from `uctx` until it ends,
+/// at the process's nice value and, with a `cpumask`, only on those CPUs.
+/// The task's exit code is the process's (see `spawn_user_process`).
+pub fn spawn_task(
+    process: Arc<Process>,
+    uctx: UserContext,
+    cpumask: Option<AxCpuMask>,
+) -> AxTaskRef {
+    let name = if process.pid == 1 {
+        "userboot".into()
+    } else {
+        format!("user-{}", process.pid)
+    };
+    let pt_root = process.aspace.lock().page_table_root();
+    let nice = process.nice.load(Ordering::Relaxed);
+    let task_process = process;
+    let mut task = axtask::TaskInner::new(
+        move || {
+            // axtask only sets the priority of the current task, so the
+            // task sets its own before anything else runs on it.
+            if nice != 0 {
+                axtask::set_priority(nice);
+            }
+            coop::wait_turn();
+            info_println!(
+                "Enter user space: pid={}, entry={:#x}, ustack={:#x}",
+                task_process.pid,
+                uctx.ip(),
+                uctx.sp(),
+            );
+            debug_entry_stop(task_process.pid, &uctx);
+            let status = run_user(&task_process, uctx);
+            println!(
+                "Process {} exited with code {}; page faults: {}; CPU time: {}",
+                task_process.pid,
+                wait::exit_code(status),
+                task_process.faults,
+                task_process.cpu_times
+            );
+            if cfg!(feature = "lazy-stack") {
+                println!(
+                    "Process {}: {} of {} stack pages committed",
+                    task_process.pid,
+                    loader::committed_stack_pages(&task_process.aspace.lock()),
+                    USER_STACK_SIZE / PAGE_SIZE_4K
+                );
+            }
+            // Children pass to init before the parent can be reaped, so
+            // that a parent waiting for its children and their children
+            // (as init does) cannot miss one.
+            task_process.reparent_children();
+            task_process.set_exit_status(status);
+            coop::leave();
+            // `axtask::exit` never returns, so drop the process reference
+            // first rather than leak it on this stack.
+            drop(task_process);
+            axtask::exit(wait::exit_code(status));
+        },
+        name,
+        KERNEL_STACK_SIZE,
+    );
+
+    // Set page table root for this task so that on context switch
+    // the scheduler installs the correct page table.
+    task.ctx_mut().set_page_table_root(pt_root);
+    if let Some(cpumask) = cpumask {
+        task.set_cpumask(cpumask);
+    }
+
+    coop::join(&task);
+    axtask::spawn_task(task)
+}
+
+/// Range of CFS nice values that `axtask::set_priority` accepts.
+pub const NICE_MIN: isize = -20;
+pub const NICE_MAX: isize = 19;
+
+/// Total setup time after which `SetupTimer` starts reporting.
+const SETUP_SLOW: Duration = Duration::from_millis(100);
+
+/// Coarse progress reports for process setup (`setup-timer` feature).
+///
+/// Setup is split into named steps. Once the whole setup has taken longer
+/// than `SETUP_SLOW`, every step boundary prints how long the step just
+/// ended took and which one runs next, so a stall can be pinned to a step
+/// (that step is the last one named). Without the feature this reads no
+/// clock and prints nothing.
+struct SetupTimer {
+    begin: Duration,
+    step: &'static str,
+    step_begin: Duration,
+}
+
+impl SetupTimer {
+    fn start(step: &'static str) -> Self {
+        let now = Self::now();
+        Self {
+            begin: now,
+            step,
+            step_begin: now,
+        }
+    }
+
+    fn now() -> Duration {
+        if cfg!(feature = "setup-timer") {
+            monotonic_time()
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Ends the current step and starts `next`.
+    fn step(&mut self, next: &'static str) {
+        self.report(next);
+        self.step = next;
+        self.step_begin = Self::now();
+    }
+
+    /// Ends the last step.
+    fn finish(self) {
+        self.report("done");
+    }
+
+    fn report(&self, next: &str) {
+        if !cfg!(feature = "setup-timer") {
+            return;
+        }
+        let now = monotonic_time();
+        if now - self.begin > SETUP_SLOW {
+            println!(
+                "Process setup: {} took {:?} ({:?} so far), next: {}",
+                self.step,
+                now - self.step_begin,
+                now - self.begin,
+                next
+            );
+        }
+    }
+}
+
+/// Seconds `debug_entry_stop` waits when `DEBUG_ENTRY_DELAY` is not set at
+/// build time.
+const DEBUG_ENTRY_DELAY: u64 = 5;
+
+/// Holds a new process back from its first instruction (`debug-entry`
+/// feature), for a debugger attached to QEMU to catch it there.
+///
+/// Prints one line, `debug-entry: pid=<pid> pc=<entry> sp=<sp>
+/// <satp|ttbr0_el1>=<value>`, with the translation register as the task
+/// runs with it (the process's page table is already installed), so a gdb
+/// script can grep it, set a breakpoint at `pc` (`hbreak *<pc>`) and
+/// continue; then sleeps for `DEBUG_ENTRY_DELAY` seconds (from the build
+/// environment, if set, else the constant of that name) before entering
+/// user mode. Without the feature it does nothing.
+fn debug_entry_stop(pid: Pid, uctx: &UserContext) {
+    if !cfg!(feature = "debug-entry") {
+        return;
+    }
+    let root: usize;
+    #[cfg(target_arch = "riscv64")]
+    unsafe {
+        core::arch::asm!("csrr {}, satp", out(reg) root)
+    };
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("mrs {}, ttbr0_el1", out(reg) root)
+    };
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::asm!("mov {}, cr3", out(reg) root)
+    };
+    let register = if cfg!(target_arch = "riscv64") {
+        "satp"
+    } else if cfg!(target_arch = "aarch64") {
+        "ttbr0_el1"
+    } else {
+        "cr3"
+    };
+    println!(
+        "debug-entry: pid={} pc={:#x} sp={:#x} {}={:#x}",
+        pid,
+        uctx.ip(),
+        uctx.sp(),
+        register,
+        root
+    );
+    let delay = option_env!("DEBUG_ENTRY_DELAY")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEBUG_ENTRY_DELAY);
+    axtask::sleep(Duration::from_secs(delay));
+}
+
+/// Enters user mode with `uctx`. With the `mask-user-irqs` feature, the
+/// user code runs with interrupts masked, so that only a trap it causes
+/// itself (a syscall, a fault) returns to the kernel: no timer tick or
+/// device interrupt perturbs it, and nothing preempts it. Interrupts that
+/// come meanwhile stay pending and are taken in the kernel once it is back,
+/// where they are enabled as usual.
+fn run_masked(uctx: &mut UserContext) -> ReturnReason {
+    if !cfg!(feature = "mask-user-irqs") {
+        return uctx.run();
+    }
+    #[cfg(target_arch = "riscv64")]
+    {
+        // In U-mode, S-mode interrupts are taken whatever sstatus.SIE says,
+        // so the software, timer and external enables in `sie` are cleared
+        // instead, and restored afterwards.
+        const SIE_MASK: usize = 1 << 1 | 1 << 5 | 1 << 9;
+        let saved: usize;
+        unsafe { core::arch::asm!("csrrc {}, sie, {}", out(reg) saved, in(reg) SIE_MASK) };
+        let reason = uctx.run();
+        unsafe { core::arch::asm!("csrs sie, {}", in(reg) saved & SIE_MASK) };
+        reason
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        // PSTATE.I masks IRQs at EL0 as well; `uctx.run` loads it from the
+        // saved SPSR.
+        const SPSR_I: u64 = 1 << 7;
+        uctx.spsr |= SPSR_I;
+        uctx.run()
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        // Likewise RFLAGS.IF, which the return to user mode loads from
+        // the saved RFLAGS.
+        const RFLAGS_IF: u64 = 1 << 9;
+        uctx.rflags &= !RFLAGS_IF;
+        uctx.run()
+    }
+}
+
+/// Runs the user context until the process exits or is killed, returning
+/// its wait status.
+///
+/// A page fault in an area reserved without frames (a `lazy-stack` stack,
+/// the `demo-heap-pages` heap) is resolved by the address space, which
+/// allocates and maps the page; it counts as a minor fault and the process
+/// resumes at the faulting instruction. A fault the kernel cannot resolve
+/// kills the process, not the kernel: the fault is reported and recorded
+/// in the process, which then ends as if killed by the matching signal. A
+/// `SIGSEGV` the process has a handler for is delivered to it instead (see
+/// `signal`). A process sent a terminating signal by `kill` ends before it
+/// would next enter user space, as does one whose interval timer raises an
+/// uncaught `SIGALRM`, or whose `rt_sigsuspend` unblocked a signal that
+/// terminates it.
+///
+/// CPU time is accounted here: the time spent in `run_masked` is user
+/// time, the rest of the loop system time. Both are measured on the
+/// monotonic clock, so a task switch in either (a preemption in user mode,
+/// a blocking syscall, a yield in a wait) is charged to the process too.
+fn run_user(process: &Process, mut uctx: UserContext) -> i32 {
+    let mut kernel_since = monotonic_time();
+    // Run user context in a loop.
+    // UserContext::run() enters user mode and returns when
+    // a syscall, interrupt, page fault, or exception occurs.
+    loop {
+        let killed = process
+            .pending_kill()
+            .or_else(|| signal::deliver_alarm(process, &mut uctx))
+            .or_else(|| signal::deliver_unblocked(process, &mut uctx));
+        if let Some(signal) = killed {
+            println!("Process {} killed by signal {}", process.pid, signal);
+            return wait::killed_status(signal);
+        }
+        let user_since = monotonic_time();
+        CpuTimes::add(&process.cpu_times.system, user_since - kernel_since);
+        let reason = run_masked(&mut uctx);
+        kernel_since = monotonic_time();
+        CpuTimes::add(&process.cpu_times.user, kernel_since - user_since);
+        match reason {
+            ReturnReason::Syscall => {
+                if let SyscallOutcome::Exit(code) = syscall::handle_syscall(process, &mut uctx) {
+                    return wait::exited_status(code);
+                }
+            }
+            ReturnReason::Interrupt => {
+                // Interrupt handled by framework, continue
+                process.interrupts.fetch_add(1, Ordering::Relaxed);
+                if cfg!(feature = "mask-user-irqs") {
+                    println!(
+                        "Warning: process {} took an interrupt in user mode with interrupts masked",
+                        process.pid
+                    );
+                }
+            }
+            ReturnReason::PageFault(vaddr, flags)
+                if vaddr.as_usize() == USER_RETURN_SENTINEL
+                    && flags.contains(PageFaultFlags::EXECUTE) =>
+            {
+                println!("User app returned without SYS_EXIT, treating as exit(0)");
+                return wait::exited_status(0);
+            }
+            ReturnReason::PageFault(vaddr, flags)
+                if vaddr.as_usize() == SIGRETURN_SENTINEL
+                    && flags.contains(PageFaultFlags::EXECUTE) =>
+            {
+                // A signal handler returned.
+                signal::sigreturn(process, &mut uctx);
+            }
+            ReturnReason::PageFault(vaddr, flags)
+                if process.aspace.lock().handle_page_fault(vaddr, flags) =>
+            {
+                FaultCounts::count(&process.faults.minor);
+            }
+            other => {
+                FaultCounts::count(&process.faults.fatal);
+                let fatal = FatalFault::new(&other, uctx.ip(), &process.aspace.lock());
+                if signal::deliver_fault(process, &mut uctx, &fatal) {
+                    continue;
+                }
+                if fatal.stack_overflow {
+                    println!("Stack overflow in process {}:", process.pid);
+                } else {
+                    println!("Fatal user fault in process {}:", process.pid);
+                }
+                fault::dump_fault_cause(&other, uctx.ip());
+                #[cfg(feature = "fault-symbols")]
+                fault::dump_fault_symbol(*process.app.lock(), uctx.ip());
+                fault::dump_user_regs(&uctx);
+                #[cfg(feature = "fault-symbols")]
+                fault::dump_user_backtrace(&process.aspace.lock(), *process.app.lock(), &uctx);
+                println!(
+                    "Killing process {} with signal {}",
+                    process.pid, fatal.signal
+                );
+                *process.fatal_fault.lock() = Some(fatal);
+                return wait::killed_status(fatal.signal);
+            }
+        }
+    }
+}
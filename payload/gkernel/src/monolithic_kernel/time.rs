@@ -0,0 +1,311 @@
+//! Clocks, sleeping, the `ITIMER_REAL` interval timer, process CPU times,
+//! and `struct timespec` conversion.
+
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use std::os::arceos::modules::axhal::time::{Duration, monotonic_time, ticks_to_nanos, wall_time};
+use std::os::arceos::modules::axtask;
+
+use super::process::{CpuTimes, Process};
+use super::uaccess;
+
+// Clock IDs and clock_nanosleep flags (uapi/linux/time.h).
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const TIMER_ABSTIME: usize = 1;
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Longest single sleep handed to the scheduler, which adds it to the
+/// current time and must not overflow (about 136 years).
+const MAX_SLEEP: Duration = Duration::from_secs(u32::MAX as u64);
+
+/// Size of `struct timespec` on LP64 targets.
+const TIMESPEC_SIZE: usize = 16;
+
+/// The real-time interval timer (uapi/linux/time.h), the only one here.
+const ITIMER_REAL: usize = 0;
+
+/// `struct itimerval`: it_interval, then it_value, each a `struct timeval`
+/// of seconds and microseconds.
+const ITIMERVAL_SIZE: usize = 32;
+const MICROS_PER_SEC: i64 = 1_000_000;
+
+/// Clock ticks per second in `times`: `USER_HZ`, which is 100 on every
+/// architecture, whatever the kernel's own tick rate.
+const CLK_TCK: u64 = 100;
+
+/// Size of `struct tms`: four `clock_t`s, which are `long`.
+const TMS_SIZE: usize = 32;
+
+/// Reads the `struct timespec` at `ptr`; null means "no timeout".
+pub fn read_timespec(uspace: &AddrSpace, ptr: usize) -> LinuxResult<Option<Duration>> {
+    if ptr == 0 {
+        return Ok(None);
+    }
+    let mut raw = [0u8; TIMESPEC_SIZE];
+    uaccess::copy_from_user(uspace, &mut raw, ptr)?;
+    let sec = i64::from_ne_bytes(raw[..8].try_into().unwrap());
+    let nsec = i64::from_ne_bytes(raw[8..].try_into().unwrap());
+    if sec < 0 || !(0..NANOS_PER_SEC).contains(&nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Some(Duration::new(sec as u64, nsec as u32)))
+}
+
+/// Writes `time` as a `struct timespec` to `ptr`.
+pub fn write_timespec(uspace: &mut AddrSpace, ptr: usize, time: Duration) -> LinuxResult {
+    let mut raw = [0u8; TIMESPEC_SIZE];
+    raw[..8].copy_from_slice(&(time.as_secs() as i64).to_ne_bytes());
+    raw[8..].copy_from_slice(&(time.subsec_nanos() as i64).to_ne_bytes());
+    uaccess::copy_to_user(uspace, ptr, &raw)
+}
+
+/// Current time of `clock`, as an offset from that clock's origin.
+fn clock_now(clock: usize) -> LinuxResult<Duration> {
+    match clock {
+        CLOCK_REALTIME => Ok(wall_time()),
+        CLOCK_MONOTONIC => Ok(monotonic_time()),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Sleeps until `clock` reads `deadline`; `clock` must be valid.
+///
+/// Returns the time still left if the sleep ends early, which callers
+/// report as `EINTR`. No signal cuts a sleep short (a `SIGALRM` that
+/// expires meanwhile is acted on after it), so in practice this only
+/// happens to sleeps longer than `MAX_SLEEP`.
+fn sleep_until(clock: usize, deadline: Duration) -> Result<(), Duration> {
+    let now = clock_now(clock).unwrap();
+    if deadline > now {
+        axtask::sleep((deadline - now).min(MAX_SLEEP));
+    }
+    let now = clock_now(clock).unwrap();
+    if now < deadline {
+        Err(deadline - now)
+    } else {
+        Ok(())
+    }
+}
+
+// ── SYS_CLOCK_GETTIME ──
+
+pub fn sys_clock_gettime(
+    uspace: &mut AddrSpace,
+    clock: usize,
+    ts_ptr: usize,
+) -> LinuxResult<usize> {
+    write_timespec(uspace, ts_ptr, clock_now(clock)?).map(|()| 0)
+}
+
+// ── SYS_CLOCK_GETRES ──
+
+/// Both clocks are read from the platform timer, so their resolution is one
+/// timer tick (at least 1 ns). A null `res_ptr` only checks the clock.
+pub fn sys_clock_getres(
+    uspace: &mut AddrSpace,
+    clock: usize,
+    res_ptr: usize,
+) -> LinuxResult<usize> {
+    clock_now(clock)?;
+    if res_ptr != 0 {
+        let tick = Duration::from_nanos(ticks_to_nanos(1).max(1));
+        write_timespec(uspace, res_ptr, tick)?;
+    }
+    Ok(0)
+}
+
+// ── SYS_NANOSLEEP / SYS_CLOCK_NANOSLEEP ──
+
+/// `nanosleep` is `clock_nanosleep` on `CLOCK_MONOTONIC` without flags.
+pub fn sys_nanosleep(process: &Process, req_ptr: usize, rem_ptr: usize) -> LinuxResult<usize> {
+    sys_clock_nanosleep(process, CLOCK_MONOTONIC, 0, req_ptr, rem_ptr)
+}
+
+/// Sleeps for the requested time, or with `TIMER_ABSTIME` until `clock`
+/// reaches it; a deadline already past returns at once. Only a relative
+/// sleep that is cut short reports the time left, in `rem_ptr` if given.
+pub fn sys_clock_nanosleep(
+    process: &Process,
+    clock: usize,
+    flags: usize,
+    req_ptr: usize,
+    rem_ptr: usize,
+) -> LinuxResult<usize> {
+    let req = read_timespec(&process.aspace.lock(), req_ptr)?.ok_or(LinuxError::EFAULT)?;
+    let now = clock_now(clock)?;
+    let absolute = flags & TIMER_ABSTIME != 0;
+    let deadline = if absolute {
+        req
+    } else {
+        now.checked_add(req).unwrap_or(Duration::MAX)
+    };
+    match sleep_until(clock, deadline) {
+        Ok(()) => Ok(0),
+        Err(left) => {
+            if !absolute && rem_ptr != 0 {
+                write_timespec(&mut process.aspace.lock(), rem_ptr, left)?;
+            }
+            Err(LinuxError::EINTR)
+        }
+    }
+}
+
+/// The `ITIMER_REAL` timer of a process, which raises `SIGALRM` when it
+/// expires. It is not a callback: nothing runs at the expiry itself. The
+/// run loop checks the timer each time the process returns to the kernel
+/// (see `signal::deliver_alarm`), which the timer interrupt makes it do at
+/// least once a tick while it runs; a process blocked in a syscall sees the
+/// signal once the syscall returns.
+#[derive(Default)]
+pub struct RealTimer {
+    /// When it next expires, on the monotonic clock; `None` while disarmed.
+    next: Option<Duration>,
+    /// Period it re-arms itself with; zero for a one-shot timer.
+    interval: Duration,
+    /// It has expired and its `SIGALRM` is not delivered yet. More expiries
+    /// meanwhile are lost, as a standard signal is pending at most once.
+    pending: bool,
+}
+
+impl RealTimer {
+    /// Records an expiry up to `now`, re-arming a periodic timer for its
+    /// next period after `now` (periods missed in between are skipped).
+    fn update(&mut self, now: Duration) {
+        let Some(next) = self.next.filter(|&next| next <= now) else {
+            return;
+        };
+        self.pending = true;
+        self.next = if self.interval.is_zero() {
+            None
+        } else {
+            let late = (now - next).as_nanos() % self.interval.as_nanos();
+            now.checked_add(self.interval - Duration::from_nanos(late as u64))
+        };
+    }
+
+    /// Whether the timer has expired since its `SIGALRM` was last taken.
+    pub fn expired(&mut self) -> bool {
+        self.update(monotonic_time());
+        self.pending
+    }
+
+    /// Takes the pending `SIGALRM`, once it has been acted on.
+    pub fn take_expiry(&mut self) {
+        self.pending = false;
+    }
+
+    /// The time left until the next expiry and the interval. An armed timer
+    /// never reports zero time left, which would read as disarmed.
+    fn remaining(&mut self, now: Duration) -> (Duration, Duration) {
+        self.update(now);
+        let left = self.next.map_or(Duration::ZERO, |next| {
+            (next - now).max(Duration::from_micros(1))
+        });
+        (left, self.interval)
+    }
+}
+
+/// Reads the `struct itimerval` at `ptr` as `(interval, value)`.
+fn read_itimerval(uspace: &AddrSpace, ptr: usize) -> LinuxResult<(Duration, Duration)> {
+    let mut raw = [0u8; ITIMERVAL_SIZE];
+    uaccess::copy_from_user(uspace, &mut raw, ptr)?;
+    let timeval = |raw: &[u8]| {
+        let sec = i64::from_ne_bytes(raw[..8].try_into().unwrap());
+        let usec = i64::from_ne_bytes(raw[8..16].try_into().unwrap());
+        if sec < 0 || !(0..MICROS_PER_SEC).contains(&usec) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(Duration::new(sec as u64, usec as u32 * 1000))
+    };
+    Ok((timeval(&raw[..16])?, timeval(&raw[16..])?))
+}
+
+/// Writes `(interval, value)` as a `struct itimerval` to `ptr`, rounding
+/// up to whole microseconds.
+fn write_itimerval(uspace: &mut AddrSpace, ptr: usize, timer: (Duration, Duration)) -> LinuxResult {
+    let mut raw = [0u8; ITIMERVAL_SIZE];
+    for (chunk, time) in raw.chunks_mut(16).zip([timer.0, timer.1]) {
+        let usec = time.subsec_nanos().div_ceil(1000);
+        let (sec, usec) = (time.as_secs() + (usec / 1_000_000) as u64, usec % 1_000_000);
+        chunk[..8].copy_from_slice(&(sec as i64).to_ne_bytes());
+        chunk[8..].copy_from_slice(&(usec as i64).to_ne_bytes());
+    }
+    uaccess::copy_to_user(uspace, ptr, &raw)
+}
+
+/// Checks `which` names a timer: `ITIMER_REAL` is the only one. The ones
+/// that count CPU time (`ITIMER_VIRTUAL`, `ITIMER_PROF`) are not
+/// implemented and fail with `EINVAL` like an unknown timer.
+fn check_itimer(which: usize) -> LinuxResult {
+    match which {
+        ITIMER_REAL => Ok(()),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+// ── SYS_GETITIMER ──
+
+pub fn sys_getitimer(process: &Process, which: usize, curr_ptr: usize) -> LinuxResult<usize> {
+    check_itimer(which)?;
+    let timer = process.itimer.lock().remaining(monotonic_time());
+    write_itimerval(&mut process.aspace.lock(), curr_ptr, timer).map(|()| 0)
+}
+
+// ── SYS_SETITIMER ──
+
+/// Arms the timer to expire after `it_value`, then every `it_interval` if
+/// that is not zero; a zero `it_value` disarms it. A null `new_ptr` counts
+/// as all zeros, as on Linux. The previous setting goes to `old_ptr`, if
+/// that is not null. A `SIGALRM` already pending stays pending.
+pub fn sys_setitimer(
+    process: &Process,
+    which: usize,
+    new_ptr: usize,
+    old_ptr: usize,
+) -> LinuxResult<usize> {
+    check_itimer(which)?;
+    let mut uspace = process.aspace.lock();
+    let (interval, value) = if new_ptr != 0 {
+        read_itimerval(&uspace, new_ptr)?
+    } else {
+        (Duration::ZERO, Duration::ZERO)
+    };
+    let now = monotonic_time();
+    let mut timer = process.itimer.lock();
+    if old_ptr != 0 {
+        write_itimerval(&mut uspace, old_ptr, timer.remaining(now))?;
+    }
+    timer.update(now);
+    timer.next = (!value.is_zero()).then(|| now.checked_add(value).unwrap_or(Duration::MAX));
+    timer.interval = interval;
+    Ok(0)
+}
+
+// ── SYS_TIMES ──
+
+/// `time` in clock ticks, rounded down.
+fn to_clock_ticks(time: Duration) -> u64 {
+    (time.as_nanos() / (NANOS_PER_SEC as u64 / CLK_TCK) as u128) as u64
+}
+
+/// Fills the `struct tms` at `buf`, if that is not null, with the user and
+/// system time of the process and of its reaped children, and returns the
+/// clock ticks since boot.
+pub fn sys_times(process: &Process, buf: usize) -> LinuxResult<usize> {
+    if buf != 0 {
+        let times = &process.cpu_times;
+        let mut raw = [0u8; TMS_SIZE];
+        for (field, counter) in raw.chunks_exact_mut(8).zip([
+            &times.user,
+            &times.system,
+            &times.children_user,
+            &times.children_system,
+        ]) {
+            field.copy_from_slice(&to_clock_ticks(CpuTimes::get(counter)).to_ne_bytes());
+        }
+        uaccess::copy_to_user(&mut process.aspace.lock(), buf, &raw)?;
+    }
+    Ok(to_clock_ticks(monotonic_time()) as usize)
+}
@@ -0,0 +1,140 @@
+//! Checked access to user memory from syscall handlers.
+//!
+//! The user page table also carries the kernel's mappings, so a raw
+//! `AddrSpace::read`/`write` would happily follow a user pointer into kernel
+//! memory. Every access here is first checked against the user areas of the
+//! address space (which never include the kernel copy) and fails with
+//! `EFAULT` if the range is not mapped with the required permissions.
+//!
+//! A page of an area mapped on demand (such as the `lazy-stack` user stack)
+//! has no frame until it is first touched, only an empty entry. Reading one
+//! sees zeros, as the program's own load would; writing one backs it with
+//! a frame first, as the program's own store would.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use std::os::arceos::modules::axhal::mem::{PAGE_SIZE_4K, va};
+use std::os::arceos::modules::axhal::paging::MappingFlags;
+
+/// Longest string accepted from user space (Linux `PATH_MAX`).
+const MAX_STR_LEN: usize = 4096;
+/// Most entries accepted in a user `argv`/`envp` array.
+const MAX_ARG_COUNT: usize = 256;
+
+fn check_access(uspace: &AddrSpace, addr: usize, len: usize, flags: MappingFlags) -> LinuxResult {
+    // A range wrapping past the top of the address space is not one
+    // `can_access_range` can take: it would panic building it.
+    if addr == 0
+        || addr.checked_add(len).is_none()
+        || !uspace.can_access_range(va!(addr), len, flags | MappingFlags::USER)
+    {
+        return Err(LinuxError::EFAULT);
+    }
+    Ok(())
+}
+
+/// Whether the page at `vaddr` has a frame, as opposed to an empty entry
+/// waiting for its first touch.
+pub fn populated(uspace: &AddrSpace, vaddr: usize) -> bool {
+    matches!(uspace.page_table().query(va!(vaddr)), Ok((_, flags, _)) if !flags.is_empty())
+}
+
+/// The pages `[addr, addr + len)` covers, as the start of each chunk of it
+/// in one page and that chunk's length.
+fn page_chunks(addr: usize, len: usize) -> impl Iterator<Item = (usize, usize)> {
+    let end = addr + len;
+    core::iter::successors(Some(addr), |&a| Some((a / PAGE_SIZE_4K + 1) * PAGE_SIZE_4K))
+        .take_while(move |&a| a < end)
+        .map(move |a| (a, ((a / PAGE_SIZE_4K + 1) * PAGE_SIZE_4K).min(end) - a))
+}
+
+/// Checks that the user buffer of `len` bytes at `addr` could be written,
+/// as `copy_to_user` would (`EFAULT` otherwise), without writing it.
+pub fn check_writable(uspace: &AddrSpace, addr: usize, len: usize) -> LinuxResult {
+    check_access(uspace, addr, len, MappingFlags::READ | MappingFlags::WRITE)
+}
+
+/// Copies `src` to the user buffer at `dst`.
+///
+/// The whole buffer must lie inside the user address space and be mapped
+/// writable, otherwise `EFAULT` is returned and nothing is written.
+pub fn copy_to_user(uspace: &mut AddrSpace, dst: usize, src: &[u8]) -> LinuxResult {
+    check_access(
+        uspace,
+        dst,
+        src.len(),
+        MappingFlags::READ | MappingFlags::WRITE,
+    )?;
+    for (page, _) in page_chunks(dst, src.len()) {
+        if !populated(uspace, page) && !uspace.handle_page_fault(va!(page), MappingFlags::WRITE) {
+            return Err(LinuxError::EFAULT);
+        }
+    }
+    uspace.write(va!(dst), src).map_err(|_| LinuxError::EFAULT)
+}
+
+/// Fills `dst` from the user buffer at `src`.
+pub fn copy_from_user(uspace: &AddrSpace, dst: &mut [u8], src: usize) -> LinuxResult {
+    check_access(uspace, src, dst.len(), MappingFlags::READ)?;
+    let mut offset = 0;
+    for (addr, len) in page_chunks(src, dst.len()) {
+        let chunk = &mut dst[offset..offset + len];
+        if populated(uspace, addr) {
+            uspace
+                .read(va!(addr), chunk)
+                .map_err(|_| LinuxError::EFAULT)?;
+        } else {
+            chunk.fill(0);
+        }
+        offset += len;
+    }
+    Ok(())
+}
+
+/// Reads the NUL-terminated string at `ptr`.
+///
+/// Fails with `ENAMETOOLONG` if no terminator is found within
+/// `MAX_STR_LEN` bytes and `EINVAL` if the bytes are not UTF-8.
+pub fn read_cstr(uspace: &AddrSpace, ptr: usize) -> LinuxResult<String> {
+    let mut bytes = Vec::new();
+    let mut addr = ptr;
+    // Read page by page so a string ending just before an unmapped page
+    // is still accepted.
+    while bytes.len() < MAX_STR_LEN {
+        let chunk_len = (PAGE_SIZE_4K - addr % PAGE_SIZE_4K).min(MAX_STR_LEN - bytes.len());
+        let mut chunk = [0u8; PAGE_SIZE_4K];
+        copy_from_user(uspace, &mut chunk[..chunk_len], addr)?;
+        if let Some(nul) = chunk[..chunk_len].iter().position(|&b| b == 0) {
+            bytes.extend_from_slice(&chunk[..nul]);
+            return String::from_utf8(bytes).map_err(|_| LinuxError::EINVAL);
+        }
+        bytes.extend_from_slice(&chunk[..chunk_len]);
+        addr += chunk_len;
+    }
+    Err(LinuxError::ENAMETOOLONG)
+}
+
+/// Reads a NULL-terminated array of string pointers, as passed for `argv`
+/// and `envp`. A null `ptr` is treated as an empty array, as Linux does.
+pub fn read_cstr_array(uspace: &AddrSpace, ptr: usize) -> LinuxResult<Vec<String>> {
+    let mut strs = Vec::new();
+    if ptr == 0 {
+        return Ok(strs);
+    }
+    loop {
+        if strs.len() >= MAX_ARG_COUNT {
+            return Err(LinuxError::E2BIG);
+        }
+        let mut word = [0u8; size_of::<usize>()];
+        let entry = ptr
+            .checked_add(strs.len() * size_of::<usize>())
+            .ok_or(LinuxError::EFAULT)?;
+        copy_from_user(uspace, &mut word, entry)?;
+        match usize::from_ne_bytes(word) {
+            0 => return Ok(strs),
+            str_ptr => strs.push(read_cstr(uspace, str_ptr)?),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Reaping children: `wait4` and `waitid`.
+//!
+//! Both syscalls go through `wait_child`, which picks an exited child and
+//! (unless asked not to) reaps it by dropping it from the parent's
+//! children; they differ only in how they name the child and report its
+//! status. Exit statuses are kept in the encoding `wait4` hands out.
+
+use axerrno::{LinuxError, LinuxResult};
+use core::sync::atomic::Ordering;
+
+use super::coop;
+use super::process::{Pid, Process};
+use super::signal::SIGCHLD;
+use super::uaccess;
+
+// wait options (uapi/linux/wait.h).
+const WNOHANG: usize = 0x1;
+const WUNTRACED: usize = 0x2;
+const WSTOPPED: usize = WUNTRACED;
+const WEXITED: usize = 0x4;
+const WCONTINUED: usize = 0x8;
+const WNOWAIT: usize = 0x0100_0000;
+
+// waitid idtype values.
+const P_ALL: usize = 0;
+const P_PID: usize = 1;
+const P_PGID: usize = 2;
+
+// si_code values for SIGCHLD (uapi/asm-generic/siginfo.h).
+const CLD_EXITED: i32 = 1;
+const CLD_KILLED: i32 = 2;
+
+/// `siginfo_t` is 128 bytes on every architecture.
+const SIGINFO_SIZE: usize = 128;
+/// `struct rusage` on LP64 targets.
+const RUSAGE_SIZE: usize = 144;
+
+/// Wait status of a process that called `exit(code)`.
+pub fn exited_status(code: i32) -> i32 {
+    (code & 0xff) << 8
+}
+
+/// Wait status of a process killed by `signal`.
+pub fn killed_status(signal: i32) -> i32 {
+    signal & 0x7f
+}
+
+/// Exit code for a wait status, as a shell reports it: the code passed to
+/// `exit`, or 128 plus the signal that killed the process.
+pub fn exit_code(status: i32) -> i32 {
+    if status & 0x7f == 0 {
+        (status >> 8) & 0xff
+    } else {
+        128 + (status & 0x7f)
+    }
+}
+
+/// Which children a wait applies to.
+#[derive(Clone, Copy)]
+enum Which {
+    Any,
+    Pid(Pid),
+    /// The children in this process group.
+    Group(Pid),
+}
+
+impl Which {
+    fn matches(self, child: &Process) -> bool {
+        match self {
+            Which::Any => true,
+            Which::Pid(pid) => child.pid == pid,
+            Which::Group(pgid) => child.pgid.load(Ordering::Relaxed) == pgid,
+        }
+    }
+}
+
+/// Finds an exited child of `process` selected by `which` and returns its
+/// pid and wait status, reaping it if `reap` is set.
+///
+/// Blocks until such a child exits, unless `nohang` is set, in which case
+/// `None` means none has exited yet. Fails with `ECHILD` if `which`
+/// selects no child at all.
+fn wait_child(
+    process: &Process,
+    which: Which,
+    nohang: bool,
+    reap: bool,
+) -> LinuxResult<Option<(Pid, i32)>> {
+    loop {
+        let mut children = process.children.lock();
+        if !children.iter().any(|c| which.matches(c)) {
+            return Err(LinuxError::ECHILD);
+        }
+        let exited = children
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| which.matches(c))
+            .find_map(|(i, c)| c.exit_status().map(|status| (i, c.pid, status)));
+        if let Some((i, pid, status)) = exited {
+            if reap {
+                let child = children.remove(i);
+                process.cpu_times.add_child(&child.cpu_times);
+            }
+            return Ok(Some((pid, status)));
+        }
+        drop(children);
+        if nohang {
+            return Ok(None);
+        }
+        coop::yield_now();
+    }
+}
+
+// ── SYS_WAIT4 ──
+
+/// `pid == 0` waits for a child in the caller's process group, and
+/// `pid < -1` for one in the group `-pid` (see `syscall::sys_setpgid`).
+/// Stopped and continued children do not exist, so `WUNTRACED` and
+/// `WCONTINUED` are accepted and never report anything.
+pub fn sys_wait4(
+    process: &Process,
+    pid: usize,
+    status_ptr: usize,
+    options: usize,
+    rusage_ptr: usize,
+) -> LinuxResult<usize> {
+    if options & !(WNOHANG | WUNTRACED | WCONTINUED) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let which = match pid as isize {
+        -1 => Which::Any,
+        0 => Which::Group(process.pgid.load(Ordering::Relaxed)),
+        pid if pid > 0 => Which::Pid(pid as Pid),
+        pid => Which::Group(pid.unsigned_abs()),
+    };
+    let Some((pid, status)) = wait_child(process, which, options & WNOHANG != 0, true)? else {
+        return Ok(0);
+    };
+    let mut uspace = process.aspace.lock();
+    if status_ptr != 0 {
+        uaccess::copy_to_user(&mut uspace, status_ptr, &status.to_ne_bytes())?;
+    }
+    if rusage_ptr != 0 {
+        // No resource accounting: report all zeros.
+        uaccess::copy_to_user(&mut uspace, rusage_ptr, &[0; RUSAGE_SIZE])?;
+    }
+    Ok(pid)
+}
+
+// ── SYS_WAITID ──
+
+/// Supports `P_ALL`, `P_PID` and `P_PGID` (the caller's group if `id` is
+/// 0). `WEXITED` is required, since exits are
+/// the only events there are; with `WNOWAIT` the child is left to be
+/// waited for again.
+pub fn sys_waitid(
+    process: &Process,
+    idtype: usize,
+    id: usize,
+    info_ptr: usize,
+    options: usize,
+) -> LinuxResult<usize> {
+    if options & !(WNOHANG | WSTOPPED | WEXITED | WCONTINUED | WNOWAIT) != 0
+        || options & WEXITED == 0
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let which = match idtype {
+        P_ALL => Which::Any,
+        P_PID if id as i32 > 0 => Which::Pid(id as i32 as Pid),
+        P_PGID if id as i32 == 0 => Which::Group(process.pgid.load(Ordering::Relaxed)),
+        P_PGID if id as i32 > 0 => Which::Group(id as i32 as Pid),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    // Clearing the siginfo up front checks the pointer before anything is
+    // reaped, and is what POSIX asks for when WNOHANG finds nothing.
+    if info_ptr != 0 {
+        uaccess::copy_to_user(&mut process.aspace.lock(), info_ptr, &[0; SIGINFO_SIZE])?;
+    }
+    let reap = options & WNOWAIT == 0;
+    let Some((pid, status)) = wait_child(process, which, options & WNOHANG != 0, reap)? else {
+        return Ok(0);
+    };
+    if info_ptr != 0 {
+        let (code, value) = if status & 0x7f == 0 {
+            (CLD_EXITED, (status >> 8) & 0xff)
+        } else {
+            (CLD_KILLED, status & 0x7f)
+        };
+        // si_signo, si_errno, si_code, then (after padding) si_pid,
+        // si_uid and si_status.
+        let mut info = [0u8; 28];
+        info[0..4].copy_from_slice(&SIGCHLD.to_ne_bytes());
+        info[8..12].copy_from_slice(&code.to_ne_bytes());
+        info[16..20].copy_from_slice(&(pid as i32).to_ne_bytes());
+        info[24..28].copy_from_slice(&value.to_ne_bytes());
+        uaccess::copy_to_user(&mut process.aspace.lock(), info_ptr, &info)?;
+    }
+    Ok(0)
+}
@@ -73,6 +73,153 @@ run_arch_tests() {
             echo "Error: $arch test failed"
             exit 1
         fi
+
+        # With `quiet`, progress messages are gone but the exit lines stay
+        out=$(cargo xtask run --arch="$arch" --guest-features=quiet 2>&1)
+        if echo "$out" | grep -q "paddr:\|handle_syscall\|Enter user space"; then
+            echo "Error: $arch quiet run still prints progress messages"
+            exit 1
+        fi
+        if echo "$out" | grep -q "monolithic kernel exit" \
+            && echo "$out" | grep -q "Hypervisor ok!"; then
+            echo "✓ $arch quiet test passed"
+        else
+            echo "Error: $arch quiet run lost its exit lines"
+            exit 1
+        fi
+
+        # Every syscall check in /bin/conformance passes (x86_64 simulates
+        # the kernel and has no user apps)
+        if [ "$arch" != "x86_64" ]; then
+            out=$(cargo xtask run --arch="$arch" --guest-features=conformance 2>&1)
+            if echo "$out" | grep -q "conformance: 0 failure(s): ok"; then
+                echo "✓ $arch conformance test passed"
+            else
+                echo "$out" | grep "conformance:"
+                echo "Error: $arch conformance test failed"
+                exit 1
+            fi
+
+            # The syscall dispatcher returns what a fixed script of calls
+            # must get, without a user program
+            out=$(cargo xtask run --arch="$arch" --guest-features=dispatch-check 2>&1)
+            if echo "$out" | grep -q "dispatch check: 0 failure(s): ok"; then
+                echo "✓ $arch dispatch check passed"
+            else
+                echo "$out" | grep "dispatch check:"
+                echo "Error: $arch dispatch check failed"
+                exit 1
+            fi
+
+            # The syscall fuzzer runs its default seed and number of calls
+            # to the end: no panic, no bad return value, no process left
+            out=$(cargo xtask run --arch="$arch" --guest-features=syscall-fuzz,quiet 2>&1)
+            if echo "$out" | grep -q "^syscall fuzz: ok"; then
+                echo "✓ $arch syscall fuzz test passed"
+            else
+                echo "$out" | grep "syscall fuzz:"
+                echo "Error: $arch syscall fuzz test failed"
+                exit 1
+            fi
+
+            # The command line the hypervisor stages picks the boot
+            # program, and the guest logs the choice
+            if [ "$arch" = "riscv64" ]; then
+                out=$(cargo xtask run --arch="$arch" --guest-features=cmdline --cmdline "init=conformance" 2>&1 | tr -d '\r')
+                if echo "$out" | grep -q "^Command line: init=conformance: starting /bin/conformance$" \
+                    && echo "$out" | grep -q "conformance: 0 failure(s): ok"; then
+                    echo "✓ $arch command line test passed"
+                else
+                    echo "$out" | grep "^Command line"
+                    echo "Error: $arch command line test failed"
+                    exit 1
+                fi
+            fi
+
+            # Under deterministic-sched, two yielding processes print the
+            # same strictly alternating output on every run
+            local expected="" run1 run2
+            for round in 1 2 3; do
+                expected+="pid 1: round $round"$'\n'"pid 2: round $round"$'\n'
+            done
+            run1=$(cargo xtask run --arch="$arch" --guest-features=demo-deterministic,quiet 2>&1 | grep "^pid [0-9]: round")
+            run2=$(cargo xtask run --arch="$arch" --guest-features=demo-deterministic,quiet 2>&1 | grep "^pid [0-9]: round")
+            if [ "$run1"$'\n' = "$expected" ] && [ "$run1" = "$run2" ]; then
+                echo "✓ $arch deterministic scheduling test passed"
+            else
+                echo "$run1"
+                echo "Error: $arch deterministic scheduling test failed"
+                exit 1
+            fi
+
+            # With capture-output, the same lines come out once, in the
+            # capture block at exit instead of live
+            out=$(cargo xtask run --arch="$arch" --guest-features=demo-deterministic,capture-output,quiet 2>&1 | tr -d '\r')
+            local captured
+            captured=$(echo "$out" | sed -n '/^captured output: [0-9]* bytes$/,/^end of captured output$/p' | grep "^pid [0-9]: round")
+            if [ "$captured"$'\n' = "$expected" ] \
+                && [ "$(echo "$out" | grep -c "^pid [0-9]: round")" = 6 ]; then
+                echo "✓ $arch output capture test passed"
+            else
+                echo "$out" | grep "^captured output:"
+                echo "Error: $arch output capture test failed"
+                exit 1
+            fi
+
+            # Two processes writing long lines at once (one write per line,
+            # more than one copy chunk each) never tear each other's lines
+            out=$(cargo xtask run --arch="$arch" --guest-features=demo-console,quiet 2>&1 | tr -d '\r')
+            local whole_a whole_b torn
+            whole_a=$(echo "$out" | grep -cE "^a{4999}$" || true)
+            whole_b=$(echo "$out" | grep -cE "^b{4999}$" || true)
+            torn=$(echo "$out" | grep -E "^[ab]{2,}" | grep -cvE "^(a{4999}|b{4999})$" || true)
+            # The kernel checks the same lines in its capture of the output
+            if [ "$whole_a" = 8 ] && [ "$whole_b" = 8 ] && [ "$torn" = 0 ] \
+                && echo "$out" | grep -q "^console demo:.*: ok$"; then
+                echo "✓ $arch console write test passed"
+            else
+                echo "$out" | grep "console demo:"
+                echo "Error: $arch console write test failed ($whole_a + $whole_b whole lines, $torn torn)"
+                exit 1
+            fi
+
+            # A forked child and its parent each print their own line, the
+            # child's first, and the parent's stack is its own
+            out=$(cargo xtask run --arch="$arch" --guest-features=demo-fork,quiet 2>&1 | tr -d '\r')
+            if echo "$out" | grep -q "^fork demo: exit 0: ok$" \
+                && [ "$(echo "$out" | grep "^fork: ")" = "fork: child"$'\n'"fork: parent" ]; then
+                echo "✓ $arch fork test passed"
+            else
+                echo "$out" | grep "^fork"
+                echo "Error: $arch fork test failed"
+                exit 1
+            fi
+
+            # With interrupts masked in user mode, the boot program still
+            # runs to the end and no interrupt reaches user mode
+            out=$(cargo xtask run --arch="$arch" --guest-features=mask-user-irqs 2>&1)
+            if echo "$out" | grep -q "Hypervisor ok!" \
+                && ! echo "$out" | grep -q "interrupts masked"; then
+                echo "✓ $arch masked interrupts test passed"
+            else
+                echo "$out" | grep "interrupts masked"
+                echo "Error: $arch masked interrupts test failed"
+                exit 1
+            fi
+
+            # With a lazily backed stack, the boot program still runs to
+            # the end, and its stack is not backed in full
+            out=$(cargo xtask run --arch="$arch" --guest-features=lazy-stack 2>&1)
+            if echo "$out" | grep -q "Hypervisor ok!" \
+                && echo "$out" | grep -qE "[0-9]+ of 16 stack pages committed" \
+                && ! echo "$out" | grep -q "16 of 16 stack pages committed"; then
+                echo "✓ $arch lazy stack test passed"
+            else
+                echo "$out" | grep "stack pages committed"
+                echo "Error: $arch lazy stack test failed"
+                exit 1
+            fi
+        fi
     done
     
     if [ "$qemu_ok" = true ]; then
@@ -100,7 +247,12 @@ print_summary() {
     echo "  1. Code format check (cargo fmt)"
     echo "  2. Lint check (cargo clippy --no-default-features)"
     echo "  3. Basic build check (cargo check --no-default-features)"
-    echo "  4. Architecture tests (riscv64, x86_64, aarch64)"
+    echo "  4. Architecture tests (riscv64, x86_64, aarch64), also with quiet"
+    echo "     and (riscv64, aarch64) the syscall conformance test, the"
+    echo "     dispatch check, the syscall fuzz test, (riscv64) the command"
+    echo "     line test, the deterministic scheduling test, the output"
+    echo "     capture test, the console write test, the fork test, the"
+    echo "     masked interrupts test and the lazy stack test"
     echo "  5. Publish readiness check (cargo publish --dry-run)"
     echo ""
 }
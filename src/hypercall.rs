@@ -0,0 +1,45 @@
+//! Hypercalls: small messages from the guest, answered by the hypervisor.
+//!
+//! The guest side (`payload/gkernel/src/hypercall.rs`, feature `hypercall`)
+//! documents the per-architecture register mapping. The call numbers and
+//! their meaning are the same everywhere:
+//!
+//! | nr | name         | arguments    | reply                         |
+//! |----|--------------|--------------|-------------------------------|
+//! | 0  | `HC_VERSION` | -            | `VERSION`                     |
+//! | 1  | `HC_ECHO`    | value        | value                         |
+//! | 2  | `HC_LOG`     | a0, a1, a2   | 0, after printing the values  |
+
+/// Protocol version reported by `HC_VERSION`.
+pub const VERSION: usize = 1;
+
+pub const HC_VERSION: usize = 0;
+pub const HC_ECHO: usize = 1;
+pub const HC_LOG: usize = 2;
+
+/// SBI extension ID carrying hypercalls on riscv64 (experimental range).
+#[cfg(target_arch = "riscv64")]
+pub const SBI_EID: usize = 0x0848_5643;
+
+/// SMCCC function ID of hypercall 0 on x86_64 (vendor-specific hypervisor
+/// service range); call `n` is `SMCCC_BASE + n`.
+#[cfg(target_arch = "x86_64")]
+pub const SMCCC_BASE: u64 = 0xC600_0000;
+
+/// Handles hypercall `nr`, returning `None` if there is no such call.
+pub fn handle(nr: usize, args: [usize; 3]) -> Option<usize> {
+    match nr {
+        HC_VERSION => Some(VERSION),
+        HC_ECHO => Some(args[0]),
+        HC_LOG => {
+            ax_println!(
+                "Guest hypercall log: {:#x} {:#x} {:#x}",
+                args[0],
+                args[1],
+                args[2]
+            );
+            Some(0)
+        }
+        _ => None,
+    }
+}
@@ -13,7 +13,7 @@ use axhal::mem::phys_to_virt;
 /// Supports binaries of any size (multi-page loading).
 /// Each page is allocated via map_alloc and written using AddrSpace::write.
 pub fn load_vm_image(fname: &str, uspace: &mut AddrSpace) -> axio::Result<()> {
-    ax_println!("app: {}", fname);
+    info_println!("app: {}", fname);
     let mut file = File::open(fname).map_err(|_| axio::Error::NotFound)?;
 
     let flags =
@@ -81,8 +81,8 @@ pub fn load_vm_image(fname: &str, uspace: &mut AddrSpace) -> axio::Result<()> {
         .query(VM_ENTRY.into())
         .map(|(pa, _, _)| pa)
         .unwrap();
-    ax_println!("paddr: PA:{:#x}", first_paddr);
-    ax_println!(
+    info_println!("paddr: PA:{:#x}", first_paddr);
+    info_println!(
         "Loaded {} bytes ({} pages) from {}",
         total_bytes,
         page_offset / PAGE_SIZE_4K,
@@ -38,6 +38,19 @@ extern crate alloc;
 #[macro_use]
 extern crate axlog;
 
+/// `ax_println!` for progress messages (load addresses, mappings, boot
+/// steps). With the `quiet` feature the branch is constant `false`, so no
+/// formatting code is generated for them; errors and the final status
+/// always print.
+#[cfg(feature = "axstd")]
+macro_rules! info_println {
+    ($($arg:tt)*) => {
+        if cfg!(not(feature = "quiet")) {
+            ax_println!($($arg)*);
+        }
+    };
+}
+
 #[cfg(feature = "axstd")]
 extern crate axfs;
 #[cfg(feature = "axstd")]
@@ -68,6 +81,14 @@ mod vcpu;
 mod x86_64_svm;
 
 // ────────────────── Common modules ──────────────────
+// Hypercalls from the guest (riscv64 and x86_64; the AArch64 guest runs
+// without a hypervisor below it).
+#[cfg(all(
+    feature = "axstd",
+    any(target_arch = "riscv64", target_arch = "x86_64")
+))]
+mod hypercall;
+
 // Loader module for loading guest binaries into an AddrSpace.
 // Currently all architectures use inline loading, but this module is
 // preserved as a reusable utility.
@@ -82,6 +103,24 @@ const VM_ENTRY: usize = 0x8020_0000;
 #[cfg(all(feature = "axstd", target_arch = "aarch64"))]
 const VM_ENTRY: usize = 0x4420_0000;
 
+// Where a user program from /sbin/app is staged for the guest, in the
+// pre-allocated RAM below its kernel (see the guest's host_app.rs).
+#[cfg(all(feature = "axstd", target_arch = "riscv64"))]
+const HOST_APP_GPA: usize = 0x8010_0000;
+#[cfg(all(feature = "axstd", target_arch = "riscv64"))]
+const HOST_APP_MAX: usize = VM_ENTRY - HOST_APP_GPA;
+#[cfg(all(feature = "axstd", target_arch = "riscv64"))]
+const HOST_APP_MAGIC: &[u8; 8] = b"GKHOSTAP";
+
+// Where the guest command line from /sbin/cmdline is staged, in the page
+// below the app window (see the guest's cmdline.rs).
+#[cfg(all(feature = "axstd", target_arch = "riscv64"))]
+const CMDLINE_GPA: usize = HOST_APP_GPA - 0x1000;
+#[cfg(all(feature = "axstd", target_arch = "riscv64"))]
+const CMDLINE_MAX: usize = 0x1000 - 16;
+#[cfg(all(feature = "axstd", target_arch = "riscv64"))]
+const CMDLINE_MAGIC: &[u8; 8] = b"GKCMDLIN";
+
 // For x86_64 with axstd: ArceOS guest kernel-base-paddr = 0x200000
 #[cfg(all(feature = "axstd", target_arch = "x86_64"))]
 const VM_ENTRY: usize = 0x20_0000;
@@ -147,7 +186,7 @@ fn riscv64_main() {
     use vcpu::_run_guest;
     use vcpu::VmCpuRegisters;
 
-    ax_println!("Starting virtualization...");
+    info_println!("Starting virtualization...");
 
     // ════════════════════════════════════════════════════
     //  Step 0: Setup H-extension CSRs  (matches h_4_0 riscv_vcpu::setup_csrs)
@@ -204,7 +243,7 @@ fn riscv64_main() {
     const PHY_MEM_START: usize = 0x8000_0000;
     const PHY_MEM_SIZE: usize = 0x100_0000; // 16 MB
 
-    ax_println!(
+    info_println!(
         "Pre-allocating {} MB guest RAM at {:#x}...",
         PHY_MEM_SIZE / (1024 * 1024),
         PHY_MEM_START
@@ -221,8 +260,8 @@ fn riscv64_main() {
     // ════════════════════════════════════════════════════
     {
         let fname = "/sbin/gkernel";
-        ax_println!("VM created success, loading images...");
-        ax_println!("app: {}", fname);
+        info_println!("VM created success, loading images...");
+        info_println!("app: {}", fname);
         let mut file = File::open(fname).expect("Cannot open guest image");
         let mut offset = 0usize;
         let mut total_bytes = 0usize;
@@ -238,7 +277,83 @@ fn riscv64_main() {
                 .expect("write guest image");
             offset += n;
         }
-        ax_println!("Loaded {} bytes from {}", total_bytes, fname);
+        info_println!("Loaded {} bytes from {}", total_bytes, fname);
+    }
+
+    // ════════════════════════════════════════════════════
+    //  Step 3b: Stage /sbin/app, if the disk has one, for a guest
+    //  built with `host-app`: magic, size (u64 LE), then the image.
+    // ════════════════════════════════════════════════════
+    if let Ok(mut file) = File::open("/sbin/app") {
+        let mut image = alloc::vec::Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = file.read(&mut buf).expect("read");
+            if n == 0 {
+                break;
+            }
+            image.extend_from_slice(&buf[..n]);
+        }
+        if image.len() + 16 > HOST_APP_MAX {
+            ax_println!(
+                "/sbin/app is {} bytes, more than the {} that fit; not staged",
+                image.len(),
+                HOST_APP_MAX - 16
+            );
+        } else {
+            uspace
+                .write(HOST_APP_GPA.into(), HOST_APP_MAGIC)
+                .expect("write app header");
+            uspace
+                .write(
+                    (HOST_APP_GPA + 8).into(),
+                    &(image.len() as u64).to_le_bytes(),
+                )
+                .expect("write app header");
+            uspace
+                .write((HOST_APP_GPA + 16).into(), &image)
+                .expect("write app image");
+            info_println!(
+                "Staged {} bytes from /sbin/app at {:#x}",
+                image.len(),
+                HOST_APP_GPA + 16
+            );
+        }
+    }
+
+    // ════════════════════════════════════════════════════
+    //  Step 3c: Stage /sbin/cmdline, if the disk has one, for a guest
+    //  built with `cmdline`: magic, length (u64 LE), then the text.
+    // ════════════════════════════════════════════════════
+    if let Ok(mut file) = File::open("/sbin/cmdline") {
+        let mut text = alloc::vec::Vec::new();
+        let mut buf = [0u8; 256];
+        loop {
+            let n = file.read(&mut buf).expect("read");
+            if n == 0 {
+                break;
+            }
+            text.extend_from_slice(&buf[..n]);
+        }
+        let text = text.trim_ascii();
+        if text.len() > CMDLINE_MAX {
+            ax_println!(
+                "/sbin/cmdline is {} bytes, more than the {} that fit; not staged",
+                text.len(),
+                CMDLINE_MAX
+            );
+        } else {
+            uspace
+                .write(CMDLINE_GPA.into(), CMDLINE_MAGIC)
+                .expect("write cmdline header");
+            uspace
+                .write((CMDLINE_GPA + 8).into(), &(text.len() as u64).to_le_bytes())
+                .expect("write cmdline header");
+            uspace
+                .write((CMDLINE_GPA + 16).into(), text)
+                .expect("write cmdline");
+            info_println!("Staged command line at {:#x}", CMDLINE_GPA + 16);
+        }
     }
 
     // ════════════════════════════════════════════════════
@@ -249,7 +364,7 @@ fn riscv64_main() {
     prepare_guest_context(&mut ctx);
 
     let ept_root = uspace.page_table_root();
-    ax_println!("bsp_entry: {:#x}; ept: {:#x}", VM_ENTRY, ept_root);
+    info_println!("bsp_entry: {:#x}; ept: {:#x}", VM_ENTRY, ept_root);
     prepare_vm_pgtable(ept_root);
 
     // ════════════════════════════════════════════════════
@@ -263,8 +378,10 @@ fn riscv64_main() {
     //    - Supervisor timer interrupt: inject to guest via hvip
     //      (required for guest preemptive multitasking)
     // ════════════════════════════════════════════════════
-    ax_println!("Entering VM run loop...");
+    info_println!("Entering VM run loop...");
 
+    // Exit status reported by the guest with its shutdown request.
+    let mut guest_status = 0u8;
     loop {
         // Disable host interrupts while guest is running (like h_4_0 vcpu_run)
         let saved_sstatus: usize;
@@ -302,11 +419,12 @@ fn riscv64_main() {
 
                 // ── Shutdown ──
                 if a7 == 8 {
-                    ax_println!("Guest: SBI legacy shutdown");
+                    info_println!("Guest: SBI legacy shutdown");
                     break;
                 }
                 if a7 == 0x53525354 {
-                    ax_println!("Guest: SBI SRST shutdown");
+                    info_println!("Guest: SBI SRST shutdown");
+                    guest_status = srst_exit_status(ctx.guest_regs.gprs.a_regs()[1] as u32);
                     break;
                 }
 
@@ -347,6 +465,19 @@ fn riscv64_main() {
                     continue;
                 }
 
+                // ── Hypercalls (answered here, never forwarded) ──
+                if a7 == hypercall::SBI_EID {
+                    let a = ctx.guest_regs.gprs.a_regs();
+                    let (error, value) = match hypercall::handle(a6, [a[0], a[1], a[2]]) {
+                        Some(value) => (0, value),
+                        None => (sbi::SBI_ERR_NOT_SUPPORTED as usize, 0),
+                    };
+                    ctx.guest_regs.gprs.set_reg(regs::GprIndex::A0, error);
+                    ctx.guest_regs.gprs.set_reg(regs::GprIndex::A1, value);
+                    ctx.guest_regs.sepc += 4;
+                    continue;
+                }
+
                 // ── Forward all other SBI calls to the real SBI (OpenSBI) ──
                 let a0 = ctx.guest_regs.gprs.a_regs()[0];
                 let a1 = ctx.guest_regs.gprs.a_regs()[1];
@@ -421,9 +552,26 @@ fn riscv64_main() {
         }
     }
 
+    if guest_status != 0 {
+        // Let the firmware end QEMU with a failure status.
+        ax_println!("Shutdown vm: guest exit status {}", guest_status);
+        sbi_rt::system_reset(sbi_rt::Shutdown, sbi_rt::SystemFailure);
+    }
     ax_println!("Shutdown vm normally!");
     panic!("Hypervisor ok!");
 
+    /// Decodes the guest exit status from an SBI SRST reset reason:
+    /// NoReason is status 0, SystemFailure a generic failure (1), and the
+    /// vendor-specific reason `0xF000_0000 | status` carries the status
+    /// byte itself (see the guest's `main`).
+    fn srst_exit_status(reason: u32) -> u8 {
+        match reason {
+            0 => 0,
+            0xF000_0000..=0xF000_00FF => reason as u8,
+            _ => 1,
+        }
+    }
+
     fn prepare_vm_pgtable(ept_root: PhysAddr) {
         let hgatp = 8usize << 60 | usize::from(ept_root) >> 12;
         unsafe {
@@ -523,7 +671,7 @@ fn aarch64_main() {
     use axstd::io::Read;
     use memory_addr::{PAGE_SIZE_4K, va};
 
-    ax_println!("Starting virtualization (bootloader mode)...");
+    info_println!("Starting virtualization (bootloader mode)...");
 
     // Guest ArceOS binary is loaded at PA 0x44200000 (upper 64MB region).
     // This avoids overlap with the hypervisor kernel (at PA 0x40200000).
@@ -531,8 +679,8 @@ fn aarch64_main() {
 
     // ── 1. Load guest binary from filesystem to physical memory ──
     let fname = "/sbin/gkernel";
-    ax_println!("VM created success, loading images...");
-    ax_println!("app: {}", fname);
+    info_println!("VM created success, loading images...");
+    info_println!("app: {}", fname);
 
     let mut file = File::open(fname).expect("Cannot open guest image");
     let mut total_bytes = 0usize;
@@ -549,7 +697,7 @@ fn aarch64_main() {
         }
         total_bytes += n;
     }
-    ax_println!(
+    info_println!(
         "Loaded {} bytes to PA {:#x}",
         total_bytes,
         GUEST_KERNEL_PADDR
@@ -577,7 +725,7 @@ fn aarch64_main() {
     let trampoline_va = _aarch64_guest_trampoline as *const () as usize;
     let trampoline_pa = usize::from(virt_to_phys(trampoline_va.into()));
     let trampoline_page_pa = trampoline_pa & !0xFFF;
-    ax_println!(
+    info_println!(
         "Trampoline at VA {:#x} -> PA {:#x} (page {:#x})",
         trampoline_va,
         trampoline_pa,
@@ -624,7 +772,7 @@ fn aarch64_main() {
     //   b) Invalidate TLB and I-cache
     //   c) Jump to guest at PA 0x44200000
     // The guest ArceOS boots at EL1 with MMU off, just like a normal boot.
-    ax_println!(
+    info_println!(
         "Entering guest at PA {:#x} via trampoline at PA {:#x}...",
         GUEST_KERNEL_PADDR,
         trampoline_pa
@@ -676,7 +824,7 @@ fn x86_64_main() {
     use x86_64_svm::svm::*;
     use x86_64_svm::vmcb::*;
 
-    ax_println!("Starting virtualization...");
+    info_println!("Starting virtualization...");
 
     // ── 1. Check AMD SVM support ──
     let (_, _, ecx, _) = unsafe { cpuid(0x8000_0001) };
@@ -729,7 +877,7 @@ fn x86_64_main() {
 
     // Pre-allocate 32MB of guest RAM (matching guest config phys-memory-size)
     const GUEST_RAM_SIZE: usize = 0x200_0000; // 32MB
-    ax_println!(
+    info_println!(
         "Pre-allocating {} MB guest RAM at GPA 0x0...",
         GUEST_RAM_SIZE / (1024 * 1024)
     );
@@ -738,7 +886,7 @@ fn x86_64_main() {
 
     // Map APIC MMIO (GPA 0xFEE00000 → HPA 0xFEE00000, identity)
     // Required for the guest to program the APIC timer for preemptive scheduling.
-    ax_println!("Mapping APIC at GPA 0xFEE00000 (identity)...");
+    info_println!("Mapping APIC at GPA 0xFEE00000 (identity)...");
     npt.map_linear(
         0xFEE0_0000usize.into(),
         PhysAddr::from(0xFEE0_0000usize),
@@ -748,7 +896,7 @@ fn x86_64_main() {
     .expect("map APIC");
 
     // Map IOAPIC MMIO (GPA 0xFEC00000 → HPA 0xFEC00000, identity)
-    ax_println!("Mapping IOAPIC at GPA 0xFEC00000 (identity)...");
+    info_println!("Mapping IOAPIC at GPA 0xFEC00000 (identity)...");
     npt.map_linear(
         0xFEC0_0000usize.into(),
         PhysAddr::from(0xFEC0_0000usize),
@@ -776,8 +924,8 @@ fn x86_64_main() {
     // ── 7. Load guest binary at GPA VM_ENTRY (0x200000 = kernel-base-paddr) ──
     {
         let fname = "/sbin/gkernel";
-        ax_println!("VM created success, loading images...");
-        ax_println!("app: {}", fname);
+        info_println!("VM created success, loading images...");
+        info_println!("app: {}", fname);
         let mut file = File::open(fname).expect("Cannot open guest image");
         let mut offset = 0usize;
         let mut total_bytes = 0usize;
@@ -792,7 +940,7 @@ fn x86_64_main() {
                 .expect("write guest binary");
             offset += n;
         }
-        ax_println!("Loaded {} bytes from {}", total_bytes, fname);
+        info_println!("Loaded {} bytes from {}", total_bytes, fname);
     }
 
     let npt_root_pa: u64 = usize::from(npt.page_table_root()) as u64;
@@ -904,7 +1052,7 @@ fn x86_64_main() {
         npt.write(MMAP_ADDR.into(), &mmap_entry)
             .expect("write mmap entry");
 
-        ax_println!(
+        info_println!(
             "MBI at GPA {:#x}, mmap at GPA {:#x} (32MB available)",
             MBI_ADDR,
             MMAP_ADDR
@@ -935,11 +1083,11 @@ fn x86_64_main() {
         unsafe {
             core::arch::asm!("sti; nop; nop; nop; cli");
         }
-        ax_println!("Host APIC timer masked");
+        info_println!("Host APIC timer masked");
     }
 
     // ── 11. Run guest in VM loop ──
-    ax_println!("Entering VM run loop (32-bit Multiboot boot → 64-bit ArceOS)...");
+    info_println!("Entering VM run loop (32-bit Multiboot boot → 64-bit ArceOS)...");
     loop {
         // Ensure SVME is set in guest EFER (may be cleared by guest's WRMSR to EFER)
         let efer = vmcb.read_u64(SAVE_EFER);
@@ -956,9 +1104,29 @@ fn x86_64_main() {
                 let guest_rax = vmcb.guest_rax();
 
                 if guest_rax == 0x84000008 {
-                    // Guest shutdown (PSCI SYSTEM_OFF convention via VMMCALL)
+                    // Guest shutdown (PSCI SYSTEM_OFF convention via VMMCALL),
+                    // with the guest's exit status in rdi.
+                    let guest_status = gprs.rdi as u8;
+                    if guest_status != 0 {
+                        // QEMU's isa-debug-exit device ends QEMU with
+                        // status `(value << 1) | 1`.
+                        ax_println!("Shutdown vm: guest exit status {}", guest_status);
+                        unsafe {
+                            core::arch::asm!("out dx, al", in("dx") 0xf4u16, in("al") guest_status);
+                        }
+                    }
                     ax_println!("Shutdown vm normally!");
                     break;
+                } else if (hypercall::SMCCC_BASE..=hypercall::SMCCC_BASE + 0xFFFF)
+                    .contains(&guest_rax)
+                {
+                    // Hypercall: reply in rax, or all ones if unknown.
+                    let nr = (guest_rax - hypercall::SMCCC_BASE) as usize;
+                    let args = [gprs.rdi as usize, gprs.rsi as usize, gprs.rdx as usize];
+                    let reply = hypercall::handle(nr, args).unwrap_or(usize::MAX);
+                    vmcb.write_u64(SAVE_RAX, reply as u64);
+                    let rip = vmcb.guest_rip();
+                    vmcb.write_u64(SAVE_RIP, rip + 3);
                 } else {
                     // Legacy putchar or unknown VMMCALL — advance RIP past 3-byte instruction
                     let func = guest_rax & 0xFF;
@@ -22,6 +22,9 @@ enum Cmd {
         /// Target architecture: riscv64, aarch64, x86_64
         #[arg(long, default_value = "riscv64")]
         arch: String,
+        /// Extra guest kernel features, comma-separated (e.g. syscall-record)
+        #[arg(long, value_delimiter = ',')]
+        guest_features: Vec<String>,
     },
     /// Build and run the kernel in QEMU
     Run {
@@ -31,6 +34,19 @@ enum Cmd {
         /// QEMU timeout in seconds (0 means no timeout)
         #[arg(long, default_value_t = 10)]
         timeout: u64,
+        /// Extra guest kernel features, comma-separated (e.g. syscall-record)
+        #[arg(long, value_delimiter = ',')]
+        guest_features: Vec<String>,
+        /// Flat user program binary to put on the disk as /sbin/app, which
+        /// the hypervisor stages for the guest (riscv64, guest feature host-app;
+        /// packed first with guest feature compressed-app)
+        #[arg(long)]
+        user_app: Option<PathBuf>,
+        /// Guest command line to put on the disk as /sbin/cmdline, which
+        /// the hypervisor stages for the guest (riscv64, guest feature
+        /// cmdline), e.g. "init=spin"
+        #[arg(long)]
+        cmdline: Option<String>,
     },
 }
 
@@ -117,7 +133,7 @@ fn install_payload_config(root: &Path, arch: &str) {
 /// Build the guest payload (gkernel) for the target architecture.
 ///
 /// All architectures: full ArceOS monolithic kernel with user-space support.
-fn build_payload(root: &Path, info: &ArchInfo, arch: &str) -> PathBuf {
+fn build_payload(root: &Path, info: &ArchInfo, arch: &str, features: &[String]) -> PathBuf {
     let payload_dir = root.join("payload").join("gkernel");
     let manifest = root.join("Cargo.toml");
 
@@ -148,9 +164,14 @@ fn build_payload(root: &Path, info: &ArchInfo, arch: &str) -> PathBuf {
     ];
 
     // All architectures use axstd (full ArceOS monolithic kernel guest)
-    // Always add guest-kernel feature
+    // Always add guest-kernel feature, plus any requested extras
     build_args.push("--features".into());
-    build_args.push("guest-kernel".into());
+    build_args.push(
+        core::iter::once("guest-kernel")
+            .chain(features.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
 
     let status = cmd.args(&build_args).status().unwrap_or_else(|e| {
         eprintln!("Error: failed to run cargo build for payload: {}", e);
@@ -213,8 +234,80 @@ fn build_payload(root: &Path, info: &ArchInfo, arch: &str) -> PathBuf {
     payload_bin
 }
 
-/// Create a 64MB FAT32 disk image containing `/sbin/gkernel`.
-fn create_fat_disk_image(path: &Path, payload_bin: &Path) {
+/// 64-bit FNV-1a hash, as the guest's `apps::Integrity` computes it.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Packs a user program for a guest built with `compressed-app`, in the
+/// format the guest's `lz` module reads: a header with the original
+/// length and hash, then literal runs (`c < 0x80`: `c + 1` bytes) and
+/// copies (`c >= 0x80`: `c - 0x80 + 3` bytes from a `u16` offset back).
+///
+/// Matches are found greedily through a hash of the next three bytes,
+/// which is plenty for the mostly zero-padded flat binaries it sees.
+fn compress_app(data: &[u8]) -> Vec<u8> {
+    const MIN_MATCH: usize = 3;
+    const MAX_MATCH: usize = 0x7f + MIN_MATCH;
+    const MAX_LITERALS: usize = 0x80;
+    const MAX_OFFSET: usize = u16::MAX as usize;
+
+    fn flush(out: &mut Vec<u8>, literals: &[u8]) {
+        for run in literals.chunks(MAX_LITERALS) {
+            out.push((run.len() - 1) as u8);
+            out.extend_from_slice(run);
+        }
+    }
+
+    let mut out = Vec::with_capacity(data.len() / 2 + 16);
+    out.extend_from_slice(b"GKLZ");
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&fnv1a(data).to_le_bytes());
+
+    let mut table = vec![usize::MAX; 1 << 16];
+    let hash = |i: usize| {
+        let v = u32::from_le_bytes([data[i], data[i + 1], data[i + 2], 0]);
+        (v.wrapping_mul(2_654_435_761) >> 16) as usize
+    };
+    let (mut i, mut literal_start) = (0, 0);
+    while i + MIN_MATCH <= data.len() {
+        let h = hash(i);
+        let candidate = table[h];
+        table[h] = i;
+        if candidate != usize::MAX && i - candidate <= MAX_OFFSET {
+            let len = data[i..]
+                .iter()
+                .zip(&data[candidate..])
+                .take(MAX_MATCH)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= MIN_MATCH {
+                flush(&mut out, &data[literal_start..i]);
+                out.push((0x80 + len - MIN_MATCH) as u8);
+                out.extend_from_slice(&((i - candidate) as u16).to_le_bytes());
+                i += len;
+                literal_start = i;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    flush(&mut out, &data[literal_start..]);
+    out
+}
+
+/// Create a 64MB FAT32 disk image containing `/sbin/gkernel`,
+/// `/sbin/app` if `user_app` is given, packed with `compress_app` if
+/// `compress` is set, and `/sbin/cmdline` if `cmdline` is given.
+fn create_fat_disk_image(
+    path: &Path,
+    payload_bin: &Path,
+    user_app: Option<&Path>,
+    compress: bool,
+    cmdline: Option<&str>,
+) {
     const DISK_SIZE: u64 = 64 * 1024 * 1024;
 
     let payload_data = std::fs::read(payload_bin).unwrap_or_else(|e| {
@@ -226,6 +319,24 @@ fn create_fat_disk_image(path: &Path, payload_bin: &Path) {
         process::exit(1);
     });
     println!("Payload binary size: {} bytes", payload_data.len());
+    let app_data = user_app.map(|app| {
+        std::fs::read(app).unwrap_or_else(|e| {
+            eprintln!("Error: failed to read user app {}: {}", app.display(), e);
+            process::exit(1);
+        })
+    });
+    let app_data = app_data.map(|data| {
+        if !compress {
+            return data;
+        }
+        if data.len() > u32::MAX as usize {
+            eprintln!("Error: user app too large to pack ({} bytes)", data.len());
+            process::exit(1);
+        }
+        let packed = compress_app(&data);
+        println!("User app packed: {} -> {} bytes", data.len(), packed.len());
+        packed
+    });
 
     let file = std::fs::OpenOptions::new()
         .read(true)
@@ -263,6 +374,24 @@ fn create_fat_disk_image(path: &Path, payload_bin: &Path) {
         });
         f.write_all(&payload_data).unwrap();
         f.flush().unwrap();
+
+        if let Some(app_data) = &app_data {
+            let mut f = root_dir.create_file("sbin/app").unwrap_or_else(|e| {
+                eprintln!("Error: failed to create /sbin/app: {}", e);
+                process::exit(1);
+            });
+            f.write_all(app_data).unwrap();
+            f.flush().unwrap();
+        }
+
+        if let Some(cmdline) = cmdline {
+            let mut f = root_dir.create_file("sbin/cmdline").unwrap_or_else(|e| {
+                eprintln!("Error: failed to create /sbin/cmdline: {}", e);
+                process::exit(1);
+            });
+            f.write_all(cmdline.as_bytes()).unwrap();
+            f.flush().unwrap();
+        }
     }
 
     println!(
@@ -299,8 +428,16 @@ fn create_pflash_image(root: &Path, arch: &str) -> PathBuf {
 }
 
 /// Build the hypervisor kernel.
-fn do_build(root: &Path, info: &ArchInfo) {
+///
+/// `quiet` is the one guest feature that also applies to the hypervisor,
+/// so that a quiet run is quiet on both sides.
+fn do_build(root: &Path, info: &ArchInfo, guest_features: &[String]) {
     let manifest = root.join("Cargo.toml");
+    let features = if guest_features.iter().any(|f| f == "quiet") {
+        "hypervisor,quiet"
+    } else {
+        "hypervisor"
+    };
     let axconfig_path = root.join(".axconfig.toml");
     let status = Command::new("cargo")
         .env("AX_CONFIG_PATH", axconfig_path.to_str().unwrap())
@@ -310,7 +447,7 @@ fn do_build(root: &Path, info: &ArchInfo) {
             "--target",
             info.target,
             "--features",
-            "hypervisor",
+            features,
             "--manifest-path",
             manifest.to_str().unwrap(),
         ])
@@ -388,6 +525,9 @@ fn do_run_qemu(
                 "max".into(),
                 "-machine".into(),
                 "virt,virtualization=on".into(),
+                // The guest reports a failing exit status via semihosting.
+                "-semihosting-config".into(),
+                "enable=on,target=native".into(),
                 "-kernel".into(),
                 bin.to_str().unwrap().into(),
             ]);
@@ -407,6 +547,9 @@ fn do_run_qemu(
                 "q35".into(),
                 "-cpu".into(),
                 "EPYC".into(),
+                // The hypervisor reports a failing guest exit status here.
+                "-device".into(),
+                "isa-debug-exit,iobase=0xf4,iosize=0x04".into(),
                 "-kernel".into(),
                 elf.to_str().unwrap().into(),
             ]);
@@ -475,27 +618,43 @@ fn main() {
     let root = project_root();
 
     match cli.command {
-        Cmd::Build { ref arch } => {
+        Cmd::Build {
+            ref arch,
+            ref guest_features,
+        } => {
             let arch = normalize_arch(arch);
             let info = arch_info(arch);
             install_config(&root, arch);
             install_payload_config(&root, arch);
-            let _payload = build_payload(&root, &info, arch);
-            do_build(&root, &info);
+            let _payload = build_payload(&root, &info, arch, guest_features);
+            do_build(&root, &info, guest_features);
             println!("Build complete for {arch} ({})", info.target);
         }
-        Cmd::Run { ref arch, timeout } => {
+        Cmd::Run {
+            ref arch,
+            timeout,
+            ref guest_features,
+            ref user_app,
+            ref cmdline,
+        } => {
             let arch = normalize_arch(arch);
             let info = arch_info(arch);
             install_config(&root, arch);
 
             // 1. Install payload config and build payload
             install_payload_config(&root, arch);
-            let payload_bin = build_payload(&root, &info, arch);
+            let payload_bin = build_payload(&root, &info, arch, guest_features);
 
             // 2. Create disk image with payload
             let disk = root.join("target").join(format!("disk-{arch}.img"));
-            create_fat_disk_image(&disk, &payload_bin);
+            let compress = guest_features.iter().any(|f| f == "compressed-app");
+            create_fat_disk_image(
+                &disk,
+                &payload_bin,
+                user_app.as_deref(),
+                compress,
+                cmdline.as_deref(),
+            );
 
             // 3. Create pflash image (for riscv64/aarch64 NPF passthrough)
             let pflash = if arch == "riscv64" || arch == "aarch64" {
@@ -505,7 +664,7 @@ fn main() {
             };
 
             // 4. Build hypervisor kernel
-            do_build(&root, &info);
+            do_build(&root, &info, guest_features);
 
             let elf = root
                 .join("target")